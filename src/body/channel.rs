@@ -0,0 +1,286 @@
+//! A body fed through a channel
+use std::error::Error as StdError;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_channel::{mpsc, oneshot};
+use futures_util::future::{self, Future};
+use futures_util::stream::Stream;
+use http::HeaderMap;
+use http_body::{Body, Frame};
+
+type Item = Frame<Bytes>;
+
+/// Create a body that is fed through a channel, and its [`Sender`].
+///
+/// The sender can be moved to another task, to stream the body while it is
+/// being sent. Only one chunk is buffered at a time, so
+/// [`send_data`](Sender::send_data) waits until the body was read far
+/// enough.
+///
+/// # Example
+///
+/// ```
+/// # async fn run() -> Result<(), hyper_util::body::ChannelError> {
+/// use hyper::body::Bytes;
+///
+/// let (mut tx, body) = hyper_util::body::channel();
+/// tokio::spawn(async move {
+///     tx.send_data(Bytes::from("hello")).await?;
+///     tx.send_data(Bytes::from(" world")).await?;
+///     Ok::<_, hyper_util::body::ChannelError>(())
+/// });
+/// let req = hyper::Request::new(body);
+/// # drop(req);
+/// # Ok(())
+/// # }
+/// ```
+pub fn channel() -> (Sender, ChannelBody) {
+    let (tx, rx) = mpsc::channel(0);
+    let (abort_tx, abort_rx) = oneshot::channel();
+    (
+        Sender {
+            tx,
+            abort: abort_tx,
+        },
+        ChannelBody {
+            rx,
+            abort: Some(abort_rx),
+            done: false,
+        },
+    )
+}
+
+/// The sending half of a [`channel`].
+///
+/// Dropping it ends the body.
+pub struct Sender {
+    tx: mpsc::Sender<Item>,
+    abort: oneshot::Sender<()>,
+}
+
+/// The body half of a [`channel`].
+pub struct ChannelBody {
+    rx: mpsc::Receiver<Item>,
+    // Resolves if the sender aborted, and is dropped once it can't anymore.
+    abort: Option<oneshot::Receiver<()>>,
+    done: bool,
+}
+
+/// An error sending to, or reading from, a [`channel`].
+pub struct ChannelError {
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Closed,
+    Aborted,
+}
+
+// ===== impl Sender =====
+
+impl Sender {
+    /// Polls to determine whether a chunk can be sent.
+    ///
+    /// Errors if the body was dropped, or trailers were sent.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ChannelError>> {
+        self.tx
+            .poll_ready(cx)
+            .map_err(|_| ChannelError::new(Kind::Closed))
+    }
+
+    /// Send a chunk of data, waiting until there is room for it.
+    pub async fn send_data(&mut self, chunk: Bytes) -> Result<(), ChannelError> {
+        future::poll_fn(|cx| self.poll_ready(cx)).await?;
+        self.tx
+            .start_send(Frame::data(chunk))
+            .map_err(|_| ChannelError::new(Kind::Closed))
+    }
+
+    /// Try to send a chunk of data right away.
+    ///
+    /// Returns the chunk back if there is no room for it, or the body was
+    /// dropped.
+    pub fn try_send_data(&mut self, chunk: Bytes) -> Result<(), Bytes> {
+        self.tx.try_send(Frame::data(chunk)).map_err(|err| {
+            err.into_inner()
+                .into_data()
+                .unwrap_or_else(|_| Bytes::new())
+        })
+    }
+
+    /// Send trailers, ending the body.
+    ///
+    /// Sending anything afterwards fails.
+    pub async fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), ChannelError> {
+        future::poll_fn(|cx| self.poll_ready(cx)).await?;
+        let res = self
+            .tx
+            .start_send(Frame::trailers(trailers))
+            .map_err(|_| ChannelError::new(Kind::Closed));
+        self.tx.close_channel();
+        res
+    }
+
+    /// Abort the body, so that reading it fails instead of ending.
+    ///
+    /// A body that ends early looks complete to the peer if it has no
+    /// content length, so aborting is needed to signal that it was cut off.
+    pub fn abort(self) {
+        let _ = self.abort.send(());
+    }
+
+    /// Checks if the body was dropped, or trailers were sent.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+impl fmt::Debug for Sender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish()
+    }
+}
+
+// ===== impl ChannelBody =====
+
+impl Body for ChannelBody {
+    type Data = Bytes;
+    type Error = ChannelError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        if let Some(ref mut abort) = self.abort {
+            match Pin::new(abort).poll(cx) {
+                Poll::Ready(Ok(())) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(ChannelError::new(Kind::Aborted))));
+                }
+                Poll::Ready(Err(_dropped)) => self.abort = None,
+                Poll::Pending => (),
+            }
+        }
+        match Pin::new(&mut self.rx).poll_next(cx) {
+            Poll::Ready(Some(frame)) => {
+                if frame.is_trailers() {
+                    self.done = true;
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            Poll::Ready(None) => {
+                self.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+}
+
+impl fmt::Debug for ChannelBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelBody").finish()
+    }
+}
+
+// ===== impl ChannelError =====
+
+impl ChannelError {
+    fn new(kind: Kind) -> ChannelError {
+        ChannelError { kind }
+    }
+
+    /// Returns true if sending failed because the body was dropped, or
+    /// trailers were sent.
+    pub fn is_closed(&self) -> bool {
+        matches!(self.kind, Kind::Closed)
+    }
+
+    /// Returns true if reading the body failed because the sender aborted
+    /// it.
+    pub fn is_aborted(&self) -> bool {
+        matches!(self.kind, Kind::Aborted)
+    }
+}
+
+impl fmt::Debug for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ChannelError").field(&self.kind).finish()
+    }
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.kind {
+            Kind::Closed => "body channel closed",
+            Kind::Aborted => "body write aborted",
+        })
+    }
+}
+
+impl StdError for ChannelError {}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+    use http_body_util::BodyExt;
+    use hyper::body::Bytes;
+
+    use super::channel;
+
+    #[tokio::test]
+    async fn streams_data_and_trailers() {
+        let (mut tx, body) = channel();
+        let send = async move {
+            tx.send_data(Bytes::from("hello")).await.unwrap();
+            tx.send_data(Bytes::from(" world")).await.unwrap();
+            let mut trailers = HeaderMap::new();
+            trailers.insert("x-done", "1".parse().unwrap());
+            tx.send_trailers(trailers).await.unwrap();
+            assert!(tx
+                .send_data(Bytes::from("late"))
+                .await
+                .unwrap_err()
+                .is_closed());
+        };
+        let (_, collected) = futures_util::future::join(send, body.collect()).await;
+        let collected = collected.unwrap();
+        assert_eq!(collected.trailers().unwrap()["x-done"], "1");
+        assert_eq!(collected.to_bytes(), "hello world");
+    }
+
+    #[tokio::test]
+    async fn applies_backpressure() {
+        let (mut tx, body) = channel();
+        tx.try_send_data(Bytes::from("a")).unwrap();
+        assert_eq!(tx.try_send_data(Bytes::from("b")).unwrap_err(), "b");
+
+        drop(body);
+        assert!(tx.is_closed());
+        assert!(tx
+            .send_data(Bytes::from("c"))
+            .await
+            .unwrap_err()
+            .is_closed());
+    }
+
+    #[tokio::test]
+    async fn abort_fails_body() {
+        let (mut tx, body) = channel();
+        tx.try_send_data(Bytes::from("partial")).unwrap();
+        tx.abort();
+        let err = body.collect().await.unwrap_err();
+        assert!(err.is_aborted());
+    }
+}
@@ -0,0 +1,140 @@
+//! A body streaming a file
+use std::fmt;
+use std::io::{self, SeekFrom};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, Take};
+
+use super::ReaderBody;
+
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A body streaming a file, or a range of it.
+///
+/// The file is read in large frames, 64 KiB by default, without buffering
+/// more than one at a time. The length of the body is known up front, so
+/// it is sent with a `content-length`.
+pub struct FileBody {
+    inner: ReaderBody<Take<File>>,
+}
+
+impl FileBody {
+    /// Open the file at `path`, and stream all of it.
+    pub async fn open<P: AsRef<Path>>(path: P) -> io::Result<FileBody> {
+        FileBody::new(File::open(path).await?).await
+    }
+
+    /// Stream `file`, from its current position to its end.
+    pub async fn new(mut file: File) -> io::Result<FileBody> {
+        let pos = file.stream_position().await?;
+        let len = file.metadata().await?.len();
+        Ok(FileBody::from_take(file.take(len.saturating_sub(pos))))
+    }
+
+    /// Stream `len` bytes of `file`, starting at `offset`.
+    ///
+    /// The range is cut short at the end of the file, so its length is
+    /// exact even if the file is shorter than `offset + len`.
+    pub async fn range(mut file: File, offset: u64, len: u64) -> io::Result<FileBody> {
+        let file_len = file.metadata().await?.len();
+        file.seek(SeekFrom::Start(offset)).await?;
+        let len = len.min(file_len.saturating_sub(offset));
+        Ok(FileBody::from_take(file.take(len)))
+    }
+
+    /// Read the file up to `size` bytes at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn with_chunk_size(self, size: usize) -> FileBody {
+        FileBody {
+            inner: ReaderBody::with_capacity(self.inner.into_inner(), size),
+        }
+    }
+
+    fn from_take(file: Take<File>) -> FileBody {
+        FileBody {
+            inner: ReaderBody::with_capacity(file, DEFAULT_CHUNK_SIZE),
+        }
+    }
+
+    /// The number of bytes left to stream.
+    pub fn remaining(&self) -> u64 {
+        self.inner.get_ref().limit()
+    }
+}
+
+impl Body for FileBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.remaining() == 0 {
+            return Poll::Ready(None);
+        }
+        match Pin::new(&mut self.inner).poll_frame(cx) {
+            Poll::Ready(None) => Poll::Ready(Some(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file ended before the body",
+            )))),
+            other => other,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining())
+    }
+}
+
+impl fmt::Debug for FileBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileBody")
+            .field("remaining", &self.remaining())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+    use hyper::body::Body;
+
+    use super::FileBody;
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn streams_file_and_ranges() {
+        let path =
+            std::env::temp_dir().join(format!("hyper-util-file-body-{}.txt", std::process::id()));
+        std::fs::write(&path, "hello world").unwrap();
+
+        let body = FileBody::open(&path).await.unwrap();
+        assert_eq!(body.size_hint().exact(), Some(11));
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "hello world");
+
+        let file = tokio::fs::File::open(&path).await.unwrap();
+        let mut body = FileBody::range(file, 6, 100)
+            .await
+            .unwrap()
+            .with_chunk_size(2);
+        assert_eq!(body.size_hint().exact(), Some(5));
+        let first = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        assert_eq!(first, "wo");
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "rld");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
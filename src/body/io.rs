@@ -0,0 +1,262 @@
+//! Adapters between bodies and Tokio's IO traits
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::{Buf, Bytes, BytesMut};
+use http::HeaderMap;
+use http_body::{Body, Frame};
+use pin_project_lite::pin_project;
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+pin_project! {
+    /// An `AsyncRead` and `AsyncBufRead` over the data of a body.
+    ///
+    /// Trailers are not part of the data, and are kept to be inspected once
+    /// the body was read. A body error is returned as an IO error, with the
+    /// body error as its source.
+    pub struct BodyReader<B: Body> {
+        #[pin]
+        body: B,
+        chunk: Option<B::Data>,
+        trailers: Option<HeaderMap>,
+    }
+}
+
+pin_project! {
+    /// A body reading its data from an `AsyncRead`.
+    ///
+    /// Each data frame is one read, of up to the configured capacity. The
+    /// body ends when the reader does.
+    pub struct ReaderBody<R> {
+        #[pin]
+        reader: R,
+        capacity: usize,
+        done: bool,
+    }
+}
+
+// ===== impl BodyReader =====
+
+impl<B: Body> BodyReader<B> {
+    /// Read the data of `body`.
+    pub fn new(body: B) -> Self {
+        BodyReader {
+            body,
+            chunk: None,
+            trailers: None,
+        }
+    }
+
+    /// The trailers of the body, once they were read.
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    /// Consume this adapter and get the inner body.
+    ///
+    /// Data that was read from the body, but not out of this adapter, is
+    /// lost.
+    pub fn into_inner(self) -> B {
+        self.body
+    }
+}
+
+impl<B> AsyncBufRead for BodyReader<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let mut this = self.project();
+        loop {
+            if let Some(ref chunk) = *this.chunk {
+                if chunk.has_remaining() {
+                    break;
+                }
+            }
+            match this.body.as_mut().poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => match frame.into_data() {
+                    Ok(data) => *this.chunk = Some(data),
+                    Err(frame) => {
+                        if let Ok(trailers) = frame.into_trailers() {
+                            *this.trailers = Some(trailers);
+                        }
+                    }
+                },
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(body_error(err.into()))),
+                Poll::Ready(None) => return Poll::Ready(Ok(&[])),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(this.chunk.as_ref().map_or(&[], |chunk| chunk.chunk())))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        if let Some(chunk) = self.project().chunk {
+            chunk.advance(amt);
+        }
+    }
+}
+
+impl<B> AsyncRead for BodyReader<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n = {
+            let chunk = match self.as_mut().poll_fill_buf(cx) {
+                Poll::Ready(Ok(chunk)) => chunk,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            };
+            let n = chunk.len().min(buf.remaining());
+            buf.put_slice(&chunk[..n]);
+            n
+        };
+        self.consume(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<B: Body> fmt::Debug for BodyReader<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyReader").finish()
+    }
+}
+
+#[allow(clippy::io_other_error)]
+fn body_error(err: Box<dyn StdError + Send + Sync>) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+// ===== impl ReaderBody =====
+
+impl<R> ReaderBody<R> {
+    /// Read the data of a body from `reader`, 8 KiB at a time.
+    pub fn new(reader: R) -> Self {
+        ReaderBody::with_capacity(reader, DEFAULT_CAPACITY)
+    }
+
+    /// Read the data of a body from `reader`, up to `capacity` bytes at a
+    /// time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn with_capacity(reader: R, capacity: usize) -> Self {
+        assert!(capacity > 0, "ReaderBody capacity must not be zero");
+        ReaderBody {
+            reader,
+            capacity,
+            done: false,
+        }
+    }
+
+    /// Get a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Consume this body and get the inner reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncRead> Body for ReaderBody<R> {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        let mut chunk = BytesMut::zeroed(*this.capacity);
+        let n = {
+            let mut buf = ReadBuf::new(&mut chunk);
+            match this.reader.poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) => buf.filled().len(),
+                Poll::Ready(Err(err)) => {
+                    *this.done = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        };
+        if n == 0 {
+            *this.done = true;
+            return Poll::Ready(None);
+        }
+        chunk.truncate(n);
+        Poll::Ready(Some(Ok(Frame::data(chunk.freeze()))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for ReaderBody<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReaderBody")
+            .field("reader", &self.reader)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+    use http_body_util::{BodyExt, StreamBody};
+    use hyper::body::{Body, Bytes, Frame};
+    use tokio::io::AsyncReadExt;
+
+    use super::{BodyReader, ReaderBody};
+
+    #[tokio::test]
+    async fn reads_body_data_and_keeps_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-done", "1".parse().unwrap());
+        let frames = vec![
+            Frame::data(Bytes::from("hello")),
+            Frame::data(Bytes::from(" world")),
+            Frame::trailers(trailers),
+        ];
+        let body = StreamBody::new(futures_util::stream::iter(
+            frames.into_iter().map(Ok::<_, std::io::Error>),
+        ));
+
+        let mut reader = BodyReader::new(body);
+        let mut read = String::new();
+        reader.read_to_string(&mut read).await.unwrap();
+        assert_eq!(read, "hello world");
+        assert_eq!(reader.trailers().unwrap()["x-done"], "1");
+    }
+
+    #[tokio::test]
+    async fn body_from_reader() {
+        let mut body = ReaderBody::with_capacity(&b"hello world"[..], 4);
+        let mut chunks = Vec::new();
+        while let Some(frame) = body.frame().await {
+            chunks.push(frame.unwrap().into_data().unwrap());
+        }
+        assert_eq!(chunks, ["hell", "o wo", "rld"]);
+        assert!(body.is_end_stream());
+    }
+}
@@ -0,0 +1,183 @@
+//! A body with a cap on its length
+use std::error::Error as StdError;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Buf;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// A body that fails once more than a limit of bytes were read.
+    ///
+    /// If the body announces an exact length over the limit, it fails before
+    /// any data is read. Otherwise, it fails on the data frame crossing the
+    /// limit. The boxed error is a [`LengthLimitExceeded`](LengthLimitExceeded),
+    /// found with `err.downcast_ref::<LengthLimitExceeded>()`, so that a
+    /// server can answer `413 Payload Too Large`.
+    pub struct Limited<B> {
+        #[pin]
+        inner: B,
+        remaining: usize,
+        limit: usize,
+    }
+}
+
+/// Error returned by a [`Limited`](Limited) body when it exceeded its limit.
+#[derive(Debug)]
+pub struct LengthLimitExceeded {
+    limit: usize,
+}
+
+// ===== impl Limited =====
+
+impl<B> Limited<B> {
+    /// Wrap a body, failing it once it is longer than `limit` bytes.
+    pub fn new(inner: B, limit: usize) -> Self {
+        Limited {
+            inner,
+            remaining: limit,
+            limit,
+        }
+    }
+
+    /// Get a reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner body.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for Limited<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Data = B::Data;
+    type Error = Box<dyn StdError + Send + Sync>;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let limit = *this.limit;
+        let exceeded = || {
+            tracing::debug!("body exceeded its length limit of {}", limit);
+            Poll::Ready(Some(Err(LengthLimitExceeded { limit }.into())))
+        };
+
+        // Fail early on a body that says it is too long.
+        if this.inner.size_hint().lower() > *this.remaining as u64 {
+            return exceeded();
+        }
+
+        let frame = match this.inner.poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => frame,
+            Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err.into()))),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+        if let Some(data) = frame.data_ref() {
+            match this.remaining.checked_sub(data.remaining()) {
+                Some(remaining) => *this.remaining = remaining,
+                None => {
+                    *this.remaining = 0;
+                    return exceeded();
+                }
+            }
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let inner = self.inner.size_hint();
+        let remaining = self.remaining as u64;
+        let mut hint = SizeHint::new();
+        // A body over the limit ends early with an error.
+        hint.set_lower(inner.lower().min(remaining));
+        hint.set_upper(inner.upper().unwrap_or(remaining).min(remaining));
+        hint
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for Limited<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Limited")
+            .field("inner", &self.inner)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+// ===== impl LengthLimitExceeded =====
+
+impl LengthLimitExceeded {
+    /// The limit that was exceeded, in bytes.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for LengthLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "body exceeded its length limit of {} bytes", self.limit)
+    }
+}
+
+impl StdError for LengthLimitExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::{BodyExt, Full, StreamBody};
+    use hyper::body::{Bytes, Frame};
+
+    use super::{LengthLimitExceeded, Limited};
+
+    #[tokio::test]
+    async fn passes_bodies_within_limit() {
+        let body = Limited::new(Full::new(Bytes::from("hello")), 5);
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "hello");
+    }
+
+    #[tokio::test]
+    async fn fails_known_length_early() {
+        let mut body = Limited::new(Full::new(Bytes::from("hello")), 4);
+        let err = body.frame().await.unwrap().unwrap_err();
+        let err = err.downcast::<LengthLimitExceeded>().unwrap();
+        assert_eq!(err.limit(), 4);
+    }
+
+    #[tokio::test]
+    async fn fails_streamed_body_crossing_limit() {
+        let chunks = ["abc", "def", "ghi"]
+            .iter()
+            .map(|chunk| Ok::<_, std::convert::Infallible>(Frame::data(Bytes::from(*chunk))));
+        let mut body = Limited::new(StreamBody::new(futures_util::stream::iter(chunks)), 7);
+
+        assert_eq!(
+            body.frame().await.unwrap().unwrap().into_data().unwrap(),
+            "abc"
+        );
+        assert_eq!(
+            body.frame().await.unwrap().unwrap().into_data().unwrap(),
+            "def"
+        );
+        let err = body.frame().await.unwrap().unwrap_err();
+        assert!(err.is::<LengthLimitExceeded>());
+    }
+}
@@ -0,0 +1,21 @@
+//! Body utilities
+
+mod channel;
+#[cfg(feature = "body-file")]
+mod file;
+#[cfg(feature = "tokio")]
+mod io;
+mod limited;
+mod stream;
+mod timeout;
+mod trailers;
+
+pub use self::channel::{channel, ChannelBody, ChannelError, Sender};
+#[cfg(feature = "body-file")]
+pub use self::file::FileBody;
+#[cfg(feature = "tokio")]
+pub use self::io::{BodyReader, ReaderBody};
+pub use self::limited::{LengthLimitExceeded, Limited};
+pub use self::stream::{BytesStreamBody, DataStream};
+pub use self::timeout::{BodyTimeout, TimeoutBody};
+pub use self::trailers::{collect_with_trailers, require_trailers, trailers, MissingTrailers};
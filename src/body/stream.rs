@@ -0,0 +1,217 @@
+//! Bridging between bodies and streams of bytes
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_util::stream::Stream;
+use http::HeaderMap;
+use http_body::{Body, Frame};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// A body streaming the chunks of a `Stream`, then optional trailers.
+    ///
+    /// Each item of the stream becomes a data frame, and an error ends the
+    /// body with that error.
+    ///
+    /// The body is `Sync` even if the stream isn't, since the stream is
+    /// only ever polled through a pinned mutable reference. This lets any
+    /// `Send` stream back a response body, whatever the framework asks of
+    /// it.
+    pub struct BytesStreamBody<S> {
+        #[pin]
+        stream: S,
+        trailers: Option<HeaderMap>,
+    }
+}
+
+pin_project! {
+    /// A `Stream` of the data frames of a body.
+    ///
+    /// Trailers aren't yielded by the stream, but kept, so that they can be
+    /// read with [`trailers`](DataStream::trailers) once it ended.
+    ///
+    /// Like [`BytesStreamBody`], the stream is `Sync` even if the body
+    /// isn't.
+    pub struct DataStream<B> {
+        #[pin]
+        body: B,
+        trailers: Option<HeaderMap>,
+    }
+}
+
+// ===== impl BytesStreamBody =====
+
+impl<S> BytesStreamBody<S> {
+    /// Create a body from a stream of chunks.
+    pub fn new(stream: S) -> Self {
+        BytesStreamBody {
+            stream,
+            trailers: None,
+        }
+    }
+
+    /// Send `trailers` once the stream ended.
+    pub fn with_trailers(mut self, trailers: HeaderMap) -> Self {
+        self.trailers = Some(trailers);
+        self
+    }
+
+    /// Gets a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this body, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, T, E> Body for BytesStreamBody<S>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: Into<Bytes>,
+{
+    type Data = Bytes;
+    type Error = E;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        match futures_util::ready!(this.stream.poll_next(cx)) {
+            Some(Ok(chunk)) => Poll::Ready(Some(Ok(Frame::data(chunk.into())))),
+            Some(Err(err)) => Poll::Ready(Some(Err(err))),
+            None => Poll::Ready(this.trailers.take().map(|t| Ok(Frame::trailers(t)))),
+        }
+    }
+}
+
+// SAFETY: the stream is never reachable through a shared reference: there
+// is no `get_ref`, and neither `Body::is_end_stream` nor `Body::size_hint`
+// look at it.
+unsafe impl<S> Sync for BytesStreamBody<S> {}
+
+impl<S> fmt::Debug for BytesStreamBody<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BytesStreamBody")
+            .field("trailers", &self.trailers)
+            .finish()
+    }
+}
+
+// ===== impl DataStream =====
+
+impl<B> DataStream<B> {
+    /// Create a stream of the data of `body`.
+    pub fn new(body: B) -> Self {
+        DataStream {
+            body,
+            trailers: None,
+        }
+    }
+
+    /// The trailers of the body, if it had any and they were reached.
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    /// Takes the trailers of the body, if it had any and they were reached.
+    pub fn take_trailers(&mut self) -> Option<HeaderMap> {
+        self.trailers.take()
+    }
+
+    /// Gets a mutable reference to the underlying body.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.body
+    }
+
+    /// Consumes this stream, returning the underlying body.
+    pub fn into_inner(self) -> B {
+        self.body
+    }
+}
+
+impl<B: Body> Stream for DataStream<B> {
+    type Item = Result<B::Data, B::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let frame = match futures_util::ready!(this.body.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => frame,
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => return Poll::Ready(None),
+            };
+            match frame.into_data() {
+                Ok(data) => return Poll::Ready(Some(Ok(data))),
+                Err(frame) => {
+                    if let Ok(trailers) = frame.into_trailers() {
+                        *this.trailers = Some(trailers);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// SAFETY: the body is never reachable through a shared reference: there
+// is no `get_ref`, and `Stream::size_hint` isn't forwarded to it.
+unsafe impl<B> Sync for DataStream<B> {}
+
+impl<B> fmt::Debug for DataStream<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataStream")
+            .field("trailers", &self.trailers)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use bytes::Bytes;
+    use futures_util::stream::{self, StreamExt};
+    use http::HeaderMap;
+    use http_body_util::BodyExt;
+
+    use super::{BytesStreamBody, DataStream};
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn round_trips_data_and_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        let chunks = stream::iter(vec![
+            Ok::<_, Infallible>("hello "),
+            Ok::<_, Infallible>("world"),
+        ]);
+        let body = BytesStreamBody::new(chunks).with_trailers(trailers);
+
+        let mut data = DataStream::new(body.boxed_unsync());
+        let mut collected = Vec::new();
+        while let Some(chunk) = data.next().await {
+            collected.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(Bytes::from(collected), "hello world");
+        assert_eq!(data.trailers().unwrap()["grpc-status"], "0");
+    }
+
+    #[test]
+    fn is_sync() {
+        fn assert_sync<T: Sync>(_: &T) {}
+        // Capturing a `Cell` makes the stream `Send`, but not `Sync`.
+        let polls = std::cell::Cell::new(0);
+        let chunks = stream::iter(vec![Ok::<_, Infallible>(Bytes::new())]).map(move |chunk| {
+            polls.set(polls.get() + 1);
+            chunk
+        });
+        let body = BytesStreamBody::new(chunks);
+        assert_sync(&body);
+        assert_sync(&DataStream::new(body));
+    }
+}
@@ -0,0 +1,195 @@
+//! A body that fails if a frame takes too long
+use std::error::Error as StdError;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use http_body::{Body, Frame, SizeHint};
+use hyper::rt::{Sleep, Timer as _};
+use pin_project_lite::pin_project;
+
+use crate::common::timer::Timer;
+
+pin_project! {
+    /// A body that fails if its next frame doesn't arrive in time.
+    ///
+    /// The timeout starts when the body is polled without a frame being
+    /// ready, and is reset by every frame. This bounds stalled uploads on a
+    /// server, and hung upstreams on a client, without limiting how long a
+    /// body may take in total, or how slowly it is read.
+    ///
+    /// A stall fails the body with a boxed [`BodyTimeout`](BodyTimeout)
+    /// error, after which the body ends.
+    pub struct TimeoutBody<B> {
+        #[pin]
+        inner: B,
+        timer: Timer,
+        timeout: Duration,
+        sleep: Option<Pin<Box<dyn Sleep>>>,
+        timed_out: bool,
+    }
+}
+
+/// Error returned by a [`TimeoutBody`](TimeoutBody) when a frame did not
+/// arrive in time.
+#[derive(Debug)]
+pub struct BodyTimeout(());
+
+// ===== impl TimeoutBody =====
+
+impl<B> TimeoutBody<B> {
+    /// Wrap a body, failing it if no frame arrives within `timeout`.
+    ///
+    /// The `timer` is used to drive the timeout.
+    pub fn new<M>(inner: B, timeout: Duration, timer: M) -> Self
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        TimeoutBody {
+            inner,
+            timer: Timer::new(timer),
+            timeout,
+            sleep: None,
+            timed_out: false,
+        }
+    }
+
+    /// Get a reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner body.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for TimeoutBody<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Data = B::Data;
+    type Error = Box<dyn StdError + Send + Sync>;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if *this.timed_out {
+            return Poll::Ready(None);
+        }
+
+        if let Poll::Ready(frame) = this.inner.poll_frame(cx) {
+            *this.sleep = None;
+            return Poll::Ready(frame.map(|res| res.map_err(Into::into)));
+        }
+
+        let timer = &*this.timer;
+        let timeout = *this.timeout;
+        let sleep = this.sleep.get_or_insert_with(|| timer.sleep(timeout));
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                tracing::debug!("body frame timed out");
+                *this.sleep = None;
+                *this.timed_out = true;
+                Poll::Ready(Some(Err(BodyTimeout(()).into())))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.timed_out || self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for TimeoutBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeoutBody")
+            .field("inner", &self.inner)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+// ===== impl BodyTimeout =====
+
+impl fmt::Display for BodyTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("body frame timed out")
+    }
+}
+
+impl StdError for BodyTimeout {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use http_body::Body;
+    use http_body_util::BodyExt;
+    use hyper::body::Bytes;
+
+    use super::{BodyTimeout, TimeoutBody};
+    use crate::body::channel;
+    use crate::rt::MockTimer;
+
+    #[tokio::test]
+    async fn fails_stalled_body() {
+        let timer = MockTimer::new();
+        let (mut tx, body) = channel();
+        let mut body = TimeoutBody::new(body, Duration::from_secs(5), timer.clone());
+
+        let frame = tokio::spawn(async move {
+            let frame = body.frame().await;
+            (body, frame)
+        });
+        tokio::task::yield_now().await;
+        timer.advance(Duration::from_secs(4));
+        tx.send_data(Bytes::from("hello")).await.unwrap();
+        let (mut body, frame) = frame.await.unwrap();
+        assert_eq!(frame.unwrap().unwrap().into_data().unwrap(), "hello");
+
+        // the frame reset the timeout
+        let frame = tokio::spawn(async move { body.frame().await });
+        tokio::task::yield_now().await;
+        timer.advance(Duration::from_secs(5));
+        let err = frame.await.unwrap().unwrap().unwrap_err();
+        assert!(err.is::<BodyTimeout>());
+        drop(tx);
+    }
+
+    #[tokio::test]
+    async fn ends_after_timeout() {
+        let timer = MockTimer::new();
+        let (mut tx, body) = channel();
+        let mut body = TimeoutBody::new(body, Duration::from_secs(5), timer.clone());
+
+        let frame = tokio::spawn(async move {
+            let frame = body.frame().await;
+            (body, frame)
+        });
+        tokio::task::yield_now().await;
+        timer.advance(Duration::from_secs(5));
+        let (mut body, frame) = frame.await.unwrap();
+        assert!(frame.unwrap().unwrap_err().is::<BodyTimeout>());
+
+        // frames sent after the timeout are not read
+        tx.send_data(Bytes::from("late")).await.unwrap();
+        assert!(body.frame().await.is_none());
+        assert!(body.is_end_stream());
+    }
+}
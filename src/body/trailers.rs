@@ -0,0 +1,129 @@
+//! Reading the trailers of a body
+use std::error::Error as StdError;
+use std::fmt;
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::future;
+use http::HeaderMap;
+use http_body::Body;
+
+/// Read a body to its end, and return its trailers, if it had any.
+///
+/// The data of the body is discarded. This suits responses such as gRPC
+/// ones, whose status is in the trailers, once the data was handled
+/// elsewhere, or isn't needed.
+pub async fn trailers<B>(body: B) -> Result<Option<HeaderMap>, B::Error>
+where
+    B: Body,
+{
+    futures_util::pin_mut!(body);
+    let mut trailers = None;
+    while let Some(frame) = future::poll_fn(|cx| body.as_mut().poll_frame(cx)).await {
+        if let Ok(map) = frame?.into_trailers() {
+            trailers = Some(map);
+        }
+    }
+    Ok(trailers)
+}
+
+/// Read a body to its end, and return its trailers, failing if it had
+/// none.
+///
+/// A body without trailers fails with an error whose source is a
+/// [`MissingTrailers`](MissingTrailers).
+pub async fn require_trailers<B>(body: B) -> Result<HeaderMap, Box<dyn StdError + Send + Sync>>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    match trailers(body).await {
+        Ok(Some(trailers)) => Ok(trailers),
+        Ok(None) => Err(MissingTrailers(()).into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Read a body to its end, and return its data and its trailers, if it
+/// had any.
+pub async fn collect_with_trailers<B>(body: B) -> Result<(Bytes, Option<HeaderMap>), B::Error>
+where
+    B: Body,
+{
+    futures_util::pin_mut!(body);
+    let mut data = BytesMut::new();
+    let mut trailers = None;
+    while let Some(frame) = future::poll_fn(|cx| body.as_mut().poll_frame(cx)).await {
+        match frame?.into_data() {
+            Ok(mut chunk) => {
+                while chunk.has_remaining() {
+                    let bytes = chunk.chunk();
+                    let n = bytes.len();
+                    data.extend_from_slice(bytes);
+                    chunk.advance(n);
+                }
+            }
+            Err(frame) => {
+                if let Ok(map) = frame.into_trailers() {
+                    trailers = Some(map);
+                }
+            }
+        }
+    }
+    Ok((data.freeze(), trailers))
+}
+
+/// Error returned by [`require_trailers`](require_trailers) when a body
+/// ended without trailers.
+#[derive(Debug)]
+pub struct MissingTrailers(());
+
+impl fmt::Display for MissingTrailers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("body ended without trailers")
+    }
+}
+
+impl StdError for MissingTrailers {}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+    use http_body_util::{Full, StreamBody};
+    use hyper::body::{Bytes, Frame};
+
+    use super::{collect_with_trailers, require_trailers, trailers, MissingTrailers};
+
+    fn body_with_trailers() -> StreamBody<
+        futures_util::stream::Iter<std::vec::IntoIter<Result<Frame<Bytes>, std::io::Error>>>,
+    > {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        let frames = vec![
+            Ok(Frame::data(Bytes::from("hello"))),
+            Ok(Frame::data(Bytes::from(" world"))),
+            Ok(Frame::trailers(trailers)),
+        ];
+        StreamBody::new(futures_util::stream::iter(frames))
+    }
+
+    #[tokio::test]
+    async fn reads_trailers() {
+        let map = trailers(body_with_trailers()).await.unwrap().unwrap();
+        assert_eq!(map["grpc-status"], "0");
+
+        let (data, map) = collect_with_trailers(body_with_trailers()).await.unwrap();
+        assert_eq!(data, "hello world");
+        assert_eq!(map.unwrap()["grpc-status"], "0");
+    }
+
+    #[tokio::test]
+    async fn requires_trailers() {
+        let map = require_trailers(body_with_trailers()).await.unwrap();
+        assert_eq!(map["grpc-status"], "0");
+
+        let err = require_trailers(Full::new(Bytes::from("hello")))
+            .await
+            .unwrap_err();
+        assert!(err.is::<MissingTrailers>());
+    }
+}
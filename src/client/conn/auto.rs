@@ -0,0 +1,383 @@
+//! Http1 or Http2 client connection.
+//!
+//! The protocol of a connection is chosen before the handshake, usually from
+//! the result of ALPN, and the connection is driven the same way whichever
+//! it is.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::future::Either;
+use http::{Request, Response};
+use http_body::Body;
+use hyper::{
+    body::Incoming,
+    client::conn::{http1, http2},
+    rt::{bounds::Http2ClientConnExec, Read, Write},
+};
+use pin_project_lite::pin_project;
+
+/// The protocol to handshake a connection with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// HTTP/1.1
+    Http1,
+    /// HTTP/2
+    Http2,
+}
+
+/// Http1 or Http2 client connection builder.
+#[derive(Clone, Debug)]
+pub struct Builder<E> {
+    http1: http1::Builder,
+    http2: http2::Builder<E>,
+}
+
+/// The sender of requests on a connection, whichever its protocol.
+///
+/// Returned by [`Builder::handshake`].
+pub struct SendRequest<B> {
+    inner: SendRequestInner<B>,
+}
+
+enum SendRequestInner<B> {
+    H1(http1::SendRequest<B>),
+    H2(http2::SendRequest<B>),
+}
+
+pin_project! {
+    /// A future driving a connection, whichever its protocol.
+    ///
+    /// Returned by [`Builder::handshake`], and must be polled for requests
+    /// to be sent.
+    pub struct Connection<I, B, E>
+    where
+        I: Read,
+        I: Write,
+        I: Unpin,
+        B: Body,
+        B: 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        E: Http2ClientConnExec<B, I>,
+        E: Unpin,
+    {
+        #[pin]
+        inner: ConnectionInner<I, B, E>,
+    }
+}
+
+pin_project! {
+    #[project = ConnectionProj]
+    enum ConnectionInner<I, B, E>
+    where
+        I: Read,
+        I: Write,
+        I: Unpin,
+        B: Body,
+        B: 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        E: Http2ClientConnExec<B, I>,
+        E: Unpin,
+    {
+        H1 {
+            #[pin]
+            conn: http1::Connection<I, B>,
+        },
+        H2 {
+            #[pin]
+            conn: http2::Connection<I, B, E>,
+        },
+    }
+}
+
+// ===== impl Protocol =====
+
+impl Protocol {
+    /// The protocol negotiated with ALPN.
+    ///
+    /// `h2` is HTTP/2, and anything else, including no protocol negotiated,
+    /// is HTTP/1.1.
+    pub fn from_alpn(alpn: Option<&[u8]>) -> Self {
+        match alpn {
+            Some(b"h2") => Protocol::Http2,
+            _ => Protocol::Http1,
+        }
+    }
+}
+
+// ===== impl Builder =====
+
+impl<E> Builder<E> {
+    /// Create a new auto connection builder.
+    ///
+    /// `executor` is used to spawn the background tasks of HTTP/2
+    /// connections.
+    pub fn new(executor: E) -> Self
+    where
+        E: Clone,
+    {
+        Builder {
+            http1: http1::Builder::new(),
+            http2: http2::Builder::new(executor),
+        }
+    }
+
+    /// Http1 configuration.
+    pub fn http1(&mut self) -> &mut http1::Builder {
+        &mut self.http1
+    }
+
+    /// Http2 configuration.
+    pub fn http2(&mut self) -> &mut http2::Builder<E> {
+        &mut self.http2
+    }
+
+    /// Handshake a connection with the given protocol.
+    ///
+    /// The returned [`Connection`] must be polled, usually in a spawned task,
+    /// for requests to be sent with the [`SendRequest`].
+    pub async fn handshake<I, B>(
+        &self,
+        io: I,
+        protocol: Protocol,
+    ) -> hyper::Result<(SendRequest<B>, Connection<I, B, E>)>
+    where
+        I: Read + Write + Unpin,
+        B: Body + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        E: Http2ClientConnExec<B, I> + Unpin + Clone,
+    {
+        match protocol {
+            Protocol::Http1 => {
+                let (tx, conn) = self.http1.handshake(io).await?;
+                Ok((
+                    SendRequest {
+                        inner: SendRequestInner::H1(tx),
+                    },
+                    Connection {
+                        inner: ConnectionInner::H1 { conn },
+                    },
+                ))
+            }
+            Protocol::Http2 => {
+                let (tx, conn) = self.http2.handshake(io).await?;
+                Ok((
+                    SendRequest {
+                        inner: SendRequestInner::H2(tx),
+                    },
+                    Connection {
+                        inner: ConnectionInner::H2 { conn },
+                    },
+                ))
+            }
+        }
+    }
+
+    /// Handshake a connection with the protocol negotiated with ALPN.
+    ///
+    /// See [`Protocol::from_alpn`].
+    pub async fn handshake_alpn<I, B>(
+        &self,
+        io: I,
+        alpn: Option<&[u8]>,
+    ) -> hyper::Result<(SendRequest<B>, Connection<I, B, E>)>
+    where
+        I: Read + Write + Unpin,
+        B: Body + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        E: Http2ClientConnExec<B, I> + Unpin + Clone,
+    {
+        self.handshake(io, Protocol::from_alpn(alpn)).await
+    }
+}
+
+// ===== impl SendRequest =====
+
+impl<B> SendRequest<B> {
+    /// The protocol of the connection.
+    pub fn protocol(&self) -> Protocol {
+        match self.inner {
+            SendRequestInner::H1(_) => Protocol::Http1,
+            SendRequestInner::H2(_) => Protocol::Http2,
+        }
+    }
+
+    /// Polls to determine whether this sender can be used yet for a request.
+    ///
+    /// If the associated connection is closed, this returns an Error.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<hyper::Result<()>> {
+        match self.inner {
+            SendRequestInner::H1(ref mut tx) => tx.poll_ready(cx),
+            SendRequestInner::H2(ref mut tx) => tx.poll_ready(cx),
+        }
+    }
+
+    /// Waits until the dispatcher is ready.
+    ///
+    /// If the associated connection is closed, this returns an Error.
+    pub async fn ready(&mut self) -> hyper::Result<()> {
+        futures_util::future::poll_fn(|cx| self.poll_ready(cx)).await
+    }
+
+    /// Checks if the connection is currently ready to send a request.
+    pub fn is_ready(&self) -> bool {
+        match self.inner {
+            SendRequestInner::H1(ref tx) => tx.is_ready(),
+            SendRequestInner::H2(ref tx) => tx.is_ready(),
+        }
+    }
+
+    /// Checks if the connection side has been closed.
+    pub fn is_closed(&self) -> bool {
+        match self.inner {
+            SendRequestInner::H1(ref tx) => tx.is_closed(),
+            SendRequestInner::H2(ref tx) => tx.is_closed(),
+        }
+    }
+}
+
+impl<B> SendRequest<B>
+where
+    B: Body + 'static,
+{
+    /// Sends a `Request` on the associated connection.
+    ///
+    /// The request is sent as is, so its URI should be in the form expected
+    /// by the protocol of the connection: origin-form for HTTP/1.1, and
+    /// absolute-form for HTTP/2.
+    pub fn send_request(
+        &mut self,
+        req: Request<B>,
+    ) -> impl Future<Output = hyper::Result<Response<Incoming>>> {
+        match self.inner {
+            SendRequestInner::H1(ref mut tx) => Either::Left(tx.send_request(req)),
+            SendRequestInner::H2(ref mut tx) => Either::Right(tx.send_request(req)),
+        }
+    }
+}
+
+impl<B> fmt::Debug for SendRequest<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendRequest")
+            .field("protocol", &self.protocol())
+            .finish()
+    }
+}
+
+// ===== impl Connection =====
+
+impl<I, B, E> Connection<I, B, E>
+where
+    I: Read + Write + Unpin,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: Http2ClientConnExec<B, I> + Unpin,
+{
+    /// The protocol of the connection.
+    pub fn protocol(&self) -> Protocol {
+        match self.inner {
+            ConnectionInner::H1 { .. } => Protocol::Http1,
+            ConnectionInner::H2 { .. } => Protocol::Http2,
+        }
+    }
+}
+
+impl<I, B, E> Future for Connection<I, B, E>
+where
+    I: Read + Write + Unpin + 'static,
+    B: Body + Unpin + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: Http2ClientConnExec<B, I> + Unpin,
+{
+    type Output = hyper::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().inner.project() {
+            ConnectionProj::H1 { conn } => conn.poll(cx),
+            ConnectionProj::H2 { conn } => conn.poll(cx),
+        }
+    }
+}
+
+impl<I, B, E> fmt::Debug for Connection<I, B, E>
+where
+    I: Read + Write + Unpin,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: Http2ClientConnExec<B, I> + Unpin,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("protocol", &self.protocol())
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "tokio", feature = "server-auto"))]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+
+    use http::{Request, Response};
+    use http_body_util::{BodyExt, Empty, Full};
+    use hyper::body::Bytes;
+    use hyper::service::service_fn;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::{Builder, Protocol};
+    use crate::rt::{TokioExecutor, TokioIo};
+    use crate::server::conn::auto as server;
+
+    #[test]
+    fn protocol_from_alpn() {
+        assert_eq!(Protocol::from_alpn(Some(b"h2")), Protocol::Http2);
+        assert_eq!(Protocol::from_alpn(Some(b"http/1.1")), Protocol::Http1);
+        assert_eq!(Protocol::from_alpn(None), Protocol::Http1);
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn handshake_either_protocol() {
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let config = server::Builder::new(TokioExecutor::new()).to_owned_config();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let service = service_fn(|req: Request<hyper::body::Incoming>| async move {
+                    let body = format!("{:?}", req.version());
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(body))))
+                });
+                tokio::spawn(config.serve_connection(TokioIo::new(stream), service));
+            }
+        });
+
+        let builder = Builder::new(TokioExecutor::new());
+        for (alpn, uri, expected) in [
+            (None, "/", "HTTP/1.1"),
+            (Some(&b"h2"[..]), "http://localhost/", "HTTP/2.0"),
+        ] {
+            let stream = TokioIo::new(TcpStream::connect(addr).await.unwrap());
+            let (mut sender, conn) = builder
+                .handshake_alpn::<_, Empty<Bytes>>(stream, alpn)
+                .await
+                .unwrap();
+            assert_eq!(sender.protocol(), conn.protocol());
+            tokio::spawn(conn);
+
+            sender.ready().await.unwrap();
+            let req = Request::builder().uri(uri).body(Empty::new()).unwrap();
+            let response = sender.send_request(req).await.unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, expected);
+        }
+    }
+}
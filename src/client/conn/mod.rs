@@ -0,0 +1,4 @@
+//! Connection utilities.
+
+#[cfg(feature = "client-auto")]
+pub mod auto;
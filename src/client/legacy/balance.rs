@@ -0,0 +1,383 @@
+//! Balancing requests over a set of upstreams.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::task::{self, Poll};
+use std::time::{Duration, Instant};
+
+use http::uri::{Authority, PathAndQuery, Scheme};
+use http::{header::HOST, Request, Uri};
+use hyper::body::Body;
+
+use super::client::{Client, Error, ErrorKind, ResponseFuture};
+use super::connect::Connect;
+
+/// Balances requests over a set of upstreams, for reverse proxies.
+///
+/// Each request is sent to the next upstream, in a smooth weighted
+/// round-robin: with the default weight of 1 for all of them, that is a
+/// plain round-robin. The scheme and authority of the request URI are
+/// replaced with those of the upstream, and the request is sent with the
+/// wrapped [`Client`], so connections to each upstream are reused from its
+/// pool.
+///
+/// Upstreams that fail to be connected to are marked down, and skipped,
+/// until a cooldown passes. If all of them are down, they are all used.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use http_body_util::Empty;
+/// use hyper::body::Bytes;
+/// use hyper_util::client::legacy::{Balancer, Client, Upstream};
+/// use hyper_util::rt::TokioExecutor;
+///
+/// let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+/// let balancer = Balancer::new(
+///     client,
+///     vec![
+///         Upstream::new("http://10.0.0.1:8080".parse().unwrap()).weight(2),
+///         Upstream::new("http://10.0.0.2:8080".parse().unwrap()),
+///     ],
+/// );
+/// let fut = balancer.get("/hello".parse().unwrap());
+/// # let _ = fut;
+/// # }
+/// # fn main() {}
+/// ```
+pub struct Balancer<C, B> {
+    client: Client<C, B>,
+    shared: Arc<Shared>,
+    preserve_host: bool,
+}
+
+/// An upstream of a [`Balancer`].
+#[derive(Clone, Debug)]
+pub struct Upstream {
+    scheme: Scheme,
+    authority: Authority,
+    weight: u32,
+}
+
+struct Shared {
+    upstreams: Vec<Upstream>,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    // The running weights of the smooth weighted round-robin.
+    current: Vec<i64>,
+    down_until: Vec<Option<Instant>>,
+}
+
+// ===== impl Balancer =====
+
+impl<C, B> Balancer<C, B> {
+    /// Balance the requests sent with `client` over `upstreams`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `upstreams` is empty.
+    pub fn new(client: Client<C, B>, upstreams: Vec<Upstream>) -> Self {
+        assert!(!upstreams.is_empty(), "Balancer needs an upstream");
+        let len = upstreams.len();
+        Balancer {
+            client,
+            shared: Arc::new(Shared {
+                upstreams,
+                cooldown: Duration::from_secs(10),
+                state: Mutex::new(State {
+                    current: vec![0; len],
+                    down_until: vec![None; len],
+                }),
+            }),
+            preserve_host: false,
+        }
+    }
+
+    /// Set how long an upstream that failed to be connected to is skipped.
+    ///
+    /// Default is 10 seconds.
+    pub fn failure_cooldown(mut self, cooldown: Duration) -> Self {
+        self.shared_mut().cooldown = cooldown;
+        self
+    }
+
+    /// Set whether the `Host` header of requests is kept, instead of being
+    /// set to the authority of the upstream.
+    ///
+    /// Default is `false`.
+    pub fn preserve_host(mut self, enabled: bool) -> Self {
+        self.preserve_host = enabled;
+        self
+    }
+
+    /// The upstreams that are currently marked down.
+    pub fn down(&self) -> Vec<&Upstream> {
+        let now = Instant::now();
+        let state = self.shared.lock();
+        self.shared
+            .upstreams
+            .iter()
+            .zip(state.down_until.iter())
+            .filter(|(_, until)| is_down(**until, now))
+            .map(|(upstream, _)| upstream)
+            .collect()
+    }
+
+    /// Get a reference to the inner `Client`.
+    pub fn get_ref(&self) -> &Client<C, B> {
+        &self.client
+    }
+
+    fn shared_mut(&mut self) -> &mut Shared {
+        Arc::get_mut(&mut self.shared).expect("Balancer is configured before it is cloned")
+    }
+}
+
+impl<C, B> Balancer<C, B>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin + Default,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Send a `GET` request for the path and query of `uri` to an upstream.
+    pub fn get(&self, uri: Uri) -> ResponseFuture {
+        let mut req = Request::new(B::default());
+        *req.uri_mut() = uri;
+        self.request(req)
+    }
+}
+
+impl<C, B> Balancer<C, B>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Send `req` to an upstream.
+    ///
+    /// Only the path and query of its URI are kept.
+    pub fn request(&self, mut req: Request<B>) -> ResponseFuture {
+        let index = self.shared.select();
+        let upstream = &self.shared.upstreams[index];
+        let path = req
+            .uri()
+            .path_and_query()
+            .cloned()
+            .unwrap_or_else(|| PathAndQuery::from_static("/"));
+        let uri = Uri::builder()
+            .scheme(upstream.scheme.clone())
+            .authority(upstream.authority.clone())
+            .path_and_query(path)
+            .build()
+            .expect("upstream and path are valid");
+        *req.uri_mut() = uri;
+        if !self.preserve_host {
+            req.headers_mut().remove(HOST);
+        }
+
+        let fut = self.client.request(req);
+        let shared = self.shared.clone();
+        ResponseFuture::new(async move {
+            let res = fut.await;
+            shared.report(index, res.as_ref().err());
+            res
+        })
+    }
+}
+
+impl<C: Clone, B> Clone for Balancer<C, B> {
+    fn clone(&self) -> Self {
+        Balancer {
+            client: self.client.clone(),
+            shared: self.shared.clone(),
+            preserve_host: self.preserve_host,
+        }
+    }
+}
+
+impl<C, B> fmt::Debug for Balancer<C, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balancer")
+            .field("upstreams", &self.shared.upstreams)
+            .field("cooldown", &self.shared.cooldown)
+            .finish()
+    }
+}
+
+impl<C, B> tower_service::Service<Request<B>> for Balancer<C, B>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Response = http::Response<hyper::body::Incoming>;
+    type Error = Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self, _: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        self.request(req)
+    }
+}
+
+// ===== impl Upstream =====
+
+impl Upstream {
+    /// An upstream at the scheme and authority of `uri`, with a weight of 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `uri` has no scheme or authority.
+    pub fn new(uri: Uri) -> Self {
+        let parts = uri.into_parts();
+        Upstream {
+            scheme: parts.scheme.expect("upstream URI has a scheme"),
+            authority: parts.authority.expect("upstream URI has an authority"),
+            weight: 1,
+        }
+    }
+
+    /// Set the weight of this upstream, relative to the others.
+    ///
+    /// An upstream with a weight of 2 gets twice the requests of one with a
+    /// weight of 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is 0.
+    pub fn weight(mut self, weight: u32) -> Self {
+        assert!(weight > 0, "upstream weight must be positive");
+        self.weight = weight;
+        self
+    }
+
+    /// The scheme of this upstream.
+    pub fn scheme(&self) -> &Scheme {
+        &self.scheme
+    }
+
+    /// The authority of this upstream.
+    pub fn authority(&self) -> &Authority {
+        &self.authority
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn select(&self) -> usize {
+        let now = Instant::now();
+        let mut state = self.lock();
+        let state = &mut *state;
+        let all_down = state.down_until.iter().all(|until| is_down(*until, now));
+
+        let mut total = 0;
+        let mut best: Option<usize> = None;
+        for (i, upstream) in self.upstreams.iter().enumerate() {
+            if !all_down && is_down(state.down_until[i], now) {
+                continue;
+            }
+            state.current[i] += i64::from(upstream.weight);
+            total += i64::from(upstream.weight);
+            best = match best {
+                Some(b) if state.current[b] >= state.current[i] => Some(b),
+                _ => Some(i),
+            };
+        }
+        let best = best.expect("an upstream is selected");
+        state.current[best] -= total;
+        best
+    }
+
+    fn report(&self, index: usize, err: Option<&Error>) {
+        let failed = match err {
+            Some(err) => matches!(
+                err.kind(),
+                ErrorKind::Dns | ErrorKind::Connect | ErrorKind::Tls
+            ),
+            None => false,
+        };
+        let mut state = self.lock();
+        state.down_until[index] = if failed {
+            Some(Instant::now() + self.cooldown)
+        } else if err.is_none() {
+            None
+        } else {
+            state.down_until[index]
+        };
+    }
+}
+
+fn is_down(until: Option<Instant>, now: Instant) -> bool {
+    match until {
+        Some(until) => until > now,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::{Shared, State, Upstream};
+
+    fn shared(weights: &[u32]) -> Shared {
+        let upstreams = weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| Upstream::new(format!("http://10.0.0.{}", i).parse().unwrap()).weight(*w))
+            .collect::<Vec<_>>();
+        Shared {
+            state: std::sync::Mutex::new(State {
+                current: vec![0; upstreams.len()],
+                down_until: vec![None; upstreams.len()],
+            }),
+            upstreams,
+            cooldown: std::time::Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn round_robin() {
+        let shared = shared(&[1, 1, 1]);
+        let picks = (0..6).map(|_| shared.select()).collect::<Vec<_>>();
+        assert_eq!(picks, [0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn weighted_is_smooth() {
+        let shared = shared(&[5, 1, 1]);
+        let picks = (0..7).map(|_| shared.select()).collect::<Vec<_>>();
+        assert_eq!(picks, [0, 0, 1, 0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn skips_down_upstreams() {
+        let shared = shared(&[1, 1]);
+        shared.lock().down_until[0] = Some(Instant::now() + shared.cooldown);
+        assert_eq!(shared.select(), 1);
+        assert_eq!(shared.select(), 1);
+
+        // all down: use them anyway
+        shared.lock().down_until[1] = Some(Instant::now() + shared.cooldown);
+        let mut picks = (0..2).map(|_| shared.select()).collect::<Vec<_>>();
+        picks.sort_unstable();
+        assert_eq!(picks, [0, 1]);
+    }
+}
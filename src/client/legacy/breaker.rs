@@ -0,0 +1,405 @@
+//! Failing fast against hosts that keep failing.
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use http::Response;
+
+use super::client::{Error, ErrorKind, PoolKey};
+use crate::events::EventsHandle;
+
+/// The configuration of a per-host circuit breaker in front of a
+/// [`Client`](super::Client).
+///
+/// After `threshold` consecutive failures of requests to a host, its circuit
+/// opens: requests to it fail right away with a
+/// [`CircuitOpen`](CircuitOpen) error, for the `cooldown`. The circuit is
+/// then half-open, and lets a few probe requests through: if they succeed,
+/// it closes again, and if they fail, it opens for another cooldown. This
+/// keeps retries from piling onto a host that is down.
+///
+/// Failing to connect to the host, and responses with a `5xx` status, are
+/// failures. Other errors, such as a request body failing, are neither
+/// failures nor successes.
+///
+/// Changes of state are reported to
+/// [`Events::circuit_changed`](crate::events::Events::circuit_changed).
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use std::time::Duration;
+/// use http_body_util::Empty;
+/// use hyper::body::Bytes;
+/// use hyper_util::client::legacy::{CircuitBreaker, Client};
+/// use hyper_util::rt::TokioExecutor;
+///
+/// let client = Client::builder(TokioExecutor::new())
+///     .circuit_breaker(CircuitBreaker::new(5, Duration::from_secs(30)))
+///     .build_http::<Empty<Bytes>>();
+/// # drop(client);
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    probes: u32,
+}
+
+/// The state of the circuit of a host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CircuitState {
+    /// Requests are sent.
+    Closed,
+    /// Requests fail right away, until the cooldown passed.
+    Open,
+    /// Only probe requests are sent, to find out if the host recovered.
+    HalfOpen,
+}
+
+/// Error returned by a [`Client`](super::Client) when the circuit of the
+/// host is open.
+///
+/// See [`CircuitBreaker`](CircuitBreaker).
+#[derive(Debug)]
+pub struct CircuitOpen(());
+
+// The circuits of the hosts of a `Client`.
+pub(super) struct Breakers {
+    config: CircuitBreaker,
+    events: EventsHandle,
+    hosts: Mutex<HashMap<PoolKey, Host>>,
+}
+
+// Hosts are only tracked while they have failures, or an open circuit.
+struct Host {
+    state: CircuitState,
+    failures: u32,
+    open_until: Instant,
+    probes: u32,
+}
+
+// A request that was let through, which has to report how it went.
+pub(super) struct Permit {
+    breakers: Arc<Breakers>,
+    key: PoolKey,
+    probe: bool,
+    done: bool,
+}
+
+enum Outcome {
+    Success,
+    Failure,
+    Neither,
+}
+
+// ===== impl CircuitBreaker =====
+
+impl CircuitBreaker {
+    /// Open the circuit of a host after `threshold` consecutive failures,
+    /// for `cooldown`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is 0.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        assert!(threshold > 0, "circuit breaker threshold must be positive");
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            probes: 1,
+        }
+    }
+
+    /// Set how many probe requests a half-open circuit lets through at a
+    /// time.
+    ///
+    /// Default is 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probes` is 0.
+    pub fn half_open_probes(mut self, probes: u32) -> Self {
+        assert!(probes > 0, "half-open probes must be positive");
+        self.probes = probes;
+        self
+    }
+}
+
+// ===== impl CircuitOpen =====
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("circuit breaker is open")
+    }
+}
+
+impl StdError for CircuitOpen {}
+
+// ===== impl Breakers =====
+
+impl Breakers {
+    pub(super) fn new(config: CircuitBreaker, events: EventsHandle) -> Self {
+        Breakers {
+            config,
+            events,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(super) fn state(&self, key: &PoolKey) -> CircuitState {
+        let now = Instant::now();
+        match self.lock().get(&key.host()) {
+            Some(host) if host.state == CircuitState::Open && host.open_until <= now => {
+                CircuitState::HalfOpen
+            }
+            Some(host) => host.state,
+            None => CircuitState::Closed,
+        }
+    }
+
+    pub(super) fn admit(self: &Arc<Self>, key: &PoolKey) -> Result<Permit, CircuitOpen> {
+        let key = key.host();
+        let mut changed = None;
+        let admitted = {
+            let mut hosts = self.lock();
+            match hosts.get_mut(&key) {
+                None => Some(false),
+                Some(host) => {
+                    if host.state == CircuitState::Open && host.open_until <= Instant::now() {
+                        host.state = CircuitState::HalfOpen;
+                        host.probes = 0;
+                        changed = Some((CircuitState::Open, CircuitState::HalfOpen));
+                    }
+                    match host.state {
+                        CircuitState::Closed => Some(false),
+                        CircuitState::HalfOpen if host.probes < self.config.probes => {
+                            host.probes += 1;
+                            Some(true)
+                        }
+                        _ => None,
+                    }
+                }
+            }
+        };
+        self.report(&key, changed);
+
+        match admitted {
+            Some(probe) => Ok(Permit {
+                breakers: self.clone(),
+                key,
+                probe,
+                done: false,
+            }),
+            None => Err(CircuitOpen(())),
+        }
+    }
+
+    fn finish(&self, key: &PoolKey, probe: bool, outcome: Outcome) {
+        let mut changed = None;
+        {
+            let mut hosts = self.lock();
+            let remove = match hosts.get_mut(key) {
+                None => {
+                    if let Outcome::Failure = outcome {
+                        let mut host = Host {
+                            state: CircuitState::Closed,
+                            failures: 0,
+                            open_until: Instant::now(),
+                            probes: 0,
+                        };
+                        changed = self.fail(&mut host);
+                        hosts.insert(key.clone(), host);
+                    }
+                    false
+                }
+                Some(host) => match host.state {
+                    CircuitState::Closed => match outcome {
+                        Outcome::Success => true,
+                        Outcome::Failure => {
+                            changed = self.fail(host);
+                            false
+                        }
+                        Outcome::Neither => false,
+                    },
+                    // Only probes decide when half-open; requests that were
+                    // let through before the circuit opened are ignored.
+                    CircuitState::HalfOpen if probe => {
+                        // A probe of an earlier half-open state may be late.
+                        host.probes = host.probes.saturating_sub(1);
+                        match outcome {
+                            Outcome::Success => {
+                                changed = Some((CircuitState::HalfOpen, CircuitState::Closed));
+                                true
+                            }
+                            Outcome::Failure => {
+                                host.state = CircuitState::Open;
+                                host.open_until = Instant::now() + self.config.cooldown;
+                                changed = Some((CircuitState::HalfOpen, CircuitState::Open));
+                                false
+                            }
+                            Outcome::Neither => false,
+                        }
+                    }
+                    _ => false,
+                },
+            };
+            if remove {
+                hosts.remove(key);
+            }
+        }
+        self.report(key, changed);
+    }
+
+    fn fail(&self, host: &mut Host) -> Option<(CircuitState, CircuitState)> {
+        host.failures += 1;
+        if host.failures < self.config.threshold {
+            return None;
+        }
+        host.state = CircuitState::Open;
+        host.open_until = Instant::now() + self.config.cooldown;
+        Some((CircuitState::Closed, CircuitState::Open))
+    }
+
+    fn report(&self, key: &PoolKey, changed: Option<(CircuitState, CircuitState)>) {
+        if let (Some((from, to)), Some(events)) = (changed, self.events.get()) {
+            events.circuit_changed(key, from, to);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PoolKey, Host>> {
+        self.hosts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for Breakers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Breakers")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+// ===== impl Permit =====
+
+impl Permit {
+    pub(super) fn finish<T>(self, res: &Result<Response<T>, Error>) {
+        let outcome = match res {
+            Ok(res) if res.status().is_server_error() => Outcome::Failure,
+            Ok(_) => Outcome::Success,
+            Err(err) => match err.kind() {
+                ErrorKind::Dns | ErrorKind::Connect | ErrorKind::Tls => Outcome::Failure,
+                _ => Outcome::Neither,
+            },
+        };
+        self.settle(outcome);
+    }
+
+    fn settle(mut self, outcome: Outcome) {
+        self.done = true;
+        self.breakers.finish(&self.key, self.probe, outcome);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        // A canceled request still has to give its probe back.
+        if !self.done {
+            self.breakers
+                .finish(&self.key, self.probe, Outcome::Neither);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{Breakers, CircuitBreaker, CircuitState, Outcome};
+    use crate::client::legacy::PoolKey;
+    use crate::events::{Events, EventsHandle};
+
+    #[derive(Clone, Default)]
+    struct Changes(Arc<Mutex<Vec<(CircuitState, CircuitState)>>>);
+
+    impl Events for Changes {
+        fn circuit_changed(&self, _: &PoolKey, from: CircuitState, to: CircuitState) {
+            self.0.lock().unwrap().push((from, to));
+        }
+    }
+
+    fn key() -> PoolKey {
+        PoolKey::for_test("http", "hyper.local")
+    }
+
+    #[test]
+    fn opens_after_threshold() {
+        let changes = Changes::default();
+        let breakers = Arc::new(Breakers::new(
+            CircuitBreaker::new(2, Duration::from_secs(60)),
+            EventsHandle::new(changes.clone()),
+        ));
+
+        breakers.admit(&key()).unwrap().settle(Outcome::Failure);
+        assert_eq!(breakers.state(&key()), CircuitState::Closed);
+        breakers.admit(&key()).unwrap().settle(Outcome::Failure);
+        assert_eq!(breakers.state(&key()), CircuitState::Open);
+        assert!(breakers.admit(&key()).is_err());
+        assert_eq!(
+            *changes.0.lock().unwrap(),
+            [(CircuitState::Closed, CircuitState::Open)]
+        );
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let breakers = Arc::new(Breakers::new(
+            CircuitBreaker::new(2, Duration::from_secs(60)),
+            EventsHandle::default(),
+        ));
+        for outcome in [Outcome::Failure, Outcome::Success, Outcome::Failure] {
+            breakers.admit(&key()).unwrap().settle(outcome);
+        }
+        assert_eq!(breakers.state(&key()), CircuitState::Closed);
+    }
+
+    #[test]
+    fn half_open_probes() {
+        let changes = Changes::default();
+        let breakers = Arc::new(Breakers::new(
+            CircuitBreaker::new(1, Duration::ZERO),
+            EventsHandle::new(changes.clone()),
+        ));
+        breakers.admit(&key()).unwrap().settle(Outcome::Failure);
+
+        // the cooldown passed: one probe at a time
+        let probe = breakers.admit(&key()).unwrap();
+        assert!(probe.probe);
+        assert!(breakers.admit(&key()).is_err());
+
+        // a dropped probe gives its turn back
+        drop(probe);
+        breakers.admit(&key()).unwrap().settle(Outcome::Failure);
+        breakers.admit(&key()).unwrap().settle(Outcome::Success);
+        assert_eq!(breakers.state(&key()), CircuitState::Closed);
+
+        assert_eq!(
+            *changes.0.lock().unwrap(),
+            [
+                (CircuitState::Closed, CircuitState::Open),
+                (CircuitState::Open, CircuitState::HalfOpen),
+                (CircuitState::HalfOpen, CircuitState::Open),
+                (CircuitState::Open, CircuitState::HalfOpen),
+                (CircuitState::HalfOpen, CircuitState::Closed),
+            ]
+        );
+    }
+}
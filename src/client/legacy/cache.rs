@@ -0,0 +1,952 @@
+//! An HTTP cache for the legacy client.
+//!
+//! A [`Cache`](Cache) wraps a [`Client`](super::Client), and answers `GET`
+//! requests with the responses it stored, as long as they are fresh, per
+//! [RFC 9111]. It is a private cache: it may store responses that are only
+//! meant for this client, such as ones with `Cache-Control: private`.
+//!
+//! - Freshness is taken from the `max-age` directive of `Cache-Control`,
+//!   then `Expires`, then a tenth of the time since `Last-Modified`.
+//! - Stale responses with an `ETag` or `Last-Modified` are revalidated with
+//!   `If-None-Match` and `If-Modified-Since`. A `304 Not Modified` refreshes
+//!   the stored response, which is then returned.
+//! - Responses with a `Vary` header are stored per variant, that is per
+//!   value of the request headers it names.
+//! - `no-store` and `no-cache` are honored, in requests and responses.
+//! - Successful unsafe requests, such as `POST`, remove the responses stored
+//!   for their URI.
+//!
+//! Responses are kept in a [`CacheStorage`](CacheStorage), a
+//! [`MemoryStorage`](MemoryStorage) by default.
+//!
+//! [RFC 9111]: https://www.rfc-editor.org/rfc/rfc9111
+//!
+//! # Example
+//!
+//! ```
+//! # #[cfg(feature = "tokio")]
+//! # async fn run() {
+//! use http_body_util::Empty;
+//! use hyper::body::Bytes;
+//! use hyper_util::client::legacy::cache::{Cache, CacheStatus};
+//! use hyper_util::client::legacy::Client;
+//! use hyper_util::rt::TokioExecutor;
+//!
+//! let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+//! let cache = Cache::new(client);
+//!
+//! let res = cache.get("http://hyper.rs".parse().unwrap()).await.unwrap();
+//! let hit = res.extensions().get::<CacheStatus>() == Some(&CacheStatus::Hit);
+//! # let _ = hit;
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{self, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::future::poll_fn;
+use http::header::{
+    HeaderMap, HeaderName, HeaderValue, AGE, CACHE_CONTROL, CONTENT_LENGTH, DATE, ETAG, EXPIRES,
+    IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, IF_UNMODIFIED_SINCE, LAST_MODIFIED,
+    PRAGMA, VARY,
+};
+use http::{Method, Request, Response, StatusCode, Uri, Version};
+use hyper::body::{Body, Frame, Incoming, SizeHint};
+use tracing::trace;
+
+use super::client::{Client, Error};
+use super::connect::Connect;
+
+/// A cache of the responses of a [`Client`](super::Client).
+///
+/// See the [module documentation](self) for what is cached.
+pub struct Cache<C, B, S = MemoryStorage> {
+    client: Client<C, B>,
+    storage: Arc<S>,
+    max_body_size: usize,
+}
+
+/// Where a [`Cache`](Cache) keeps its responses.
+///
+/// Responses are stored by the URI of their request, as one
+/// [`CachedResponse`](CachedResponse) per variant.
+pub trait CacheStorage: Send + Sync + 'static {
+    /// Get the responses stored for `key`.
+    fn get(&self, key: &str) -> Vec<CachedResponse>;
+
+    /// Store `responses` for `key`, replacing those that were.
+    fn put(&self, key: &str, responses: Vec<CachedResponse>);
+
+    /// Remove the responses stored for `key`.
+    fn remove(&self, key: &str);
+}
+
+/// A [`CacheStorage`](CacheStorage) in memory.
+///
+/// When it holds more keys than its maximum, the ones stored first are
+/// evicted.
+pub struct MemoryStorage {
+    max_entries: usize,
+    inner: Mutex<MemoryInner>,
+}
+
+struct MemoryInner {
+    entries: HashMap<String, Vec<CachedResponse>>,
+    order: VecDeque<String>,
+}
+
+/// A response stored by a [`Cache`](Cache).
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+    // The values of the request headers named by `Vary`.
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    request_time: SystemTime,
+    response_time: SystemTime,
+}
+
+/// How a [`Cache`](Cache) answered a request, in the extensions of its
+/// response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// The response was stored, and fresh.
+    Hit,
+    /// The response was stored, and revalidated with the server.
+    Revalidated,
+    /// The response came from the server.
+    Miss,
+}
+
+/// The body of a response of a [`Cache`](Cache).
+pub struct CacheBody {
+    buffered: Option<Bytes>,
+    rest: Option<Incoming>,
+    error: Option<hyper::Error>,
+}
+
+/// A future returned by a [`Cache`](Cache).
+#[must_use = "futures do nothing unless polled"]
+pub struct CacheFuture {
+    inner: Pin<Box<dyn Future<Output = Result<Response<CacheBody>, Error>> + Send>>,
+}
+
+#[derive(Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    max_age: Option<u64>,
+}
+
+// ===== impl Cache =====
+
+impl<C, B> Cache<C, B> {
+    /// Cache the responses of `client` in memory.
+    pub fn new(client: Client<C, B>) -> Self {
+        Cache::with_storage(client, MemoryStorage::new())
+    }
+}
+
+impl<C, B, S> Cache<C, B, S> {
+    /// Cache the responses of `client` in `storage`.
+    pub fn with_storage(client: Client<C, B>, storage: S) -> Self {
+        Cache {
+            client,
+            storage: Arc::new(storage),
+            max_body_size: 1024 * 1024,
+        }
+    }
+
+    /// Set the largest body of a response that is stored.
+    ///
+    /// Default is 1 MiB.
+    pub fn max_body_size(mut self, max: usize) -> Self {
+        self.max_body_size = max;
+        self
+    }
+
+    /// Get a reference to the storage.
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Get a reference to the inner `Client`.
+    pub fn get_ref(&self) -> &Client<C, B> {
+        &self.client
+    }
+}
+
+impl<C, B, S> Cache<C, B, S>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin + Default,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    S: CacheStorage,
+{
+    /// Send a `GET` request to `uri`.
+    pub fn get(&self, uri: Uri) -> CacheFuture {
+        let mut req = Request::new(B::default());
+        *req.uri_mut() = uri;
+        self.request(req)
+    }
+}
+
+impl<C, B, S> Cache<C, B, S>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    S: CacheStorage,
+{
+    /// Send `req`, or answer it with a stored response.
+    pub fn request(&self, req: Request<B>) -> CacheFuture {
+        let client = self.client.clone();
+        let storage = self.storage.clone();
+        let max_body_size = self.max_body_size;
+        CacheFuture {
+            inner: Box::pin(send(client, storage, max_body_size, req)),
+        }
+    }
+}
+
+async fn send<C, B, S>(
+    client: Client<C, B>,
+    storage: Arc<S>,
+    max_body_size: usize,
+    mut req: Request<B>,
+) -> Result<Response<CacheBody>, Error>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    S: CacheStorage,
+{
+    let key = cache_key(req.uri());
+    let method = req.method().clone();
+    if method != Method::GET {
+        let res = client.request(req).await?;
+        let status = res.status();
+        if !is_safe(&method) && (status.is_success() || status.is_redirection()) {
+            storage.remove(&key);
+        }
+        return Ok(miss(res));
+    }
+
+    let req_cc = CacheControl::parse(req.headers());
+    let req_no_cache = req_cc.no_cache
+        || (!req.headers().contains_key(CACHE_CONTROL)
+            && req.headers().get(PRAGMA).map(HeaderValue::as_bytes) == Some(b"no-cache"));
+    let is_conditional = [
+        IF_MATCH,
+        IF_NONE_MATCH,
+        IF_MODIFIED_SINCE,
+        IF_UNMODIFIED_SINCE,
+        IF_RANGE,
+    ]
+    .iter()
+    .any(|name| req.headers().contains_key(name));
+    if req_cc.no_store || is_conditional {
+        return client.request(req).await.map(miss);
+    }
+
+    let mut variants = storage.get(&key);
+    let variant = variants
+        .iter()
+        .position(|cached| cached.matches(req.headers()));
+    let mut revalidating = None;
+    if let Some(i) = variant {
+        let cached = &variants[i];
+        let now = SystemTime::now();
+        let age = cached.current_age(now);
+        let within_max_age = match req_cc.max_age {
+            Some(max_age) => age <= Duration::from_secs(max_age),
+            None => true,
+        };
+        if !req_no_cache && within_max_age && age < cached.freshness_lifetime() {
+            trace!("cache hit for {}", key);
+            return Ok(cached.to_response(CacheStatus::Hit, now));
+        }
+        if let Some(etag) = cached.headers.get(ETAG) {
+            req.headers_mut().insert(IF_NONE_MATCH, etag.clone());
+            revalidating = Some(i);
+        }
+        if let Some(modified) = cached.headers.get(LAST_MODIFIED) {
+            req.headers_mut()
+                .insert(IF_MODIFIED_SINCE, modified.clone());
+            revalidating = Some(i);
+        }
+    }
+
+    let req_headers = req.headers().clone();
+    let request_time = SystemTime::now();
+    let res = client.request(req).await?;
+    let response_time = SystemTime::now();
+
+    if let (Some(i), StatusCode::NOT_MODIFIED) = (revalidating, res.status()) {
+        trace!("cache revalidated {}", key);
+        let cached = &mut variants[i];
+        cached.refresh(res.headers(), request_time, response_time);
+        let res = cached.to_response(CacheStatus::Revalidated, response_time);
+        storage.put(&key, variants);
+        return Ok(res);
+    }
+
+    let vary = match vary(res.headers(), &req_headers) {
+        Some(vary) if is_storable(&res) => vary,
+        _ => return Ok(miss(res)),
+    };
+    let too_large = match res.body().size_hint().upper() {
+        Some(len) => len > max_body_size as u64,
+        None => false,
+    };
+    if too_large {
+        return Ok(miss(res));
+    }
+
+    let (parts, body) = res.into_parts();
+    let body = match collect(body, max_body_size).await {
+        Ok(body) => body,
+        Err(partial) => {
+            let mut res = Response::from_parts(parts, partial);
+            res.extensions_mut().insert(CacheStatus::Miss);
+            return Ok(res);
+        }
+    };
+    let cached = CachedResponse {
+        status: parts.status,
+        version: parts.version,
+        headers: parts.headers.clone(),
+        body: body.clone(),
+        vary,
+        request_time,
+        response_time,
+    };
+    variants.retain(|other| other.vary != cached.vary);
+    variants.push(cached);
+    storage.put(&key, variants);
+
+    let mut res = Response::from_parts(parts, CacheBody::full(body));
+    res.extensions_mut().insert(CacheStatus::Miss);
+    Ok(res)
+}
+
+impl<C: Clone, B, S> Clone for Cache<C, B, S> {
+    fn clone(&self) -> Self {
+        Cache {
+            client: self.client.clone(),
+            storage: self.storage.clone(),
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+impl<C, B, S> fmt::Debug for Cache<C, B, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("max_body_size", &self.max_body_size)
+            .finish()
+    }
+}
+
+impl<C, B, S> tower_service::Service<Request<B>> for Cache<C, B, S>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    S: CacheStorage,
+{
+    type Response = Response<CacheBody>;
+    type Error = Error;
+    type Future = CacheFuture;
+
+    fn poll_ready(&mut self, _: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        self.request(req)
+    }
+}
+
+// ===== impl CacheFuture =====
+
+impl Future for CacheFuture {
+    type Output = Result<Response<CacheBody>, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl fmt::Debug for CacheFuture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Future<Response<CacheBody>>")
+    }
+}
+
+// ===== impl CacheStorage =====
+
+impl<T: CacheStorage> CacheStorage for Arc<T> {
+    fn get(&self, key: &str) -> Vec<CachedResponse> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &str, responses: Vec<CachedResponse>) {
+        (**self).put(key, responses)
+    }
+
+    fn remove(&self, key: &str) {
+        (**self).remove(key)
+    }
+}
+
+// ===== impl MemoryStorage =====
+
+impl MemoryStorage {
+    /// An empty storage, for up to 1024 keys.
+    pub fn new() -> Self {
+        MemoryStorage {
+            max_entries: 1024,
+            inner: Mutex::new(MemoryInner {
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Set the most keys that are stored.
+    ///
+    /// Default is 1024.
+    pub fn max_entries(mut self, max: usize) -> Self {
+        self.max_entries = max;
+        self
+    }
+
+    /// The number of keys stored.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Whether nothing is stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemoryInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        MemoryStorage::new()
+    }
+}
+
+impl CacheStorage for MemoryStorage {
+    fn get(&self, key: &str) -> Vec<CachedResponse> {
+        self.lock().entries.get(key).cloned().unwrap_or_default()
+    }
+
+    fn put(&self, key: &str, responses: Vec<CachedResponse>) {
+        let mut inner = self.lock();
+        if inner.entries.insert(key.to_owned(), responses).is_none() {
+            inner.order.push_back(key.to_owned());
+        }
+        while inner.entries.len() > self.max_entries {
+            match inner.order.pop_front() {
+                Some(oldest) => {
+                    inner.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let mut inner = self.lock();
+        if inner.entries.remove(key).is_some() {
+            inner.order.retain(|k| k != key);
+        }
+    }
+}
+
+impl fmt::Debug for MemoryStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStorage")
+            .field("len", &self.len())
+            .field("max_entries", &self.max_entries)
+            .finish()
+    }
+}
+
+// ===== impl CachedResponse =====
+
+impl CachedResponse {
+    /// The status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The body of the response.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// When the response was received.
+    pub fn response_time(&self) -> SystemTime {
+        self.response_time
+    }
+
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| headers.get(name) == value.as_ref())
+    }
+
+    fn date(&self) -> SystemTime {
+        self.headers
+            .get(DATE)
+            .and_then(parse_http_date)
+            .unwrap_or(self.response_time)
+    }
+
+    // RFC 9111, section 4.2.1.
+    fn freshness_lifetime(&self) -> Duration {
+        let cc = CacheControl::parse(&self.headers);
+        if cc.no_cache {
+            return Duration::ZERO;
+        }
+        if let Some(max_age) = cc.max_age {
+            return Duration::from_secs(max_age);
+        }
+        let date = self.date();
+        if let Some(expires) = self.headers.get(EXPIRES) {
+            // An invalid date means it already expired.
+            return parse_http_date(expires)
+                .and_then(|expires| expires.duration_since(date).ok())
+                .unwrap_or(Duration::ZERO);
+        }
+        match self.headers.get(LAST_MODIFIED).and_then(parse_http_date) {
+            Some(modified) => date.duration_since(modified).unwrap_or(Duration::ZERO) / 10,
+            None => Duration::ZERO,
+        }
+    }
+
+    // RFC 9111, section 4.2.3.
+    fn current_age(&self, now: SystemTime) -> Duration {
+        let apparent_age = self
+            .response_time
+            .duration_since(self.date())
+            .unwrap_or(Duration::ZERO);
+        let age = self
+            .headers
+            .get(AGE)
+            .and_then(|age| age.to_str().ok())
+            .and_then(|age| age.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::ZERO);
+        let response_delay = self
+            .response_time
+            .duration_since(self.request_time)
+            .unwrap_or(Duration::ZERO);
+        let initial_age = std::cmp::max(apparent_age, age + response_delay);
+        initial_age
+            + now
+                .duration_since(self.response_time)
+                .unwrap_or(Duration::ZERO)
+    }
+
+    // RFC 9111, section 3.2: the headers of a 304 replace the stored ones.
+    fn refresh(
+        &mut self,
+        headers: &HeaderMap,
+        request_time: SystemTime,
+        response_time: SystemTime,
+    ) {
+        for name in headers.keys() {
+            if name == CONTENT_LENGTH {
+                continue;
+            }
+            self.headers.remove(name);
+            for value in headers.get_all(name) {
+                self.headers.append(name.clone(), value.clone());
+            }
+        }
+        self.request_time = request_time;
+        self.response_time = response_time;
+    }
+
+    fn to_response(&self, status: CacheStatus, now: SystemTime) -> Response<CacheBody> {
+        let mut res = Response::new(CacheBody::full(self.body.clone()));
+        *res.status_mut() = self.status;
+        *res.version_mut() = self.version;
+        *res.headers_mut() = self.headers.clone();
+        res.headers_mut()
+            .insert(AGE, HeaderValue::from(self.current_age(now).as_secs()));
+        res.extensions_mut().insert(status);
+        res
+    }
+}
+
+// ===== impl CacheBody =====
+
+impl CacheBody {
+    fn full(body: Bytes) -> Self {
+        CacheBody {
+            buffered: Some(body),
+            rest: None,
+            error: None,
+        }
+    }
+}
+
+impl Body for CacheBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, hyper::Error>>> {
+        if let Some(buffered) = self.buffered.take() {
+            if !buffered.is_empty() {
+                return Poll::Ready(Some(Ok(Frame::data(buffered))));
+            }
+        }
+        if let Some(err) = self.error.take() {
+            return Poll::Ready(Some(Err(err)));
+        }
+        match self.rest {
+            Some(ref mut rest) => Pin::new(rest).poll_frame(cx),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        let buffered = match self.buffered {
+            Some(ref buffered) => buffered.is_empty(),
+            None => true,
+        };
+        let rest = match self.rest {
+            Some(ref rest) => rest.is_end_stream(),
+            None => true,
+        };
+        buffered && rest && self.error.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = self.buffered.as_ref().map_or(0, |b| b.len() as u64);
+        match self.rest {
+            Some(ref rest) => {
+                let rest = rest.size_hint();
+                let mut hint = SizeHint::new();
+                hint.set_lower(rest.lower() + buffered);
+                if let Some(upper) = rest.upper() {
+                    hint.set_upper(upper + buffered);
+                }
+                hint
+            }
+            None => SizeHint::with_exact(buffered),
+        }
+    }
+}
+
+impl fmt::Debug for CacheBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CacheBody").finish()
+    }
+}
+
+// ===== impl CacheControl =====
+
+impl CacheControl {
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut cc = CacheControl::default();
+        for value in headers.get_all(CACHE_CONTROL) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            for directive in value.split(',') {
+                let directive = directive.trim();
+                let (name, arg) = match directive.find('=') {
+                    Some(i) => (&directive[..i], Some(directive[i + 1..].trim_matches('"'))),
+                    None => (directive, None),
+                };
+                if name.eq_ignore_ascii_case("no-store") {
+                    cc.no_store = true;
+                } else if name.eq_ignore_ascii_case("no-cache") {
+                    cc.no_cache = true;
+                } else if name.eq_ignore_ascii_case("max-age") {
+                    // A duplicate or invalid max-age makes it stale.
+                    cc.max_age = match (cc.max_age, arg.and_then(|arg| arg.parse().ok())) {
+                        (None, Some(max_age)) => Some(max_age),
+                        _ => Some(0),
+                    };
+                }
+            }
+        }
+        cc
+    }
+}
+
+fn miss(res: Response<Incoming>) -> Response<CacheBody> {
+    let mut res = res.map(|body| CacheBody {
+        buffered: None,
+        rest: Some(body),
+        error: None,
+    });
+    res.extensions_mut().insert(CacheStatus::Miss);
+    res
+}
+
+fn cache_key(uri: &Uri) -> String {
+    uri.to_string()
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+fn is_storable(res: &Response<Incoming>) -> bool {
+    // The statuses that are cacheable by default, except partial content.
+    let cacheable = matches!(
+        res.status().as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    );
+    cacheable && !CacheControl::parse(res.headers()).no_store
+}
+
+// The request headers named by the `Vary` of a response, or `None` if it
+// varies on anything.
+fn vary(
+    headers: &HeaderMap,
+    req_headers: &HeaderMap,
+) -> Option<Vec<(HeaderName, Option<HeaderValue>)>> {
+    let mut vary = Vec::new();
+    for value in headers.get_all(VARY) {
+        let value = value.to_str().ok()?;
+        for name in value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            if name == "*" {
+                return None;
+            }
+            let name = HeaderName::from_bytes(name.as_bytes()).ok()?;
+            let value = req_headers.get(&name).cloned();
+            vary.push((name, value));
+        }
+    }
+    Some(vary)
+}
+
+// Reads a body up to `max` bytes, or returns what was read, with the rest.
+async fn collect(mut body: Incoming, max: usize) -> Result<Bytes, CacheBody> {
+    let mut buf = BytesMut::new();
+    loop {
+        match poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+            None => return Ok(buf.freeze()),
+            Some(Ok(frame)) => {
+                // Trailers aren't stored.
+                if let Ok(data) = frame.into_data() {
+                    buf.extend_from_slice(&data);
+                    if buf.len() > max {
+                        return Err(CacheBody {
+                            buffered: Some(buf.freeze()),
+                            rest: Some(body),
+                            error: None,
+                        });
+                    }
+                }
+            }
+            Some(Err(err)) => {
+                return Err(CacheBody {
+                    buffered: Some(buf.freeze()),
+                    rest: None,
+                    error: Some(err),
+                })
+            }
+        }
+    }
+}
+
+// Parses an IMF-fixdate, such as `Sun, 06 Nov 1994 08:49:37 GMT`, the only
+// format senders generate.
+fn parse_http_date(value: &HeaderValue) -> Option<SystemTime> {
+    let s = value.to_str().ok()?;
+    if s.len() != 29 || s.get(3..5)? != ", " || s.get(25..)? != " GMT" {
+        return None;
+    }
+    let day: i64 = s.get(5..7)?.parse().ok()?;
+    let month = match s.get(8..11)? {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+    let year: i64 = s.get(12..16)?.parse().ok()?;
+    let hour: u64 = s.get(17..19)?.parse().ok()?;
+    let min: u64 = s.get(20..22)?.parse().ok()?;
+    let sec: u64 = s.get(23..25)?.parse().ok()?;
+    if !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+
+    // Days since the epoch, from the civil calendar.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146_097 + doe - 719_468).ok()?;
+
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86_400 + hour * 3_600 + min * 60 + sec))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use bytes::Bytes;
+    use http::header::{HeaderMap, HeaderValue};
+    use http::{StatusCode, Version};
+
+    use super::{parse_http_date, CacheControl, CacheStorage, CachedResponse, MemoryStorage};
+
+    fn cached(
+        headers: &[(&'static str, &'static str)],
+        response_time: SystemTime,
+    ) -> CachedResponse {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        CachedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: map,
+            body: Bytes::new(),
+            vary: Vec::new(),
+            request_time: response_time,
+            response_time,
+        }
+    }
+
+    #[test]
+    fn parses_http_dates() {
+        let date = parse_http_date(&HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert_eq!(date, Some(UNIX_EPOCH + Duration::from_secs(784_111_777)));
+        let date = parse_http_date(&HeaderValue::from_static("Thu, 29 Feb 2024 00:00:00 GMT"));
+        assert_eq!(date, Some(UNIX_EPOCH + Duration::from_secs(1_709_164_800)));
+        assert_eq!(
+            parse_http_date(&HeaderValue::from_static("Sunday, 06-Nov-94 08:49:37 GMT")),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_cache_control() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "cache-control",
+            HeaderValue::from_static("private, No-Cache, max-age=\"60\""),
+        );
+        let cc = CacheControl::parse(&headers);
+        assert!(cc.no_cache);
+        assert!(!cc.no_store);
+        assert_eq!(cc.max_age, Some(60));
+
+        headers.append("cache-control", HeaderValue::from_static("max-age=30"));
+        assert_eq!(CacheControl::parse(&headers).max_age, Some(0));
+    }
+
+    #[test]
+    fn freshness() {
+        let date = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        let res = cached(
+            &[
+                ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+                ("cache-control", "max-age=60"),
+                ("age", "10"),
+            ],
+            date,
+        );
+        assert_eq!(res.freshness_lifetime(), Duration::from_secs(60));
+        assert_eq!(
+            res.current_age(date + Duration::from_secs(5)),
+            Duration::from_secs(15)
+        );
+
+        let res = cached(
+            &[
+                ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+                ("expires", "Sun, 06 Nov 1994 08:50:37 GMT"),
+            ],
+            date,
+        );
+        assert_eq!(res.freshness_lifetime(), Duration::from_secs(60));
+
+        let res = cached(
+            &[
+                ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+                ("last-modified", "Sun, 06 Nov 1994 08:32:57 GMT"),
+            ],
+            date,
+        );
+        assert_eq!(res.freshness_lifetime(), Duration::from_secs(100));
+
+        let res = cached(&[("cache-control", "max-age=60, no-cache")], date);
+        assert_eq!(res.freshness_lifetime(), Duration::ZERO);
+    }
+
+    #[test]
+    fn memory_storage_evicts_oldest() {
+        let storage = MemoryStorage::new().max_entries(2);
+        let res = cached(&[], SystemTime::now());
+        for key in ["a", "b", "c"] {
+            storage.put(key, vec![res.clone()]);
+        }
+        assert_eq!(storage.len(), 2);
+        assert!(storage.get("a").is_empty());
+        assert_eq!(storage.get("c").len(), 1);
+
+        storage.remove("b");
+        storage.put("d", vec![res]);
+        assert_eq!(storage.len(), 2);
+    }
+}
@@ -0,0 +1,333 @@
+//! Pooled connections without a `Client`.
+//!
+//! A [`SendRequestPool`] keeps the request senders of connections made by
+//! the caller, so that frameworks doing their own dispatch, such as custom
+//! retries or routing, can still reuse connections.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::future::{self, Either};
+use http::{Request, Response};
+use hyper::body::{Body, Incoming};
+
+use super::pool::{self, Key, Pool, Pooled, Reservation, Ver};
+
+type BoxSendFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// The request sender of a connection, as kept in a [`SendRequestPool`].
+#[non_exhaustive]
+pub enum SendRequest<B> {
+    /// An HTTP/1 connection, used by one request at a time.
+    #[cfg(feature = "http1")]
+    Http1(hyper::client::conn::http1::SendRequest<B>),
+    /// An HTTP/2 connection, shared by concurrent requests.
+    #[cfg(feature = "http2")]
+    Http2(hyper::client::conn::http2::SendRequest<B>),
+}
+
+/// A connection checked out of a [`SendRequestPool`].
+///
+/// It is returned to the pool when dropped, if it is still ready for
+/// another request. An HTTP/1 connection is only ready once the response
+/// body was read, and [`SendRequest::ready`] resolved.
+pub type PooledSendRequest<B, K> = Pooled<SendRequest<B>, K>;
+
+/// A pool of the request senders of connections, keyed by `K`.
+///
+/// Unlike the [`Client`](super::Client), the pool doesn't make connections:
+/// the caller makes them, and either [inserts](SendRequestPool::insert) them
+/// or has them made on demand with
+/// [`checkout_or_connect`](SendRequestPool::checkout_or_connect).
+pub struct SendRequestPool<B, K: Key> {
+    pool: Pool<SendRequest<B>, K>,
+}
+
+// ===== impl SendRequest =====
+
+impl<B> SendRequest<B> {
+    /// Polls to determine whether the connection can send a request.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<hyper::Result<()>> {
+        match *self {
+            #[cfg(feature = "http1")]
+            SendRequest::Http1(ref mut tx) => tx.poll_ready(cx),
+            #[cfg(feature = "http2")]
+            SendRequest::Http2(ref mut tx) => tx.poll_ready(cx),
+        }
+    }
+
+    /// Waits until the connection can send a request.
+    pub async fn ready(&mut self) -> hyper::Result<()> {
+        future::poll_fn(|cx| self.poll_ready(cx)).await
+    }
+
+    /// Checks if the connection can send a request right away.
+    pub fn is_ready(&self) -> bool {
+        match *self {
+            #[cfg(feature = "http1")]
+            SendRequest::Http1(ref tx) => tx.is_ready(),
+            #[cfg(feature = "http2")]
+            SendRequest::Http2(ref tx) => tx.is_ready(),
+        }
+    }
+
+    /// Checks if the connection was closed.
+    pub fn is_closed(&self) -> bool {
+        match *self {
+            #[cfg(feature = "http1")]
+            SendRequest::Http1(ref tx) => tx.is_closed(),
+            #[cfg(feature = "http2")]
+            SendRequest::Http2(ref tx) => tx.is_closed(),
+        }
+    }
+}
+
+impl<B> SendRequest<B>
+where
+    B: Body + 'static,
+{
+    /// Sends a request on the connection.
+    pub fn send_request(
+        &mut self,
+        req: Request<B>,
+    ) -> impl Future<Output = hyper::Result<Response<Incoming>>> {
+        #[cfg(all(feature = "http1", feature = "http2"))]
+        return match *self {
+            SendRequest::Http1(ref mut tx) => Either::Left(tx.send_request(req)),
+            SendRequest::Http2(ref mut tx) => Either::Right(tx.send_request(req)),
+        };
+
+        #[cfg(all(feature = "http1", not(feature = "http2")))]
+        return match *self {
+            SendRequest::Http1(ref mut tx) => tx.send_request(req),
+        };
+
+        #[cfg(all(not(feature = "http1"), feature = "http2"))]
+        return match *self {
+            SendRequest::Http2(ref mut tx) => tx.send_request(req),
+        };
+    }
+}
+
+#[cfg(feature = "http1")]
+impl<B> From<hyper::client::conn::http1::SendRequest<B>> for SendRequest<B> {
+    fn from(tx: hyper::client::conn::http1::SendRequest<B>) -> Self {
+        SendRequest::Http1(tx)
+    }
+}
+
+#[cfg(feature = "http2")]
+impl<B> From<hyper::client::conn::http2::SendRequest<B>> for SendRequest<B> {
+    fn from(tx: hyper::client::conn::http2::SendRequest<B>) -> Self {
+        SendRequest::Http2(tx)
+    }
+}
+
+impl<B> pool::Poolable for SendRequest<B>
+where
+    B: Send + 'static,
+{
+    fn is_open(&self) -> bool {
+        self.is_ready()
+    }
+
+    fn reserve(self) -> Reservation<Self> {
+        match self {
+            #[cfg(feature = "http1")]
+            SendRequest::Http1(tx) => Reservation::Unique(SendRequest::Http1(tx)),
+            #[cfg(feature = "http2")]
+            SendRequest::Http2(tx) => {
+                Reservation::Shared(SendRequest::Http2(tx.clone()), SendRequest::Http2(tx))
+            }
+        }
+    }
+
+    fn can_share(&self) -> bool {
+        match *self {
+            #[cfg(feature = "http1")]
+            SendRequest::Http1(_) => false,
+            #[cfg(feature = "http2")]
+            SendRequest::Http2(_) => true,
+        }
+    }
+}
+
+impl<B> fmt::Debug for SendRequest<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "http1")]
+            SendRequest::Http1(_) => f.write_str("SendRequest::Http1"),
+            #[cfg(feature = "http2")]
+            SendRequest::Http2(_) => f.write_str("SendRequest::Http2"),
+        }
+    }
+}
+
+// ===== impl SendRequestPool =====
+
+impl<B, K> SendRequestPool<B, K>
+where
+    B: Send + 'static,
+    K: Key,
+{
+    /// Create a pool configured with `config`.
+    ///
+    /// `executor` runs the background sweeping of idle connections, when the
+    /// pool has a `timer`.
+    pub fn new<E, M>(config: pool::Config, executor: E, timer: Option<M>) -> Self
+    where
+        E: hyper::rt::Executor<BoxSendFuture> + Send + Sync + Clone + 'static,
+        M: hyper::rt::Timer + Send + Sync + Clone + 'static,
+    {
+        SendRequestPool {
+            pool: Pool::new(config, executor, timer),
+        }
+    }
+
+    /// Wait for an idle connection for `key`.
+    ///
+    /// This never makes a connection: it resolves once one is inserted or
+    /// returned to the pool, or with an error if the pool is disabled.
+    pub fn checkout(
+        &self,
+        key: K,
+    ) -> impl Future<Output = Result<PooledSendRequest<B, K>, pool::Error>> {
+        self.pool.checkout(key)
+    }
+
+    /// Check out an idle connection for `key`, or make one with `connect`.
+    ///
+    /// Whichever is ready first is used, so an idle connection returned to
+    /// the pool while connecting is taken instead, dropping the connect
+    /// future.
+    pub async fn checkout_or_connect<F, Fut, T, E>(
+        &self,
+        key: K,
+        connect: F,
+    ) -> Result<PooledSendRequest<B, K>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        T: Into<SendRequest<B>>,
+    {
+        let checkout = self.pool.checkout(key.clone());
+        let connect = connect();
+        futures_util::pin_mut!(connect);
+        let connect = match future::select(checkout, connect).await {
+            Either::Left((Ok(pooled), _)) => return Ok(pooled),
+            Either::Left((Err(_err), connect)) => {
+                tracing::trace!("checkout failed, connecting: {}", _err);
+                connect.await
+            }
+            Either::Right((connect, _)) => connect,
+        };
+        Ok(self.insert(key, connect?))
+    }
+
+    /// Insert a new connection for `key`, checking it out right away.
+    ///
+    /// An HTTP/2 connection is shared with the next checkouts, and an
+    /// HTTP/1 connection joins the pool once the returned guard is dropped.
+    pub fn insert(&self, key: K, tx: impl Into<SendRequest<B>>) -> PooledSendRequest<B, K> {
+        let connecting = self
+            .pool
+            .connecting(&key, Ver::Auto)
+            .expect("HTTP/1 connecting never locks");
+        self.pool.pooled(connecting, tx.into())
+    }
+
+    /// The idle, active and pending connections of the pool.
+    pub fn stats(&self) -> pool::PoolStats<K> {
+        self.pool.stats()
+    }
+}
+
+impl<B, K: Key> Clone for SendRequestPool<B, K> {
+    fn clone(&self) -> Self {
+        SendRequestPool {
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+impl<B, K: Key> fmt::Debug for SendRequestPool<B, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendRequestPool").finish()
+    }
+}
+
+#[cfg(all(test, feature = "tokio", feature = "http1", feature = "server"))]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use http::{Request, Response};
+    use http_body_util::{BodyExt, Empty, Full};
+    use hyper::body::Bytes;
+    use hyper::service::service_fn;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::SendRequestPool;
+    use crate::client::legacy::pool::{Config, Reuse};
+    use crate::rt::{TokioExecutor, TokioIo, TokioTimer};
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn reuses_returned_connections() {
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let service = service_fn(|_| async {
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from("hello"))))
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service),
+                );
+            }
+        });
+
+        let pool = SendRequestPool::<Empty<Bytes>, &'static str>::new(
+            Config {
+                idle_timeout: None,
+                max_idle_per_host: usize::MAX,
+                reuse: Reuse::Lifo,
+                max_lifetime: None,
+                max_idle: usize::MAX,
+                max_active_per_host: usize::MAX,
+                sweep_interval: None,
+                max_checkout_queue: usize::MAX,
+                checkout_timeout: None,
+                max_connecting_per_host: usize::MAX,
+                shards: 1,
+            },
+            TokioExecutor::new(),
+            Option::<TokioTimer>::None,
+        );
+        let connects = AtomicUsize::new(0);
+        let connect = || async {
+            connects.fetch_add(1, Ordering::SeqCst);
+            let stream = TokioIo::new(TcpStream::connect(addr).await?);
+            let (tx, conn) = hyper::client::conn::http1::handshake(stream).await?;
+            tokio::spawn(conn);
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(tx)
+        };
+
+        for reused in [false, true] {
+            let mut tx = pool.checkout_or_connect("a", connect).await.unwrap();
+            assert_eq!(tx.is_reused(), reused);
+            let res = tx.send_request(Request::new(Empty::new())).await.unwrap();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, "hello");
+            tx.ready().await.unwrap();
+        }
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.stats().host(&"a").unwrap().idle(), 1);
+    }
+}
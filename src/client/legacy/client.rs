@@ -4,26 +4,34 @@
 //! For now, to enable people to use hyper 1.0 quicker, this `Client` exists
 //! in much the same way it did in hyper 0.14.
 
+use std::collections::hash_map::{Entry, HashMap, RandomState};
 use std::error::Error as StdError;
 use std::fmt;
 use std::future::Future;
+use std::hash::{BuildHasher, Hash, Hasher};
 use std::pin::Pin;
-use std::task::{self, Poll};
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{self, Poll, Waker};
+use std::time::{Duration, Instant};
 
+use bytes::Bytes;
 use futures_util::future::{self, Either, FutureExt, TryFutureExt};
-use http::uri::Scheme;
-use hyper::header::{HeaderValue, HOST};
+use http::uri::{Authority, Scheme};
+use hyper::header::{HeaderValue, CONNECTION, HOST};
 use hyper::rt::Timer;
 use hyper::{body::Body, Method, Request, Response, Uri, Version};
-use tracing::{debug, trace, warn};
+use tracing::{debug, debug_span, field, trace, warn, Instrument};
 
+use super::breaker::{Breakers, CircuitBreaker, CircuitState};
+use super::connect::proxy::{self, ProxyAddrs};
 #[cfg(feature = "tokio")]
 use super::connect::HttpConnector;
-use super::connect::{Alpn, Connect, Connected, Connection};
+use super::connect::{Connect, Connected, Connection, LivenessProbe};
 use super::pool::{self, Ver};
 
-use crate::common::{lazy as hyper_lazy, timer, Exec, Lazy, SyncWrapper};
+use crate::common::{lazy as hyper_lazy, timer, trace as tracing_span, Exec, Lazy, SyncWrapper};
+use crate::events::{self, ConnectionEvent, Events, EventsHandle, RequestEvent, Side};
 
 type BoxSendFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
 
@@ -31,6 +39,13 @@ type BoxSendFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
 ///
 /// `Client` is cheap to clone and cloning is the recommended way to share a `Client`. The
 /// underlying connection pool will be reused.
+///
+/// With the `tracing` feature, each request has a `request` span, recording
+/// the HTTP version, whether the connection was reused, and the time to the
+/// first and last byte of the response. The last byte is only known for
+/// HTTP/1 connections that are pooled. Checking out a connection, connecting,
+/// including DNS and TLS for the included connectors, and the HTTP handshake
+/// each have a span within it.
 #[cfg_attr(docsrs, doc(cfg(any(feature = "http1", feature = "http2"))))]
 pub struct Client<C, B> {
     config: Config,
@@ -41,6 +56,75 @@ pub struct Client<C, B> {
     #[cfg(feature = "http2")]
     h2_builder: hyper::client::conn::http2::Builder<Exec>,
     pool: pool::Pool<PoolClient<B>, PoolKey>,
+    pool_identity: Option<PoolIdentity>,
+    keys: Arc<KeyInterner>,
+    events: EventsHandle,
+    breakers: Option<Arc<Breakers>>,
+    drain: Arc<Drain>,
+    pending: Arc<AtomicUsize>,
+    preconnect: Option<Arc<Preconnect>>,
+}
+
+// The pool keys of a `Client` and its clones, so that requests to the same
+// host share one key instead of each allocating their own.
+//
+// Like the pool, the keys are split into shards by their hash, each with
+// its own lock, so that requests to different hosts don't contend.
+struct KeyInterner {
+    hasher: RandomState,
+    shards: Box<[RwLock<InternedKeys>]>,
+}
+
+// The interned keys of a shard, at most `capacity`. Once full, a new key
+// replaces an old one with the clock algorithm: the hand sweeps the slots,
+// sparing the keys found since it last passed them.
+struct InternedKeys {
+    capacity: usize,
+    by_hash: HashMap<u64, Vec<usize>>,
+    slots: Vec<InternedKey>,
+    hand: usize,
+}
+
+struct InternedKey {
+    hash: u64,
+    key: PoolKey,
+    found: AtomicBool,
+}
+
+// The requests in flight of a `Client` and its clones, for `shutdown`.
+struct Drain {
+    closed: AtomicBool,
+    in_flight: AtomicUsize,
+    waiters: Mutex<Vec<Waker>>,
+    timer: Option<timer::Timer>,
+}
+
+// A request in flight, until its response headers arrived, or its HTTP/1
+// connection is ready for the next request.
+struct InFlight(Arc<Drain>);
+
+// A request waiting for a connection, counted in the `pending` of a `Client`
+// and its clones.
+struct Pending(Arc<AtomicUsize>);
+
+// How long requests to each host wait for a connection, on average, to open
+// connections before they are needed.
+struct Preconnect {
+    threshold: Duration,
+    hosts: Mutex<HashMap<PoolKey, Pressure>>,
+}
+
+struct Pressure {
+    // A moving average of how long requests waited for a connection.
+    wait: Duration,
+    // Whether a connection is being opened ahead of time.
+    dialing: bool,
+}
+
+// A connection being opened ahead of time, until it's done.
+struct Preconnecting {
+    preconnect: Arc<Preconnect>,
+    key: PoolKey,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -48,17 +132,20 @@ struct Config {
     retry_canceled_requests: bool,
     set_host: bool,
     ver: Ver,
+    check_liveness: bool,
+    max_pending_requests: usize,
+    preconnect: Option<Duration>,
 }
 
 /// Client errors
 #[derive(Debug)]
 pub struct Error {
-    kind: ErrorKind,
+    kind: Kind,
     source: Option<Box<dyn StdError + Send + Sync>>,
 }
 
 #[derive(Debug)]
-enum ErrorKind {
+enum Kind {
     Canceled,
     ChannelClosed,
     Connect,
@@ -66,25 +153,130 @@ enum ErrorKind {
     UserUnsupportedVersion,
     UserAbsoluteUriRequired,
     SendRequest,
+    PoolTimeout,
+    PoolQueueFull,
+    CircuitOpen,
+    Shutdown,
+}
+
+/// The kind of a [`Error`](Error), to tell failures apart without matching
+/// on their `Display` output, such as for metrics and retries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Resolving the host of the destination failed.
+    Dns,
+    /// Establishing the connection failed, or timed out.
+    Connect,
+    /// The TLS handshake failed.
+    Tls,
+    /// No pooled connection became available within the checkout timeout.
+    PoolTimeout,
+    /// The queue of requests waiting for a pooled connection was full.
+    PoolFull,
+    /// The circuit of the host is open, so the request was not sent.
+    CircuitOpen,
+    /// The response didn't arrive in time.
+    RequestTimeout,
+    /// The peer violated the HTTP protocol, such as with an invalid
+    /// response.
+    Protocol,
+    /// Reading the request body failed, or writing it was aborted.
+    Body,
+    /// Reading from or writing to the connection failed.
+    Io,
+    /// The request was canceled before it was sent, such as because the
+    /// connection closed.
+    Canceled,
+    /// The request can't be sent, such as because its URI isn't absolute.
+    User,
+    /// Any other failure.
+    Other,
 }
 
+/// Error returned by a [`Client`](Client) when no pooled connection became
+/// available within the checkout timeout.
+///
+/// See [`Builder::pool_checkout_timeout`](Builder::pool_checkout_timeout).
+#[derive(Debug)]
+pub struct PoolTimeout(());
+
 macro_rules! e {
     ($kind:ident) => {
         Error {
-            kind: ErrorKind::$kind,
+            kind: Kind::$kind,
             source: None,
         }
     };
     ($kind:ident, $src:expr) => {
         Error {
-            kind: ErrorKind::$kind,
+            kind: Kind::$kind,
             source: Some($src.into()),
         }
     };
 }
 
-// We might change this... :shrug:
-type PoolKey = (http::uri::Scheme, http::uri::Authority);
+/// The key that pooled connections are reused by.
+///
+/// Connections are only reused for requests with the same scheme and
+/// authority, that were made through a `Client` with the same
+/// [`PoolIdentity`](PoolIdentity), and have the same
+/// [`ProxyAddrs`](super::connect::proxy::ProxyAddrs) extension, if any.
+///
+/// Keys are shared: cloning one is cheap, and a `Client` reuses the same key
+/// for all requests to a host.
+#[derive(Clone)]
+pub struct PoolKey(Arc<PoolKeyInner>);
+
+#[derive(PartialEq, Eq, Hash)]
+struct PoolKeyInner {
+    scheme: Scheme,
+    authority: Authority,
+    identity: Option<PoolIdentity>,
+    proxy_addrs: Option<ProxyAddrs>,
+}
+
+/// Identifies how the connections of a `Client` are established.
+///
+/// Connections through different proxies, with different client
+/// certificates, or with different TLS server names must never be reused for
+/// each other. A pool is only ever used by one connector, unless it is
+/// shared, such as with an [`IdlePool`](super::IdlePool) passed to
+/// [`Builder::build_with_pool`](Builder::build_with_pool). Clients sharing a
+/// pool should each set a `PoolIdentity` that describes their connector.
+///
+/// # Example
+///
+/// ```
+/// use hyper_util::client::legacy::PoolIdentity;
+///
+/// let direct = PoolIdentity::new("direct");
+/// let proxied = PoolIdentity::new("http-proxy=http://proxy.local:3128");
+/// assert_ne!(direct, proxied);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PoolIdentity(Bytes);
+
+/// A request extension to send the request on a dedicated connection.
+///
+/// The `Client` makes a new connection for a request with this extension,
+/// instead of checking one out of the pool, and closes it once the response
+/// is done. An HTTP/1 request gets a `Connection: close` header. This is for
+/// requests that must not share connection state with others.
+///
+/// # Example
+///
+/// ```
+/// use http_body_util::Empty;
+/// use hyper::body::Bytes;
+/// use hyper::Request;
+/// use hyper_util::client::legacy::NoPool;
+///
+/// let mut req = Request::new(Empty::<Bytes>::new());
+/// req.extensions_mut().insert(NoPool);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoPool;
 
 /// A `Future` that will resolve to an HTTP Response.
 ///
@@ -213,14 +405,135 @@ where
             other => return ResponseFuture::error_version(other),
         };
 
+        let in_flight = match self.drain.start() {
+            Some(in_flight) => in_flight,
+            None => {
+                debug!("client was shut down");
+                return ResponseFuture::new(future::err(e!(Shutdown)));
+            }
+        };
+
+        let proxy_addrs = req.extensions().get::<ProxyAddrs>().copied();
         let pool_key = match extract_domain(req.uri_mut(), is_http_connect) {
-            Ok(s) => s,
+            Ok((scheme, authority)) => {
+                self.keys
+                    .intern(scheme, authority, self.pool_identity.as_ref(), proxy_addrs)
+            }
             Err(err) => {
                 return ResponseFuture::new(future::err(err));
             }
         };
 
-        ResponseFuture::new(self.clone().send_request(req, pool_key))
+        let span = tracing_span::span(|| {
+            debug_span!(
+                "request",
+                method = %req.method(),
+                uri = %req.uri(),
+                version = field::Empty,
+                reused = field::Empty,
+                first_byte = field::Empty,
+                last_byte = field::Empty,
+            )
+        });
+        let events = match self.events.get() {
+            Some(events) => events,
+            None => {
+                return ResponseFuture::new(
+                    self.clone()
+                        .send_request_guarded(req, pool_key, in_flight)
+                        .instrument(span),
+                )
+            }
+        };
+        let id = events::next_id();
+        let method = req.method().clone();
+        let uri = req.uri().clone();
+        events.request_started(&RequestEvent::new(Side::Client, id, &method, &uri));
+        let started = Instant::now();
+        let events = self.events.clone();
+        let fut = self
+            .clone()
+            .send_request_guarded(req, pool_key, in_flight)
+            .instrument(span);
+        ResponseFuture::new(async move {
+            let res = fut.await;
+            if let Some(events) = events.get() {
+                let (status, error) = match res {
+                    Ok(ref res) => (Some(res.status()), None),
+                    Err(ref err) => (None, Some(err as &(dyn StdError + 'static))),
+                };
+                events.request_finished(
+                    &RequestEvent::new(Side::Client, id, &method, &uri).finished(
+                        started.elapsed(),
+                        status,
+                        error,
+                    ),
+                );
+            }
+            res
+        })
+    }
+
+    /// Evict the idle connections in the pool that are closed or expired.
+    ///
+    /// This is done periodically if the `Client` was built with a pool
+    /// timer. Integrations without one, or that want to control when the
+    /// work happens, can sweep on demand instead.
+    pub fn sweep_idle(&self) {
+        self.pool.sweep();
+    }
+
+    /// The state of the circuit of the host of `key`.
+    ///
+    /// Always `Closed` if the `Client` has no
+    /// [`circuit_breaker`](Builder::circuit_breaker).
+    pub fn circuit_state(&self, key: &PoolKey) -> CircuitState {
+        match self.breakers {
+            Some(ref breakers) => breakers.state(key),
+            None => CircuitState::Closed,
+        }
+    }
+
+    /// Shut down this `Client` and its clones, waiting for the requests in
+    /// flight to finish.
+    ///
+    /// New requests fail right away, with an error of kind
+    /// [`ErrorKind::Canceled`]. Idle connections are closed, which sends a
+    /// `GOAWAY` on HTTP/2 connections, and connections are no longer kept
+    /// idle once their requests finish.
+    ///
+    /// A request is in flight until its response arrived, and for HTTP/1
+    /// until its response body was read. Returns `false` if some were still
+    /// in flight after `timeout`.
+    ///
+    /// A `Timer` is required for the timeout to take effect, see
+    /// [`Builder::pool_timer`]. Without one, this waits for all requests.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        debug!("shutting down client");
+        self.drain.closed.store(true, Ordering::SeqCst);
+        self.pool.close();
+
+        let drain = &*self.drain;
+        let finished = future::poll_fn(|cx| drain.poll_finished(cx));
+        match drain.timer {
+            Some(ref timer) => {
+                futures_util::pin_mut!(finished);
+                let sleep = timer.sleep(timeout);
+                matches!(future::select(finished, sleep).await, Either::Left(_))
+            }
+            None => {
+                finished.await;
+                true
+            }
+        }
+    }
+
+    /// Take a snapshot of the connections in the pool, per host.
+    ///
+    /// This includes the idle and open connections of each host, and the
+    /// requests waiting for one, so that they can be exported as metrics.
+    pub fn pool_stats(&self) -> pool::PoolStats<PoolKey> {
+        self.pool.stats()
     }
 
     /*
@@ -258,12 +571,66 @@ where
     }
     */
 
+    // Sends the request through the circuit breaker of its host, if any.
+    async fn send_request_guarded(
+        self,
+        req: Request<B>,
+        pool_key: PoolKey,
+        in_flight: InFlight,
+    ) -> Result<Response<hyper::body::Incoming>, Error> {
+        let permit = match self.breakers {
+            Some(ref breakers) => match breakers.admit(&pool_key) {
+                Ok(permit) => Some(permit),
+                Err(open) => {
+                    debug!("circuit open for {:?}", pool_key);
+                    return Err(e!(CircuitOpen, open));
+                }
+            },
+            None => None,
+        };
+        let res = self.send_request(req, pool_key, in_flight).await;
+        if let Some(permit) = permit {
+            permit.finish(&res);
+        }
+        res
+    }
+
     async fn send_request(
         self,
         mut req: Request<B>,
         pool_key: PoolKey,
+        in_flight: InFlight,
     ) -> Result<Response<hyper::body::Incoming>, Error> {
-        let mut pooled = self.connection_for(pool_key).await?;
+        let started = Instant::now();
+        let span = tracing_span::current();
+        let pending = match Pending::start(&self.pending, self.config.max_pending_requests) {
+            Some(pending) => pending,
+            None => {
+                debug!("too many requests waiting for a connection");
+                return Err(e!(PoolQueueFull));
+            }
+        };
+        let no_pool = req.extensions().get::<NoPool>().is_some();
+        let preconnect_key = match self.preconnect {
+            Some(_) if !no_pool => Some(pool_key.clone()),
+            _ => None,
+        };
+        let mut pooled = self.connection_for(pool_key, no_pool).await?;
+        drop(pending);
+        if let Some(key) = preconnect_key {
+            if !pooled.is_http2() {
+                self.preconnect(key, started.elapsed());
+            }
+        }
+        span.record("reused", pooled.is_reused());
+        span.record(
+            "version",
+            if pooled.is_http2() {
+                "HTTP/2"
+            } else {
+                "HTTP/1.1"
+            },
+        );
 
         if pooled.is_http1() {
             if req.version() == Version::HTTP_2 {
@@ -285,6 +652,11 @@ where
                 });
             }
 
+            if no_pool {
+                req.headers_mut()
+                    .insert(CONNECTION, HeaderValue::from_static("close"));
+            }
+
             // CONNECT always sends authority-form, so check it first...
             if req.method() == Method::CONNECT {
                 authority_form(req.uri_mut());
@@ -322,6 +694,10 @@ where
         }
 
         let res = fut.await?;
+        span.record("first_byte", field::debug(started.elapsed()));
+        if res.body().is_end_stream() {
+            span.record("last_byte", field::debug(started.elapsed()));
+        }
 
         // If pooled is HTTP/2, we can toss this reference immediately.
         //
@@ -342,13 +718,17 @@ where
                 // At this point, `pooled` is dropped, and had a chance
                 // to insert into the pool (if conn was idle)
                 //drop(delayed_tx);
+                span.record("last_byte", field::debug(started.elapsed()));
+                drop(in_flight);
             });
 
             self.exec.execute(on_idle);
         } else {
             // There's no body to delay, but the connection isn't
             // ready yet. Only re-insert when it's ready
-            let on_idle = future::poll_fn(move |cx| pooled.poll_ready(cx)).map(|_| ());
+            let on_idle = future::poll_fn(move |cx| pooled.poll_ready(cx)).map(move |_| {
+                drop(in_flight);
+            });
 
             self.exec.execute(on_idle);
         }
@@ -356,12 +736,43 @@ where
         Ok(res)
     }
 
+    // Open a connection to a host in the background, if requests to it
+    // have been waiting for one, and none is idle for the next request.
+    fn preconnect(&self, key: PoolKey, wait: Duration) {
+        let preconnect = match self.preconnect {
+            Some(ref preconnect) => preconnect,
+            None => return,
+        };
+        if !preconnect.is_hot(&key, wait) || self.pool.has_idle(&key) {
+            return;
+        }
+        let preconnecting = match Preconnecting::start(preconnect, key.clone()) {
+            Some(preconnecting) => preconnecting,
+            None => return,
+        };
+        debug!("pre-connecting to {:?}", key);
+        let connecting = self.connect_to(key, false).map(move |res| {
+            drop(preconnecting);
+            // A new connection is returned to the pool as it's dropped.
+            if let Err(_err) = res {
+                trace!("pre-connect error: {}", _err);
+            }
+        });
+        self.exec.execute(connecting);
+    }
+
     async fn connection_for(
         &self,
         pool_key: PoolKey,
+        no_pool: bool,
     ) -> Result<pool::Pooled<PoolClient<B>, PoolKey>, Error> {
         loop {
-            match self.one_connection_for(pool_key.clone()).await {
+            let span = tracing_span::span(|| debug_span!("checkout"));
+            match self
+                .one_connection_for(pool_key.clone(), no_pool)
+                .instrument(span)
+                .await
+            {
                 Ok(pooled) => return Ok(pooled),
                 Err(ClientConnectError::Normal(err)) => return Err(err),
                 Err(ClientConnectError::CheckoutIsClosed(reason)) => {
@@ -382,11 +793,13 @@ where
     async fn one_connection_for(
         &self,
         pool_key: PoolKey,
+        no_pool: bool,
     ) -> Result<pool::Pooled<PoolClient<B>, PoolKey>, ClientConnectError> {
-        // Return a single connection if pooling is not enabled
-        if !self.pool.is_enabled() {
+        // Return a single connection if pooling is not enabled, or not
+        // wanted for this request.
+        if !self.pool.is_enabled() || no_pool {
             return self
-                .connect_to(pool_key)
+                .connect_to(pool_key, no_pool)
                 .await
                 .map_err(ClientConnectError::Normal);
         }
@@ -405,7 +818,7 @@ where
         //   connection future is spawned into the runtime to complete,
         //   and then be inserted into the pool as an idle connection.
         let checkout = self.pool.checkout(pool_key.clone());
-        let connect = self.connect_to(pool_key);
+        let connect = self.connect_to(pool_key, false);
         let is_ver_h2 = self.config.ver == Ver::Http2;
 
         // The order of the `select` is depended on below...
@@ -475,9 +888,11 @@ where
     fn connect_to(
         &self,
         pool_key: PoolKey,
+        no_pool: bool,
     ) -> impl Lazy<Output = Result<pool::Pooled<PoolClient<B>, PoolKey>, Error>> + Send + Unpin
     {
         let executor = self.exec.clone();
+        let events = self.events.clone();
         let pool = self.pool.clone();
         #[cfg(feature = "http1")]
         let h1_builder = self.h1_builder.clone();
@@ -485,111 +900,185 @@ where
         let h2_builder = self.h2_builder.clone();
         let ver = self.config.ver;
         let is_ver_h2 = ver == Ver::Http2;
+        let check_liveness = self.config.check_liveness;
         let connector = self.connector.clone();
-        let dst = domain_as_uri(pool_key.clone());
+        let proxy_addrs = pool_key.0.proxy_addrs;
+        let dst = domain_as_uri(&pool_key);
         hyper_lazy(move || {
-            // Try to take a "connecting lock".
-            //
-            // If the pool_key is for HTTP/2, and there is already a
-            // connection being established, then this can't take a
-            // second lock. The "connect_to" future is Canceled.
-            let connecting = match pool.connecting(&pool_key, ver) {
-                Some(lock) => lock,
-                None => {
-                    let canceled = e!(Canceled);
-                    // TODO
-                    //crate::Error::new_canceled().with("HTTP/2 connection in progress");
-                    return Either::Right(future::err(canceled));
+            // Wait until the host is below its limit of open connections,
+            // and then of connections being established. A dedicated
+            // connection isn't shared, so it doesn't wait for another dial.
+            let dial_pool = pool.clone();
+            let dial_key = pool_key.clone();
+            let slot = pool.active_slot(&pool_key).and_then(move |active| {
+                let dial = if no_pool {
+                    Either::Left(future::ok(None))
+                } else {
+                    Either::Right(dial_pool.dial_slot(&dial_key))
+                };
+                dial.map_ok(move |dial| (active, dial))
+            });
+            slot.then(move |slots| {
+                let (active, dial) = match slots {
+                    Ok(slots) => slots,
+                    Err(pool::Error::CheckoutTimedOut) => {
+                        return Either::Right(future::err(e!(PoolTimeout, PoolTimeout(()))));
+                    }
+                    Err(_) => return Either::Right(future::err(e!(PoolQueueFull))),
+                };
+                // A connection made while waiting to dial is used instead,
+                // by the pool checkout.
+                if dial.is_some() && pool.has_idle(&pool_key) {
+                    trace!("idle connection while waiting to dial {:?}", pool_key);
+                    return Either::Right(future::err(e!(Canceled)));
                 }
-            };
-            Either::Left(
-                connector
-                    .connect(super::connect::sealed::Internal, dst)
-                    .map_err(|src| e!(Connect, src))
-                    .and_then(move |io| {
-                        let connected = io.connected();
-                        // If ALPN is h2 and we aren't http2_only already,
-                        // then we need to convert our pool checkout into
-                        // a single HTTP2 one.
-                        let connecting = if connected.alpn == Alpn::H2 && !is_ver_h2 {
-                            match connecting.alpn_h2(&pool) {
-                                Some(lock) => {
-                                    trace!("ALPN negotiated h2, updating pool");
-                                    lock
-                                }
-                                None => {
-                                    // Another connection has already upgraded,
-                                    // the pool checkout should finish up for us.
-                                    let canceled = e!(Canceled, "ALPN upgraded to HTTP/2");
-                                    return Either::Right(future::err(canceled));
-                                }
-                            }
-                        } else {
-                            connecting
-                        };
-
-                        #[cfg_attr(not(feature = "http2"), allow(unused))]
-                        let is_h2 = is_ver_h2 || connected.alpn == Alpn::H2;
-
-                        Either::Left(Box::pin(async move {
-                            let tx = if is_h2 {
-                                #[cfg(feature = "http2")] {
-                                    let (mut tx, conn) =
-                                        h2_builder.handshake(io).await.map_err(Error::tx)?;
-
-                                    trace!(
-                                        "http2 handshake complete, spawning background dispatcher task"
-                                    );
-                                    executor.execute(
-                                        conn.map_err(|e| debug!("client connection error: {}", e))
-                                            .map(|_| ()),
-                                    );
-
-                                    // Wait for 'conn' to ready up before we
-                                    // declare this tx as usable
-                                    tx.ready().await.map_err(Error::tx)?;
-                                    PoolTx::Http2(tx)
+                // Try to take a "connecting lock".
+                //
+                // If the pool_key is for HTTP/2, and there is already a
+                // connection being established, then this can't take a
+                // second lock. The "connect_to" future is Canceled.
+                let connecting_ver = if no_pool { Ver::Auto } else { ver };
+                let connecting = match pool.connecting(&pool_key, connecting_ver) {
+                    Some(lock) => lock,
+                    None => {
+                        let canceled = e!(Canceled);
+                        // TODO
+                        //crate::Error::new_canceled().with("HTTP/2 connection in progress");
+                        return Either::Right(future::err(canceled));
+                    }
+                };
+                let span = tracing_span::span(|| debug_span!("connect", dst = %dst));
+                // The connector is called when this is first polled.
+                let mut dialing = connector.connect(super::connect::sealed::Internal, dst);
+                let dialing = future::poll_fn(move |cx| {
+                    proxy::protocol::scope(proxy_addrs, || Pin::new(&mut dialing).poll(cx))
+                });
+                Either::Left(
+                    dialing
+                        .instrument(span)
+                        .map_err(|src| e!(Connect, src))
+                        .and_then(move |io| {
+                            let connected = io.connected();
+                            // If ALPN is h2 and we aren't http2_only already,
+                            // then we need to convert our pool checkout into
+                            // a single HTTP2 one.
+                            let connecting = if connected.is_negotiated_h2() && !is_ver_h2 && !no_pool {
+                                match connecting.alpn_h2(&pool) {
+                                    Some(lock) => {
+                                        trace!("ALPN negotiated h2, updating pool");
+                                        lock
+                                    }
+                                    None => {
+                                        // Another connection has already upgraded,
+                                        // the pool checkout should finish up for us.
+                                        let canceled = e!(Canceled, "ALPN upgraded to HTTP/2");
+                                        return Either::Right(future::err(canceled));
+                                    }
                                 }
-                                #[cfg(not(feature = "http2"))]
-                                panic!("http2 feature is not enabled");
                             } else {
-                                #[cfg(feature = "http1")] {
-                                    let (mut tx, conn) =
-                                        h1_builder.handshake(io).await.map_err(Error::tx)?;
-
-                                    trace!(
-                                        "http1 handshake complete, spawning background dispatcher task"
-                                    );
-                                    executor.execute(
-                                        conn.with_upgrades()
-                                            .map_err(|e| debug!("client connection error: {}", e))
-                                            .map(|_| ()),
-                                    );
-
-                                    // Wait for 'conn' to ready up before we
-                                    // declare this tx as usable
-                                    tx.ready().await.map_err(Error::tx)?;
-                                    PoolTx::Http1(tx)
-                                }
-                                #[cfg(not(feature = "http1"))] {
-                                    panic!("http1 feature is not enabled");
-                                }
+                                connecting
+                            };
+
+                            #[cfg_attr(not(feature = "http2"), allow(unused))]
+                            let is_h2 = is_ver_h2 || connected.is_negotiated_h2();
+                            // HTTP/2 connections can't be probed, since the
+                            // peer may send frames at any time. Their pings
+                            // and GOAWAY are tracked by hyper instead.
+                            let probe = if check_liveness && !is_h2 {
+                                io.liveness_probe()
+                            } else {
+                                None
                             };
 
-                            Ok(pool.pooled(
-                                connecting,
-                                PoolClient {
+                            let span = tracing_span::span(|| debug_span!("handshake", h2 = is_h2));
+                            Either::Left(Box::pin(async move {
+                                let tx = if is_h2 {
+                                    #[cfg(feature = "http2")] {
+                                        let (mut tx, conn) =
+                                            h2_builder.handshake(io).await.map_err(Error::tx)?;
+
+                                        trace!(
+                                            "http2 handshake complete, spawning background dispatcher task"
+                                        );
+                                        executor.execute(watch_conn(&events, Version::HTTP_2, conn));
+
+                                        // Wait for 'conn' to ready up before we
+                                        // declare this tx as usable
+                                        tx.ready().await.map_err(Error::tx)?;
+                                        PoolTx::Http2(tx)
+                                    }
+                                    #[cfg(not(feature = "http2"))]
+                                    panic!("http2 feature is not enabled");
+                                } else {
+                                    #[cfg(feature = "http1")] {
+                                        let (mut tx, conn) =
+                                            h1_builder.handshake(io).await.map_err(Error::tx)?;
+
+                                        trace!(
+                                            "http1 handshake complete, spawning background dispatcher task"
+                                        );
+                                        executor.execute(watch_conn(
+                                            &events,
+                                            Version::HTTP_11,
+                                            conn.with_upgrades(),
+                                        ));
+
+                                        // Wait for 'conn' to ready up before we
+                                        // declare this tx as usable
+                                        tx.ready().await.map_err(Error::tx)?;
+                                        PoolTx::Http1(tx)
+                                    }
+                                    #[cfg(not(feature = "http1"))] {
+                                        panic!("http1 feature is not enabled");
+                                    }
+                                };
+
+                                let client = PoolClient {
                                     conn_info: connected,
                                     tx,
-                                },
-                            ))
-                        }))
-                    }),
-            )
+                                    active: active.map(Arc::new),
+                                    probe,
+                                };
+                                let pooled = if no_pool {
+                                    pool.unpooled(connecting, client)
+                                } else {
+                                    pool.pooled(connecting, client)
+                                };
+                                drop(dial);
+                                Ok(pooled)
+                            }.instrument(span)))
+                        }),
+                )
+            })
         })
     }
 }
 
+// Report a new connection to `events`, and wrap the future driving it to
+// report when it closes.
+fn watch_conn<F>(events: &EventsHandle, version: Version, conn: F) -> impl Future<Output = ()>
+where
+    F: Future<Output = hyper::Result<()>>,
+{
+    let id = events.get().map(|events| {
+        let id = events::next_id();
+        events.connection_opened(&ConnectionEvent::new(Side::Client, id, version));
+        id
+    });
+    let events = events.clone();
+    conn.map(move |res| {
+        if let Err(ref e) = res {
+            debug!("client connection error: {}", e);
+        }
+        if let (Some(events), Some(id)) = (events.get(), id) {
+            let error = res.as_ref().err().map(|e| e as &(dyn StdError + 'static));
+            events.connection_closed(
+                &ConnectionEvent::new(Side::Client, id, version).with_error(error),
+            );
+        }
+    })
+}
+
 impl<C, B> tower_service::Service<Request<B>> for Client<C, B>
 where
     C: Connect + Clone + Send + Sync + 'static,
@@ -641,6 +1130,13 @@ impl<C: Clone, B> Clone for Client<C, B> {
             h2_builder: self.h2_builder.clone(),
             connector: self.connector.clone(),
             pool: self.pool.clone(),
+            pool_identity: self.pool_identity.clone(),
+            keys: self.keys.clone(),
+            events: self.events.clone(),
+            breakers: self.breakers.clone(),
+            drain: self.drain.clone(),
+            pending: self.pending.clone(),
+            preconnect: self.preconnect.clone(),
         }
     }
 }
@@ -651,10 +1147,111 @@ impl<C, B> fmt::Debug for Client<C, B> {
     }
 }
 
+// ===== impl Drain =====
+
+impl Drain {
+    fn start(self: &Arc<Self>) -> Option<InFlight> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlight(self.clone());
+        if self.closed.load(Ordering::SeqCst) {
+            return None;
+        }
+        Some(in_flight)
+    }
+
+    fn poll_finished(&self, cx: &mut task::Context<'_>) -> Poll<()> {
+        let mut waiters = self.waiters.lock().unwrap();
+        if self.in_flight.load(Ordering::SeqCst) == 0 {
+            return Poll::Ready(());
+        }
+        if !waiters.iter().any(|w| w.will_wake(cx.waker())) {
+            waiters.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let waiters = std::mem::take(&mut *self.0.waiters.lock().unwrap());
+            for waker in waiters {
+                waker.wake();
+            }
+        }
+    }
+}
+
+// ===== impl Pending =====
+
+impl Pending {
+    fn start(count: &Arc<AtomicUsize>, max: usize) -> Option<Pending> {
+        let pending = Pending(count.clone());
+        if count.fetch_add(1, Ordering::SeqCst) >= max {
+            return None;
+        }
+        Some(pending)
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// ===== impl Preconnect =====
+
+// How many hosts the pressure is kept for.
+const MAX_PRESSURE_HOSTS: usize = 1024;
+
+impl Preconnect {
+    // Record that a request to `key` waited `wait` for a connection, and
+    // whether requests to it wait long enough to connect ahead of time.
+    fn is_hot(&self, key: &PoolKey, wait: Duration) -> bool {
+        let mut hosts = self.hosts.lock().unwrap();
+        if hosts.len() >= MAX_PRESSURE_HOSTS && !hosts.contains_key(key) {
+            hosts.retain(|_, pressure| pressure.dialing);
+        }
+        let pressure = hosts.entry(key.clone()).or_insert(Pressure {
+            wait,
+            dialing: false,
+        });
+        // Weigh the latest wait by a quarter.
+        pressure.wait = (pressure.wait * 3 + wait) / 4;
+        !pressure.dialing && pressure.wait >= self.threshold
+    }
+}
+
+impl Preconnecting {
+    fn start(preconnect: &Arc<Preconnect>, key: PoolKey) -> Option<Preconnecting> {
+        let mut hosts = preconnect.hosts.lock().unwrap();
+        let pressure = hosts.get_mut(&key)?;
+        if pressure.dialing {
+            return None;
+        }
+        pressure.dialing = true;
+        Some(Preconnecting {
+            preconnect: preconnect.clone(),
+            key,
+        })
+    }
+}
+
+impl Drop for Preconnecting {
+    fn drop(&mut self) {
+        if let Ok(mut hosts) = self.preconnect.hosts.lock() {
+            if let Some(pressure) = hosts.get_mut(&self.key) {
+                pressure.dialing = false;
+            }
+        }
+    }
+}
+
 // ===== impl ResponseFuture =====
 
 impl ResponseFuture {
-    fn new<F>(value: F) -> Self
+    pub(super) fn new<F>(value: F) -> Self
     where
         F: Future<Output = Result<Response<hyper::body::Incoming>, Error>> + Send + 'static,
     {
@@ -685,11 +1282,15 @@ impl Future for ResponseFuture {
 
 // ===== impl PoolClient =====
 
+/// A connection as it is kept in the pool of a `Client`.
 // FIXME: allow() required due to `impl Trait` leaking types to this lint
 #[allow(missing_debug_implementations)]
-struct PoolClient<B> {
+pub struct PoolClient<B> {
     conn_info: Connected,
     tx: PoolTx<B>,
+    // Holds a slot of the pool's per-host limit while the connection is open.
+    active: Option<Arc<pool::Active>>,
+    probe: Option<LivenessProbe>,
 }
 
 enum PoolTx<B> {
@@ -812,16 +1413,22 @@ where
             PoolTx::Http1(tx) => pool::Reservation::Unique(PoolClient {
                 conn_info: self.conn_info,
                 tx: PoolTx::Http1(tx),
+                active: self.active,
+                probe: self.probe,
             }),
             #[cfg(feature = "http2")]
             PoolTx::Http2(tx) => {
                 let b = PoolClient {
                     conn_info: self.conn_info.clone(),
                     tx: PoolTx::Http2(tx.clone()),
+                    active: self.active.clone(),
+                    probe: None,
                 };
                 let a = PoolClient {
                     conn_info: self.conn_info,
                     tx: PoolTx::Http2(tx),
+                    active: self.active,
+                    probe: None,
                 };
                 pool::Reservation::Shared(a, b)
             }
@@ -831,6 +1438,14 @@ where
     fn can_share(&self) -> bool {
         self.is_http2()
     }
+
+    fn is_alive(&self) -> bool {
+        self.is_ready()
+            && match self.probe {
+                Some(ref probe) => probe.is_alive(),
+                None => true,
+            }
+    }
 }
 
 enum ClientConnectError {
@@ -887,23 +1502,18 @@ fn authority_form(uri: &mut Uri) {
     };
 }
 
-fn extract_domain(uri: &mut Uri, is_http_connect: bool) -> Result<PoolKey, Error> {
-    let uri_clone = uri.clone();
-    match (uri_clone.scheme(), uri_clone.authority()) {
-        (Some(scheme), Some(auth)) => Ok((scheme.clone(), auth.clone())),
-        (None, Some(auth)) if is_http_connect => {
-            let scheme = match auth.port_u16() {
-                Some(443) => {
-                    set_scheme(uri, Scheme::HTTPS);
-                    Scheme::HTTPS
-                }
-                _ => {
-                    set_scheme(uri, Scheme::HTTP);
-                    Scheme::HTTP
-                }
+fn extract_domain(uri: &mut Uri, is_http_connect: bool) -> Result<(&Scheme, &Authority), Error> {
+    if is_http_connect && uri.scheme().is_none() {
+        if let Some(port) = uri.authority().map(|auth| auth.port_u16()) {
+            let scheme = match port {
+                Some(443) => Scheme::HTTPS,
+                _ => Scheme::HTTP,
             };
-            Ok((scheme, auth.clone()))
+            set_scheme(uri, scheme);
         }
+    }
+    match (uri.scheme(), uri.authority()) {
+        (Some(scheme), Some(auth)) => Ok((scheme, auth)),
         _ => {
             debug!("Client requires absolute-form URIs, received: {:?}", uri);
             Err(e!(UserAbsoluteUriRequired))
@@ -911,10 +1521,10 @@ fn extract_domain(uri: &mut Uri, is_http_connect: bool) -> Result<PoolKey, Error
     }
 }
 
-fn domain_as_uri((scheme, auth): PoolKey) -> Uri {
+fn domain_as_uri(key: &PoolKey) -> Uri {
     http::uri::Builder::new()
-        .scheme(scheme)
-        .authority(auth)
+        .scheme(key.scheme().clone())
+        .authority(key.authority().clone())
         .path_and_query("/")
         .build()
         .expect("domain is valid Uri")
@@ -977,6 +1587,10 @@ pub struct Builder {
     h2_builder: hyper::client::conn::http2::Builder<Exec>,
     pool_config: pool::Config,
     pool_timer: Option<timer::Timer>,
+    pool_observer: Option<Arc<dyn pool::PoolObserver<PoolKey>>>,
+    pool_identity: Option<PoolIdentity>,
+    events: EventsHandle,
+    circuit_breaker: Option<CircuitBreaker>,
 }
 
 impl Builder {
@@ -991,6 +1605,9 @@ impl Builder {
                 retry_canceled_requests: true,
                 set_host: true,
                 ver: Ver::Auto,
+                check_liveness: false,
+                max_pending_requests: usize::MAX,
+                preconnect: None,
             },
             exec: exec.clone(),
             #[cfg(feature = "http1")]
@@ -1000,8 +1617,21 @@ impl Builder {
             pool_config: pool::Config {
                 idle_timeout: Some(Duration::from_secs(90)),
                 max_idle_per_host: std::usize::MAX,
+                reuse: pool::Reuse::Lifo,
+                max_lifetime: None,
+                max_idle: usize::MAX,
+                max_active_per_host: usize::MAX,
+                sweep_interval: None,
+                max_checkout_queue: usize::MAX,
+                checkout_timeout: None,
+                max_connecting_per_host: usize::MAX,
+                shards: 1,
             },
             pool_timer: None,
+            pool_observer: None,
+            pool_identity: None,
+            events: EventsHandle::default(),
+            circuit_breaker: None,
         }
     }
     /// Set an optional timeout for idle sockets being kept-alive.
@@ -1052,6 +1682,242 @@ impl Builder {
         self
     }
 
+    /// Sets the maximum idle connections allowed in the pool, across all
+    /// hosts.
+    ///
+    /// Together with `pool_max_idle_per_host`, this limits how much of the
+    /// pool a single host can take up.
+    ///
+    /// Default is `usize::MAX` (no limit).
+    pub fn pool_max_idle(&mut self, max_idle: usize) -> &mut Self {
+        self.pool_config.max_idle = max_idle;
+        self
+    }
+
+    /// Sets the maximum open connections per host, both idle and in use.
+    ///
+    /// Once a host has this many connections, requests to it wait for one of
+    /// them to become idle, or to close, instead of opening another one.
+    /// This has no effect if pooling is disabled.
+    ///
+    /// Default is `usize::MAX` (no limit).
+    pub fn pool_max_active_per_host(&mut self, max_active: usize) -> &mut Self {
+        self.pool_config.max_active_per_host = max_active;
+        self
+    }
+
+    /// Sets the maximum number of requests that may wait for a connection to
+    /// a host at its `pool_max_active_per_host` limit.
+    ///
+    /// Waiting requests get a connection in the order they arrived. Once
+    /// this many are waiting, further requests fail right away.
+    ///
+    /// Default is `usize::MAX` (no limit).
+    pub fn pool_max_checkout_queue(&mut self, max: usize) -> &mut Self {
+        self.pool_config.max_checkout_queue = max;
+        self
+    }
+
+    /// Sets the maximum number of requests that may wait for a connection,
+    /// across all hosts.
+    ///
+    /// A request waits from when it starts checking out or connecting, until
+    /// it has a connection to send on. Once this many are waiting, further
+    /// requests fail right away with an error of kind
+    /// [`ErrorKind::PoolFull`], instead of queueing up until they time out.
+    ///
+    /// Default is `usize::MAX` (no limit).
+    pub fn max_pending_requests(&mut self, max: usize) -> &mut Self {
+        self.client_config.max_pending_requests = max;
+        self
+    }
+
+    /// Open connections to hosts before requests need them, once requests
+    /// to a host wait at least `threshold` for a connection on average.
+    ///
+    /// The wait of each request covers checking out an idle connection, or
+    /// dialing a new one, so hosts whose connections take long to establish
+    /// become hot after a few cold dials. When a request to a hot host takes
+    /// its last idle HTTP/1 connection, another one is opened in the
+    /// background and pooled, so that the next request finds it idle. Only
+    /// one is opened ahead of time per host at once.
+    ///
+    /// Default is `None` (no connections are opened ahead of time).
+    pub fn pool_preconnect<D>(&mut self, threshold: D) -> &mut Self
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.client_config.preconnect = threshold.into();
+        self
+    }
+
+    /// Sets the maximum number of connections per host that may be
+    /// established at once.
+    ///
+    /// When many requests to a host without idle connections arrive at
+    /// once, only this many dial, and the others wait for one of those
+    /// connections, or for a turn to dial. With HTTP/2, including when
+    /// negotiated with ALPN, a limit of `1` makes them all share the one
+    /// new connection.
+    ///
+    /// Default is `usize::MAX` (no limit).
+    pub fn pool_max_connecting_per_host(&mut self, max: usize) -> &mut Self {
+        self.pool_config.max_connecting_per_host = max;
+        self
+    }
+
+    /// Sets the number of shards the connection pool is split into.
+    ///
+    /// Each shard has its own lock, and holds the connections of the hosts
+    /// that hash to it, so that requests to different hosts contend less
+    /// on busy many-core machines. The pool keys the `Client` looks up for
+    /// each request are split the same way. The `pool_max_idle` limit still
+    /// applies to the whole pool, and a custom pool from
+    /// [`Builder::build_with_pool`] is never sharded.
+    ///
+    /// Default is `1`, which is a single lock. A value of `0` is treated
+    /// as `1`.
+    pub fn pool_shards(&mut self, shards: usize) -> &mut Self {
+        self.pool_config.shards = shards;
+        self
+    }
+
+    /// Set an optional timeout for requests waiting for a connection to a
+    /// host at its `pool_max_active_per_host` limit.
+    ///
+    /// Requests that wait longer fail with an error whose source is a
+    /// [`PoolTimeout`](PoolTimeout).
+    /// A `Timer` is required for this to take effect. See `Builder::pool_timer`
+    ///
+    /// Default is `None` (no timeout).
+    pub fn pool_checkout_timeout<D>(&mut self, timeout: D) -> &mut Self
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.pool_config.checkout_timeout = timeout.into();
+        self
+    }
+
+    /// Set whether to check that idle connections are still alive before
+    /// reusing them.
+    ///
+    /// HTTP/1 connections are probed with a non-blocking peek, which
+    /// detects a peer that closed the connection while it was idle, before
+    /// a request is written to it. This needs a
+    /// [`liveness_probe`](super::connect::Connection::liveness_probe) from
+    /// the connection, which costs an extra socket handle for the
+    /// `HttpConnector`. HTTP/2 connections are always checked for a GOAWAY
+    /// or a failed keep-alive ping.
+    ///
+    /// Default is `false`.
+    pub fn pool_check_liveness(&mut self, enabled: bool) -> &mut Self {
+        self.client_config.check_liveness = enabled;
+        self
+    }
+
+    /// Set an observer of the lifecycle of pooled connections.
+    ///
+    /// The observer is told when connections are created, reused, returned
+    /// to the pool, evicted, or closed, along with their key and timing.
+    pub fn pool_observer<O>(&mut self, observer: O) -> &mut Self
+    where
+        O: pool::PoolObserver<PoolKey>,
+    {
+        self.pool_observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Report the connections and requests of the `Client`, and the events
+    /// of its pool, to `events`.
+    ///
+    /// The pool events are reported along with those of the
+    /// [`pool_observer`](Builder::pool_observer), if there is one. See the
+    /// [`events`](crate::events) module for details.
+    pub fn events<V>(&mut self, events: V) -> &mut Self
+    where
+        V: Events,
+    {
+        self.events = EventsHandle::new(events);
+        self
+    }
+
+    /// Set a per-host circuit breaker, to fail fast against hosts that keep
+    /// failing.
+    ///
+    /// See [`CircuitBreaker`](super::CircuitBreaker) for how it works.
+    ///
+    /// Default is `None`.
+    pub fn circuit_breaker<B>(&mut self, breaker: B) -> &mut Self
+    where
+        B: Into<Option<CircuitBreaker>>,
+    {
+        self.circuit_breaker = breaker.into();
+        self
+    }
+
+    /// Set the identity of the connector, which is part of the key that
+    /// connections are pooled by.
+    ///
+    /// See [`PoolIdentity`](PoolIdentity) for when this is needed.
+    ///
+    /// Default is `None`.
+    pub fn pool_identity<I>(&mut self, identity: I) -> &mut Self
+    where
+        I: Into<Option<PoolIdentity>>,
+    {
+        self.pool_identity = identity.into();
+        self
+    }
+
+    /// Set how often idle connections are swept for expired and closed
+    /// ones.
+    ///
+    /// A `Timer` is required for sweeping in the background. Without one,
+    /// `Client::sweep_idle` can be called to sweep on demand.
+    ///
+    /// Default is `None`, which sweeps at the rate of the idle timeout.
+    pub fn pool_sweep_interval<D>(&mut self, val: D) -> &mut Self
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.pool_config.sweep_interval = val.into();
+        self
+    }
+
+    /// Sets which idle connection of a host is reused first.
+    ///
+    /// `Reuse::Lifo` reuses the most recently idle connection, which tends
+    /// to have the best latency and lets unneeded connections expire.
+    /// `Reuse::Fifo` spreads requests across all idle connections, keeping
+    /// more of them alive.
+    ///
+    /// This has no effect on a `Client` built with
+    /// [`build_with_pool`](Builder::build_with_pool).
+    ///
+    /// Default is `Reuse::Lifo`.
+    pub fn pool_reuse(&mut self, reuse: pool::Reuse) -> &mut Self {
+        self.pool_config.reuse = reuse;
+        self
+    }
+
+    /// Set a maximum lifetime for pooled connections.
+    ///
+    /// Connections that have been open for longer are retired: they are not
+    /// reused for new requests, but requests already in flight on them are
+    /// allowed to complete. This lets DNS changes and load balancers take
+    /// effect on long-lived clients.
+    ///
+    /// Pass `None` to disable the limit.
+    ///
+    /// Default is `None`.
+    pub fn pool_max_connection_lifetime<D>(&mut self, val: D) -> &mut Self
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.pool_config.max_lifetime = val.into();
+        self
+    }
+
     // HTTP/1 options
 
     /// Sets the exact size of the read buffer to *always* use.
@@ -1082,6 +1948,27 @@ impl Builder {
         self
     }
 
+    /// Set the maximum number of headers.
+    ///
+    /// When a response is received, the parser will reserve a buffer to store
+    /// headers for optimal performance.
+    ///
+    /// If the client receives more headers than the buffer size, the error
+    /// "message header too large" is returned.
+    ///
+    /// Note that headers are allocated on the stack by default, which has
+    /// higher performance. After setting this value, headers will be allocated
+    /// in heap memory, that is, heap memory allocation will occur for each
+    /// response, and there will be a performance drop of about 5%.
+    ///
+    /// Default is 100.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_max_headers(&mut self, val: usize) -> &mut Self {
+        self.h1_builder.max_headers(val);
+        self
+    }
+
     /// Set whether HTTP/1 connections will accept spaces between header names
     /// and the colon that follow them in responses.
     ///
@@ -1251,6 +2138,11 @@ impl Builder {
     /// as part of the connection process. This will not make the `Client`
     /// utilize ALPN by itself.
     ///
+    /// This isn't needed to use HTTP/2 with connectors that negotiate ALPN:
+    /// when a connection reports `h2` through
+    /// [`Connected::negotiated_alpn`](crate::client::legacy::connect::Connected::negotiated_alpn),
+    /// the `Client` uses HTTP/2 on it, and HTTP/1 elsewhere.
+    ///
     /// Note that setting this to true prevents HTTP/1 from being allowed.
     ///
     /// Default is false.
@@ -1303,6 +2195,26 @@ impl Builder {
         self
     }
 
+    /// Sets the initial maximum of locally initiated (send) streams.
+    ///
+    /// This value will be overwritten by the value included in the initial
+    /// SETTINGS frame received from the peer as part of a [connection preface].
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// If not set, hyper will use a default.
+    ///
+    /// [connection preface]: https://httpwg.org/specs/rfc9113.html#preface
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_initial_max_send_streams(
+        &mut self,
+        initial: impl Into<Option<usize>>,
+    ) -> &mut Self {
+        self.h2_builder.initial_max_send_streams(initial);
+        self
+    }
+
     /// Sets the maximum frame size to use for HTTP2.
     ///
     /// Passing `None` will do nothing.
@@ -1460,7 +2372,7 @@ impl Builder {
     #[cfg(feature = "tokio")]
     pub fn build_http<B>(&self) -> Client<HttpConnector, B>
     where
-        B: Body + Send,
+        B: Body + Send + 'static,
         B::Data: Send,
     {
         let mut connector = HttpConnector::new();
@@ -1474,20 +2386,98 @@ impl Builder {
     pub fn build<C, B>(&self, connector: C) -> Client<C, B>
     where
         C: Connect + Clone,
-        B: Body + Send,
+        B: Body + Send + 'static,
+        B::Data: Send,
+    {
+        let exec = self.exec.clone();
+        let timer = self.pool_timer.clone();
+        self.build_client(connector, pool::Pool::new(self.pool_config, exec, timer))
+    }
+
+    /// Combine the configuration of this builder with a connector and an
+    /// [`IdlePool`](super::IdlePool) to create a `Client`.
+    ///
+    /// The `Client` keeps its idle connections in `idle`, instead of the
+    /// default pool, which reuses the most recently idle connection of a
+    /// host first. Limits such as `pool_max_idle_per_host` and
+    /// `pool_idle_timeout` still apply.
+    pub fn build_with_pool<C, B, P>(&self, connector: C, idle: P) -> Client<C, B>
+    where
+        C: Connect + Clone,
+        B: Body + Send + 'static,
         B::Data: Send,
+        P: pool::IdlePool<pool::Idle<PoolClient<B>>, PoolKey>,
     {
         let exec = self.exec.clone();
         let timer = self.pool_timer.clone();
+        self.build_client(
+            connector,
+            pool::Pool::with_idle(self.pool_config, exec, timer, idle),
+        )
+    }
+
+    fn build_client<C, B>(
+        &self,
+        connector: C,
+        pool: pool::Pool<PoolClient<B>, PoolKey>,
+    ) -> Client<C, B> {
+        let pool = if self.events.is_enabled() {
+            pool.with_observer(Arc::new(EventsObserver {
+                events: self.events.clone(),
+                observer: self.pool_observer.clone(),
+            }))
+        } else {
+            match self.pool_observer {
+                Some(ref observer) => pool.with_observer(observer.clone()),
+                None => pool,
+            }
+        };
         Client {
             config: self.client_config,
-            exec: exec.clone(),
+            exec: self.exec.clone(),
             #[cfg(feature = "http1")]
             h1_builder: self.h1_builder.clone(),
             #[cfg(feature = "http2")]
             h2_builder: self.h2_builder.clone(),
             connector,
-            pool: pool::Pool::new(self.pool_config, exec, timer),
+            pool,
+            pool_identity: self.pool_identity.clone(),
+            keys: Arc::new(KeyInterner::new(self.pool_config.shards)),
+            events: self.events.clone(),
+            breakers: self
+                .circuit_breaker
+                .map(|config| Arc::new(Breakers::new(config, self.events.clone()))),
+            drain: Arc::new(Drain {
+                closed: AtomicBool::new(false),
+                in_flight: AtomicUsize::new(0),
+                waiters: Mutex::new(Vec::new()),
+                timer: self.pool_timer.clone(),
+            }),
+            pending: Arc::new(AtomicUsize::new(0)),
+            preconnect: self.client_config.preconnect.map(|threshold| {
+                Arc::new(Preconnect {
+                    threshold,
+                    hosts: Mutex::new(HashMap::new()),
+                })
+            }),
+        }
+    }
+}
+
+// Reports pool events to the `Events` of a `Client`, and to its own pool
+// observer, if it has one.
+struct EventsObserver {
+    events: EventsHandle,
+    observer: Option<Arc<dyn pool::PoolObserver<PoolKey>>>,
+}
+
+impl pool::PoolObserver<PoolKey> for EventsObserver {
+    fn on_event(&self, event: pool::PoolEvent, key: &PoolKey, info: &pool::PoolEventInfo) {
+        if let Some(ref observer) = self.observer {
+            observer.on_event(event, key, info);
+        }
+        if let Some(events) = self.events.get() {
+            events.pool_event(event, key, info);
         }
     }
 }
@@ -1516,8 +2506,99 @@ impl StdError for Error {
 }
 
 impl Error {
+    /// The kind of this error, found by looking through its sources.
+    pub fn kind(&self) -> ErrorKind {
+        match self.kind {
+            Kind::Canceled | Kind::Shutdown => ErrorKind::Canceled,
+            Kind::PoolTimeout => ErrorKind::PoolTimeout,
+            Kind::PoolQueueFull => ErrorKind::PoolFull,
+            Kind::CircuitOpen => ErrorKind::CircuitOpen,
+            Kind::UserUnsupportedRequestMethod
+            | Kind::UserUnsupportedVersion
+            | Kind::UserAbsoluteUriRequired => ErrorKind::User,
+            Kind::Connect => self.connect_kind(),
+            Kind::SendRequest | Kind::ChannelClosed => self.send_kind(),
+        }
+    }
+
+    /// Whether the request may be retried without knowing if it's
+    /// idempotent, because it was not sent.
+    ///
+    /// This is the case when the connection couldn't be established, or no
+    /// pooled connection became available, or the request was canceled
+    /// before it was sent. TLS failures aren't, since they're rarely
+    /// transient.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::Dns
+                | ErrorKind::Connect
+                | ErrorKind::PoolTimeout
+                | ErrorKind::PoolFull
+                | ErrorKind::Canceled
+        )
+    }
+
+    fn connect_kind(&self) -> ErrorKind {
+        let mut kind = None;
+        self.walk_sources(|err| {
+            kind = connect_source_kind(err);
+            kind.is_some()
+        });
+        kind.unwrap_or(ErrorKind::Connect)
+    }
+
+    fn send_kind(&self) -> ErrorKind {
+        let mut kind = ErrorKind::Other;
+        self.walk_sources(|err| {
+            if let Some(err) = err.downcast_ref::<hyper::Error>() {
+                kind = if err.is_canceled() {
+                    ErrorKind::Canceled
+                } else if err.is_timeout() {
+                    ErrorKind::RequestTimeout
+                } else if err.is_parse() || err.is_parse_status() {
+                    ErrorKind::Protocol
+                } else if err.is_user() || err.is_body_write_aborted() {
+                    ErrorKind::Body
+                } else if err.is_incomplete_message() || err.is_closed() {
+                    ErrorKind::Io
+                } else {
+                    return false;
+                };
+                return true;
+            }
+            if err.is::<std::io::Error>() {
+                kind = ErrorKind::Io;
+                return true;
+            }
+            false
+        });
+        kind
+    }
+
+    // Calls `f` with each source until it returns `true`, including the
+    // errors wrapped by `io::Error`s, whose `source` skips them.
+    fn walk_sources(&self, mut f: impl FnMut(&(dyn StdError + 'static)) -> bool) {
+        let mut source = self
+            .source
+            .as_ref()
+            .map(|e| &**e as &(dyn StdError + 'static));
+        while let Some(err) = source {
+            if f(err) {
+                return;
+            }
+            source = match err.downcast_ref::<std::io::Error>() {
+                Some(io) => match io.get_ref() {
+                    Some(inner) => Some(inner as &(dyn StdError + 'static)),
+                    None => None,
+                },
+                None => err.source(),
+            };
+        }
+    }
+
     fn is_canceled(&self) -> bool {
-        matches!(self.kind, ErrorKind::Canceled)
+        matches!(self.kind, Kind::Canceled)
     }
 
     fn tx(src: hyper::Error) -> Self {
@@ -1528,3 +2609,316 @@ impl Error {
         e!(ChannelClosed, src)
     }
 }
+
+#[allow(unused_variables)]
+fn connect_source_kind(err: &(dyn StdError + 'static)) -> Option<ErrorKind> {
+    #[cfg(feature = "tokio")]
+    {
+        if matches!(err.downcast_ref::<super::connect::ConnectError>(), Some(e) if e.is_dns())
+            || err.is::<super::connect::dns::InvalidNameError>()
+        {
+            return Some(ErrorKind::Dns);
+        }
+    }
+    #[cfg(feature = "rustls")]
+    {
+        if err.is::<::rustls::Error>() {
+            return Some(ErrorKind::Tls);
+        }
+    }
+    #[cfg(feature = "native-tls")]
+    {
+        if err.is::<::native_tls::Error>() {
+            return Some(ErrorKind::Tls);
+        }
+    }
+    None
+}
+
+// ==== impl PoolTimeout ====
+
+impl fmt::Display for PoolTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("timed out waiting for a pooled connection")
+    }
+}
+
+impl StdError for PoolTimeout {}
+
+// ==== impl PoolKey ====
+
+impl PoolKey {
+    /// The scheme of the destination.
+    pub fn scheme(&self) -> &Scheme {
+        &self.0.scheme
+    }
+
+    /// The authority of the destination.
+    pub fn authority(&self) -> &Authority {
+        &self.0.authority
+    }
+
+    /// The identity of the connector, if the `Client` has one.
+    pub fn identity(&self) -> Option<&PoolIdentity> {
+        self.0.identity.as_ref()
+    }
+
+    // The key of the host, for every client address.
+    pub(super) fn host(&self) -> PoolKey {
+        if self.0.proxy_addrs.is_none() {
+            return self.clone();
+        }
+        PoolKey(Arc::new(PoolKeyInner {
+            scheme: self.0.scheme.clone(),
+            authority: self.0.authority.clone(),
+            identity: self.0.identity.clone(),
+            proxy_addrs: None,
+        }))
+    }
+
+    #[cfg(test)]
+    pub(super) fn for_test(scheme: &str, authority: &str) -> PoolKey {
+        PoolKey(Arc::new(PoolKeyInner {
+            scheme: scheme.parse().unwrap(),
+            authority: authority.parse().unwrap(),
+            identity: None,
+            proxy_addrs: None,
+        }))
+    }
+
+    /// The PROXY protocol addresses of the requests, if they have them.
+    pub fn proxy_addrs(&self) -> Option<&ProxyAddrs> {
+        self.0.proxy_addrs.as_ref()
+    }
+
+    fn matches(
+        &self,
+        scheme: &Scheme,
+        authority: &Authority,
+        identity: Option<&PoolIdentity>,
+        proxy_addrs: Option<ProxyAddrs>,
+    ) -> bool {
+        self.0.scheme == *scheme
+            && self.0.authority == *authority
+            && self.0.identity.as_ref() == identity
+            && self.0.proxy_addrs == proxy_addrs
+    }
+}
+
+impl PartialEq for PoolKey {
+    fn eq(&self, other: &PoolKey) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0 == other.0
+    }
+}
+
+impl Eq for PoolKey {}
+
+impl Hash for PoolKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl fmt::Debug for PoolKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolKey")
+            .field("scheme", &self.0.scheme)
+            .field("authority", &self.0.authority)
+            .field("identity", &self.0.identity)
+            .field("proxy_addrs", &self.0.proxy_addrs)
+            .finish()
+    }
+}
+
+// ==== impl KeyInterner ====
+
+// How many keys are interned at most, across all shards.
+const MAX_INTERNED_KEYS: usize = 1024;
+
+impl KeyInterner {
+    fn new(shards: usize) -> KeyInterner {
+        let shards = shards.max(1);
+        let capacity = (MAX_INTERNED_KEYS / shards).max(1);
+        KeyInterner {
+            hasher: RandomState::new(),
+            shards: (0..shards)
+                .map(|_| {
+                    RwLock::new(InternedKeys {
+                        capacity,
+                        by_hash: HashMap::new(),
+                        slots: Vec::new(),
+                        hand: 0,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    // Get the key of a request, only allocating the first time a host is
+    // seen, or after its key was evicted.
+    #[allow(clippy::manual_hash_one)] // `BuildHasher::hash_one` is newer than the MSRV
+    fn intern(
+        &self,
+        scheme: &Scheme,
+        authority: &Authority,
+        identity: Option<&PoolIdentity>,
+        proxy_addrs: Option<ProxyAddrs>,
+    ) -> PoolKey {
+        let mut hasher = self.hasher.build_hasher();
+        (scheme, authority, identity, proxy_addrs).hash(&mut hasher);
+        let hash = hasher.finish();
+        let find = |keys: &InternedKeys| {
+            let interned = keys.by_hash.get(&hash)?.iter().find_map(|&i| {
+                let interned = &keys.slots[i];
+                if interned
+                    .key
+                    .matches(scheme, authority, identity, proxy_addrs)
+                {
+                    Some(interned)
+                } else {
+                    None
+                }
+            })?;
+            interned.found.store(true, Ordering::Relaxed);
+            Some(interned.key.clone())
+        };
+
+        let shard = &self.shards[(hash % self.shards.len() as u64) as usize];
+        if let Some(key) = find(&shard.read().unwrap()) {
+            return key;
+        }
+        let key = PoolKey(Arc::new(PoolKeyInner {
+            scheme: scheme.clone(),
+            authority: authority.clone(),
+            identity: identity.cloned(),
+            proxy_addrs,
+        }));
+        let mut keys = shard.write().unwrap();
+        // Another request may have interned it meanwhile.
+        if let Some(key) = find(&keys) {
+            return key;
+        }
+        keys.insert(hash, key.clone());
+        key
+    }
+}
+
+impl InternedKeys {
+    fn insert(&mut self, hash: u64, key: PoolKey) {
+        let interned = InternedKey {
+            hash,
+            key,
+            found: AtomicBool::new(false),
+        };
+        if self.slots.len() < self.capacity {
+            self.by_hash.entry(hash).or_default().push(self.slots.len());
+            self.slots.push(interned);
+            return;
+        }
+
+        // Each slot the hand spares is cleared, so it stops within one
+        // sweep.
+        let i = loop {
+            let i = self.hand;
+            self.hand = (i + 1) % self.slots.len();
+            if !std::mem::replace(self.slots[i].found.get_mut(), false) {
+                break i;
+            }
+        };
+        let evicted = std::mem::replace(&mut self.slots[i], interned);
+        if let Entry::Occupied(mut entry) = self.by_hash.entry(evicted.hash) {
+            entry.get_mut().retain(|&j| j != i);
+            if entry.get().is_empty() {
+                entry.remove();
+            }
+        }
+        self.by_hash.entry(hash).or_default().push(i);
+    }
+}
+
+// ==== impl PoolIdentity ====
+
+impl PoolIdentity {
+    /// Create an identity from a description of a connector.
+    ///
+    /// Anything that sets the connector apart, such as the proxy, or the
+    /// fingerprint of the client certificate, should be part of it.
+    pub fn new(identity: impl Into<Bytes>) -> Self {
+        PoolIdentity(identity.into())
+    }
+
+    /// The description of the connector.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{KeyInterner, MAX_INTERNED_KEYS};
+
+    #[test]
+    fn interns_pool_keys() {
+        let keys = KeyInterner::new(1);
+        let uri: http::Uri = "http://example.com/a".parse().unwrap();
+        let other: http::Uri = "http://example.com:8080/b".parse().unwrap();
+        let intern = |uri: &http::Uri| {
+            keys.intern(uri.scheme().unwrap(), uri.authority().unwrap(), None, None)
+        };
+
+        let first = intern(&uri);
+        let second = intern(&uri);
+        assert!(Arc::ptr_eq(&first.0, &second.0));
+        assert_ne!(first, intern(&other));
+    }
+
+    #[test]
+    fn evicts_interned_keys_once_full() {
+        let keys = KeyInterner::new(1);
+        let intern = |uri: &http::Uri| {
+            keys.intern(uri.scheme().unwrap(), uri.authority().unwrap(), None, None)
+        };
+        let host =
+            |i: usize| -> http::Uri { format!("http://host{}.example/", i).parse().unwrap() };
+
+        let hot = intern(&host(0));
+        for i in 1..MAX_INTERNED_KEYS * 3 {
+            // Found again after each sweep of the hand, it is never evicted.
+            assert!(Arc::ptr_eq(&hot.0, &intern(&host(0)).0));
+            intern(&host(i));
+        }
+        let keys_len = keys.shards[0].read().unwrap().slots.len();
+        assert_eq!(keys_len, MAX_INTERNED_KEYS);
+
+        // New keys are still interned once full.
+        let last = intern(&host(MAX_INTERNED_KEYS * 3));
+        assert!(Arc::ptr_eq(
+            &last.0,
+            &intern(&host(MAX_INTERNED_KEYS * 3)).0
+        ));
+    }
+
+    #[test]
+    fn shards_interned_keys() {
+        let keys = KeyInterner::new(4);
+        let intern = |uri: &http::Uri| {
+            keys.intern(uri.scheme().unwrap(), uri.authority().unwrap(), None, None)
+        };
+        let host =
+            |i: usize| -> http::Uri { format!("http://host{}.example/", i).parse().unwrap() };
+
+        let interned: Vec<_> = (0..64).map(|i| intern(&host(i))).collect();
+        for (i, key) in interned.iter().enumerate() {
+            assert!(Arc::ptr_eq(&key.0, &intern(&host(i)).0));
+        }
+        let lens: Vec<_> = keys
+            .shards
+            .iter()
+            .map(|shard| shard.read().unwrap().slots.len())
+            .collect();
+        assert_eq!(lens.iter().sum::<usize>(), 64);
+        assert!(lens.iter().all(|&len| len < 64));
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use http::Uri;
+
+use super::proxy::{SocksV5, Tunnel};
+#[cfg(feature = "tokio")]
+use super::HttpConnector;
+use super::{RetryConnector, RetryPolicy, TimeoutConnector};
+
+/// A builder to assemble a stack of connectors.
+///
+/// Each method wraps the connector built so far in another one, so they
+/// are applied from the inside out: the first layer added is the one
+/// closest to the network. Common stacks are:
+///
+/// - direct: `ConnectorBuilder::new().build()`
+/// - through an HTTP proxy: `ConnectorBuilder::new().http_proxy(proxy).build()`
+/// - TLS through a SOCKS5 proxy:
+///   `ConnectorBuilder::new().socks5_proxy(proxy).rustls(config).build()`
+///
+/// # Pooling
+///
+/// The `Client` pools connections by the scheme and authority of the
+/// destination, and by the [`PoolIdentity`](crate::client::legacy::PoolIdentity)
+/// of the `Client`. Since a `Client` only ever uses one connector, every
+/// connection in its pool went through the same stack. Use a separate
+/// `Client` for each stack, so that connections through different proxies
+/// are never reused for each other. If those clients share an idle pool,
+/// give each of them a different `PoolIdentity`.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use std::time::Duration;
+/// use hyper_util::client::legacy::connect::ConnectorBuilder;
+/// use hyper_util::client::legacy::Client;
+/// use hyper_util::rt::{TokioExecutor, TokioTimer};
+///
+/// let connector = ConnectorBuilder::new()
+///     .http_proxy("http://proxy.local:3128".parse().unwrap())
+///     .timeout(Duration::from_secs(10), TokioTimer::new())
+///     .build();
+///
+/// let client = Client::builder(TokioExecutor::new()).build(connector);
+/// # let _: Client<_, http_body_util::Empty<bytes::Bytes>> = client;
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConnectorBuilder<C> {
+    inner: C,
+}
+
+#[cfg(feature = "tokio")]
+impl ConnectorBuilder<HttpConnector> {
+    /// Start a stack with an `HttpConnector`.
+    ///
+    /// The connector is configured to allow any scheme, so that it can be
+    /// wrapped by TLS and proxy connectors.
+    pub fn new() -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        ConnectorBuilder { inner: http }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Default for ConnectorBuilder<HttpConnector> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> ConnectorBuilder<C> {
+    /// Start a stack with a custom connector.
+    pub fn with_connector(inner: C) -> Self {
+        ConnectorBuilder { inner }
+    }
+
+    /// Tunnel through the HTTP proxy at `proxy_dst`.
+    ///
+    /// Use [`map`](ConnectorBuilder::map) to configure the [`Tunnel`](Tunnel)
+    /// further, such as to add proxy authorization.
+    pub fn http_proxy(self, proxy_dst: Uri) -> ConnectorBuilder<Tunnel<C>> {
+        self.map(|inner| Tunnel::new(proxy_dst, inner))
+    }
+
+    /// Connect through the SOCKS5 proxy at `proxy_dst`.
+    pub fn socks5_proxy(self, proxy_dst: Uri) -> ConnectorBuilder<SocksV5<C>> {
+        self.map(|inner| SocksV5::new(proxy_dst, inner))
+    }
+
+    /// Negotiate TLS with `rustls` for `https` destinations.
+    #[cfg(feature = "rustls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
+    pub fn rustls(
+        self,
+        config: impl Into<std::sync::Arc<::rustls::ClientConfig>>,
+    ) -> ConnectorBuilder<super::rustls::HttpsConnector<C>> {
+        self.map(|inner| super::rustls::HttpsConnector::with_config(inner, config))
+    }
+
+    /// Negotiate TLS with `native-tls` for `https` destinations.
+    #[cfg(feature = "native-tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "native-tls")))]
+    pub fn native_tls(
+        self,
+        tls: ::native_tls::TlsConnector,
+    ) -> ConnectorBuilder<super::native_tls::HttpsConnector<C>> {
+        self.map(|inner| super::native_tls::HttpsConnector::with_connector(inner, tls))
+    }
+
+    /// Fail connections that aren't established within `timeout`, including
+    /// the work of every layer added so far.
+    pub fn timeout<M>(self, timeout: Duration, timer: M) -> ConnectorBuilder<TimeoutConnector<C>>
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        self.map(|inner| TimeoutConnector::new(inner, timeout, timer))
+    }
+
+    /// Retry failed connections of the layers added so far, according to
+    /// `policy`.
+    pub fn retry<M>(self, policy: RetryPolicy, timer: M) -> ConnectorBuilder<RetryConnector<C>>
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        self.map(|inner| RetryConnector::new(inner, policy, timer))
+    }
+
+    /// Wrap the stack with any other connector.
+    pub fn map<F, D>(self, f: F) -> ConnectorBuilder<D>
+    where
+        F: FnOnce(C) -> D,
+    {
+        ConnectorBuilder {
+            inner: f(self.inner),
+        }
+    }
+
+    /// Build the connector.
+    pub fn build(self) -> C {
+        self.inner
+    }
+}
@@ -4,6 +4,14 @@
 //!
 //! - A [`GaiResolver`](GaiResolver) that is the default resolver for the
 //!   `HttpConnector`.
+//! - A [`TimeoutResolver`](TimeoutResolver) that bounds how long any other
+//!   resolver may take.
+//! - A [`FamilyResolver`](FamilyResolver) that filters or reorders resolved
+//!   addresses by IP family.
+//! - A [`SpreadResolver`](SpreadResolver) that varies the order of resolved
+//!   addresses, to spread connections across them.
+//! - A [`CachingResolver`](CachingResolver) that caches resolved addresses,
+//!   optionally using them past their expiry while refreshing them.
 //! - The `Name` type used as an argument to custom resolvers.
 //!
 //! # Resolvers are `Service`s
@@ -21,18 +29,26 @@
 //!     Ok::<_, Infallible>(iter::once(SocketAddr::from(([127, 0, 0, 1], 8080))))
 //! });
 //! ```
+use std::collections::HashMap;
 use std::error::Error;
 use std::future::Future;
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
 use std::pin::Pin;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::{self, Poll};
+use std::time::{Duration, Instant};
 use std::{fmt, io, vec};
 
+use hyper::rt::Sleep;
+use pin_project_lite::pin_project;
 use tokio::task::JoinHandle;
 use tower_service::Service;
 use tracing::debug;
 
+use crate::common::timer;
+
 pub(super) use self::sealed::Resolve;
 
 /// A domain name to resolve into IP addresses.
@@ -101,6 +117,193 @@ impl fmt::Display for InvalidNameError {
 
 impl Error for InvalidNameError {}
 
+/// A resolver that enforces a deadline on every lookup of an inner resolver.
+///
+/// If the inner resolver does not produce addresses before the timeout
+/// elapses, the lookup future is dropped (cancelling it, if the inner
+/// resolver supports that) and a [`ResolveTimeout`](ResolveTimeout) error is
+/// returned instead.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use std::time::Duration;
+/// use hyper_util::client::legacy::connect::dns::{GaiResolver, TimeoutResolver};
+/// use hyper_util::client::legacy::connect::HttpConnector;
+/// use hyper_util::rt::TokioTimer;
+///
+/// let resolver = TimeoutResolver::new(GaiResolver::new(), Duration::from_secs(2), TokioTimer::new());
+/// let connector = HttpConnector::new_with_resolver(resolver);
+/// # drop(connector);
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct TimeoutResolver<R> {
+    inner: R,
+    timeout: Duration,
+    timer: timer::Timer,
+}
+
+pin_project! {
+    /// A future to resolve a name returned by `TimeoutResolver`.
+    pub struct TimeoutResolverFuture<F> {
+        #[pin]
+        inner: F,
+        sleep: Pin<Box<dyn Sleep>>,
+    }
+}
+
+/// A resolver that filters or reorders the addresses of an inner resolver by
+/// IP family.
+///
+/// This is useful when a network has broken IPv6 (or IPv4) routing, and
+/// works independently of the `HttpConnector`'s Happy Eyeballs settings.
+/// When preferring a family, the connector will treat that family as the
+/// preferred one, and still fall back to the other.
+///
+/// If filtering removes every address, the lookup yields no addresses and
+/// connecting will fail.
+#[derive(Clone, Debug)]
+pub struct FamilyResolver<R> {
+    inner: R,
+    family: AddrFamily,
+}
+
+/// Which IP family a [`FamilyResolver`](FamilyResolver) keeps or prefers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddrFamily {
+    /// Only keep IPv4 addresses.
+    Ipv4Only,
+    /// Only keep IPv6 addresses.
+    Ipv6Only,
+    /// Keep all addresses, with IPv4 addresses ordered first.
+    PreferIpv4,
+    /// Keep all addresses, with IPv6 addresses ordered first.
+    PreferIpv6,
+}
+
+/// An iterator of IP addresses returned by `FamilyResolver`.
+pub struct FamilyAddrs {
+    inner: SocketAddrs,
+}
+
+pin_project! {
+    /// A future to resolve a name returned by `FamilyResolver`.
+    pub struct FamilyFuture<F> {
+        #[pin]
+        inner: F,
+        family: AddrFamily,
+    }
+}
+
+/// A resolver that varies the order of the addresses of an inner resolver.
+///
+/// The `HttpConnector` tries addresses in the order they are resolved. When
+/// many clients resolve the same name, they would all pick the first address.
+/// Wrapping the resolver with a `SpreadResolver` spreads those connections
+/// across all of the returned addresses instead.
+///
+/// Clones of a `SpreadResolver` share the round-robin position.
+#[derive(Clone, Debug)]
+pub struct SpreadResolver<R> {
+    inner: R,
+    strategy: SpreadStrategy,
+    counter: Arc<AtomicUsize>,
+}
+
+/// How a [`SpreadResolver`](SpreadResolver) orders addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpreadStrategy {
+    /// Shuffle the addresses randomly on every lookup.
+    Random,
+    /// Rotate the addresses by one position on every lookup.
+    RoundRobin,
+}
+
+/// An iterator of IP addresses returned by `SpreadResolver`.
+pub struct SpreadAddrs {
+    inner: SocketAddrs,
+}
+
+pin_project! {
+    /// A future to resolve a name returned by `SpreadResolver`.
+    pub struct SpreadFuture<F> {
+        #[pin]
+        inner: F,
+        strategy: SpreadStrategy,
+        seed: usize,
+    }
+}
+
+/// A resolver that caches the addresses of an inner resolver.
+///
+/// Addresses are reused for `ttl` after they were resolved. With
+/// [`stale_while_revalidate`](CachingResolver::stale_while_revalidate),
+/// expired addresses are still used for a while, so that a lookup doesn't
+/// wait on the inner resolver: it is refreshed in the background instead.
+/// If the refresh fails, such as during a resolver outage, the stale
+/// addresses keep being used until the stale window ends.
+///
+/// Clones of a `CachingResolver` share the cache.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use std::time::Duration;
+/// use hyper_util::client::legacy::connect::dns::{CachingResolver, GaiResolver};
+/// use hyper_util::client::legacy::connect::HttpConnector;
+///
+/// let resolver = CachingResolver::new(GaiResolver::new(), Duration::from_secs(30))
+///     .stale_while_revalidate(Duration::from_secs(300));
+/// let connector = HttpConnector::new_with_resolver(resolver);
+/// # drop(connector);
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct CachingResolver<R> {
+    inner: R,
+    cache: Arc<Cache>,
+}
+
+struct Cache {
+    ttl: Duration,
+    stale: Duration,
+    entries: Mutex<HashMap<Name, CacheEntry>>,
+}
+
+struct CacheEntry {
+    addrs: Vec<SocketAddr>,
+    expires_at: Instant,
+    refreshing: bool,
+}
+
+/// An iterator of IP addresses returned by `CachingResolver`.
+pub struct CachingAddrs {
+    inner: SocketAddrs,
+}
+
+pin_project! {
+    /// A future to resolve a name returned by `CachingResolver`.
+    pub struct CachingFuture<F> {
+        #[pin]
+        inner: Option<F>,
+        cached: Option<Vec<SocketAddr>>,
+        name: Name,
+        cache: Arc<Cache>,
+    }
+}
+
+/// Error returned by a [`TimeoutResolver`](TimeoutResolver) when a lookup
+/// did not complete in time.
+#[derive(Debug)]
+pub struct ResolveTimeout(());
+
 impl GaiResolver {
     /// Construct a new `GaiResolver`.
     pub fn new() -> Self {
@@ -179,6 +382,538 @@ impl fmt::Debug for GaiAddrs {
     }
 }
 
+// ===== impl TimeoutResolver =====
+
+impl<R> TimeoutResolver<R> {
+    /// Wrap a resolver, failing any lookup that takes longer than `timeout`.
+    ///
+    /// The `timer` is used to drive the deadline.
+    pub fn new<M>(inner: R, timeout: Duration, timer: M) -> Self
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        TimeoutResolver {
+            inner,
+            timeout,
+            timer: timer::Timer::new(timer),
+        }
+    }
+
+    /// Get a reference to the inner resolver.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner resolver.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner resolver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> Service<Name> for TimeoutResolver<R>
+where
+    R: Service<Name>,
+    R::Response: Iterator<Item = SocketAddr>,
+    R::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Response = R::Response;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = TimeoutResolverFuture<R::Future>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        TimeoutResolverFuture {
+            inner: self.inner.call(name),
+            sleep: hyper::rt::Timer::sleep(&self.timer, self.timeout),
+        }
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for TimeoutResolver<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeoutResolver")
+            .field("inner", &self.inner)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl<F, A, E> Future for TimeoutResolverFuture<F>
+where
+    F: Future<Output = Result<A, E>>,
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Output = Result<A, Box<dyn Error + Send + Sync>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        if let Poll::Ready(res) = this.inner.poll(cx) {
+            return Poll::Ready(res.map_err(Into::into));
+        }
+
+        match this.sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                debug!("dns lookup timed out");
+                Poll::Ready(Err(ResolveTimeout(()).into()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<F> fmt::Debug for TimeoutResolverFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("TimeoutResolverFuture")
+    }
+}
+
+impl fmt::Display for ResolveTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("dns lookup timed out")
+    }
+}
+
+impl Error for ResolveTimeout {}
+
+// ===== impl FamilyResolver =====
+
+impl<R> FamilyResolver<R> {
+    /// Wrap a resolver, filtering or reordering its addresses by `family`.
+    pub fn new(inner: R, family: AddrFamily) -> Self {
+        FamilyResolver { inner, family }
+    }
+
+    /// Wrap a resolver, only keeping IPv4 addresses.
+    pub fn ipv4_only(inner: R) -> Self {
+        FamilyResolver::new(inner, AddrFamily::Ipv4Only)
+    }
+
+    /// Wrap a resolver, only keeping IPv6 addresses.
+    pub fn ipv6_only(inner: R) -> Self {
+        FamilyResolver::new(inner, AddrFamily::Ipv6Only)
+    }
+
+    /// Get a reference to the inner resolver.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner resolver.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner resolver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> Service<Name> for FamilyResolver<R>
+where
+    R: Service<Name>,
+    R::Response: Iterator<Item = SocketAddr>,
+{
+    type Response = FamilyAddrs;
+    type Error = R::Error;
+    type Future = FamilyFuture<R::Future>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        FamilyFuture {
+            inner: self.inner.call(name),
+            family: self.family,
+        }
+    }
+}
+
+impl<F, A, E> Future for FamilyFuture<F>
+where
+    F: Future<Output = Result<A, E>>,
+    A: Iterator<Item = SocketAddr>,
+{
+    type Output = Result<FamilyAddrs, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let family = *this.family;
+        this.inner.poll(cx).map_ok(|addrs| {
+            let addrs: Vec<SocketAddr> = match family {
+                AddrFamily::Ipv4Only => addrs.filter(SocketAddr::is_ipv4).collect(),
+                AddrFamily::Ipv6Only => addrs.filter(SocketAddr::is_ipv6).collect(),
+                AddrFamily::PreferIpv4 => {
+                    let (mut v4, v6): (Vec<_>, Vec<_>) = addrs.partition(SocketAddr::is_ipv4);
+                    v4.extend(v6);
+                    v4
+                }
+                AddrFamily::PreferIpv6 => {
+                    let (mut v6, v4): (Vec<_>, Vec<_>) = addrs.partition(SocketAddr::is_ipv6);
+                    v6.extend(v4);
+                    v6
+                }
+            };
+            FamilyAddrs {
+                inner: SocketAddrs::new(addrs),
+            }
+        })
+    }
+}
+
+impl<F> fmt::Debug for FamilyFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("FamilyFuture")
+    }
+}
+
+impl Iterator for FamilyAddrs {
+    type Item = SocketAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl fmt::Debug for FamilyAddrs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("FamilyAddrs")
+    }
+}
+
+// ===== impl SpreadResolver =====
+
+impl<R> SpreadResolver<R> {
+    /// Wrap a resolver, ordering its addresses according to `strategy`.
+    pub fn new(inner: R, strategy: SpreadStrategy) -> Self {
+        SpreadResolver {
+            inner,
+            strategy,
+            counter: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Wrap a resolver, shuffling its addresses randomly.
+    pub fn random(inner: R) -> Self {
+        SpreadResolver::new(inner, SpreadStrategy::Random)
+    }
+
+    /// Wrap a resolver, rotating its addresses on every lookup.
+    pub fn round_robin(inner: R) -> Self {
+        SpreadResolver::new(inner, SpreadStrategy::RoundRobin)
+    }
+
+    /// Get a reference to the inner resolver.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner resolver.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner resolver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> Service<Name> for SpreadResolver<R>
+where
+    R: Service<Name>,
+    R::Response: Iterator<Item = SocketAddr>,
+{
+    type Response = SpreadAddrs;
+    type Error = R::Error;
+    type Future = SpreadFuture<R::Future>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        SpreadFuture {
+            inner: self.inner.call(name),
+            strategy: self.strategy,
+            seed: self.counter.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl<F, A, E> Future for SpreadFuture<F>
+where
+    F: Future<Output = Result<A, E>>,
+    A: Iterator<Item = SocketAddr>,
+{
+    type Output = Result<SpreadAddrs, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let strategy = *this.strategy;
+        let seed = *this.seed;
+        this.inner.poll(cx).map_ok(|addrs| {
+            let mut addrs: Vec<SocketAddr> = addrs.collect();
+            if !addrs.is_empty() {
+                match strategy {
+                    SpreadStrategy::Random => shuffle(&mut addrs, seed),
+                    SpreadStrategy::RoundRobin => {
+                        let mid = seed % addrs.len();
+                        addrs.rotate_left(mid);
+                    }
+                }
+            }
+            SpreadAddrs {
+                inner: SocketAddrs::new(addrs),
+            }
+        })
+    }
+}
+
+// A Fisher-Yates shuffle, using a xorshift generator seeded from the
+// randomly keyed std hasher. Good enough for spreading load, not for
+// anything that needs real randomness.
+fn shuffle(addrs: &mut [SocketAddr], seed: usize) {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_usize(seed);
+    // xorshift must never be seeded with zero.
+    let mut state = hasher.finish() | 1;
+
+    for i in (1..addrs.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let j = (state % (i as u64 + 1)) as usize;
+        addrs.swap(i, j);
+    }
+}
+
+impl<F> fmt::Debug for SpreadFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("SpreadFuture")
+    }
+}
+
+impl Iterator for SpreadAddrs {
+    type Item = SocketAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl fmt::Debug for SpreadAddrs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("SpreadAddrs")
+    }
+}
+
+// ===== impl CachingResolver =====
+
+impl<R> CachingResolver<R> {
+    /// Wrap a resolver, reusing its addresses for `ttl`.
+    pub fn new(inner: R, ttl: Duration) -> Self {
+        CachingResolver {
+            inner,
+            cache: Arc::new(Cache {
+                ttl,
+                stale: Duration::from_secs(0),
+                entries: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Keep using expired addresses for up to `stale` past their `ttl`,
+    /// while refreshing them in the background.
+    ///
+    /// The refresh is spawned on the current Tokio runtime.
+    ///
+    /// Default is no stale window, so lookups of expired addresses wait on
+    /// the inner resolver.
+    pub fn stale_while_revalidate(mut self, stale: Duration) -> Self {
+        self.cache = Arc::new(Cache {
+            ttl: self.cache.ttl,
+            stale,
+            entries: Mutex::new(HashMap::new()),
+        });
+        self
+    }
+
+    /// Get a reference to the inner resolver.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner resolver.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner resolver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> Service<Name> for CachingResolver<R>
+where
+    R: Service<Name>,
+    R::Response: Iterator<Item = SocketAddr>,
+    R::Error: Into<Box<dyn Error + Send + Sync>>,
+    R::Future: Send + 'static,
+{
+    type Response = CachingAddrs;
+    type Error = R::Error;
+    type Future = CachingFuture<R::Future>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let now = Instant::now();
+        let mut cached = None;
+        let mut refresh = false;
+        if let Some(entry) = self.cache.entries.lock().unwrap().get_mut(&name) {
+            if now < entry.expires_at {
+                cached = Some(entry.addrs.clone());
+            } else if now < entry.expires_at + self.cache.stale {
+                cached = Some(entry.addrs.clone());
+                refresh = !entry.refreshing;
+                entry.refreshing = true;
+            }
+        }
+
+        if refresh {
+            debug!("refreshing stale dns entry for {}", name);
+            let lookup = CachingFuture {
+                inner: Some(self.inner.call(name.clone())),
+                cached: None,
+                name: name.clone(),
+                cache: self.cache.clone(),
+            };
+            let cache = self.cache.clone();
+            let refresh_name = name.clone();
+            tokio::spawn(async move {
+                if let Err(e) = lookup.await {
+                    let _e = e.into();
+                    debug!("dns refresh for {} failed: {}", refresh_name, _e);
+                    cache.refresh_failed(&refresh_name);
+                }
+            });
+        }
+
+        let inner = match cached {
+            Some(_) => None,
+            None => Some(self.inner.call(name.clone())),
+        };
+        CachingFuture {
+            inner,
+            cached,
+            name,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for CachingResolver<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachingResolver")
+            .field("inner", &self.inner)
+            .field("ttl", &self.cache.ttl)
+            .field("stale", &self.cache.stale)
+            .finish()
+    }
+}
+
+impl Cache {
+    fn insert(&self, name: Name, addrs: Vec<SocketAddr>) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        // Forget the entries that can no longer be used, even stale.
+        let stale = self.stale;
+        entries.retain(|_, entry| now < entry.expires_at + stale);
+        entries.insert(
+            name,
+            CacheEntry {
+                addrs,
+                expires_at: now + self.ttl,
+                refreshing: false,
+            },
+        );
+    }
+
+    fn refresh_failed(&self, name: &Name) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(name) {
+            entry.refreshing = false;
+        }
+    }
+}
+
+impl<F, A, E> Future for CachingFuture<F>
+where
+    F: Future<Output = Result<A, E>>,
+    A: Iterator<Item = SocketAddr>,
+{
+    type Output = Result<CachingAddrs, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Some(addrs) = this.cached.take() {
+            return Poll::Ready(Ok(CachingAddrs {
+                inner: SocketAddrs::new(addrs),
+            }));
+        }
+
+        let inner = this
+            .inner
+            .as_pin_mut()
+            .expect("CachingFuture polled after completion");
+        let addrs: Vec<SocketAddr> = match inner.poll(cx) {
+            Poll::Ready(Ok(addrs)) => addrs.collect(),
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        this.cache.insert(this.name.clone(), addrs.clone());
+        Poll::Ready(Ok(CachingAddrs {
+            inner: SocketAddrs::new(addrs),
+        }))
+    }
+}
+
+impl<F> fmt::Debug for CachingFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("CachingFuture")
+    }
+}
+
+impl Iterator for CachingAddrs {
+    type Item = SocketAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl fmt::Debug for CachingAddrs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("CachingAddrs")
+    }
+}
+
 pub(super) struct SocketAddrs {
     iter: vec::IntoIter<SocketAddr>,
 }
@@ -353,6 +1088,184 @@ mod tests {
         assert!(fallback.is_empty());
     }
 
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn test_timeout_resolver_times_out() {
+        use crate::rt::TokioTimer;
+
+        let pending = tower::service_fn(|_: Name| {
+            futures_util::future::pending::<Result<vec::IntoIter<SocketAddr>, io::Error>>()
+        });
+        let mut resolver =
+            TimeoutResolver::new(pending, Duration::from_millis(10), TokioTimer::new());
+
+        let err = resolve(&mut resolver, Name::new("example.com".into()))
+            .await
+            .unwrap_err();
+        assert!(err.is::<ResolveTimeout>());
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn test_timeout_resolver_passes_through() {
+        use crate::rt::TokioTimer;
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], 80));
+        let ready =
+            tower::service_fn(
+                move |_: Name| async move { Ok::<_, io::Error>(vec![addr].into_iter()) },
+            );
+        let mut resolver = TimeoutResolver::new(ready, Duration::from_secs(5), TokioTimer::new());
+
+        let addrs = resolve(&mut resolver, Name::new("example.com".into()))
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(addrs, vec![addr]);
+    }
+
+    #[tokio::test]
+    async fn test_family_resolver() {
+        let v4 = SocketAddr::from(([127, 0, 0, 1], 80));
+        let v6 = SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 80));
+        let inner = tower::service_fn(move |_: Name| async move {
+            Ok::<_, io::Error>(vec![v6, v4, v6].into_iter())
+        });
+
+        let cases = [
+            (AddrFamily::Ipv4Only, vec![v4]),
+            (AddrFamily::Ipv6Only, vec![v6, v6]),
+            (AddrFamily::PreferIpv4, vec![v4, v6, v6]),
+            (AddrFamily::PreferIpv6, vec![v6, v6, v4]),
+        ];
+
+        for (family, expected) in cases {
+            let mut resolver = FamilyResolver::new(inner, family);
+            let addrs = resolve(&mut resolver, Name::new("example.com".into()))
+                .await
+                .unwrap()
+                .collect::<Vec<_>>();
+            assert_eq!(addrs, expected, "{:?}", family);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spread_resolver_round_robin() {
+        let a = SocketAddr::from(([10, 0, 0, 1], 80));
+        let b = SocketAddr::from(([10, 0, 0, 2], 80));
+        let c = SocketAddr::from(([10, 0, 0, 3], 80));
+        let inner = tower::service_fn(move |_: Name| async move {
+            Ok::<_, io::Error>(vec![a, b, c].into_iter())
+        });
+        let resolver = SpreadResolver::round_robin(inner);
+
+        let mut firsts = Vec::new();
+        for _ in 0..4 {
+            // clones share the rotation
+            let mut resolver = resolver.clone();
+            let mut addrs = resolve(&mut resolver, Name::new("example.com".into()))
+                .await
+                .unwrap();
+            firsts.push(addrs.next().unwrap());
+            assert_eq!(addrs.count(), 2);
+        }
+        assert_eq!(firsts, vec![a, b, c, a]);
+    }
+
+    #[tokio::test]
+    async fn test_spread_resolver_random_keeps_all() {
+        let expected = (1..=8)
+            .map(|i| SocketAddr::from(([10, 0, 0, i], 80)))
+            .collect::<Vec<_>>();
+        let addrs = expected.clone();
+        let inner = tower::service_fn(move |_: Name| {
+            let addrs = addrs.clone();
+            async move { Ok::<_, io::Error>(addrs.into_iter()) }
+        });
+        let mut resolver = SpreadResolver::random(inner);
+
+        let mut addrs = resolve(&mut resolver, Name::new("example.com".into()))
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        addrs.sort();
+        assert_eq!(addrs, expected);
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn test_caching_resolver_reuses_addrs() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 80));
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counted = lookups.clone();
+        let inner = tower::service_fn(move |_: Name| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            async move { Ok::<_, io::Error>(vec![addr].into_iter()) }
+        });
+        let mut resolver = CachingResolver::new(inner, Duration::from_secs(60));
+
+        for _ in 0..3 {
+            let addrs = resolve(&mut resolver, Name::new("example.com".into()))
+                .await
+                .unwrap()
+                .collect::<Vec<_>>();
+            assert_eq!(addrs, vec![addr]);
+        }
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        resolve(&mut resolver, Name::new("example.org".into()))
+            .await
+            .unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn test_caching_resolver_stale_while_revalidate() {
+        let a = SocketAddr::from(([10, 0, 0, 1], 80));
+        let b = SocketAddr::from(([10, 0, 0, 2], 80));
+        // the first lookup resolves `a`, then the resolver is down, and
+        // then it resolves `b`
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counted = lookups.clone();
+        let inner = tower::service_fn(move |_: Name| {
+            let n = counted.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match n {
+                    0 => Ok(vec![a].into_iter()),
+                    1 => Err(io::Error::new(io::ErrorKind::NotFound, "resolver down")),
+                    _ => Ok(vec![b].into_iter()),
+                }
+            }
+        });
+        // everything is expired right away, but usable while stale
+        let mut resolver = CachingResolver::new(inner, Duration::from_secs(0))
+            .stale_while_revalidate(Duration::from_secs(60));
+        let name = || Name::new("example.com".into());
+
+        let addrs = resolve(&mut resolver, name()).await.unwrap();
+        assert_eq!(addrs.collect::<Vec<_>>(), vec![a]);
+
+        // the failed refresh keeps the stale address
+        let addrs = resolve(&mut resolver, name()).await.unwrap();
+        assert_eq!(addrs.collect::<Vec<_>>(), vec![a]);
+        while lookups.load(Ordering::SeqCst) < 2 {
+            tokio::task::yield_now().await;
+        }
+        tokio::task::yield_now().await;
+
+        // the next refresh succeeds, and is used once done
+        let addrs = resolve(&mut resolver, name()).await.unwrap();
+        assert_eq!(addrs.collect::<Vec<_>>(), vec![a]);
+        loop {
+            let addrs = resolve(&mut resolver, name()).await.unwrap();
+            if addrs.collect::<Vec<_>>() == vec![b] {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
     #[test]
     fn test_name_from_str() {
         const DOMAIN: &str = "test.example.com";
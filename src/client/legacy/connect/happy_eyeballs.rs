@@ -0,0 +1,263 @@
+//! Happy Eyeballs connection racing.
+//!
+//! This module implements the dual-stack connection racing of
+//! [RFC 8305](https://tools.ietf.org/html/rfc8305), which the
+//! [`HttpConnector`](super::HttpConnector) uses to connect over TCP.
+//!
+//! The addresses are split into a preferred family, that of the first
+//! address, and a fallback family. The preferred addresses are tried in
+//! order. If no connection is established after the fallback delay, the
+//! fallback addresses are tried concurrently, and the first successful
+//! connection wins.
+//!
+//! [`connect`](connect) races plain TCP connections. Custom connectors that
+//! need to dial each address differently can pass their own dial function to
+//! [`connect_with`](connect_with).
+//!
+//! # Example
+//!
+//! ```
+//! # async fn run() -> std::io::Result<()> {
+//! use std::net::SocketAddr;
+//! use hyper_util::client::legacy::connect::happy_eyeballs;
+//!
+//! let addrs: Vec<SocketAddr> = vec![
+//!     "[::1]:8080".parse().unwrap(),
+//!     "127.0.0.1:8080".parse().unwrap(),
+//! ];
+//! let stream = happy_eyeballs::connect(addrs, &happy_eyeballs::Config::new()).await?;
+//! # drop(stream);
+//! # Ok(())
+//! # }
+//! ```
+use std::future::Future;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use futures_util::future::Either;
+use tokio::net::TcpStream;
+use tracing::{debug, trace};
+
+use super::dns::SocketAddrs;
+
+/// Configuration for Happy Eyeballs connection racing.
+#[derive(Clone, Debug)]
+pub struct Config {
+    fallback_delay: Option<Duration>,
+    connect_timeout: Option<Duration>,
+}
+
+// ===== impl Config =====
+
+impl Config {
+    /// Construct a new `Config` with the default settings.
+    pub fn new() -> Config {
+        Config {
+            fallback_delay: Some(Duration::from_millis(300)),
+            connect_timeout: None,
+        }
+    }
+
+    /// Set how long to wait for the preferred family before also trying the
+    /// fallback family.
+    ///
+    /// If `None`, addresses are tried one after the other, in order.
+    ///
+    /// Default is 300 milliseconds.
+    pub fn fallback_delay(&mut self, delay: Option<Duration>) -> &mut Self {
+        self.fallback_delay = delay;
+        self
+    }
+
+    /// Set a timeout for connecting to each family.
+    ///
+    /// The timeout is split evenly between the addresses of a family, so
+    /// that a single unresponsive address doesn't use all of it.
+    ///
+    /// Default is `None`.
+    pub fn connect_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.connect_timeout = timeout;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
+}
+
+/// Connect over TCP to one of `addrs`, racing address families.
+pub async fn connect<I>(addrs: I, config: &Config) -> io::Result<TcpStream>
+where
+    I: IntoIterator<Item = SocketAddr>,
+{
+    connect_with(addrs, config, TcpStream::connect).await
+}
+
+/// Connect to one of `addrs` with `dial`, racing address families.
+///
+/// `dial` is called for each address that is tried, and may be called for
+/// an address of each family concurrently. The error of the last failed
+/// attempt is returned if none succeeds.
+pub async fn connect_with<I, F, Fut, T, E>(addrs: I, config: &Config, dial: F) -> Result<T, E>
+where
+    I: IntoIterator<Item = SocketAddr>,
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: From<io::Error>,
+{
+    let addrs = SocketAddrs::new(addrs.into_iter().collect());
+    race(addrs, None, None, config, dial).await
+}
+
+/// Race the addresses, preferring the family of a local address if only
+/// one is set.
+pub(super) async fn race<F, Fut, T, E>(
+    addrs: SocketAddrs,
+    local_addr_ipv4: Option<Ipv4Addr>,
+    local_addr_ipv6: Option<Ipv6Addr>,
+    config: &Config,
+    dial: F,
+) -> Result<T, E>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: From<io::Error>,
+{
+    let fallback_delay = match config.fallback_delay {
+        Some(delay) => delay,
+        None => return connect_remote(addrs, config.connect_timeout, &dial).await,
+    };
+
+    let (preferred, fallback) = addrs.split_by_preference(local_addr_ipv4, local_addr_ipv6);
+    if fallback.is_empty() {
+        return connect_remote(preferred, config.connect_timeout, &dial).await;
+    }
+
+    let preferred_fut = connect_remote(preferred, config.connect_timeout, &dial);
+    futures_util::pin_mut!(preferred_fut);
+
+    let fallback_fut = connect_remote(fallback, config.connect_timeout, &dial);
+    futures_util::pin_mut!(fallback_fut);
+
+    let fallback_delay = tokio::time::sleep(fallback_delay);
+    futures_util::pin_mut!(fallback_delay);
+
+    let (result, future) = match futures_util::future::select(preferred_fut, fallback_delay).await {
+        Either::Left((result, _fallback_delay)) => (result, Either::Right(fallback_fut)),
+        Either::Right(((), preferred_fut)) => {
+            // Delay is done, start polling both the preferred and the fallback
+            futures_util::future::select(preferred_fut, fallback_fut)
+                .await
+                .factor_first()
+        }
+    };
+
+    if result.is_err() {
+        // Fallback to the remaining future (could be preferred or fallback)
+        // if we get an error
+        future.await
+    } else {
+        result
+    }
+}
+
+async fn connect_remote<F, Fut, T, E>(
+    addrs: SocketAddrs,
+    connect_timeout: Option<Duration>,
+    dial: &F,
+) -> Result<T, E>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: From<io::Error>,
+{
+    let connect_timeout = connect_timeout.and_then(|t| t.checked_div(addrs.len() as u32));
+
+    let mut err = None;
+    for addr in addrs {
+        debug!("connecting to {}", addr);
+        let result = match connect_timeout {
+            Some(dur) => match tokio::time::timeout(dur, dial(addr)).await {
+                Ok(result) => result,
+                Err(e) => Err(io::Error::new(io::ErrorKind::TimedOut, e).into()),
+            },
+            None => dial(addr).await,
+        };
+        match result {
+            Ok(conn) => {
+                debug!("connected to {}", addr);
+                return Ok(conn);
+            }
+            Err(e) => {
+                trace!("connect error for {}", addr);
+                err = Some(e);
+            }
+        }
+    }
+
+    match err {
+        Some(e) => Err(e),
+        None => Err(io::Error::new(io::ErrorKind::NotConnected, "Network unreachable").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use super::{connect, connect_with, Config};
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn connects_to_listener() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let stream = connect(vec![addr], &Config::new()).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn falls_back_after_delay() {
+        let slow: SocketAddr = "[::1]:1".parse().unwrap();
+        let fast: SocketAddr = "127.0.0.1:1".parse().unwrap();
+
+        let mut config = Config::new();
+        config.fallback_delay(Some(Duration::from_millis(50)));
+
+        let dialed = connect_with(vec![slow, fast], &config, |addr| async move {
+            if addr == slow {
+                futures_util::future::pending::<()>().await;
+            }
+            Ok::<_, std::io::Error>(addr)
+        })
+        .await
+        .unwrap();
+        assert_eq!(dialed, fast);
+    }
+
+    #[tokio::test]
+    async fn returns_last_error() {
+        let addrs: Vec<SocketAddr> = vec!["127.0.0.1:1".parse().unwrap()];
+        let mut config = Config::new();
+        config.fallback_delay(None);
+
+        let err = connect_with(addrs, &config, |_| async {
+            Err::<(), std::io::Error>(std::io::ErrorKind::ConnectionRefused.into())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+
+        let err = connect_with(Vec::new(), &config, |_| async {
+            Ok::<_, std::io::Error>(())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotConnected);
+    }
+}
@@ -9,16 +9,16 @@ use std::sync::Arc;
 use std::task::{self, Poll};
 use std::time::Duration;
 
-use futures_util::future::Either;
 use http::uri::{Scheme, Uri};
 use pin_project_lite::pin_project;
 use socket2::TcpKeepalive;
 use tokio::net::{TcpSocket, TcpStream};
-use tokio::time::Sleep;
-use tracing::{debug, trace, warn};
+use tracing::{debug_span, trace, warn, Instrument};
 
 use super::dns::{self, resolve, GaiResolver, Resolve};
-use super::{Connected, Connection};
+use super::happy_eyeballs;
+use super::{Connected, Connection, LivenessProbe};
+use crate::common::trace as tracing_span;
 use crate::rt::TokioIo;
 
 /// A connector for the `http` scheme.
@@ -414,7 +414,9 @@ where
         let addrs = if let Some(addrs) = dns::SocketAddrs::try_parse(host, port) {
             addrs
         } else {
+            let span = tracing_span::span(|| debug_span!("dns", host));
             let addrs = resolve(&mut self.resolver, dns::Name::new(host.into()))
+                .instrument(span)
                 .await
                 .map_err(ConnectError::dns)?;
             let addrs = addrs
@@ -452,6 +454,26 @@ impl Connection for TokioIo<TcpStream> {
             connected
         }
     }
+
+    fn liveness_probe(&self) -> Option<LivenessProbe> {
+        socket_probe(socket2::SockRef::from(self.inner()))
+    }
+}
+
+/// Probe a socket by peeking at it, using a duplicate of its handle.
+///
+/// An idle connection is only alive if the read would block: the peer
+/// has neither closed it nor sent data that nobody asked for.
+pub(super) fn socket_probe(socket: socket2::SockRef<'_>) -> Option<LivenessProbe> {
+    let socket = socket.try_clone().ok()?;
+    socket.set_nonblocking(true).ok()?;
+    Some(LivenessProbe::new(move || {
+        let mut buf = [std::mem::MaybeUninit::uninit(); 1];
+        match socket.peek(&mut buf) {
+            Err(e) => e.kind() == io::ErrorKind::WouldBlock,
+            Ok(_) => false,
+        }
+    }))
 }
 
 impl HttpInfo {
@@ -492,6 +514,8 @@ impl<R: Resolve> Future for HttpConnecting<R> {
     }
 }
 
+const DNS_ERROR: &str = "dns error";
+
 // Not publicly exported (so missing_docs doesn't trigger).
 pub struct ConnectError {
     msg: Box<str>,
@@ -514,7 +538,11 @@ impl ConnectError {
     where
         E: Into<Box<dyn StdError + Send + Sync>>,
     {
-        ConnectError::new("dns error", cause)
+        ConnectError::new(DNS_ERROR, cause)
+    }
+
+    pub(crate) fn is_dns(&self) -> bool {
+        &*self.msg == DNS_ERROR
     }
 
     fn m<S, E>(msg: S) -> impl FnOnce(E) -> ConnectError
@@ -551,6 +579,12 @@ impl fmt::Display for ConnectError {
     }
 }
 
+impl From<io::Error> for ConnectError {
+    fn from(err: io::Error) -> ConnectError {
+        ConnectError::new("tcp connect error", err)
+    }
+}
+
 impl StdError for ConnectError {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         self.cause.as_ref().map(|e| &**e as _)
@@ -558,87 +592,33 @@ impl StdError for ConnectError {
 }
 
 struct ConnectingTcp<'a> {
-    preferred: ConnectingTcpRemote,
-    fallback: Option<ConnectingTcpFallback>,
+    addrs: dns::SocketAddrs,
     config: &'a Config,
 }
 
 impl<'a> ConnectingTcp<'a> {
-    fn new(remote_addrs: dns::SocketAddrs, config: &'a Config) -> Self {
-        if let Some(fallback_timeout) = config.happy_eyeballs_timeout {
-            let (preferred_addrs, fallback_addrs) = remote_addrs
-                .split_by_preference(config.local_address_ipv4, config.local_address_ipv6);
-            if fallback_addrs.is_empty() {
-                return ConnectingTcp {
-                    preferred: ConnectingTcpRemote::new(preferred_addrs, config.connect_timeout),
-                    fallback: None,
-                    config,
-                };
-            }
-
-            ConnectingTcp {
-                preferred: ConnectingTcpRemote::new(preferred_addrs, config.connect_timeout),
-                fallback: Some(ConnectingTcpFallback {
-                    delay: tokio::time::sleep(fallback_timeout),
-                    remote: ConnectingTcpRemote::new(fallback_addrs, config.connect_timeout),
-                }),
-                config,
-            }
-        } else {
-            ConnectingTcp {
-                preferred: ConnectingTcpRemote::new(remote_addrs, config.connect_timeout),
-                fallback: None,
-                config,
-            }
-        }
-    }
-}
-
-struct ConnectingTcpFallback {
-    delay: Sleep,
-    remote: ConnectingTcpRemote,
-}
-
-struct ConnectingTcpRemote {
-    addrs: dns::SocketAddrs,
-    connect_timeout: Option<Duration>,
-}
-
-impl ConnectingTcpRemote {
-    fn new(addrs: dns::SocketAddrs, connect_timeout: Option<Duration>) -> Self {
-        let connect_timeout = connect_timeout.and_then(|t| t.checked_div(addrs.len() as u32));
-
-        Self {
-            addrs,
-            connect_timeout,
-        }
+    fn new(addrs: dns::SocketAddrs, config: &'a Config) -> Self {
+        ConnectingTcp { addrs, config }
     }
-}
 
-impl ConnectingTcpRemote {
-    async fn connect(&mut self, config: &Config) -> Result<TcpStream, ConnectError> {
-        let mut err = None;
-        for addr in &mut self.addrs {
-            debug!("connecting to {}", addr);
-            match connect(&addr, config, self.connect_timeout)?.await {
-                Ok(tcp) => {
-                    debug!("connected to {}", addr);
-                    return Ok(tcp);
-                }
-                Err(e) => {
-                    trace!("connect error for {}: {:?}", addr, e);
-                    err = Some(e);
-                }
-            }
-        }
-
-        match err {
-            Some(e) => Err(e),
-            None => Err(ConnectError::new(
-                "tcp connect error",
-                std::io::Error::new(std::io::ErrorKind::NotConnected, "Network unreachable"),
-            )),
-        }
+    async fn connect(self) -> Result<TcpStream, ConnectError> {
+        let config = self.config;
+        let mut happy_eyeballs = happy_eyeballs::Config::new();
+        happy_eyeballs
+            .fallback_delay(config.happy_eyeballs_timeout)
+            .connect_timeout(config.connect_timeout);
+
+        happy_eyeballs::race(
+            self.addrs,
+            config.local_address_ipv4,
+            config.local_address_ipv6,
+            &happy_eyeballs,
+            |addr| {
+                let connecting = connect(&addr, config);
+                async move { connecting?.await }
+            },
+        )
+        .await
     }
 }
 
@@ -673,7 +653,6 @@ fn bind_local_address(
 fn connect(
     addr: &SocketAddr,
     config: &Config,
-    connect_timeout: Option<Duration>,
 ) -> Result<impl Future<Output = Result<TcpStream, ConnectError>>, ConnectError> {
     // TODO(eliza): if Tokio's `TcpSocket` gains support for setting the
     // keepalive timeout, it would be nice to use that instead of socket2,
@@ -751,56 +730,7 @@ fn connect(
     }
 
     let connect = socket.connect(*addr);
-    Ok(async move {
-        match connect_timeout {
-            Some(dur) => match tokio::time::timeout(dur, connect).await {
-                Ok(Ok(s)) => Ok(s),
-                Ok(Err(e)) => Err(e),
-                Err(e) => Err(io::Error::new(io::ErrorKind::TimedOut, e)),
-            },
-            None => connect.await,
-        }
-        .map_err(ConnectError::m("tcp connect error"))
-    })
-}
-
-impl ConnectingTcp<'_> {
-    async fn connect(mut self) -> Result<TcpStream, ConnectError> {
-        match self.fallback {
-            None => self.preferred.connect(self.config).await,
-            Some(mut fallback) => {
-                let preferred_fut = self.preferred.connect(self.config);
-                futures_util::pin_mut!(preferred_fut);
-
-                let fallback_fut = fallback.remote.connect(self.config);
-                futures_util::pin_mut!(fallback_fut);
-
-                let fallback_delay = fallback.delay;
-                futures_util::pin_mut!(fallback_delay);
-
-                let (result, future) =
-                    match futures_util::future::select(preferred_fut, fallback_delay).await {
-                        Either::Left((result, _fallback_delay)) => {
-                            (result, Either::Right(fallback_fut))
-                        }
-                        Either::Right(((), preferred_fut)) => {
-                            // Delay is done, start polling both the preferred and the fallback
-                            futures_util::future::select(preferred_fut, fallback_fut)
-                                .await
-                                .factor_first()
-                        }
-                    };
-
-                if result.is_err() {
-                    // Fallback to the remaining future (could be preferred or fallback)
-                    // if we get an error
-                    future.await
-                } else {
-                    result
-                }
-            }
-        }
-    }
+    Ok(async move { connect.await.map_err(ConnectError::m("tcp connect error")) })
 }
 
 #[cfg(test)]
@@ -879,6 +809,43 @@ mod tests {
         assert_eq!(&*err.msg, super::INVALID_MISSING_SCHEME);
     }
 
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test]
+    async fn liveness_probe() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        use super::super::Connection;
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dst = format!("http://{}", server.local_addr().unwrap());
+
+        let alive = connect(HttpConnector::new(), dst.parse().unwrap())
+            .await
+            .unwrap();
+        let (_stream, _) = server.accept().unwrap();
+        assert!(alive.liveness_probe().unwrap().is_alive());
+
+        let closed = connect(HttpConnector::new(), dst.parse().unwrap())
+            .await
+            .unwrap();
+        drop(server.accept().unwrap());
+        let probe = closed.liveness_probe().unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!probe.is_alive());
+
+        let unsolicited = connect(HttpConnector::new(), dst.parse().unwrap())
+            .await
+            .unwrap();
+        let (mut stream, _) = server.accept().unwrap();
+        stream
+            .write_all(b"HTTP/1.1 408 Request Timeout\r\n\r\n")
+            .unwrap();
+        let probe = unsolicited.liveness_probe().unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!probe.is_alive());
+    }
+
     // NOTE: pnet crate that we use in this test doesn't compile on Windows
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     #[cfg_attr(miri, ignore)]
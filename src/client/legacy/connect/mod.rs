@@ -4,6 +4,15 @@
 //!
 //! - A default [`HttpConnector`][] that does DNS resolution and establishes
 //!   connections over TCP.
+//! - A `UnixConnector` that establishes connections over Unix domain sockets.
+//! - An HTTPS connector using `rustls`, with the `rustls` feature.
+//! - An HTTPS connector using `native-tls`, with the `native-tls` feature.
+//! - Happy Eyeballs connection racing for custom connectors, in
+//!   [`happy_eyeballs`].
+//! - A `TimeoutConnector` that bounds how long any other connector may take.
+//! - A `RetryConnector` that retries failed connections with backoff.
+//! - Connectors that go through HTTP and SOCKS5 proxies, in [`proxy`].
+//! - A `ConnectorBuilder` that stacks TCP, proxy, and TLS connectors.
 //! - Types to build custom connectors.
 //!
 //! # Connectors
@@ -65,14 +74,34 @@
 use std::fmt;
 
 use ::http::Extensions;
+use bytes::Bytes;
 
+pub use self::builder::ConnectorBuilder;
+#[cfg(feature = "tokio")]
+pub(crate) use self::http::ConnectError;
 #[cfg(feature = "tokio")]
 pub use self::http::{HttpConnector, HttpInfo};
+pub use self::retry::{ErrorClass, RetryConnecting, RetryConnector, RetryPolicy};
+pub use self::timeout::{ConnectTimeout, TimeoutConnecting, TimeoutConnector};
+#[cfg(all(unix, feature = "tokio"))]
+pub use self::unix::{UnixConnector, UnixInfo, UNIX_SCHEME};
 
+mod builder;
 #[cfg(feature = "tokio")]
 pub mod dns;
 #[cfg(feature = "tokio")]
+pub mod happy_eyeballs;
+#[cfg(feature = "tokio")]
 mod http;
+#[cfg(feature = "native-tls")]
+pub mod native_tls;
+pub mod proxy;
+mod retry;
+#[cfg(feature = "rustls")]
+pub mod rustls;
+mod timeout;
+#[cfg(all(unix, feature = "tokio"))]
+mod unix;
 
 pub use self::sealed::Connect;
 
@@ -80,6 +109,47 @@ pub use self::sealed::Connect;
 pub trait Connection {
     /// Return metadata describing the connection.
     fn connected(&self) -> Connected;
+
+    /// Return a probe to check if the connection is still usable, without
+    /// taking it away from its owner.
+    ///
+    /// This is only called if the `Client` checks liveness before reusing
+    /// pooled connections. The default returns `None`, which means the
+    /// connection can't be checked.
+    fn liveness_probe(&self) -> Option<LivenessProbe> {
+        None
+    }
+}
+
+/// Checks if an idle connection is still usable.
+///
+/// For example, a probe of a TCP connection can peek at the socket, to see
+/// if the peer has closed it, or unexpectedly sent data, while idle.
+pub struct LivenessProbe {
+    probe: Box<dyn Fn() -> bool + Send + Sync>,
+}
+
+impl LivenessProbe {
+    /// Create a probe from a function returning if the connection is alive.
+    pub fn new<F>(probe: F) -> LivenessProbe
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        LivenessProbe {
+            probe: Box::new(probe),
+        }
+    }
+
+    /// Check if the connection is still alive.
+    pub fn is_alive(&self) -> bool {
+        (self.probe)()
+    }
+}
+
+impl fmt::Debug for LivenessProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LivenessProbe").finish()
+    }
 }
 
 /// Extra information about the connected transport.
@@ -95,9 +165,10 @@ pub struct Connected {
 
 pub(super) struct Extra(Box<dyn ExtraInner>);
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub(super) enum Alpn {
     H2,
+    Other(Bytes),
     None,
 }
 
@@ -140,6 +211,31 @@ impl Connected {
     }
 
     /// Set extra connection information to be set in the extensions of every `Response`.
+    ///
+    /// Any number of values can be attached, as long as their types differ.
+    /// A connector wrapping another connector can add its own values to the
+    /// `Connected` of the inner connection, and the values of both will be
+    /// available:
+    ///
+    /// ```
+    /// # use hyper_util::client::legacy::connect::Connected;
+    /// #[derive(Clone)]
+    /// struct ProxyInfo(&'static str);
+    ///
+    /// #[derive(Clone)]
+    /// struct SourceInfo(&'static str);
+    ///
+    /// let connected = Connected::new()
+    ///     .extra(ProxyInfo("socks5://10.0.0.1"))
+    ///     .extra(SourceInfo("10.0.0.2"));
+    ///
+    /// assert_eq!(connected.get_extra::<ProxyInfo>().unwrap().0, "socks5://10.0.0.1");
+    /// assert_eq!(connected.get_extra::<SourceInfo>().unwrap().0, "10.0.0.2");
+    /// ```
+    ///
+    /// The `Client` inserts the values into the extensions of each `Response`
+    /// received over this connection, where they can be read with
+    /// `res.extensions().get::<T>()`.
     pub fn extra<T: Clone + Send + Sync + 'static>(mut self, extra: T) -> Connected {
         if let Some(prev) = self.extra {
             self.extra = Some(Extra(Box::new(ExtraChain(prev.0, extra))));
@@ -156,6 +252,14 @@ impl Connected {
         }
     }
 
+    /// Returns a copy of the extra connection information of type `T`, if
+    /// it was set.
+    pub fn get_extra<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        let mut extensions = Extensions::new();
+        self.get_extras(&mut extensions);
+        extensions.remove::<T>()
+    }
+
     /// Set that the connected transport negotiated HTTP/2 as its next protocol.
     pub fn negotiated_h2(mut self) -> Connected {
         self.alpn = Alpn::H2;
@@ -167,12 +271,38 @@ impl Connected {
         self.alpn == Alpn::H2
     }
 
+    /// Set the protocol the connected transport negotiated through ALPN.
+    ///
+    /// Connectors that perform a TLS handshake should report the protocol
+    /// selected by the server here. If it is `h2`, the `Client` will use
+    /// HTTP/2 on this connection, even if it isn't configured as
+    /// `http2_only`.
+    pub fn negotiated_alpn(mut self, protocol: impl Into<Bytes>) -> Connected {
+        let protocol = protocol.into();
+        self.alpn = if protocol == "h2" {
+            Alpn::H2
+        } else {
+            Alpn::Other(protocol)
+        };
+        self
+    }
+
+    /// Returns the protocol the connected transport negotiated through ALPN,
+    /// if any.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        match self.alpn {
+            Alpn::H2 => Some(b"h2"),
+            Alpn::Other(ref protocol) => Some(protocol),
+            Alpn::None => None,
+        }
+    }
+
     // Don't public expose that `Connected` is `Clone`, unsure if we want to
     // keep that contract...
     #[cfg(feature = "http2")]
     pub(super) fn clone(&self) -> Connected {
         Connected {
-            alpn: self.alpn,
+            alpn: self.alpn.clone(),
             is_proxied: self.is_proxied,
             extra: self.extra.clone(),
         }
@@ -387,4 +517,28 @@ mod tests {
         assert_eq!(ex2.get::<Ex1>(), Some(&Ex1(99)));
         assert_eq!(ex2.get::<Ex2>(), Some(&Ex2("hiccup")));
     }
+
+    #[test]
+    fn test_connected_get_extra() {
+        let c1 = Connected::new().extra(Ex1(7)).extra(Ex2("seven"));
+
+        assert_eq!(c1.get_extra::<Ex1>(), Some(Ex1(7)));
+        assert_eq!(c1.get_extra::<Ex2>(), Some(Ex2("seven")));
+        assert_eq!(c1.get_extra::<Ex3>(), None);
+    }
+
+    #[test]
+    fn test_connected_alpn() {
+        let c1 = Connected::new();
+        assert_eq!(c1.alpn_protocol(), None);
+        assert!(!c1.is_negotiated_h2());
+
+        let c2 = Connected::new().negotiated_alpn(&b"h2"[..]);
+        assert_eq!(c2.alpn_protocol(), Some(&b"h2"[..]));
+        assert!(c2.is_negotiated_h2());
+
+        let c3 = Connected::new().negotiated_alpn(&b"http/1.1"[..]);
+        assert_eq!(c3.alpn_protocol(), Some(&b"http/1.1"[..]));
+        assert!(!c3.is_negotiated_h2());
+    }
 }
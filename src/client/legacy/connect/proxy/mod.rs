@@ -0,0 +1,82 @@
+//! Proxy connectors.
+//!
+//! This module contains:
+//!
+//! - A [`Tunnel`](Tunnel) that establishes a connection through an HTTP
+//!   proxy, using the `CONNECT` method.
+//! - A [`SocksV5`](SocksV5) that establishes a connection through a SOCKS5
+//!   proxy.
+//! - A [`ProxyProtocol`](ProxyProtocol) that sends a PROXY protocol header
+//!   with the address of the original client, for L4 backends.
+//!
+//! The `Tunnel` and `SocksV5` wrap another connector, which is used to reach
+//! the proxy itself, and yield that connector's transport once the proxy has
+//! connected it to the destination. They can in turn be wrapped by a TLS connector, so that TLS
+//! is negotiated with the destination through the proxy. See
+//! [`ConnectorBuilder`](super::ConnectorBuilder) to assemble such stacks.
+use std::io;
+use std::pin::Pin;
+
+use futures_util::future::poll_fn;
+use hyper::rt::{Read, ReadBuf, Write};
+
+pub use self::protocol::{ProxyAddrs, ProxyProtocol, ProxyProtocolConnecting};
+pub use self::socks::{SocksError, SocksV5};
+pub use self::tunnel::{Tunnel, TunnelError};
+
+pub(crate) mod protocol;
+mod socks;
+mod tunnel;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+async fn read<T>(io: &mut T, buf: &mut [u8]) -> io::Result<usize>
+where
+    T: Read + Unpin,
+{
+    poll_fn(|cx| {
+        let mut buf = ReadBuf::new(&mut *buf);
+        futures_util::ready!(Pin::new(&mut *io).poll_read(cx, buf.unfilled()))?;
+        std::task::Poll::Ready(Ok(buf.filled().len()))
+    })
+    .await
+}
+
+async fn read_exact<T>(io: &mut T, mut buf: &mut [u8]) -> io::Result<()>
+where
+    T: Read + Unpin,
+{
+    while !buf.is_empty() {
+        let n = read(io, buf).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf = &mut buf[n..];
+    }
+    Ok(())
+}
+
+async fn write_all<T>(io: &mut T, mut buf: &[u8]) -> io::Result<()>
+where
+    T: Write + Unpin,
+{
+    while !buf.is_empty() {
+        let n = poll_fn(|cx| Pin::new(&mut *io).poll_write(cx, buf)).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        buf = &buf[n..];
+    }
+    poll_fn(|cx| Pin::new(&mut *io).poll_flush(cx)).await
+}
+
+/// Get the host and port of a destination, defaulting the port by scheme.
+fn host_port(dst: &http::Uri) -> Option<(&str, u16)> {
+    let host = dst.host()?;
+    let port = match dst.port_u16() {
+        Some(port) => port,
+        None if dst.scheme() == Some(&http::uri::Scheme::HTTPS) => 443,
+        None => 80,
+    };
+    Some((host, port))
+}
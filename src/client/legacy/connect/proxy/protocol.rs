@@ -0,0 +1,277 @@
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{self, Poll};
+
+use http::Uri;
+use hyper::rt::{Read, Write};
+use tracing::trace;
+
+use super::{write_all, BoxError};
+
+const SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+const CMD_LOCAL: u8 = 0x20;
+const CMD_PROXY: u8 = 0x21;
+const FAM_UNSPEC: u8 = 0x00;
+const FAM_TCP4: u8 = 0x11;
+const FAM_TCP6: u8 = 0x21;
+
+/// A connector that sends a PROXY protocol v2 header on every connection of
+/// an inner connector.
+///
+/// Some L4 backends expect the address of the original client in a PROXY
+/// protocol header, sent before anything else on the connection. Insert a
+/// [`ProxyAddrs`](ProxyAddrs) into the extensions of a request to send its
+/// addresses; connections for requests without one send a `LOCAL` header,
+/// which carries no addresses.
+///
+/// Requests with different `ProxyAddrs` never share pooled connections,
+/// since the header is only sent once, when connecting.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run(client_addr: std::net::SocketAddr) {
+/// use http_body_util::Empty;
+/// use hyper::body::Bytes;
+/// use hyper_util::client::legacy::connect::proxy::{ProxyAddrs, ProxyProtocol};
+/// use hyper_util::client::legacy::connect::HttpConnector;
+/// use hyper_util::client::legacy::Client;
+/// use hyper_util::rt::TokioExecutor;
+///
+/// let connector = ProxyProtocol::new(HttpConnector::new());
+/// let client = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(connector);
+///
+/// let mut req = http::Request::new(Empty::new());
+/// *req.uri_mut() = "http://backend.local".parse().unwrap();
+/// req.extensions_mut().insert(ProxyAddrs::new(client_addr));
+/// let fut = client.request(req);
+/// # drop(fut);
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct ProxyProtocol<C> {
+    inner: C,
+}
+
+/// The addresses a [`ProxyProtocol`](ProxyProtocol) sends in its header,
+/// as a request extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProxyAddrs {
+    source: SocketAddr,
+    destination: Option<SocketAddr>,
+}
+
+thread_local! {
+    // The addresses of the request the client is connecting for, set while
+    // it polls the connector, since connectors only get the destination.
+    static CURRENT: Cell<Option<ProxyAddrs>> = const { Cell::new(None) };
+}
+
+/// Call `f` with `addrs` as the addresses any `ProxyProtocol` called from it
+/// sends.
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub(crate) fn scope<R>(addrs: Option<ProxyAddrs>, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<ProxyAddrs>);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            CURRENT.with(|current| current.set(self.0));
+        }
+    }
+
+    let _reset = Reset(CURRENT.with(|current| current.replace(addrs)));
+    f()
+}
+
+// ===== impl ProxyProtocol =====
+
+impl<C> ProxyProtocol<C> {
+    /// Wrap a connector, sending a PROXY protocol header on its connections.
+    pub fn new(inner: C) -> Self {
+        ProxyProtocol { inner }
+    }
+
+    /// Get a reference to the inner connector.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner connector.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner connector.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C> tower_service::Service<Uri> for ProxyProtocol<C>
+where
+    C: tower_service::Service<Uri>,
+    C::Future: Send + 'static,
+    C::Response: Read + Write + Unpin + Send + 'static,
+    C::Error: Into<BoxError>,
+{
+    type Response = C::Response;
+    type Error = BoxError;
+    type Future = ProxyProtocolConnecting<C::Response>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let header = encode(CURRENT.with(Cell::get));
+        let connecting = self.inner.call(dst);
+        ProxyProtocolConnecting {
+            fut: Box::pin(async move {
+                let mut io = connecting.await.map_err(Into::into)?;
+                trace!("sending PROXY header");
+                write_all(&mut io, &header).await?;
+                Ok(io)
+            }),
+        }
+    }
+}
+
+/// A future returned by the `ProxyProtocol` when connecting.
+#[must_use = "futures do nothing unless polled"]
+pub struct ProxyProtocolConnecting<T> {
+    fut: Pin<Box<dyn Future<Output = Result<T, BoxError>> + Send>>,
+}
+
+impl<T> Future for ProxyProtocolConnecting<T> {
+    type Output = Result<T, BoxError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.fut.as_mut().poll(cx)
+    }
+}
+
+impl<T> fmt::Debug for ProxyProtocolConnecting<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyProtocolConnecting").finish()
+    }
+}
+
+// ===== impl ProxyAddrs =====
+
+impl ProxyAddrs {
+    /// The addresses of a connection from the client at `source`.
+    ///
+    /// Unless set with [`with_destination`](ProxyAddrs::with_destination),
+    /// the unspecified address is sent as the destination.
+    pub fn new(source: SocketAddr) -> Self {
+        ProxyAddrs {
+            source,
+            destination: None,
+        }
+    }
+
+    /// Set the address the client connected to, such as the local address
+    /// of the server connection.
+    pub fn with_destination(mut self, destination: SocketAddr) -> Self {
+        self.destination = Some(destination);
+        self
+    }
+
+    /// The address of the client.
+    pub fn source(&self) -> SocketAddr {
+        self.source
+    }
+
+    /// The address the client connected to, if set.
+    pub fn destination(&self) -> Option<SocketAddr> {
+        self.destination
+    }
+}
+
+fn encode(addrs: Option<ProxyAddrs>) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(16 + 36);
+    buf.extend_from_slice(SIGNATURE);
+    let addrs = match addrs {
+        Some(addrs) => addrs,
+        None => {
+            buf.extend_from_slice(&[CMD_LOCAL, FAM_UNSPEC, 0, 0]);
+            return buf;
+        }
+    };
+
+    let src = addrs.source;
+    let dst = addrs.destination.unwrap_or_else(|| {
+        let ip = match src.ip() {
+            IpAddr::V4(_) => IpAddr::from([0u8; 4]),
+            IpAddr::V6(_) => IpAddr::from([0u8; 16]),
+        };
+        SocketAddr::new(ip, 0)
+    });
+    buf.push(CMD_PROXY);
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            buf.extend_from_slice(&[FAM_TCP4, 0, 12]);
+            buf.extend_from_slice(&s.octets());
+            buf.extend_from_slice(&d.octets());
+        }
+        // Mixed families are both sent as IPv6, IPv4 ones mapped.
+        (s, d) => {
+            buf.extend_from_slice(&[FAM_TCP6, 0, 36]);
+            buf.extend_from_slice(&to_v6(s).octets());
+            buf.extend_from_slice(&to_v6(d).octets());
+        }
+    }
+    buf.extend_from_slice(&src.port().to_be_bytes());
+    buf.extend_from_slice(&dst.port().to_be_bytes());
+    buf
+}
+
+fn to_v6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, ProxyAddrs};
+
+    #[test]
+    fn encodes_ipv4() {
+        let addrs = ProxyAddrs::new("10.0.0.1:5000".parse().unwrap())
+            .with_destination("10.0.0.2:443".parse().unwrap());
+        assert_eq!(
+            encode(Some(addrs)),
+            b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c\
+              \x0a\x00\x00\x01\x0a\x00\x00\x02\x13\x88\x01\xbb"
+        );
+    }
+
+    #[test]
+    fn encodes_mixed_as_ipv6() {
+        let addrs = ProxyAddrs::new("[::1]:80".parse().unwrap())
+            .with_destination("127.0.0.1:81".parse().unwrap());
+        let header = encode(Some(addrs));
+        assert_eq!(&header[12..16], b"\x21\x21\x00\x24");
+        assert_eq!(header.len(), 16 + 36);
+        assert_eq!(
+            &header[32..48],
+            &"::ffff:127.0.0.1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets()
+        );
+        assert_eq!(&header[48..], b"\x00\x50\x00\x51");
+    }
+
+    #[test]
+    fn encodes_local_without_addrs() {
+        assert_eq!(encode(None), b"\r\n\r\n\0\r\nQUIT\n\x20\x00\x00\x00");
+    }
+}
@@ -0,0 +1,390 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::task::{self, Poll};
+
+use http::Uri;
+use hyper::rt::{Read, Write};
+use tracing::debug;
+
+use super::{host_port, read_exact, write_all, BoxError};
+
+const VERSION: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const USER_PASS: u8 = 0x02;
+const NO_ACCEPTABLE: u8 = 0xff;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// A connector that connects through a SOCKS5 proxy.
+///
+/// The inner connector is used to connect to the proxy. Once the proxy has
+/// connected to the destination, its transport is returned.
+///
+/// Domain names are resolved by the proxy.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use hyper_util::client::legacy::connect::proxy::SocksV5;
+/// use hyper_util::client::legacy::connect::HttpConnector;
+///
+/// let mut http = HttpConnector::new();
+/// http.enforce_http(false);
+///
+/// let proxy = "socks5://proxy.local:1080".parse().unwrap();
+/// let connector = SocksV5::new(proxy, http).with_auth("user".into(), "pass".into());
+/// # drop(connector);
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct SocksV5<C> {
+    inner: C,
+    proxy_dst: Uri,
+    auth: Option<(String, String)>,
+}
+
+/// An error returned by a [`SocksV5`](SocksV5).
+pub struct SocksError {
+    kind: Kind,
+    source: Option<BoxError>,
+}
+
+#[derive(Debug)]
+enum Kind {
+    ConnectFailed,
+    Io,
+    MissingHost,
+    HostTooLong,
+    AuthTooLong,
+    NoAcceptableAuth,
+    AuthFailed,
+    Malformed,
+    Reply(u8),
+}
+
+// ===== impl SocksV5 =====
+
+impl<C> SocksV5<C> {
+    /// Create a new `SocksV5` to the proxy at `proxy_dst`, connecting to it
+    /// with `connector`.
+    pub fn new(proxy_dst: Uri, connector: C) -> Self {
+        SocksV5 {
+            inner: connector,
+            proxy_dst,
+            auth: None,
+        }
+    }
+
+    /// Authenticate to the proxy with a username and password.
+    ///
+    /// Both must be at most 255 bytes long.
+    pub fn with_auth(mut self, user: String, pass: String) -> Self {
+        self.auth = Some((user, pass));
+        self
+    }
+
+    /// Get a reference to the inner connector.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner connector.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner connector.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: fmt::Debug> fmt::Debug for SocksV5<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SocksV5")
+            .field("inner", &self.inner)
+            .field("proxy_dst", &self.proxy_dst)
+            .field("auth", &self.auth.is_some())
+            .finish()
+    }
+}
+
+impl<C> tower_service::Service<Uri> for SocksV5<C>
+where
+    C: tower_service::Service<Uri>,
+    C::Future: Send + 'static,
+    C::Response: Read + Write + Unpin + Send + 'static,
+    C::Error: Into<BoxError>,
+{
+    type Response = C::Response;
+    type Error = SocksError;
+    type Future = SocksConnecting<C::Response>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner
+            .poll_ready(cx)
+            .map_err(|e| SocksError::new(Kind::ConnectFailed).with(e))
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let (host, port) = match host_port(&dst) {
+            Some((host, port)) => (host.to_owned(), port),
+            None => {
+                return SocksConnecting {
+                    fut: Box::pin(async { Err(SocksError::new(Kind::MissingHost)) }),
+                }
+            }
+        };
+
+        let connecting = self.inner.call(self.proxy_dst.clone());
+        let auth = self.auth.clone();
+        SocksConnecting {
+            fut: Box::pin(async move {
+                let mut io = connecting
+                    .await
+                    .map_err(|e| SocksError::new(Kind::ConnectFailed).with(e))?;
+                handshake(&mut io, &host, port, auth.as_ref()).await?;
+                Ok(io)
+            }),
+        }
+    }
+}
+
+async fn handshake<T>(
+    io: &mut T,
+    host: &str,
+    port: u16,
+    auth: Option<&(String, String)>,
+) -> Result<(), SocksError>
+where
+    T: Read + Write + Unpin,
+{
+    debug!("socks5 connecting to {}:{}", host, port);
+
+    // greeting
+    let greeting: &[u8] = if auth.is_some() {
+        &[VERSION, 2, NO_AUTH, USER_PASS]
+    } else {
+        &[VERSION, 1, NO_AUTH]
+    };
+    write_all(io, greeting).await.map_err(SocksError::io)?;
+
+    let mut buf = [0; 2];
+    read_exact(io, &mut buf).await.map_err(SocksError::io)?;
+    match (buf, auth) {
+        ([VERSION, NO_AUTH], _) => (),
+        ([VERSION, USER_PASS], Some((user, pass))) => {
+            if user.len() > 255 || pass.len() > 255 {
+                return Err(SocksError::new(Kind::AuthTooLong));
+            }
+            let mut req = vec![0x01, user.len() as u8];
+            req.extend_from_slice(user.as_bytes());
+            req.push(pass.len() as u8);
+            req.extend_from_slice(pass.as_bytes());
+            write_all(io, &req).await.map_err(SocksError::io)?;
+
+            read_exact(io, &mut buf).await.map_err(SocksError::io)?;
+            if buf[1] != 0x00 {
+                return Err(SocksError::new(Kind::AuthFailed));
+            }
+        }
+        ([VERSION, NO_ACCEPTABLE], _) => return Err(SocksError::new(Kind::NoAcceptableAuth)),
+        _ => return Err(SocksError::new(Kind::Malformed)),
+    }
+
+    // connect request
+    let mut req = vec![VERSION, CMD_CONNECT, 0x00];
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(IpAddr::V4(ip)) => {
+            req.push(ATYP_IPV4);
+            req.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            req.push(ATYP_IPV6);
+            req.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err(SocksError::new(Kind::HostTooLong));
+            }
+            req.push(ATYP_DOMAIN);
+            req.push(host.len() as u8);
+            req.extend_from_slice(host.as_bytes());
+        }
+    }
+    req.extend_from_slice(&port.to_be_bytes());
+    write_all(io, &req).await.map_err(SocksError::io)?;
+
+    // reply
+    let mut buf = [0; 4];
+    read_exact(io, &mut buf).await.map_err(SocksError::io)?;
+    if buf[0] != VERSION {
+        return Err(SocksError::new(Kind::Malformed));
+    }
+    if buf[1] != 0x00 {
+        return Err(SocksError::new(Kind::Reply(buf[1])));
+    }
+
+    // skip the bound address
+    let len = match buf[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0; 1];
+            read_exact(io, &mut len).await.map_err(SocksError::io)?;
+            len[0] as usize
+        }
+        _ => return Err(SocksError::new(Kind::Malformed)),
+    };
+    let mut bound = vec![0; len + 2];
+    read_exact(io, &mut bound).await.map_err(SocksError::io)?;
+
+    Ok(())
+}
+
+/// A future returned by the `SocksV5` when connecting.
+#[must_use = "futures do nothing unless polled"]
+#[allow(missing_debug_implementations)]
+pub struct SocksConnecting<T> {
+    fut: Pin<Box<dyn Future<Output = Result<T, SocksError>> + Send>>,
+}
+
+impl<T> Future for SocksConnecting<T> {
+    type Output = Result<T, SocksError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.fut.as_mut().poll(cx)
+    }
+}
+
+// ===== impl SocksError =====
+
+impl SocksError {
+    fn new(kind: Kind) -> Self {
+        SocksError { kind, source: None }
+    }
+
+    fn io(err: std::io::Error) -> Self {
+        SocksError::new(Kind::Io).with(err)
+    }
+
+    fn with<E: Into<BoxError>>(mut self, source: E) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+}
+
+impl fmt::Debug for SocksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_tuple("hyper_util::client::legacy::connect::proxy::SocksError");
+        f.field(&self.kind);
+        if let Some(ref source) = self.source {
+            f.field(source);
+        }
+        f.finish()
+    }
+}
+
+impl fmt::Display for SocksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Kind::ConnectFailed => f.write_str("failed to connect to proxy"),
+            Kind::Io => f.write_str("io error during socks handshake"),
+            Kind::MissingHost => f.write_str("missing destination host"),
+            Kind::HostTooLong => f.write_str("destination host too long"),
+            Kind::AuthTooLong => f.write_str("socks username or password too long"),
+            Kind::NoAcceptableAuth => f.write_str("no acceptable socks auth method"),
+            Kind::AuthFailed => f.write_str("socks authentication failed"),
+            Kind::Malformed => f.write_str("malformed socks response"),
+            Kind::Reply(code) => write!(f, "socks proxy replied with error code {}", code),
+        }
+    }
+}
+
+impl StdError for SocksError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source.as_ref().map(|e| &**e as _)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::super::super::sealed::{Connect, Internal};
+    use super::super::super::HttpConnector;
+    use super::SocksV5;
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn socks_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut greeting = [0; 4];
+            stream.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 2, 0, 2]);
+            stream.write_all(&[5, 2]).await.unwrap();
+
+            let mut auth = [0; 11];
+            stream.read_exact(&mut auth).await.unwrap();
+            assert_eq!(&auth, b"\x01\x04user\x04pass");
+            stream.write_all(&[1, 0]).await.unwrap();
+
+            let mut req = [0; 15];
+            stream.read_exact(&mut req).await.unwrap();
+            assert_eq!(&req, b"\x05\x01\x00\x03\x08hyper.rs\x01\xbb");
+            stream
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0x1f, 0x90])
+                .await
+                .unwrap();
+        });
+
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        let proxy = format!("socks5://{}", addr).parse().unwrap();
+        let socks = SocksV5::new(proxy, http).with_auth("user".into(), "pass".into());
+
+        socks
+            .connect(Internal, "https://hyper.rs".parse().unwrap())
+            .await
+            .unwrap();
+        server.await.unwrap();
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn socks_reply_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 64];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream.write_all(&[5, 0]).await.unwrap();
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream.write_all(&[5, 5, 0, 1]).await.unwrap();
+        });
+
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        let proxy = format!("socks5://{}", addr).parse().unwrap();
+
+        let err = SocksV5::new(proxy, http)
+            .connect(Internal, "http://127.0.0.1:80".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "socks proxy replied with error code 5");
+    }
+}
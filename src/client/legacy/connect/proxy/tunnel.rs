@@ -0,0 +1,537 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{self, Poll};
+
+use http::header::{HeaderMap, HeaderValue, PROXY_AUTHORIZATION};
+use http::Uri;
+use hyper::rt::{Read, Write};
+use tower::ServiceExt;
+use tracing::debug;
+
+use super::{host_port, read, write_all, BoxError};
+
+/// A connector that tunnels through an HTTP proxy using the `CONNECT`
+/// method.
+///
+/// The inner connector is used to connect to the proxy. Once the proxy has
+/// accepted the `CONNECT` request, its transport is returned, now connected
+/// to the destination.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use hyper_util::client::legacy::connect::proxy::Tunnel;
+/// use hyper_util::client::legacy::connect::HttpConnector;
+///
+/// let proxy = "http://proxy.local:3128".parse().unwrap();
+/// let connector = Tunnel::new(proxy, HttpConnector::new());
+/// # drop(connector);
+/// # }
+/// # fn main() {}
+/// ```
+///
+/// # Authentication
+///
+/// Credentials set with [`with_basic_auth`](Tunnel::with_basic_auth),
+/// [`with_bearer_auth`](Tunnel::with_bearer_auth) or
+/// [`with_auth`](Tunnel::with_auth) are sent with every `CONNECT` request.
+///
+/// For other schemes, such as those needing a nonce from the proxy, set a
+/// callback with [`with_auth_challenge`](Tunnel::with_auth_challenge). When
+/// the proxy responds with `407 Proxy Authentication Required`, it's called
+/// with the headers of that response, and the `Proxy-Authorization` it
+/// returns is used for a single re-attempt, over a new connection to the
+/// proxy.
+#[derive(Clone, Debug)]
+pub struct Tunnel<C> {
+    headers: HeaderMap,
+    inner: C,
+    proxy_dst: Uri,
+    challenge: Option<AuthChallenge>,
+}
+
+#[derive(Clone)]
+struct AuthChallenge(Arc<ChallengeFn>);
+
+type ChallengeFn = dyn Fn(&HeaderMap) -> Option<HeaderValue> + Send + Sync;
+
+// What the proxy responded to a `CONNECT` request.
+enum Tunneled {
+    Established,
+    AuthRequired(HeaderMap),
+}
+
+/// An error returned by a [`Tunnel`](Tunnel).
+pub struct TunnelError {
+    kind: Kind,
+    source: Option<BoxError>,
+}
+
+#[derive(Debug)]
+enum Kind {
+    ConnectFailed,
+    Io,
+    MissingHost,
+    ProxyAuthRequired,
+    ProxyHeadersTooLong,
+    TunnelUnexpectedEof,
+    TunnelUnsuccessful,
+}
+
+// ===== impl Tunnel =====
+
+impl<C> Tunnel<C> {
+    /// Create a new `Tunnel` to the proxy at `proxy_dst`, connecting to it
+    /// with `connector`.
+    pub fn new(proxy_dst: Uri, connector: C) -> Self {
+        Tunnel {
+            headers: HeaderMap::new(),
+            inner: connector,
+            proxy_dst,
+            challenge: None,
+        }
+    }
+
+    /// Add a `Proxy-Authorization` header value to the `CONNECT` request.
+    pub fn with_auth(mut self, mut auth: HeaderValue) -> Self {
+        // just in case the user forgot
+        auth.set_sensitive(true);
+        self.headers.insert(PROXY_AUTHORIZATION, auth);
+        self
+    }
+
+    /// Authenticate to the proxy with the `Basic` scheme.
+    pub fn with_basic_auth(self, username: &str, password: &str) -> Self {
+        let credentials = base64(format!("{}:{}", username, password).as_bytes());
+        match HeaderValue::from_str(&format!("Basic {}", credentials)) {
+            Ok(auth) => self.with_auth(auth),
+            Err(_) => unreachable!("base64 is a valid header value"),
+        }
+    }
+
+    /// Authenticate to the proxy with a `Bearer` token.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` isn't a valid header value.
+    pub fn with_bearer_auth(self, token: &str) -> Self {
+        let auth = HeaderValue::from_str(&format!("Bearer {}", token))
+            .expect("bearer token is a valid header value");
+        self.with_auth(auth)
+    }
+
+    /// Answer a `407 Proxy Authentication Required` from the proxy.
+    ///
+    /// `challenge` is called with the headers of the response, such as its
+    /// `Proxy-Authenticate` challenges, and returns the `Proxy-Authorization`
+    /// to re-attempt with, or `None` to fail.
+    pub fn with_auth_challenge<F>(mut self, challenge: F) -> Self
+    where
+        F: Fn(&HeaderMap) -> Option<HeaderValue> + Send + Sync + 'static,
+    {
+        self.challenge = Some(AuthChallenge(Arc::new(challenge)));
+        self
+    }
+
+    /// Add extra headers to the `CONNECT` request.
+    pub fn with_headers(mut self, mut headers: HeaderMap) -> Self {
+        self.headers.extend(headers.drain());
+        self
+    }
+
+    /// Get a reference to the inner connector.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner connector.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner connector.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C> tower_service::Service<Uri> for Tunnel<C>
+where
+    C: tower_service::Service<Uri> + Clone + Send + 'static,
+    C::Future: Send + 'static,
+    C::Response: Read + Write + Unpin + Send + 'static,
+    C::Error: Into<BoxError>,
+{
+    type Response = C::Response;
+    type Error = TunnelError;
+    type Future = Tunneling<C::Response>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner
+            .poll_ready(cx)
+            .map_err(|e| TunnelError::new(Kind::ConnectFailed).with(e))
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let (host, port) = match host_port(&dst) {
+            Some((host, port)) => (host.to_owned(), port),
+            None => {
+                return Tunneling {
+                    fut: Box::pin(async { Err(TunnelError::new(Kind::MissingHost)) }),
+                }
+            }
+        };
+
+        let connecting = self.inner.call(self.proxy_dst.clone());
+        let mut headers = self.headers.clone();
+        let retry = self
+            .challenge
+            .clone()
+            .map(|challenge| (challenge, self.inner.clone(), self.proxy_dst.clone()));
+        Tunneling {
+            fut: Box::pin(async move {
+                let mut io = connecting
+                    .await
+                    .map_err(|e| TunnelError::new(Kind::ConnectFailed).with(e))?;
+                let response = match tunnel(&mut io, &host, port, &headers).await? {
+                    Tunneled::Established => return Ok(io),
+                    Tunneled::AuthRequired(response) => response,
+                };
+
+                let (challenge, connector, proxy_dst) = match retry {
+                    Some(retry) => retry,
+                    None => return Err(TunnelError::new(Kind::ProxyAuthRequired)),
+                };
+                let mut auth = match (challenge.0)(&response) {
+                    Some(auth) => auth,
+                    None => return Err(TunnelError::new(Kind::ProxyAuthRequired)),
+                };
+                auth.set_sensitive(true);
+                headers.insert(PROXY_AUTHORIZATION, auth);
+                drop(io);
+
+                debug!("proxy authentication required, re-attempting");
+                let mut io = connector
+                    .oneshot(proxy_dst)
+                    .await
+                    .map_err(|e| TunnelError::new(Kind::ConnectFailed).with(e))?;
+                match tunnel(&mut io, &host, port, &headers).await? {
+                    Tunneled::Established => Ok(io),
+                    Tunneled::AuthRequired(_) => Err(TunnelError::new(Kind::ProxyAuthRequired)),
+                }
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for AuthChallenge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuthChallenge")
+    }
+}
+
+async fn tunnel<T>(
+    io: &mut T,
+    host: &str,
+    port: u16,
+    headers: &HeaderMap,
+) -> Result<Tunneled, TunnelError>
+where
+    T: Read + Write + Unpin,
+{
+    debug!("tunneling to {}:{}", host, port);
+
+    let mut buf = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n",
+        host = host,
+        port = port
+    )
+    .into_bytes();
+    for (name, value) in headers {
+        buf.extend_from_slice(name.as_str().as_bytes());
+        buf.extend_from_slice(b": ");
+        buf.extend_from_slice(value.as_bytes());
+        buf.extend_from_slice(b"\r\n");
+    }
+    buf.extend_from_slice(b"\r\n");
+
+    write_all(io, &buf)
+        .await
+        .map_err(|e| TunnelError::new(Kind::Io).with(e))?;
+
+    let mut buf = [0; 8192];
+    let mut pos = 0;
+
+    loop {
+        let n = read(io, &mut buf[pos..])
+            .await
+            .map_err(|e| TunnelError::new(Kind::Io).with(e))?;
+        if n == 0 {
+            return Err(TunnelError::new(Kind::TunnelUnexpectedEof));
+        }
+        pos += n;
+
+        let recvd = &buf[..pos];
+        // wait until the whole status code has been read
+        if recvd.len() < 12 {
+            continue;
+        }
+        if recvd.starts_with(b"HTTP/1.1 200") || recvd.starts_with(b"HTTP/1.0 200") {
+            if recvd.ends_with(b"\r\n\r\n") {
+                return Ok(Tunneled::Established);
+            }
+            if pos == buf.len() {
+                return Err(TunnelError::new(Kind::ProxyHeadersTooLong));
+            }
+        } else if recvd.starts_with(b"HTTP/1.1 407") || recvd.starts_with(b"HTTP/1.0 407") {
+            // the challenges are in the headers, after which there may be
+            // a body, which is ignored since the connection isn't reused
+            if let Some(end) = find(recvd, b"\r\n\r\n") {
+                return Ok(Tunneled::AuthRequired(parse_headers(&recvd[..end])));
+            }
+            if pos == buf.len() {
+                return Err(TunnelError::new(Kind::ProxyHeadersTooLong));
+            }
+        } else {
+            return Err(TunnelError::new(Kind::TunnelUnsuccessful));
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// Parse the header lines of a response head, skipping the status line and
+// any line that isn't a valid header.
+fn parse_headers(head: &[u8]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for line in head.split(|&b| b == b'\n').skip(1) {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let colon = match line.iter().position(|&b| b == b':') {
+            Some(colon) => colon,
+            None => continue,
+        };
+        let name = http::header::HeaderName::from_bytes(&line[..colon]);
+        let value = HeaderValue::from_bytes(trim(&line[colon + 1..]));
+        if let (Ok(name), Ok(value)) = (name, value) {
+            headers.append(name, value);
+        }
+    }
+    headers
+}
+
+fn trim(mut bytes: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = bytes {
+        bytes = rest;
+    }
+    while let [rest @ .., b' ' | b'\t'] = bytes {
+        bytes = rest;
+    }
+    bytes
+}
+
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len() * 4 / 3 + 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// A future returned by the `Tunnel` when connecting.
+#[must_use = "futures do nothing unless polled"]
+#[allow(missing_debug_implementations)]
+pub struct Tunneling<T> {
+    fut: Pin<Box<dyn Future<Output = Result<T, TunnelError>> + Send>>,
+}
+
+impl<T> Future for Tunneling<T> {
+    type Output = Result<T, TunnelError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.fut.as_mut().poll(cx)
+    }
+}
+
+// ===== impl TunnelError =====
+
+impl TunnelError {
+    fn new(kind: Kind) -> Self {
+        TunnelError { kind, source: None }
+    }
+
+    fn with<E: Into<BoxError>>(mut self, source: E) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+}
+
+impl fmt::Debug for TunnelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_tuple("hyper_util::client::legacy::connect::proxy::TunnelError");
+        f.field(&self.kind);
+        if let Some(ref source) = self.source {
+            f.field(source);
+        }
+        f.finish()
+    }
+}
+
+impl fmt::Display for TunnelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.kind {
+            Kind::ConnectFailed => "failed to connect to proxy",
+            Kind::Io => "io error while tunneling",
+            Kind::MissingHost => "missing destination host",
+            Kind::ProxyAuthRequired => "proxy authorization required",
+            Kind::ProxyHeadersTooLong => "proxy response headers too long",
+            Kind::TunnelUnexpectedEof => "unexpected end of proxy response",
+            Kind::TunnelUnsuccessful => "unsuccessful tunnel",
+        })
+    }
+}
+
+impl StdError for TunnelError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source.as_ref().map(|e| &**e as _)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::super::super::sealed::{Connect, Internal};
+    use super::super::super::HttpConnector;
+    use super::{base64, Tunnel};
+
+    async fn proxy(response: &'static [u8]) -> (http::Uri, tokio::task::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            buf.truncate(n);
+            stream.write_all(response).await.unwrap();
+            buf
+        });
+        (format!("http://{}", addr).parse().unwrap(), handle)
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn tunnel_sends_connect() {
+        let (proxy_dst, handle) = proxy(b"HTTP/1.1 200 OK\r\n\r\n").await;
+        let tunnel = Tunnel::new(proxy_dst, HttpConnector::new())
+            .with_auth(http::HeaderValue::from_static("Basic Zm9vOmJhcg=="));
+
+        tunnel
+            .connect(Internal, "https://hyper.rs".parse().unwrap())
+            .await
+            .unwrap();
+
+        let req = handle.await.unwrap();
+        assert_eq!(
+            req,
+            &b"CONNECT hyper.rs:443 HTTP/1.1\r\nHost: hyper.rs:443\r\nproxy-authorization: Basic Zm9vOmJhcg==\r\n\r\n"[..]
+        );
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn tunnel_rejected() {
+        let (proxy_dst, _handle) =
+            proxy(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").await;
+        let tunnel = Tunnel::new(proxy_dst, HttpConnector::new());
+
+        let err = tunnel
+            .connect(Internal, "http://hyper.rs".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "proxy authorization required");
+    }
+
+    #[test]
+    fn base64_encodes() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foo:bar"), "Zm9vOmJhcg==");
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn tunnel_basic_auth() {
+        let (proxy_dst, handle) = proxy(b"HTTP/1.1 200 OK\r\n\r\n").await;
+        let tunnel = Tunnel::new(proxy_dst, HttpConnector::new()).with_basic_auth("foo", "bar");
+
+        tunnel
+            .connect(Internal, "http://hyper.rs".parse().unwrap())
+            .await
+            .unwrap();
+
+        let req = String::from_utf8(handle.await.unwrap()).unwrap();
+        assert!(req.contains("\r\nproxy-authorization: Basic Zm9vOmJhcg==\r\n"));
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn tunnel_answers_challenge_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_dst = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let handle = tokio::spawn(async move {
+            let mut reqs = Vec::new();
+            for response in [
+                &b"HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Custom nonce=\"abc\"\r\nContent-Length: 2\r\n\r\nno"[..],
+                b"HTTP/1.1 200 OK\r\n\r\n",
+            ] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                reqs.push(String::from_utf8(buf[..n].to_vec()).unwrap());
+                stream.write_all(response).await.unwrap();
+            }
+            reqs
+        });
+
+        let tunnel = Tunnel::new(proxy_dst, HttpConnector::new()).with_auth_challenge(|res| {
+            let challenge = res.get("proxy-authenticate")?.to_str().ok()?;
+            let nonce = challenge.strip_prefix("Custom nonce=")?.trim_matches('"');
+            http::HeaderValue::from_str(&format!("Custom {}-signed", nonce)).ok()
+        });
+        tunnel
+            .connect(Internal, "http://hyper.rs".parse().unwrap())
+            .await
+            .unwrap();
+
+        let reqs = handle.await.unwrap();
+        assert!(!reqs[0].contains("proxy-authorization"));
+        assert!(reqs[1].contains("\r\nproxy-authorization: Custom abc-signed\r\n"));
+    }
+}
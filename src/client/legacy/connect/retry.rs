@@ -0,0 +1,383 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{self, Poll};
+use std::time::Duration;
+
+use futures_util::future::poll_fn;
+use http::Uri;
+use tracing::debug;
+
+use super::ConnectTimeout;
+use crate::common::timer;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// A connector that retries failed connections of an inner connector.
+///
+/// Failed connections are retried after an exponential backoff, with
+/// optional jitter. How many times a failure is retried depends on its
+/// [`ErrorClass`](ErrorClass), as configured in a
+/// [`RetryPolicy`](RetryPolicy).
+///
+/// These are retries of establishing a connection, independent from the
+/// request-level retries of the `Client`.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use std::time::Duration;
+/// use hyper_util::client::legacy::connect::{ErrorClass, HttpConnector, RetryConnector, RetryPolicy};
+/// use hyper_util::rt::TokioTimer;
+///
+/// let mut policy = RetryPolicy::new();
+/// policy
+///     .max_retries(ErrorClass::Refused, 5)
+///     .initial_backoff(Duration::from_millis(50));
+///
+/// let connector = RetryConnector::new(HttpConnector::new(), policy, TokioTimer::new());
+/// # drop(connector);
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct RetryConnector<C> {
+    inner: C,
+    policy: RetryPolicy,
+    timer: timer::Timer,
+}
+
+/// Configures when and how often a [`RetryConnector`](RetryConnector)
+/// retries.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_retries: [usize; 4],
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+}
+
+/// The kind of a connection failure, used to pick how often it is retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The destination refused the connection.
+    Refused,
+    /// Connecting took too long.
+    Timeout,
+    /// The destination could not be reached.
+    Unreachable,
+    /// Any other failure.
+    Other,
+}
+
+/// A future returned by the `RetryConnector` when connecting.
+#[must_use = "futures do nothing unless polled"]
+#[allow(missing_debug_implementations)]
+pub struct RetryConnecting<T> {
+    fut: Pin<Box<dyn Future<Output = Result<T, BoxError>> + Send>>,
+}
+
+// ===== impl RetryConnector =====
+
+impl<C> RetryConnector<C> {
+    /// Wrap a connector, retrying failed connections according to `policy`.
+    ///
+    /// The `timer` is used to wait between attempts.
+    pub fn new<M>(inner: C, policy: RetryPolicy, timer: M) -> Self
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        RetryConnector {
+            inner,
+            policy,
+            timer: timer::Timer::new(timer),
+        }
+    }
+
+    /// Get a reference to the inner connector.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner connector.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner connector.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C> tower_service::Service<Uri> for RetryConnector<C>
+where
+    C: tower_service::Service<Uri> + Clone + Send + 'static,
+    C::Response: Send + 'static,
+    C::Future: Send + 'static,
+    C::Error: Into<BoxError>,
+{
+    type Response = C::Response;
+    type Error = BoxError;
+    type Future = RetryConnecting<C::Response>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let first = self.inner.call(dst.clone());
+        let mut inner = self.inner.clone();
+        let policy = self.policy.clone();
+        let timer = self.timer.clone();
+
+        RetryConnecting {
+            fut: Box::pin(async move {
+                let mut result = first.await.map_err(Into::into);
+                let mut retries = [0; 4];
+                let mut backoff = policy.initial_backoff;
+
+                loop {
+                    let err = match result {
+                        Ok(conn) => return Ok(conn),
+                        Err(err) => err,
+                    };
+
+                    let class = ErrorClass::of(&*err);
+                    let retried = &mut retries[class as usize];
+                    if *retried >= policy.max_retries[class as usize] {
+                        return Err(err);
+                    }
+                    *retried += 1;
+
+                    let delay = if policy.jitter {
+                        jitter(backoff)
+                    } else {
+                        backoff
+                    };
+                    debug!("connect error ({:?}), retrying in {:?}", class, delay);
+                    hyper::rt::Timer::sleep(&timer, delay).await;
+                    backoff = std::cmp::min(backoff.saturating_mul(2), policy.max_backoff);
+
+                    poll_fn(|cx| inner.poll_ready(cx))
+                        .await
+                        .map_err(Into::into)?;
+                    result = inner.call(dst.clone()).await.map_err(Into::into);
+                }
+            }),
+        }
+    }
+}
+
+impl<C: fmt::Debug> fmt::Debug for RetryConnector<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryConnector")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<T> Future for RetryConnecting<T> {
+    type Output = Result<T, BoxError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.fut.as_mut().poll(cx)
+    }
+}
+
+/// Pick a random duration between zero and `backoff` ("full jitter").
+fn jitter(backoff: Duration) -> Duration {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let random = RandomState::new().build_hasher().finish();
+    let nanos = backoff.as_nanos() as u64;
+    if nanos == 0 {
+        return backoff;
+    }
+    Duration::from_nanos(random % (nanos + 1))
+}
+
+// ===== impl RetryPolicy =====
+
+impl RetryPolicy {
+    /// Construct a new `RetryPolicy` with the default settings.
+    ///
+    /// By default, refused connections are retried up to 3 times, timeouts
+    /// once, and other failures not at all. The backoff starts at 100
+    /// milliseconds and is capped at 5 seconds, with jitter.
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: [3, 1, 0, 0],
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+        }
+    }
+
+    /// Set how many times failures of a class are retried.
+    pub fn max_retries(&mut self, class: ErrorClass, retries: usize) -> &mut Self {
+        self.max_retries[class as usize] = retries;
+        self
+    }
+
+    /// Set the backoff before the first retry.
+    ///
+    /// The backoff doubles after every retry.
+    ///
+    /// Default is 100 milliseconds.
+    pub fn initial_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the maximum backoff between retries.
+    ///
+    /// Default is 5 seconds.
+    pub fn max_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set whether to wait a random duration of up to the backoff, instead
+    /// of the full backoff.
+    ///
+    /// This spreads out the retries of many clients failing at once.
+    ///
+    /// Default is `true`.
+    pub fn jitter(&mut self, enabled: bool) -> &mut Self {
+        self.jitter = enabled;
+        self
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new()
+    }
+}
+
+// ===== impl ErrorClass =====
+
+impl ErrorClass {
+    /// Classify a connection error, by looking through its sources.
+    pub fn of(err: &(dyn StdError + 'static)) -> ErrorClass {
+        let mut source = Some(err);
+        while let Some(err) = source {
+            if err.is::<ConnectTimeout>() {
+                return ErrorClass::Timeout;
+            }
+            if let Some(io) = err.downcast_ref::<io::Error>() {
+                return match io.kind() {
+                    io::ErrorKind::ConnectionRefused => ErrorClass::Refused,
+                    io::ErrorKind::TimedOut => ErrorClass::Timeout,
+                    io::ErrorKind::NotConnected | io::ErrorKind::AddrNotAvailable => {
+                        ErrorClass::Unreachable
+                    }
+                    // `HostUnreachable` and `NetworkUnreachable` are newer
+                    // than our MSRV, so match them by name.
+                    kind => match format!("{:?}", kind).as_str() {
+                        "HostUnreachable" | "NetworkUnreachable" => ErrorClass::Unreachable,
+                        _ => ErrorClass::Other,
+                    },
+                };
+            }
+            source = err.source();
+        }
+        ErrorClass::Other
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use http::Uri;
+    use tower::ServiceExt;
+
+    use super::{ErrorClass, RetryConnector, RetryPolicy};
+    use crate::rt::TokioTimer;
+
+    fn failing(
+        failures: usize,
+        kind: io::ErrorKind,
+    ) -> (
+        impl tower_service::Service<
+                Uri,
+                Response = (),
+                Error = io::Error,
+                Future = impl Send + 'static,
+            > + Clone
+            + Send
+            + 'static,
+        Arc<AtomicUsize>,
+    ) {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let svc = tower::service_fn(move |_: Uri| {
+            let attempt = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if attempt < failures {
+                    Err(io::Error::from(kind))
+                } else {
+                    Ok(())
+                }
+            }
+        });
+        (svc, attempts)
+    }
+
+    fn policy() -> RetryPolicy {
+        let mut policy = RetryPolicy::new();
+        policy
+            .initial_backoff(Duration::from_millis(1))
+            .jitter(false);
+        policy
+    }
+
+    #[tokio::test]
+    async fn retries_refused() {
+        let (svc, attempts) = failing(2, io::ErrorKind::ConnectionRefused);
+        let connector = RetryConnector::new(svc, policy(), TokioTimer::new());
+
+        connector
+            .oneshot("http://example.domain".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_per_class() {
+        let (svc, attempts) = failing(5, io::ErrorKind::TimedOut);
+        let connector = RetryConnector::new(svc, policy(), TokioTimer::new());
+
+        let err = connector
+            .oneshot("http://example.domain".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorClass::of(&*err), ErrorClass::Timeout);
+        // one attempt, plus one retry
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn no_retries_for_other() {
+        let (svc, attempts) = failing(1, io::ErrorKind::PermissionDenied);
+        let connector = RetryConnector::new(svc, policy(), TokioTimer::new());
+
+        connector
+            .oneshot("http://example.domain".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,400 @@
+//! HTTPS connector using [`rustls`](https://docs.rs/rustls).
+//!
+//! This module contains:
+//!
+//! - An [`HttpsConnector`](HttpsConnector) that wraps another connector,
+//!   typically the [`HttpConnector`](super::HttpConnector), and performs a
+//!   TLS handshake when the destination uses the `https` scheme.
+//! - The [`MaybeHttpsStream`](MaybeHttpsStream) transport it returns.
+//! - [`TlsInfo`](TlsInfo), set in the extensions of every `Response`
+//!   received over TLS.
+//!
+//! # Example
+//!
+//! ```
+//! # fn run() {
+//! use hyper_util::client::legacy::connect::rustls::HttpsConnector;
+//! use hyper_util::client::legacy::Client;
+//! use hyper_util::rt::TokioExecutor;
+//!
+//! let client = Client::builder(TokioExecutor::new()).build(HttpsConnector::new());
+//! # let _: Client<_, http_body_util::Empty<bytes::Bytes>> = client;
+//! # }
+//! # fn main() {}
+//! ```
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{self, Poll};
+
+use ::rustls::pki_types::ServerName;
+use ::rustls::{ClientConfig, RootCertStore};
+use http::uri::{Scheme, Uri};
+use hyper::rt::{Read, ReadBufCursor, Write};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use tracing::{debug_span, trace, Instrument};
+
+use super::{Connected, Connection, HttpConnector, LivenessProbe};
+use crate::common::trace as tracing_span;
+use crate::rt::TokioIo;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// A connector for the `https` scheme, using `rustls`.
+///
+/// Connections to `http` destinations are passed through without TLS,
+/// unless [`https_only`](HttpsConnector::https_only) is set.
+///
+/// # ALPN
+///
+/// If the `ClientConfig` advertises `h2` through ALPN and the server selects
+/// it, the returned connection reports it in its [`Connected`](Connected),
+/// and the `Client` will use HTTP/2 on it.
+///
+/// # Certificate verification
+///
+/// [`HttpsConnector::new`](HttpsConnector::new) trusts the Mozilla root
+/// certificates. To use other roots, client certificates, or a custom
+/// certificate verifier, build a `ClientConfig` and pass it to
+/// [`HttpsConnector::with_config`](HttpsConnector::with_config).
+#[derive(Clone)]
+pub struct HttpsConnector<C> {
+    http: C,
+    tls: TlsConnector,
+    https_only: bool,
+}
+
+/// Extra information about the TLS session of a connection made by the
+/// `HttpsConnector`.
+///
+/// It is set in the extensions of every `Response` received over a TLS
+/// connection.
+#[derive(Clone, Debug)]
+pub struct TlsInfo {
+    peer_certificate: Option<Vec<u8>>,
+}
+
+/// A stream that might be protected with TLS.
+#[allow(clippy::large_enum_variant)]
+pub enum MaybeHttpsStream<T> {
+    /// A stream over plain text.
+    Http(T),
+    /// A stream protected with TLS.
+    Https(TokioIo<TlsStream<TokioIo<T>>>),
+}
+
+// ===== impl HttpsConnector =====
+
+impl HttpsConnector<HttpConnector> {
+    /// Construct a new `HttpsConnector`, using the Mozilla root certificates.
+    ///
+    /// ALPN will offer `h2` (if the `http2` feature is enabled) and
+    /// `http/1.1`.
+    pub fn new() -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let mut config = ClientConfig::builder_with_provider(Arc::new(
+            ::rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        config.alpn_protocols = default_alpn();
+
+        HttpsConnector::with_config(http, config)
+    }
+}
+
+impl Default for HttpsConnector<HttpConnector> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> HttpsConnector<C> {
+    /// Construct a new `HttpsConnector` from an inner connector and a
+    /// `rustls` client configuration.
+    ///
+    /// The ALPN protocols, server name indication, and certificate
+    /// verification of `config` are used as is.
+    ///
+    /// If the inner connector is an `HttpConnector`, it must have
+    /// `enforce_http(false)` set, or `https` destinations will be rejected.
+    pub fn with_config(http: C, config: impl Into<Arc<ClientConfig>>) -> Self {
+        HttpsConnector {
+            http,
+            tls: TlsConnector::from(config.into()),
+            https_only: false,
+        }
+    }
+
+    /// Set whether to reject destinations that don't use the `https` scheme.
+    ///
+    /// Default is `false`.
+    pub fn https_only(&mut self, enable: bool) -> &mut Self {
+        self.https_only = enable;
+        self
+    }
+}
+
+fn default_alpn() -> Vec<Vec<u8>> {
+    let mut alpn = Vec::new();
+    if cfg!(feature = "http2") {
+        alpn.push(b"h2".to_vec());
+    }
+    alpn.push(b"http/1.1".to_vec());
+    alpn
+}
+
+impl<C> fmt::Debug for HttpsConnector<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpsConnector")
+            .field("https_only", &self.https_only)
+            .finish()
+    }
+}
+
+impl<C> tower_service::Service<Uri> for HttpsConnector<C>
+where
+    C: tower_service::Service<Uri>,
+    C::Response: Read + Write + Connection + Unpin + Send + 'static,
+    C::Future: Send + 'static,
+    C::Error: Into<BoxError>,
+{
+    type Response = MaybeHttpsStream<C::Response>;
+    type Error = BoxError;
+    type Future = HttpsConnecting<C::Response>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let is_https = dst.scheme() == Some(&Scheme::HTTPS);
+        if !is_https && self.https_only {
+            return HttpsConnecting {
+                fut: Box::pin(async { Err("invalid URL, scheme is not https".into()) }),
+            };
+        }
+
+        let server_name = match dst.host() {
+            Some(host) if is_https => {
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                match ServerName::try_from(host.to_owned()) {
+                    Ok(name) => Some(name),
+                    Err(e) => {
+                        return HttpsConnecting {
+                            fut: Box::pin(async move { Err(e.into()) }),
+                        }
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let connecting = self.http.call(dst);
+        let tls = self.tls.clone();
+        HttpsConnecting {
+            fut: Box::pin(async move {
+                let io = connecting.await.map_err(Into::into)?;
+                match server_name {
+                    Some(name) => {
+                        trace!("starting tls handshake with {:?}", name);
+                        let span = tracing_span::span(|| debug_span!("tls", server_name = ?name));
+                        let tls = tls.connect(name, TokioIo::new(io)).instrument(span).await?;
+                        Ok(MaybeHttpsStream::Https(TokioIo::new(tls)))
+                    }
+                    None => Ok(MaybeHttpsStream::Http(io)),
+                }
+            }),
+        }
+    }
+}
+
+/// A future returned by the `HttpsConnector` when connecting.
+#[must_use = "futures do nothing unless polled"]
+#[allow(missing_debug_implementations)]
+pub struct HttpsConnecting<T> {
+    fut: Pin<Box<dyn Future<Output = Result<MaybeHttpsStream<T>, BoxError>> + Send>>,
+}
+
+impl<T> Future for HttpsConnecting<T> {
+    type Output = Result<MaybeHttpsStream<T>, BoxError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.fut.as_mut().poll(cx)
+    }
+}
+
+// ===== impl TlsInfo =====
+
+impl TlsInfo {
+    /// Get the DER encoded end-entity certificate presented by the server,
+    /// if any.
+    pub fn peer_certificate(&self) -> Option<&[u8]> {
+        self.peer_certificate.as_deref()
+    }
+}
+
+// ===== impl MaybeHttpsStream =====
+
+impl<T> MaybeHttpsStream<T> {
+    /// Returns whether this stream is protected with TLS.
+    pub fn is_https(&self) -> bool {
+        matches!(self, MaybeHttpsStream::Https(_))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MaybeHttpsStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaybeHttpsStream::Http(s) => f.debug_tuple("Http").field(s).finish(),
+            MaybeHttpsStream::Https(_) => f.debug_tuple("Https").finish(),
+        }
+    }
+}
+
+impl<T> Connection for MaybeHttpsStream<T>
+where
+    T: Connection,
+{
+    fn connected(&self) -> Connected {
+        match self {
+            MaybeHttpsStream::Http(s) => s.connected(),
+            MaybeHttpsStream::Https(s) => {
+                let (tcp, tls) = s.inner().get_ref();
+                let info = TlsInfo {
+                    peer_certificate: tls
+                        .peer_certificates()
+                        .and_then(|certs| certs.first())
+                        .map(|cert| cert.to_vec()),
+                };
+                let connected = tcp.inner().connected().extra(info);
+                match tls.alpn_protocol() {
+                    Some(protocol) => connected.negotiated_alpn(protocol.to_vec()),
+                    None => connected,
+                }
+            }
+        }
+    }
+
+    fn liveness_probe(&self) -> Option<LivenessProbe> {
+        // Peeking below TLS still sees the peer closing the connection,
+        // or sending data, such as a close_notify alert.
+        match self {
+            MaybeHttpsStream::Http(s) => s.liveness_probe(),
+            MaybeHttpsStream::Https(s) => s.inner().get_ref().0.inner().liveness_probe(),
+        }
+    }
+}
+
+impl<T> Read for MaybeHttpsStream<T>
+where
+    T: Read + Write + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        match Pin::get_mut(self) {
+            MaybeHttpsStream::Http(s) => Pin::new(s).poll_read(cx, buf),
+            MaybeHttpsStream::Https(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<T> Write for MaybeHttpsStream<T>
+where
+    T: Read + Write + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match Pin::get_mut(self) {
+            MaybeHttpsStream::Http(s) => Pin::new(s).poll_write(cx, buf),
+            MaybeHttpsStream::Https(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match Pin::get_mut(self) {
+            MaybeHttpsStream::Http(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            MaybeHttpsStream::Https(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        match Pin::get_mut(self) {
+            MaybeHttpsStream::Http(s) => Pin::new(s).poll_flush(cx),
+            MaybeHttpsStream::Https(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        match Pin::get_mut(self) {
+            MaybeHttpsStream::Http(s) => Pin::new(s).poll_shutdown(cx),
+            MaybeHttpsStream::Https(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            MaybeHttpsStream::Http(s) => s.is_write_vectored(),
+            MaybeHttpsStream::Https(s) => s.is_write_vectored(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sealed::{Connect, ConnectSvc, Internal};
+    use super::HttpsConnector;
+
+    async fn connect<C>(
+        connector: C,
+        dst: http::Uri,
+    ) -> Result<<C::_Svc as ConnectSvc>::Connection, <C::_Svc as ConnectSvc>::Error>
+    where
+        C: Connect,
+    {
+        connector.connect(Internal, dst).await
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn http_passes_through() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let dst = format!("http://{}", addr).parse().unwrap();
+        let stream = connect(HttpsConnector::new(), dst).await.unwrap();
+        assert!(!stream.is_https());
+    }
+
+    #[tokio::test]
+    async fn https_only_rejects_http() {
+        let mut connector = HttpsConnector::new();
+        connector.https_only(true);
+
+        let dst = "http://example.domain".parse().unwrap();
+        let err = connect(connector, dst).await.unwrap_err();
+        assert_eq!(err.to_string(), "invalid URL, scheme is not https");
+    }
+}
@@ -0,0 +1,218 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{self, Poll};
+use std::time::Duration;
+
+use http::Uri;
+use hyper::rt::Sleep;
+use pin_project_lite::pin_project;
+use tracing::debug;
+
+use crate::common::timer;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// A connector that enforces a deadline on every connection of an inner
+/// connector.
+///
+/// The deadline covers everything the inner connector does before yielding
+/// a connection, such as resolving, dialing, and a TLS handshake. If it
+/// elapses first, the connecting future is dropped and a
+/// [`ConnectTimeout`](ConnectTimeout) error is returned instead.
+///
+/// This is useful for connectors that don't expose their own timeout
+/// settings.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use std::time::Duration;
+/// use hyper_util::client::legacy::connect::{HttpConnector, TimeoutConnector};
+/// use hyper_util::rt::TokioTimer;
+///
+/// let connector = TimeoutConnector::new(HttpConnector::new(), Duration::from_secs(5), TokioTimer::new());
+/// # drop(connector);
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct TimeoutConnector<C> {
+    inner: C,
+    timeout: Duration,
+    timer: timer::Timer,
+}
+
+pin_project! {
+    /// A future returned by the `TimeoutConnector` when connecting.
+    #[must_use = "futures do nothing unless polled"]
+    pub struct TimeoutConnecting<F> {
+        #[pin]
+        inner: F,
+        sleep: Pin<Box<dyn Sleep>>,
+    }
+}
+
+/// Error returned by a [`TimeoutConnector`](TimeoutConnector) when a
+/// connection was not established in time.
+#[derive(Debug)]
+pub struct ConnectTimeout(());
+
+// ===== impl TimeoutConnector =====
+
+impl<C> TimeoutConnector<C> {
+    /// Wrap a connector, failing any connection that takes longer than
+    /// `timeout` to establish.
+    ///
+    /// The `timer` is used to drive the deadline.
+    pub fn new<M>(inner: C, timeout: Duration, timer: M) -> Self
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        TimeoutConnector {
+            inner,
+            timeout,
+            timer: timer::Timer::new(timer),
+        }
+    }
+
+    /// Get a reference to the inner connector.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner connector.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner connector.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C> tower_service::Service<Uri> for TimeoutConnector<C>
+where
+    C: tower_service::Service<Uri>,
+    C::Error: Into<BoxError>,
+{
+    type Response = C::Response;
+    type Error = BoxError;
+    type Future = TimeoutConnecting<C::Future>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        TimeoutConnecting {
+            inner: self.inner.call(dst),
+            sleep: hyper::rt::Timer::sleep(&self.timer, self.timeout),
+        }
+    }
+}
+
+impl<C: fmt::Debug> fmt::Debug for TimeoutConnector<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeoutConnector")
+            .field("inner", &self.inner)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl<F, T, E> Future for TimeoutConnecting<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<BoxError>,
+{
+    type Output = Result<T, BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        if let Poll::Ready(res) = this.inner.poll(cx) {
+            return Poll::Ready(res.map_err(Into::into));
+        }
+
+        match this.sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                debug!("connect timed out");
+                Poll::Ready(Err(ConnectTimeout(()).into()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<F> fmt::Debug for TimeoutConnecting<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("TimeoutConnecting")
+    }
+}
+
+impl fmt::Display for ConnectTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("connect timed out")
+    }
+}
+
+impl StdError for ConnectTimeout {}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+
+    use http::Uri;
+
+    use super::super::sealed::{Connect, ConnectSvc, Internal};
+    use super::{ConnectTimeout, TimeoutConnector};
+    use crate::rt::TokioTimer;
+
+    async fn connect<C>(
+        connector: C,
+        dst: Uri,
+    ) -> Result<<C::_Svc as ConnectSvc>::Connection, <C::_Svc as ConnectSvc>::Error>
+    where
+        C: Connect,
+    {
+        connector.connect(Internal, dst).await
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn times_out() {
+        let pending = tower::service_fn(|_: Uri| {
+            futures_util::future::pending::<
+                Result<crate::rt::TokioIo<tokio::net::TcpStream>, io::Error>,
+            >()
+        });
+        let connector =
+            TimeoutConnector::new(pending, Duration::from_millis(10), TokioTimer::new());
+
+        let err = connect(connector, "http://example.domain".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert!(err.is::<ConnectTimeout>());
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn passes_through() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let connector = TimeoutConnector::new(
+            super::super::HttpConnector::new(),
+            Duration::from_secs(5),
+            TokioTimer::new(),
+        );
+        let dst = format!("http://{}", addr).parse().unwrap();
+        connect(connector, dst).await.unwrap();
+    }
+}
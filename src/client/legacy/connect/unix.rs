@@ -0,0 +1,222 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{self, Poll};
+
+use http::uri::Uri;
+use tokio::net::UnixStream;
+use tracing::debug;
+
+use super::{Connected, Connection, LivenessProbe};
+use crate::rt::TokioIo;
+
+/// The URI scheme used to address a Unix domain socket.
+///
+/// See [`UnixConnector::uri`](UnixConnector::uri) for how the socket path is
+/// encoded into the URI.
+pub const UNIX_SCHEME: &str = "http+unix";
+
+/// A connector for Unix domain sockets.
+///
+/// This allows the `Client` to talk to local daemons that listen on a Unix
+/// domain socket, such as the Docker daemon.
+///
+/// The destination `Uri` must use the `http+unix` scheme, with the socket
+/// path hex-encoded as the host. Use [`UnixConnector::uri`](UnixConnector::uri)
+/// to build such a `Uri`. Since the socket path is the authority of the
+/// `Uri`, the `Client` pools connections separately for each socket path.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use hyper_util::client::legacy::connect::UnixConnector;
+/// use hyper_util::client::legacy::Client;
+/// use hyper_util::rt::TokioExecutor;
+///
+/// let client = Client::builder(TokioExecutor::new()).build(UnixConnector::new());
+/// let uri = UnixConnector::uri("/var/run/docker.sock", "/version").unwrap();
+///
+/// let future = client.get(uri);
+/// # let _: &Client<_, http_body_util::Empty<bytes::Bytes>> = &client;
+/// # drop(future);
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Default)]
+pub struct UnixConnector {
+    _priv: (),
+}
+
+/// Extra information about the transport when a `UnixConnector` is used.
+#[derive(Clone, Debug)]
+pub struct UnixInfo {
+    path: PathBuf,
+}
+
+impl UnixConnector {
+    /// Construct a new `UnixConnector`.
+    pub fn new() -> UnixConnector {
+        UnixConnector { _priv: () }
+    }
+
+    /// Build a `Uri` that targets `path_and_query` on the socket at `socket_path`.
+    ///
+    /// The socket path is hex-encoded into the host of the `Uri`, since a
+    /// path cannot otherwise be represented in an authority.
+    pub fn uri<P: AsRef<Path>>(socket_path: P, path_and_query: &str) -> Result<Uri, http::Error> {
+        Uri::builder()
+            .scheme(UNIX_SCHEME)
+            .authority(encode(socket_path.as_ref()))
+            .path_and_query(path_and_query)
+            .build()
+    }
+}
+
+impl fmt::Debug for UnixConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("UnixConnector")
+    }
+}
+
+impl tower_service::Service<Uri> for UnixConnector {
+    type Response = TokioIo<UnixStream>;
+    type Error = io::Error;
+    type Future = UnixConnecting;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        UnixConnecting {
+            fut: Box::pin(async move {
+                let path = socket_path(&dst)?;
+                debug!("connecting to unix socket {:?}", path);
+                let stream = UnixStream::connect(path).await?;
+                Ok(TokioIo::new(stream))
+            }),
+        }
+    }
+}
+
+/// Decode the socket path from a `Uri` using the `http+unix` scheme.
+fn socket_path(dst: &Uri) -> io::Result<PathBuf> {
+    if dst.scheme_str() != Some(UNIX_SCHEME) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid URL, scheme is not http+unix",
+        ));
+    }
+    dst.host()
+        .and_then(decode)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid URL, bad socket path"))
+}
+
+fn encode(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str()
+        .as_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn decode(host: &str) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    if host.is_empty() {
+        return None;
+    }
+    let bytes = host
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [hi, lo] => Some((hex_val(*hi)? << 4) | hex_val(*lo)?),
+            _ => None,
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+fn hex_val(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+impl Connection for TokioIo<UnixStream> {
+    fn connected(&self) -> Connected {
+        let connected = Connected::new();
+        match self.inner().peer_addr() {
+            Ok(addr) => match addr.as_pathname() {
+                Some(path) => connected.extra(UnixInfo {
+                    path: path.to_owned(),
+                }),
+                None => connected,
+            },
+            Err(_) => connected,
+        }
+    }
+
+    fn liveness_probe(&self) -> Option<LivenessProbe> {
+        super::http::socket_probe(socket2::SockRef::from(self.inner()))
+    }
+}
+
+impl UnixInfo {
+    /// Get the path of the socket that was connected to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+// Not publicly exported (so missing_docs doesn't trigger).
+#[must_use = "futures do nothing unless polled"]
+#[allow(missing_debug_implementations)]
+pub struct UnixConnecting {
+    fut: Pin<Box<dyn Future<Output = io::Result<TokioIo<UnixStream>>> + Send>>,
+}
+
+impl Future for UnixConnecting {
+    type Output = io::Result<TokioIo<UnixStream>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.fut.as_mut().poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{socket_path, UnixConnector};
+
+    #[test]
+    fn uri_roundtrip() {
+        let uri = UnixConnector::uri("/var/run/docker.sock", "/v1.43/info?all=1").unwrap();
+        assert_eq!(uri.scheme_str(), Some("http+unix"));
+        assert_eq!(uri.path_and_query().unwrap(), "/v1.43/info?all=1");
+        assert_eq!(
+            socket_path(&uri).unwrap(),
+            Path::new("/var/run/docker.sock")
+        );
+    }
+
+    #[test]
+    fn rejects_bad_uris() {
+        let http = "http://localhost/".parse().unwrap();
+        assert!(socket_path(&http).is_err());
+
+        let not_hex = "http+unix://zz/".parse().unwrap();
+        assert!(socket_path(&not_hex).is_err());
+    }
+}
@@ -0,0 +1,326 @@
+//! Hedging requests against slow responses.
+
+use std::collections::VecDeque;
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::task::{self, Poll};
+use std::time::{Duration, Instant};
+
+use futures_util::future::{self, Either};
+use http::{Method, Request};
+use hyper::body::Body;
+use hyper::rt::Timer as _;
+use tracing::trace;
+
+use super::client::{Client, Error, ResponseFuture};
+use super::connect::Connect;
+use crate::common::timer::Timer;
+
+// How many latencies the percentile is taken from, and how many are needed
+// before it is used.
+const WINDOW: usize = 128;
+const MIN_SAMPLES: usize = 16;
+
+/// Hedges idempotent requests, sending them a second time if the response
+/// is slow.
+///
+/// If the response headers of a request haven't arrived after a delay, the
+/// request is sent again with the wrapped [`Client`], and whichever response
+/// arrives first is returned. The other attempt is canceled. This trades a
+/// few extra requests for a shorter tail latency.
+///
+/// Only requests with an idempotent method, such as `GET` or `PUT`, are
+/// hedged, since they may be handled twice by the server. Their bodies are
+/// cloned for the second attempt.
+///
+/// An HTTP/1 connection carries one request at a time, so the second
+/// attempt is sent on another connection of the pool. An HTTP/2 connection
+/// is shared, so it may be sent on the same one.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use std::time::Duration;
+/// use http_body_util::Empty;
+/// use hyper::body::Bytes;
+/// use hyper_util::client::legacy::{Client, Hedge};
+/// use hyper_util::rt::{TokioExecutor, TokioTimer};
+///
+/// let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+/// let hedge = Hedge::new(client, TokioTimer::new(), Duration::from_millis(100)).percentile(0.95);
+/// let fut = hedge.get("http://hyper.rs".parse().unwrap());
+/// # let _ = fut;
+/// # }
+/// # fn main() {}
+/// ```
+pub struct Hedge<C, B> {
+    client: Client<C, B>,
+    timer: Timer,
+    delay: Duration,
+    percentile: Option<f64>,
+    latencies: Arc<Mutex<VecDeque<Duration>>>,
+}
+
+// ===== impl Hedge =====
+
+impl<C, B> Hedge<C, B> {
+    /// Hedge the requests sent with `client`, after `delay`.
+    ///
+    /// The `timer` is used to wait for the delay.
+    pub fn new<M>(client: Client<C, B>, timer: M, delay: Duration) -> Self
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        Hedge {
+            client,
+            timer: Timer::new(timer),
+            delay,
+            percentile: None,
+            latencies: Arc::new(Mutex::new(VecDeque::with_capacity(WINDOW))),
+        }
+    }
+
+    /// Hedge after the latency at `percentile` of recent requests, instead
+    /// of the fixed delay.
+    ///
+    /// Only the latencies of first attempts are counted: a request answered
+    /// by its hedge is not. The fixed delay is used until enough latencies
+    /// were seen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `percentile` is not between 0 and 1.
+    pub fn percentile(mut self, percentile: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&percentile),
+            "percentile must be between 0 and 1"
+        );
+        self.percentile = Some(percentile);
+        self
+    }
+
+    /// The delay after which the next request is hedged.
+    pub fn delay(&self) -> Duration {
+        let percentile = match self.percentile {
+            Some(percentile) => percentile,
+            None => return self.delay,
+        };
+        let mut latencies = {
+            let latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+            if latencies.len() < MIN_SAMPLES {
+                return self.delay;
+            }
+            latencies.iter().copied().collect::<Vec<_>>()
+        };
+        latencies.sort_unstable();
+        let index = ((latencies.len() - 1) as f64 * percentile).round() as usize;
+        latencies[index]
+    }
+
+    /// Get a reference to the inner `Client`.
+    pub fn get_ref(&self) -> &Client<C, B> {
+        &self.client
+    }
+}
+
+impl<C, B> Hedge<C, B>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin + Clone + Default,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Send a `GET` request to `uri`.
+    pub fn get(&self, uri: http::Uri) -> ResponseFuture {
+        let mut req = Request::new(B::default());
+        *req.uri_mut() = uri;
+        self.request(req)
+    }
+}
+
+impl<C, B> Hedge<C, B>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin + Clone,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Send `req`, hedging it if its method is idempotent.
+    pub fn request(&self, req: Request<B>) -> ResponseFuture {
+        if !is_idempotent(req.method()) {
+            return self.client.request(req);
+        }
+
+        let hedge = clone_request(&req);
+        let client = self.client.clone();
+        let timer = self.timer.clone();
+        let delay = self.delay();
+        let latencies = self.latencies.clone();
+        ResponseFuture::new(async move {
+            let started = Instant::now();
+            let first = client.request(req);
+            let sleep = timer.sleep(delay);
+            // Only the latency of the first attempt is recorded. When the
+            // hedge wins, the time to its response is shorter than the first
+            // attempt would have taken, and recording it would make hedging
+            // ever more eager.
+            let (res, first_won) = match future::select(first, sleep).await {
+                Either::Left((res, _)) => (res, true),
+                Either::Right((_, first)) => {
+                    trace!("hedging request");
+                    let second = client.request(hedge);
+                    // The first success wins; an error waits for the other.
+                    match future::select(first, second).await {
+                        Either::Left((Ok(res), _)) => (Ok(res), true),
+                        Either::Right((Ok(res), _)) => (Ok(res), false),
+                        Either::Left((Err(_), other)) => (other.await, false),
+                        Either::Right((Err(_), other)) => (other.await, true),
+                    }
+                }
+            };
+            if res.is_ok() && first_won {
+                let mut latencies = latencies.lock().unwrap_or_else(|e| e.into_inner());
+                if latencies.len() == WINDOW {
+                    latencies.pop_front();
+                }
+                latencies.push_back(started.elapsed());
+            }
+            res
+        })
+    }
+}
+
+impl<C: Clone, B> Clone for Hedge<C, B> {
+    fn clone(&self) -> Self {
+        Hedge {
+            client: self.client.clone(),
+            timer: self.timer.clone(),
+            delay: self.delay,
+            percentile: self.percentile,
+            latencies: self.latencies.clone(),
+        }
+    }
+}
+
+impl<C, B> fmt::Debug for Hedge<C, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hedge")
+            .field("delay", &self.delay)
+            .field("percentile", &self.percentile)
+            .finish()
+    }
+}
+
+impl<C, B> tower_service::Service<Request<B>> for Hedge<C, B>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: Body + Send + 'static + Unpin + Clone,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Response = http::Response<hyper::body::Incoming>;
+    type Error = Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self, _: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        self.request(req)
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+fn clone_request<B: Clone>(req: &Request<B>) -> Request<B> {
+    let mut clone = Request::new(req.body().clone());
+    *clone.method_mut() = req.method().clone();
+    *clone.uri_mut() = req.uri().clone();
+    *clone.version_mut() = req.version();
+    *clone.headers_mut() = req.headers().clone();
+    *clone.extensions_mut() = req.extensions().clone();
+    clone
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use http_body_util::Empty;
+    use hyper::body::Bytes;
+    use hyper::service::service_fn;
+    use tokio::net::TcpListener;
+
+    use super::{Hedge, MIN_SAMPLES};
+    use crate::client::legacy::Client;
+    use crate::rt::{TokioExecutor, TokioIo, TokioTimer};
+
+    #[test]
+    fn delay_from_percentile() {
+        let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+        let hedge = Hedge::new(client, TokioTimer::new(), Duration::from_secs(1)).percentile(0.9);
+        assert_eq!(hedge.delay(), Duration::from_secs(1));
+
+        let mut latencies = hedge.latencies.lock().unwrap();
+        for ms in 1..=(MIN_SAMPLES as u64 * 2) {
+            latencies.push_back(Duration::from_millis(ms));
+        }
+        drop(latencies);
+        assert_eq!(hedge.delay(), Duration::from_millis(29));
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn hedge_wins_against_slow_first_attempt() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let served = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let served = served.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |_| {
+                        let first = served.fetch_add(1, Ordering::SeqCst) == 0;
+                        async move {
+                            if first {
+                                tokio::time::sleep(Duration::from_secs(5)).await;
+                            }
+                            Ok::<_, Infallible>(http::Response::new(Empty::<Bytes>::new()))
+                        }
+                    });
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+        let hedge =
+            Hedge::new(client, TokioTimer::new(), Duration::from_millis(20)).percentile(0.9);
+        let uri: http::Uri = format!("http://{}", addr).parse().unwrap();
+
+        let res = tokio::time::timeout(Duration::from_secs(2), hedge.get(uri))
+            .await
+            .expect("the hedge should answer before the slow first attempt")
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(hedge.latencies.lock().unwrap().is_empty());
+    }
+}
@@ -1,10 +1,33 @@
 #[cfg(any(feature = "http1", feature = "http2"))]
+mod balance;
+#[cfg(any(feature = "http1", feature = "http2"))]
+mod breaker;
+#[cfg(all(feature = "client-cache", any(feature = "http1", feature = "http2")))]
+pub mod cache;
+#[cfg(any(feature = "http1", feature = "http2"))]
+mod checkout;
+#[cfg(any(feature = "http1", feature = "http2"))]
 mod client;
 #[cfg(any(feature = "http1", feature = "http2"))]
-pub use client::{Builder, Client, Error, ResponseFuture};
+mod hedge;
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub use balance::{Balancer, Upstream};
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub use breaker::{CircuitBreaker, CircuitOpen, CircuitState};
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub use checkout::{PooledSendRequest, SendRequest, SendRequestPool};
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub use client::{
+    Builder, Client, Error, ErrorKind, NoPool, PoolIdentity, PoolKey, PoolTimeout, ResponseFuture,
+};
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub use hedge::Hedge;
 
 pub mod connect;
 #[doc(hidden)]
 // Publicly available, but just for legacy purposes. A better pool will be
 // designed.
 pub mod pool;
+pub use pool::{
+    HostStats, Idle, IdlePool, PoolEvent, PoolEventInfo, PoolObserver, PoolStats, Reuse,
+};
@@ -1,16 +1,18 @@
 #![allow(dead_code)]
 
+use std::collections::hash_map::RandomState;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::Infallible;
 use std::error::Error as StdError;
 use std::fmt::{self, Debug};
 use std::future::Future;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash, Hasher};
 use std::marker::Unpin;
 use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
-use std::sync::{Arc, Mutex, Weak};
-use std::task::{self, Poll};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LockResult, Mutex, MutexGuard, PoisonError, Weak};
+use std::task::{self, Poll, Waker};
 
 use std::time::{Duration, Instant};
 
@@ -27,9 +29,26 @@ use crate::common::{exec, exec::Exec, timer::Timer};
 #[allow(missing_debug_implementations)]
 pub struct Pool<T, K: Key> {
     // If the pool is disabled, this is None.
-    inner: Option<Arc<Mutex<PoolInner<T, K>>>>,
+    inner: Option<Arc<Shards<T, K>>>,
+    observer: Observer<K>,
 }
 
+// The pool is split into shards by the hash of the key, each with its own
+// lock, so that checkouts for different keys don't contend. All the state
+// of a key lives in a single shard, while the idle limit of the pool and
+// the IdleTask cover all of them.
+struct Shards<T, K: Eq + Hash> {
+    list: Box<[Shard<T, K>]>,
+    hasher: RandomState,
+    // Set by `Pool::close`, so that checking it doesn't lock every shard.
+    closed: AtomicBool,
+    // A oneshot channel is used to allow the interval to be notified when
+    // the Pool completely drops. That way, the interval can cancel immediately.
+    idle_interval_ref: Mutex<Option<oneshot::Sender<Infallible>>>,
+}
+
+type Shard<T, K> = Arc<Mutex<PoolInner<T, K>>>;
+
 // Before using a pooled connection, make sure the sender is not dead.
 //
 // This is a trait to allow the `client::pool::tests` to work for `i32`.
@@ -42,10 +61,195 @@ pub trait Poolable: Unpin + Send + Sized + 'static {
     /// Allows for HTTP/2 to return a shared reservation.
     fn reserve(self) -> Reservation<Self>;
     fn can_share(&self) -> bool;
+    /// Check if an idle connection is still usable, before reusing it.
+    ///
+    /// This can do more work than `is_open`, such as probing the transport.
+    fn is_alive(&self) -> bool {
+        self.is_open()
+    }
 }
 
 pub trait Key: Eq + Hash + Clone + Debug + Unpin + Send + 'static {}
 
+/// Observes the lifecycle of the connections in a `Pool`.
+///
+/// The observer is called from the task that used the pool, so it should
+/// return quickly. It is never called with the pool locked, so it may use
+/// the pool, or the `Client` it observes.
+pub trait PoolObserver<K>: Send + Sync + 'static {
+    /// Called when `event` happened to a connection for `key`.
+    fn on_event(&self, event: PoolEvent, key: &K, info: &PoolEventInfo);
+}
+
+/// An event in the lifecycle of a pooled connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PoolEvent {
+    /// A new connection was established.
+    Created,
+    /// An idle connection was checked out for another request.
+    Reused,
+    /// A connection was returned to the pool, and is now idle.
+    Returned,
+    /// An idle connection was evicted, because of the idle timeout or the
+    /// max lifetime.
+    EvictedIdle,
+    /// A connection was evicted, because it was closed or broken.
+    EvictedError,
+    /// An open connection was closed by the pool instead of kept idle,
+    /// because of the pool limits or the max lifetime.
+    Closed,
+}
+
+/// Timing information about the connection a `PoolEvent` happened to.
+#[derive(Clone, Copy, Debug)]
+pub struct PoolEventInfo {
+    age: Duration,
+    idle: Option<Duration>,
+}
+
+impl PoolEventInfo {
+    fn new(created_at: Instant, idle_at: Option<Instant>) -> Self {
+        // Avoid `Instant::elapsed` to avoid issues like rust-lang/rust#86470.
+        let now = Instant::now();
+        PoolEventInfo {
+            age: now.saturating_duration_since(created_at),
+            idle: idle_at.map(|idle_at| now.saturating_duration_since(idle_at)),
+        }
+    }
+
+    /// How long ago the connection was established.
+    pub fn age(&self) -> Duration {
+        self.age
+    }
+
+    /// How long the connection has been idle, if it was idle.
+    pub fn idle(&self) -> Option<Duration> {
+        self.idle
+    }
+}
+
+/// A snapshot of the connections in a `Pool`.
+#[derive(Clone, Debug)]
+pub struct PoolStats<K> {
+    hosts: HashMap<K, HostStats>,
+}
+
+/// A snapshot of the connections for one key in a `Pool`.
+#[derive(Clone, Debug, Default)]
+pub struct HostStats {
+    idle: usize,
+    active: usize,
+    pending: usize,
+    queued: usize,
+    idle_ages: Vec<Duration>,
+}
+
+impl<K: Key> PoolStats<K> {
+    /// The stats of every key with connections, or requests waiting for one.
+    pub fn hosts(&self) -> impl Iterator<Item = (&K, &HostStats)> {
+        self.hosts.iter()
+    }
+
+    /// The stats of `key`, if it has connections, or requests waiting for
+    /// one.
+    pub fn host(&self, key: &K) -> Option<&HostStats> {
+        self.hosts.get(key)
+    }
+
+    /// The number of idle connections, across all keys.
+    pub fn idle(&self) -> usize {
+        self.hosts.values().map(HostStats::idle).sum()
+    }
+
+    /// The number of open connections, across all keys.
+    pub fn active(&self) -> usize {
+        self.hosts.values().map(HostStats::active).sum()
+    }
+
+    /// The number of requests waiting for a connection, across all keys.
+    pub fn pending(&self) -> usize {
+        self.hosts.values().map(HostStats::pending).sum()
+    }
+}
+
+impl HostStats {
+    /// The number of idle connections.
+    pub fn idle(&self) -> usize {
+        self.idle
+    }
+
+    /// The number of open connections, including idle ones and ones that
+    /// are still being established.
+    ///
+    /// This is what the max active connections per host limits.
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// The number of requests waiting for a connection.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// The number of requests waiting for the number of open connections
+    /// to drop below the limit, so they can establish one.
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// How long each idle connection has been idle, shortest first.
+    pub fn idle_ages(&self) -> &[Duration] {
+        &self.idle_ages
+    }
+}
+
+/// Storage for the idle connections of a `Pool`.
+///
+/// The `Pool` still hands connections to waiting checkouts, shares HTTP/2
+/// connections, and evicts closed and expired connections. An `IdlePool`
+/// only decides where idle connections are kept and which one is checked
+/// out next, so alternative pooling strategies, such as per-tenant pools,
+/// can be plugged into the `Client` with
+/// [`Builder::build_with_pool`](crate::client::legacy::Builder::build_with_pool).
+///
+/// The connection type is opaque, so implementations are generic over `T`.
+pub trait IdlePool<T, K>: Send + 'static {
+    /// Take an idle connection for `key` out of the pool, if there is one.
+    fn checkout(&mut self, key: &K) -> Option<T>;
+
+    /// Put an idle connection for `key` into the pool.
+    ///
+    /// It counts against the idle limit of the pool until it is taken out
+    /// again with `checkout`, `close` or `retain`.
+    fn checkin(&mut self, key: K, value: T);
+
+    /// Drop all idle connections for `key`.
+    ///
+    /// This is called when a checkout found no usable connection for `key`,
+    /// so any state kept for it can be released.
+    fn close(&mut self, key: &K);
+
+    /// The number of idle connections for `key`.
+    fn idle_count(&self, key: &K) -> usize;
+
+    /// The number of idle connections for all keys.
+    fn total_idle_count(&self) -> usize;
+
+    /// Keep only the idle connections for which `f` returns `true`.
+    fn retain(&mut self, f: &mut dyn FnMut(&K, &mut T) -> bool);
+
+    /// Call `f` with each idle connection, without changing the pool.
+    ///
+    /// This is used to read the idle connections in
+    /// [`Client::pool_stats`](crate::client::legacy::Client::pool_stats).
+    /// The default calls `f` with none of them, so the stats of a pool that
+    /// doesn't implement it have no idle connections.
+    fn for_each(&self, f: &mut dyn FnMut(&K, &T)) {
+        let _ = f;
+    }
+}
+
 impl<T> Key for T where T: Eq + Hash + Clone + Debug + Unpin + Send + 'static {}
 
 /// A marker to identify what version a pooled connection is.
@@ -84,8 +288,22 @@ struct PoolInner<T, K: Eq + Hash> {
     connecting: HashSet<K>,
     // These are internal Conns sitting in the event loop in the KeepAlive
     // state, waiting to receive a new Request to send on the socket.
-    idle: HashMap<K, Vec<Idle<T>>>,
+    idle: Box<dyn IdlePool<Idle<T>, K>>,
+    // The idle connections of all shards, which `max_idle` limits.
+    idle_total: Arc<AtomicUsize>,
     max_idle_per_host: usize,
+    max_idle: usize,
+    // The number of open connections per key, including those that are
+    // still being established.
+    active: HashMap<K, Arc<ActiveCount>>,
+    max_active_per_host: usize,
+    // How many checkouts may wait for a free active slot, and for how long.
+    max_checkout_queue: usize,
+    checkout_timeout: Option<Duration>,
+    // The number of connections per key that are being established, and
+    // how many may be at once.
+    dialing: HashMap<K, Arc<ActiveCount>>,
+    max_connecting_per_host: usize,
     // These are outstanding Checkouts that are waiting for a socket to be
     // able to send a Request one. This is used when "racing" for a new
     // connection.
@@ -95,13 +313,18 @@ struct PoolInner<T, K: Eq + Hash> {
     // this list is checked for any parked Checkouts, and tries to notify
     // them that the Conn could be used instead of waiting for a brand new
     // connection.
-    waiters: HashMap<K, VecDeque<oneshot::Sender<T>>>,
-    // A oneshot channel is used to allow the interval to be notified when
-    // the Pool completely drops. That way, the interval can cancel immediately.
-    idle_interval_ref: Option<oneshot::Sender<Infallible>>,
+    waiters: HashMap<K, VecDeque<oneshot::Sender<Idle<T>>>>,
+    // The shards of the pool, for the IdleTask to sweep.
+    shards: Weak<Shards<T, K>>,
     exec: Exec,
     timer: Option<Timer>,
     timeout: Option<Duration>,
+    max_lifetime: Option<Duration>,
+    // How often the IdleTask sweeps, if not the idle timeout.
+    sweep_interval: Option<Duration>,
+    observer: ShardObserver<K>,
+    // Set by `Pool::close`, after which connections are no longer kept idle.
+    closed: bool,
 }
 
 // This is because `Weak::new()` *allocates* space for `T`, even if it
@@ -112,52 +335,165 @@ struct WeakOpt<T>(Option<Weak<T>>);
 pub struct Config {
     pub idle_timeout: Option<Duration>,
     pub max_idle_per_host: usize,
+    pub reuse: Reuse,
+    pub max_lifetime: Option<Duration>,
+    pub max_idle: usize,
+    pub max_active_per_host: usize,
+    pub sweep_interval: Option<Duration>,
+    pub max_checkout_queue: usize,
+    pub checkout_timeout: Option<Duration>,
+    pub max_connecting_per_host: usize,
+    pub shards: usize,
+}
+
+/// Which idle connection of a host the pool reuses first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reuse {
+    /// Reuse the most recently idle connection.
+    ///
+    /// Requests go to the connections that were used last, which tend to
+    /// have the lowest latency, and connections that aren't needed expire.
+    Lifo,
+    /// Reuse the connection that has been idle the longest.
+    ///
+    /// Requests are spread across all idle connections, which keeps more
+    /// of them alive.
+    Fifo,
 }
 
 impl Config {
     pub fn is_enabled(&self) -> bool {
-        self.max_idle_per_host > 0
+        self.max_idle_per_host > 0 && self.max_idle > 0
     }
 }
 
 impl<T, K: Key> Pool<T, K> {
     pub fn new<E, M>(config: Config, executor: E, timer: Option<M>) -> Pool<T, K>
+    where
+        T: Send + 'static,
+        E: hyper::rt::Executor<exec::BoxSendFuture> + Send + Sync + Clone + 'static,
+        M: hyper::rt::Timer + Send + Sync + Clone + 'static,
+    {
+        let shards = (0..config.shards.max(1))
+            .map(|_| Box::new(IdleMap::new(config.reuse)) as Box<dyn IdlePool<Idle<T>, K>>)
+            .collect();
+        Pool::with_shards(config, executor, timer, shards)
+    }
+
+    /// Create a pool that keeps its idle connections in `idle`.
+    ///
+    /// Since there is a single `idle`, the pool isn't sharded.
+    pub fn with_idle<E, M, P>(config: Config, executor: E, timer: Option<M>, idle: P) -> Pool<T, K>
+    where
+        E: hyper::rt::Executor<exec::BoxSendFuture> + Send + Sync + Clone + 'static,
+        M: hyper::rt::Timer + Send + Sync + Clone + 'static,
+        P: IdlePool<Idle<T>, K>,
+    {
+        Pool::with_shards(config, executor, timer, vec![Box::new(idle)])
+    }
+
+    fn with_shards<E, M>(
+        config: Config,
+        executor: E,
+        timer: Option<M>,
+        idles: Vec<Box<dyn IdlePool<Idle<T>, K>>>,
+    ) -> Pool<T, K>
     where
         E: hyper::rt::Executor<exec::BoxSendFuture> + Send + Sync + Clone + 'static,
         M: hyper::rt::Timer + Send + Sync + Clone + 'static,
     {
+        if !config.is_enabled() {
+            return Pool {
+                inner: None,
+                observer: Observer(None),
+            };
+        }
         let exec = Exec::new(executor);
         let timer = timer.map(|t| Timer::new(t));
-        let inner = if config.is_enabled() {
-            Some(Arc::new(Mutex::new(PoolInner {
-                connecting: HashSet::new(),
-                idle: HashMap::new(),
-                idle_interval_ref: None,
-                max_idle_per_host: config.max_idle_per_host,
-                waiters: HashMap::new(),
-                exec,
-                timer,
-                timeout: config.idle_timeout,
-            })))
-        } else {
-            None
-        };
+        let idle_total = Arc::new(AtomicUsize::new(0));
+        let shards = Arc::new_cyclic(|shards| {
+            let list = idles
+                .into_iter()
+                .map(|idle| {
+                    Arc::new(Mutex::new(PoolInner {
+                        connecting: HashSet::new(),
+                        idle,
+                        idle_total: idle_total.clone(),
+                        max_idle_per_host: config.max_idle_per_host,
+                        max_idle: config.max_idle,
+                        active: HashMap::new(),
+                        max_active_per_host: config.max_active_per_host,
+                        max_checkout_queue: config.max_checkout_queue,
+                        checkout_timeout: config.checkout_timeout,
+                        dialing: HashMap::new(),
+                        max_connecting_per_host: config.max_connecting_per_host,
+                        waiters: HashMap::new(),
+                        shards: shards.clone(),
+                        exec: exec.clone(),
+                        timer: timer.clone(),
+                        timeout: config.idle_timeout,
+                        max_lifetime: config.max_lifetime,
+                        sweep_interval: config.sweep_interval,
+                        observer: ShardObserver {
+                            observer: Observer(None),
+                            pending: Vec::new(),
+                        },
+                        closed: false,
+                    }))
+                })
+                .collect();
+            Shards {
+                list,
+                hasher: RandomState::new(),
+                closed: AtomicBool::new(false),
+                idle_interval_ref: Mutex::new(None),
+            }
+        });
+
+        Pool {
+            inner: Some(shards),
+            observer: Observer(None),
+        }
+    }
 
-        Pool { inner }
+    /// Report the lifecycle events of connections in this pool to `observer`.
+    pub fn with_observer(mut self, observer: Arc<dyn PoolObserver<K>>) -> Pool<T, K> {
+        for shard in self.shards() {
+            lock_shard(shard).unwrap().observer.observer = Observer(Some(observer.clone()));
+        }
+        self.observer = Observer(Some(observer));
+        self
     }
 
     pub(crate) fn is_enabled(&self) -> bool {
         self.inner.is_some()
     }
 
+    /// The shard that holds the state of `key`, if the pool is enabled.
+    // `BuildHasher::hash_one` is newer than the MSRV.
+    #[allow(clippy::manual_hash_one)]
+    fn shard(&self, key: &K) -> Option<&Shard<T, K>> {
+        let shards = self.inner.as_ref()?;
+        if shards.list.len() == 1 {
+            return Some(&shards.list[0]);
+        }
+        let mut hasher = shards.hasher.build_hasher();
+        key.hash(&mut hasher);
+        Some(&shards.list[(hasher.finish() % shards.list.len() as u64) as usize])
+    }
+
+    fn shards(&self) -> impl Iterator<Item = &Shard<T, K>> {
+        self.inner.iter().flat_map(|shards| shards.list.iter())
+    }
+
     #[cfg(test)]
     pub(super) fn no_timer(&self) {
         // Prevent an actual interval from being created for this pool...
-        {
-            let mut inner = self.inner.as_ref().unwrap().lock().unwrap();
-            assert!(inner.idle_interval_ref.is_none(), "timer already spawned");
+        if let Some(ref shards) = self.inner {
+            let mut idle_interval_ref = shards.idle_interval_ref.lock().unwrap();
+            assert!(idle_interval_ref.is_none(), "timer already spawned");
             let (tx, _) = oneshot::channel();
-            inner.idle_interval_ref = Some(tx);
+            *idle_interval_ref = Some(tx);
         }
     }
 }
@@ -177,8 +513,8 @@ impl<T: Poolable, K: Key> Pool<T, K> {
     /// connections. This does nothing for HTTP/1.
     pub fn connecting(&self, key: &K, ver: Ver) -> Option<Connecting<T, K>> {
         if ver == Ver::Http2 {
-            if let Some(ref enabled) = self.inner {
-                let mut inner = enabled.lock().unwrap();
+            if let Some(enabled) = self.shard(key) {
+                let mut inner = lock_shard(enabled).unwrap();
                 return if inner.connecting.insert(key.clone()) {
                     let connecting = Connecting {
                         key: key.clone(),
@@ -201,9 +537,185 @@ impl<T: Poolable, K: Key> Pool<T, K> {
         })
     }
 
+    /// Returns an `ActiveSlot` which is a future that resolves once another
+    /// connection for `key` may be established.
+    ///
+    /// The slot stays taken until the returned `Active` is dropped, which
+    /// should be kept with the connection for as long as it is open.
+    ///
+    /// Errors if too many checkouts are already waiting for a slot, or if
+    /// none is freed within the checkout timeout.
+    pub fn active_slot(&self, key: &K) -> ActiveSlot {
+        let mut slot = ActiveSlot {
+            count: None,
+            ticket: None,
+            timer: None,
+            timeout: None,
+            sleep: None,
+        };
+        if let Some(enabled) = self.shard(key) {
+            let mut inner = lock_shard(enabled).unwrap();
+            let max = inner.max_active_per_host;
+            let max_queue = inner.max_checkout_queue;
+            let count = inner
+                .active
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    Arc::new(ActiveCount {
+                        max,
+                        max_queue,
+                        state: Mutex::new(ActiveState {
+                            count: 0,
+                            queue: VecDeque::new(),
+                            next_ticket: 0,
+                        }),
+                    })
+                })
+                .clone();
+            slot.count = Some(count);
+            slot.timer = inner.timer.clone();
+            slot.timeout = inner.checkout_timeout;
+        }
+        slot
+    }
+
+    /// Returns an `ActiveSlot` which is a future that resolves once another
+    /// connection for `key` may start being established.
+    ///
+    /// The slot stays taken until the returned `Active` is dropped, which
+    /// should be once the connection is established, or failed. Resolves
+    /// right away if there is no limit, or the pool is closed.
+    pub fn dial_slot(&self, key: &K) -> ActiveSlot {
+        let mut slot = ActiveSlot {
+            count: None,
+            ticket: None,
+            timer: None,
+            timeout: None,
+            sleep: None,
+        };
+        if let Some(enabled) = self.shard(key) {
+            let mut inner = lock_shard(enabled).unwrap();
+            let max = inner.max_connecting_per_host;
+            if max == usize::MAX || inner.closed {
+                return slot;
+            }
+            let count = inner
+                .dialing
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    Arc::new(ActiveCount {
+                        max,
+                        max_queue: usize::MAX,
+                        state: Mutex::new(ActiveState {
+                            count: 0,
+                            queue: VecDeque::new(),
+                            next_ticket: 0,
+                        }),
+                    })
+                })
+                .clone();
+            slot.count = Some(count);
+        }
+        slot
+    }
+
+    /// Whether there is an idle connection for `key` to check out.
+    pub fn has_idle(&self, key: &K) -> bool {
+        match self.shard(key) {
+            Some(enabled) => lock_shard(enabled).unwrap().idle.idle_count(key) > 0,
+            None => false,
+        }
+    }
+
+    /// Evict the idle connections that are closed or expired, right away.
+    ///
+    /// This is otherwise done periodically, if the pool has a timer.
+    pub fn sweep(&self) {
+        if self.inner.is_some() {
+            trace!("sweeping idle connections");
+        }
+        for shard in self.shards() {
+            lock_shard(shard).unwrap().clear_expired();
+        }
+    }
+
+    /// Close the idle connections, and stop keeping connections idle.
+    ///
+    /// Checkouts no longer wait for an idle connection, and connections
+    /// returned to the pool are dropped, which closes HTTP/1 connections
+    /// and lets HTTP/2 connections go away once their streams finish.
+    pub fn close(&self) {
+        if let Some(ref shards) = self.inner {
+            trace!("closing pool");
+            shards.closed.store(true, Ordering::SeqCst);
+        }
+        for shard in self.shards() {
+            let mut inner = lock_shard(shard).unwrap();
+            inner.closed = true;
+            let inner = &mut *inner;
+            let observer = &mut inner.observer;
+            let mut closed = 0;
+            inner.idle.retain(&mut |key, entry| {
+                observer.notify_idle(PoolEvent::Closed, key, entry);
+                closed += 1;
+                false
+            });
+            inner.idle_total.fetch_sub(closed, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the pool was closed with `close`.
+    pub fn is_closed(&self) -> bool {
+        match self.inner {
+            Some(ref shards) => shards.closed.load(Ordering::SeqCst),
+            None => false,
+        }
+    }
+
+    /// Take a snapshot of the connections in the pool, per key.
+    ///
+    /// The snapshot is empty if the pool is disabled.
+    pub fn stats(&self) -> PoolStats<K> {
+        let mut stats = PoolStats {
+            hosts: HashMap::new(),
+        };
+        let now = Instant::now();
+        let hosts = &mut stats.hosts;
+
+        // Each key lives in a single shard, so the shards add up.
+        for shard in self.shards() {
+            let inner = lock_shard(shard).unwrap();
+            inner.idle.for_each(&mut |key, idle| {
+                let host = hosts.entry(key.clone()).or_default();
+                host.idle += 1;
+                host.idle_ages
+                    .push(now.saturating_duration_since(idle.idle_at));
+            });
+            for (key, count) in &inner.active {
+                let state = count.state.lock().unwrap();
+                if state.count == 0 && state.queue.is_empty() {
+                    continue;
+                }
+                let host = hosts.entry(key.clone()).or_default();
+                host.active = state.count;
+                host.queued = state.queue.len();
+            }
+            for (key, waiters) in &inner.waiters {
+                let pending = waiters.iter().filter(|tx| !tx.is_canceled()).count();
+                if pending > 0 {
+                    hosts.entry(key.clone()).or_default().pending = pending;
+                }
+            }
+        }
+        for host in hosts.values_mut() {
+            host.idle_ages.sort_unstable();
+        }
+        stats
+    }
+
     #[cfg(test)]
     fn locked(&self) -> std::sync::MutexGuard<'_, PoolInner<T, K>> {
-        self.inner.as_ref().expect("enabled").lock().expect("lock")
+        self.shards().next().expect("enabled").lock().expect("lock")
     }
 
     /* Used in client/tests.rs...
@@ -228,12 +740,12 @@ impl<T: Poolable, K: Key> Pool<T, K> {
         #[cfg_attr(not(feature = "http2"), allow(unused_mut))] mut connecting: Connecting<T, K>,
         value: T,
     ) -> Pooled<T, K> {
-        let (value, pool_ref) = if let Some(ref enabled) = self.inner {
+        let (value, pool_ref) = if let Some(enabled) = self.shard(&connecting.key) {
             match value.reserve() {
                 #[cfg(feature = "http2")]
                 Reservation::Shared(to_insert, to_return) => {
-                    let mut inner = enabled.lock().unwrap();
-                    inner.put(connecting.key.clone(), to_insert, enabled);
+                    let mut inner = lock_shard(enabled).unwrap();
+                    inner.put(connecting.key.clone(), to_insert, Instant::now());
                     // Do this here instead of Drop for Connecting because we
                     // already have a lock, no need to lock the mutex twice.
                     inner.connected(&connecting.key);
@@ -259,16 +771,36 @@ impl<T: Poolable, K: Key> Pool<T, K> {
 
             (value, WeakOpt::none())
         };
+        let created_at = Instant::now();
+        self.observer
+            .notify(PoolEvent::Created, &connecting.key, created_at, None);
         Pooled {
             key: connecting.key.clone(),
             is_reused: false,
+            created_at,
             pool: pool_ref,
             value: Some(value),
         }
     }
 
-    fn reuse(&self, key: &K, value: T) -> Pooled<T, K> {
+    /// Wrap a new connection that is never returned to the pool, nor
+    /// shared with other checkouts.
+    pub fn unpooled(&self, connecting: Connecting<T, K>, value: T) -> Pooled<T, K> {
+        // Only an HTTP/1 `Connecting` can be unpooled, it has no lock.
+        debug_assert!(connecting.pool.upgrade().is_none());
+        Pooled {
+            key: connecting.key.clone(),
+            is_reused: false,
+            created_at: Instant::now(),
+            pool: WeakOpt::none(),
+            value: Some(value),
+        }
+    }
+
+    fn reuse(&self, key: &K, value: T, created_at: Instant, idle_at: Instant) -> Pooled<T, K> {
         debug!("reuse idle connection for {:?}", key);
+        self.observer
+            .notify(PoolEvent::Reused, key, created_at, Some(idle_at));
         // TODO: unhack this
         // In Pool::pooled(), which is used for inserting brand new connections,
         // there's some code that adjusts the pool reference taken depending
@@ -279,13 +811,14 @@ impl<T: Poolable, K: Key> Pool<T, K> {
         // shared... :(
         let mut pool_ref = WeakOpt::none();
         if !value.can_share() {
-            if let Some(ref enabled) = self.inner {
+            if let Some(enabled) = self.shard(key) {
                 pool_ref = WeakOpt::downgrade(enabled);
             }
         }
 
         Pooled {
             is_reused: true,
+            created_at,
             key: key.clone(),
             pool: pool_ref,
             value: Some(value),
@@ -293,39 +826,45 @@ impl<T: Poolable, K: Key> Pool<T, K> {
     }
 }
 
-/// Pop off this list, looking for a usable connection that hasn't expired.
+/// Pop off the idle connections, looking for a usable one that hasn't expired.
 struct IdlePopper<'a, T, K> {
     key: &'a K,
-    list: &'a mut Vec<Idle<T>>,
+    idle: &'a mut dyn IdlePool<Idle<T>, K>,
+    idle_total: &'a AtomicUsize,
+    observer: &'a mut ShardObserver<K>,
 }
 
-impl<'a, T: Poolable + 'a, K: Debug> IdlePopper<'a, T, K> {
+impl<'a, T: Poolable + 'a, K: Key> IdlePopper<'a, T, K> {
     fn pop(self, expiration: &Expiration) -> Option<Idle<T>> {
-        while let Some(entry) = self.list.pop() {
+        while let Some(entry) = self.idle.checkout(self.key) {
+            self.idle_total.fetch_sub(1, Ordering::Relaxed);
             // If the connection has been closed, or is older than our idle
             // timeout, simply drop it and keep looking...
-            if !entry.value.is_open() {
+            if !entry.value.is_alive() {
                 trace!("removing closed connection for {:?}", self.key);
+                self.observer
+                    .notify_idle(PoolEvent::EvictedError, self.key, &entry);
                 continue;
             }
-            // TODO: Actually, since the `idle` list is pushed to the end always,
-            // that would imply that if *this* entry is expired, then anything
-            // "earlier" in the list would *have* to be expired also... Right?
-            //
-            // In that case, we could just break out of the loop and drop the
-            // whole list...
-            if expiration.expires(entry.idle_at) {
+            if expiration.expires(&entry) {
                 trace!("removing expired connection for {:?}", self.key);
+                self.observer
+                    .notify_idle(PoolEvent::EvictedIdle, self.key, &entry);
                 continue;
             }
 
             let value = match entry.value.reserve() {
                 #[cfg(feature = "http2")]
                 Reservation::Shared(to_reinsert, to_checkout) => {
-                    self.list.push(Idle {
-                        idle_at: Instant::now(),
-                        value: to_reinsert,
-                    });
+                    self.idle.checkin(
+                        self.key.clone(),
+                        Idle {
+                            idle_at: Instant::now(),
+                            created_at: entry.created_at,
+                            value: to_reinsert,
+                        },
+                    );
+                    self.idle_total.fetch_add(1, Ordering::Relaxed);
                     to_checkout
                 }
                 Reservation::Unique(unique) => unique,
@@ -333,6 +872,7 @@ impl<'a, T: Poolable + 'a, K: Debug> IdlePopper<'a, T, K> {
 
             return Some(Idle {
                 idle_at: entry.idle_at,
+                created_at: entry.created_at,
                 value,
             });
         }
@@ -342,8 +882,23 @@ impl<'a, T: Poolable + 'a, K: Debug> IdlePopper<'a, T, K> {
 }
 
 impl<T: Poolable, K: Key> PoolInner<T, K> {
-    fn put(&mut self, key: K, value: T, __pool_ref: &Arc<Mutex<PoolInner<T, K>>>) {
-        if value.can_share() && self.idle.contains_key(&key) {
+    fn put(&mut self, key: K, value: T, created_at: Instant) {
+        if self.closed {
+            trace!("put; pool closed, dropping connection for {:?}", key);
+            self.observer
+                .notify(PoolEvent::Closed, &key, created_at, None);
+            return;
+        }
+        if let Some(max_lifetime) = self.max_lifetime {
+            // Avoid `Instant::elapsed` to avoid issues like rust-lang/rust#86470.
+            if Instant::now().saturating_duration_since(created_at) > max_lifetime {
+                trace!("put; retiring connection past max lifetime for {:?}", key);
+                self.observer
+                    .notify(PoolEvent::Closed, &key, created_at, None);
+                return;
+            }
+        }
+        if value.can_share() && self.idle.idle_count(&key) > 0 {
             trace!("put; existing idle HTTP/2 connection for {:?}", key);
             return;
         }
@@ -362,6 +917,11 @@ impl<T: Poolable, K: Key> PoolInner<T, K> {
                         }
                         Reservation::Unique(uniq) => uniq,
                     };
+                    let reserved = Idle {
+                        idle_at: Instant::now(),
+                        created_at,
+                        value: reserved,
+                    };
                     match tx.send(reserved) {
                         Ok(()) => {
                             if value.is_none() {
@@ -371,7 +931,7 @@ impl<T: Poolable, K: Key> PoolInner<T, K> {
                             }
                         }
                         Err(e) => {
-                            value = Some(e);
+                            value = Some(e.value);
                         }
                     }
                 }
@@ -386,22 +946,33 @@ impl<T: Poolable, K: Key> PoolInner<T, K> {
 
         match value {
             Some(value) => {
-                // borrow-check scope...
-                {
-                    let idle_list = self.idle.entry(key.clone()).or_default();
-                    if self.max_idle_per_host <= idle_list.len() {
-                        trace!("max idle per host for {:?}, dropping connection", key);
-                        return;
-                    }
+                if self.max_idle_per_host <= self.idle.idle_count(&key) {
+                    trace!("max idle per host for {:?}, dropping connection", key);
+                    self.observer
+                        .notify(PoolEvent::Closed, &key, created_at, None);
+                    return;
+                }
+                if self.max_idle <= self.idle_total.load(Ordering::Relaxed) {
+                    trace!("max idle for pool, dropping connection for {:?}", key);
+                    self.observer
+                        .notify(PoolEvent::Closed, &key, created_at, None);
+                    return;
+                }
 
-                    debug!("pooling idle connection for {:?}", key);
-                    idle_list.push(Idle {
+                debug!("pooling idle connection for {:?}", key);
+                self.observer
+                    .notify(PoolEvent::Returned, &key, created_at, None);
+                self.idle.checkin(
+                    key,
+                    Idle {
                         value,
                         idle_at: Instant::now(),
-                    });
-                }
+                        created_at,
+                    },
+                );
+                self.idle_total.fetch_add(1, Ordering::Relaxed);
 
-                self.spawn_idle_interval(__pool_ref);
+                self.spawn_idle_interval();
             }
             None => trace!("put; found waiter for {:?}", key),
         }
@@ -418,11 +989,10 @@ impl<T: Poolable, K: Key> PoolInner<T, K> {
         self.waiters.remove(key);
     }
 
-    fn spawn_idle_interval(&mut self, pool_ref: &Arc<Mutex<PoolInner<T, K>>>) {
-        if self.idle_interval_ref.is_some() {
-            return;
-        }
-        let dur = if let Some(dur) = self.timeout {
+    // Spawn the IdleTask of the pool, which sweeps all of its shards, if no
+    // shard did yet.
+    fn spawn_idle_interval(&mut self) {
+        let dur = if let Some(dur) = self.sweep_interval.or(self.timeout) {
             dur
         } else {
             return;
@@ -432,15 +1002,23 @@ impl<T: Poolable, K: Key> PoolInner<T, K> {
         } else {
             return;
         };
+        let shards = match self.shards.upgrade() {
+            Some(shards) => shards,
+            None => return,
+        };
+        let mut idle_interval_ref = shards.idle_interval_ref.lock().unwrap();
+        if idle_interval_ref.is_some() {
+            return;
+        }
         let (tx, rx) = oneshot::channel();
-        self.idle_interval_ref = Some(tx);
+        *idle_interval_ref = Some(tx);
 
         let interval = IdleTask {
             timer: timer.clone(),
             duration: dur,
             deadline: Instant::now(),
             fut: timer.sleep_until(Instant::now()), // ready at first tick
-            pool: WeakOpt::downgrade(pool_ref),
+            pool: Arc::downgrade(&shards),
             pool_drop_notifier: rx,
         };
 
@@ -466,33 +1044,35 @@ impl<T, K: Eq + Hash> PoolInner<T, K> {
 }
 
 impl<T: Poolable, K: Key> PoolInner<T, K> {
-    /// This should *only* be called by the IdleTask
+    /// Called by the IdleTask, and by `Pool::sweep`.
     fn clear_expired(&mut self) {
-        let dur = self.timeout.expect("interval assumes timeout");
-
-        let now = Instant::now();
-        //self.last_idle_check_at = now;
-
-        self.idle.retain(|key, values| {
-            values.retain(|entry| {
-                if !entry.value.is_open() {
-                    trace!("idle interval evicting closed for {:?}", key);
-                    return false;
-                }
+        let expiration = Expiration::new(self.timeout, self.max_lifetime);
+        let observer = &mut self.observer;
+        let mut evicted = 0;
 
-                // Avoid `Instant::sub` to avoid issues like rust-lang/rust#86470.
-                if now.saturating_duration_since(entry.idle_at) > dur {
-                    trace!("idle interval evicting expired for {:?}", key);
-                    return false;
-                }
+        self.idle.retain(&mut |key, entry| {
+            if !entry.value.is_open() {
+                trace!("idle interval evicting closed for {:?}", key);
+                observer.notify_idle(PoolEvent::EvictedError, key, entry);
+                evicted += 1;
+                return false;
+            }
 
-                // Otherwise, keep this value...
-                true
-            });
+            if expiration.expires(entry) {
+                trace!("idle interval evicting expired for {:?}", key);
+                observer.notify_idle(PoolEvent::EvictedIdle, key, entry);
+                evicted += 1;
+                return false;
+            }
 
-            // returning false evicts this key/val
-            !values.is_empty()
+            // Otherwise, keep this value...
+            true
         });
+        self.idle_total.fetch_sub(evicted, Ordering::Relaxed);
+
+        // Forget the counts of keys without connections or waiting slots.
+        self.active.retain(|_, count| Arc::strong_count(count) > 1);
+        self.dialing.retain(|_, count| Arc::strong_count(count) > 1);
     }
 }
 
@@ -500,6 +1080,7 @@ impl<T, K: Key> Clone for Pool<T, K> {
     fn clone(&self) -> Pool<T, K> {
         Pool {
             inner: self.inner.clone(),
+            observer: self.observer.clone(),
         }
     }
 }
@@ -509,6 +1090,7 @@ impl<T, K: Key> Clone for Pool<T, K> {
 pub struct Pooled<T: Poolable, K: Key> {
     value: Option<T>,
     is_reused: bool,
+    created_at: Instant,
     key: K,
     pool: WeakOpt<Mutex<PoolInner<T, K>>>,
 }
@@ -550,12 +1132,22 @@ impl<T: Poolable, K: Key> Drop for Pooled<T, K> {
             if !value.is_open() {
                 // If we *already* know the connection is done here,
                 // it shouldn't be re-inserted back into the pool.
+                if let Some(pool) = self.pool.upgrade() {
+                    if let Ok(mut inner) = lock_shard(&pool) {
+                        inner.observer.notify(
+                            PoolEvent::EvictedError,
+                            &self.key,
+                            self.created_at,
+                            None,
+                        );
+                    }
+                }
                 return;
             }
 
             if let Some(pool) = self.pool.upgrade() {
-                if let Ok(mut inner) = pool.lock() {
-                    inner.put(self.key.clone(), value, &pool);
+                if let Ok(mut inner) = lock_shard(&pool) {
+                    inner.put(self.key.clone(), value, self.created_at);
                 }
             } else if !value.can_share() {
                 trace!("pool dropped, dropping pooled ({:?})", self.key);
@@ -572,17 +1164,102 @@ impl<T: Poolable, K: Key> fmt::Debug for Pooled<T, K> {
     }
 }
 
-struct Idle<T> {
+/// An idle connection, as stored in an [`IdlePool`](IdlePool).
+pub struct Idle<T> {
     idle_at: Instant,
+    created_at: Instant,
     value: T,
 }
 
+impl<T> Idle<T> {
+    /// The time this connection became idle.
+    pub fn idle_at(&self) -> Instant {
+        self.idle_at
+    }
+
+    /// The time this connection was established.
+    pub fn created_at(&self) -> Instant {
+        self.created_at
+    }
+}
+
+impl<T> fmt::Debug for Idle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Idle")
+            .field("idle_at", &self.idle_at)
+            .field("created_at", &self.created_at)
+            .finish()
+    }
+}
+
+/// The default `IdlePool`, keeping a list of idle connections per key.
+struct IdleMap<T, K> {
+    lists: HashMap<K, VecDeque<T>>,
+    reuse: Reuse,
+}
+
+impl<T, K> IdleMap<T, K> {
+    fn new(reuse: Reuse) -> Self {
+        IdleMap {
+            lists: HashMap::new(),
+            reuse,
+        }
+    }
+}
+
+impl<T, K> IdlePool<T, K> for IdleMap<T, K>
+where
+    T: Send + 'static,
+    K: Eq + Hash + Send + 'static,
+{
+    fn checkout(&mut self, key: &K) -> Option<T> {
+        let list = self.lists.get_mut(key)?;
+        // Connections are always pushed to the back of the list.
+        match self.reuse {
+            Reuse::Lifo => list.pop_back(),
+            Reuse::Fifo => list.pop_front(),
+        }
+    }
+
+    fn checkin(&mut self, key: K, value: T) {
+        self.lists.entry(key).or_default().push_back(value);
+    }
+
+    fn close(&mut self, key: &K) {
+        self.lists.remove(key);
+    }
+
+    fn idle_count(&self, key: &K) -> usize {
+        self.lists.get(key).map_or(0, VecDeque::len)
+    }
+
+    fn total_idle_count(&self) -> usize {
+        self.lists.values().map(VecDeque::len).sum()
+    }
+
+    fn retain(&mut self, f: &mut dyn FnMut(&K, &mut T) -> bool) {
+        self.lists.retain(|key, values| {
+            values.retain_mut(|value| f(key, value));
+            // returning false evicts this key/val
+            !values.is_empty()
+        });
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&K, &T)) {
+        for (key, values) in &self.lists {
+            for value in values {
+                f(key, value);
+            }
+        }
+    }
+}
+
 // FIXME: allow() required due to `impl Trait` leaking types to this lint
 #[allow(missing_debug_implementations)]
 pub struct Checkout<T, K: Key> {
     key: K,
     pool: Pool<T, K>,
-    waiter: Option<oneshot::Receiver<T>>,
+    waiter: Option<oneshot::Receiver<Idle<T>>>,
 }
 
 #[derive(Debug)]
@@ -591,6 +1268,8 @@ pub enum Error {
     PoolDisabled,
     CheckoutNoLongerWanted,
     CheckedOutClosedValue,
+    CheckoutQueueFull,
+    CheckoutTimedOut,
 }
 
 impl Error {
@@ -605,6 +1284,8 @@ impl fmt::Display for Error {
             Error::PoolDisabled => "pool is disabled",
             Error::CheckedOutClosedValue => "checked out connection was closed",
             Error::CheckoutNoLongerWanted => "request was canceled",
+            Error::CheckoutQueueFull => "too many checkouts waiting for a connection",
+            Error::CheckoutTimedOut => "timed out waiting for a connection",
         })
     }
 }
@@ -618,9 +1299,14 @@ impl<T: Poolable, K: Key> Checkout<T, K> {
     ) -> Poll<Option<Result<Pooled<T, K>, Error>>> {
         if let Some(mut rx) = self.waiter.take() {
             match Pin::new(&mut rx).poll(cx) {
-                Poll::Ready(Ok(value)) => {
-                    if value.is_open() {
-                        Poll::Ready(Some(Ok(self.pool.reuse(&self.key, value))))
+                Poll::Ready(Ok(entry)) => {
+                    if entry.value.is_open() {
+                        Poll::Ready(Some(Ok(self.pool.reuse(
+                            &self.key,
+                            entry.value,
+                            entry.created_at,
+                            entry.idle_at,
+                        ))))
                     } else {
                         Poll::Ready(Some(Err(Error::CheckedOutClosedValue)))
                     }
@@ -640,31 +1326,27 @@ impl<T: Poolable, K: Key> Checkout<T, K> {
 
     fn checkout(&mut self, cx: &mut task::Context<'_>) -> Option<Pooled<T, K>> {
         let entry = {
-            let mut inner = self.pool.inner.as_ref()?.lock().unwrap();
-            let expiration = Expiration::new(inner.timeout);
-            let maybe_entry = inner.idle.get_mut(&self.key).and_then(|list| {
-                trace!("take? {:?}: expiration = {:?}", self.key, expiration.0);
-                // A block to end the mutable borrow on list,
-                // so the map below can check is_empty()
-                {
-                    let popper = IdlePopper {
-                        key: &self.key,
-                        list,
-                    };
-                    popper.pop(&expiration)
-                }
-                .map(|e| (e, list.is_empty()))
-            });
+            let mut inner = lock_shard(self.pool.shard(&self.key)?).unwrap();
+            let expiration = Expiration::new(inner.timeout, inner.max_lifetime);
+            trace!(
+                "take? {:?}: expiration = {:?}",
+                self.key,
+                expiration.idle_timeout
+            );
+            let inner = &mut *inner;
+            let entry = IdlePopper {
+                key: &self.key,
+                idle: &mut *inner.idle,
+                idle_total: &inner.idle_total,
+                observer: &mut inner.observer,
+            }
+            .pop(&expiration);
 
-            let (entry, empty) = if let Some((e, empty)) = maybe_entry {
-                (Some(e), empty)
-            } else {
+            if entry.is_none() {
                 // No entry found means nuke the list for sure.
-                (None, true)
-            };
-            if empty {
-                //TODO: This could be done with the HashMap::entry API instead.
-                inner.idle.remove(&self.key);
+                let closed = inner.idle.idle_count(&self.key);
+                inner.idle.close(&self.key);
+                inner.idle_total.fetch_sub(closed, Ordering::Relaxed);
             }
 
             if entry.is_none() && self.waiter.is_none() {
@@ -684,7 +1366,7 @@ impl<T: Poolable, K: Key> Checkout<T, K> {
             entry
         };
 
-        entry.map(|e| self.pool.reuse(&self.key, e.value))
+        entry.map(|e| self.pool.reuse(&self.key, e.value, e.created_at, e.idle_at))
     }
 }
 
@@ -696,6 +1378,11 @@ impl<T: Poolable, K: Key> Future for Checkout<T, K> {
             return Poll::Ready(Ok(pooled));
         }
 
+        if self.pool.is_closed() {
+            // Like a closed checked out value, so that connecting goes on.
+            return Poll::Ready(Err(Error::CheckedOutClosedValue));
+        }
+
         if let Some(pooled) = self.checkout(cx) {
             Poll::Ready(Ok(pooled))
         } else if !self.pool.is_enabled() {
@@ -712,7 +1399,7 @@ impl<T, K: Key> Drop for Checkout<T, K> {
     fn drop(&mut self) {
         if self.waiter.take().is_some() {
             trace!("checkout dropped for {:?}", self.key);
-            if let Some(Ok(mut inner)) = self.pool.inner.as_ref().map(|i| i.lock()) {
+            if let Some(Ok(mut inner)) = self.pool.shard(&self.key).map(|shard| lock_shard(shard)) {
                 inner.clean_waiters(&self.key);
             }
         }
@@ -741,48 +1428,278 @@ impl<T: Poolable, K: Key> Drop for Connecting<T, K> {
     fn drop(&mut self) {
         if let Some(pool) = self.pool.upgrade() {
             // No need to panic on drop, that could abort!
-            if let Ok(mut inner) = pool.lock() {
+            if let Ok(mut inner) = lock_shard(&pool) {
                 inner.connected(&self.key);
             }
         }
     }
 }
 
-struct Expiration(Option<Duration>);
+struct Observer<K>(Option<Arc<dyn PoolObserver<K>>>);
 
-impl Expiration {
-    fn new(dur: Option<Duration>) -> Expiration {
-        Expiration(dur)
+impl<K: Key> Observer<K> {
+    fn notify(&self, event: PoolEvent, key: &K, created_at: Instant, idle_at: Option<Instant>) {
+        if let Some(ref observer) = self.0 {
+            observer.on_event(event, key, &PoolEventInfo::new(created_at, idle_at));
+        }
     }
+}
 
-    fn expires(&self, instant: Instant) -> bool {
-        match self.0 {
-            // Avoid `Instant::elapsed` to avoid issues like rust-lang/rust#86470.
-            Some(timeout) => Instant::now().saturating_duration_since(instant) > timeout,
-            None => false,
+// The observer of a shard. Its events happen with the shard locked, so they
+// are kept until the `ShardGuard` unlocks it, and reported then.
+struct ShardObserver<K> {
+    observer: Observer<K>,
+    pending: Vec<(PoolEvent, K, PoolEventInfo)>,
+}
+
+impl<K: Key> ShardObserver<K> {
+    fn notify(&mut self, event: PoolEvent, key: &K, created_at: Instant, idle_at: Option<Instant>) {
+        if self.observer.0.is_some() {
+            let info = PoolEventInfo::new(created_at, idle_at);
+            self.pending.push((event, key.clone(), info));
         }
     }
-}
 
-pin_project_lite::pin_project! {
-    struct IdleTask<T, K: Key> {
-        timer: Timer,
-        duration: Duration,
-        deadline: Instant,
-        fut: Pin<Box<dyn Sleep>>,
-        pool: WeakOpt<Mutex<PoolInner<T, K>>>,
-        // This allows the IdleTask to be notified as soon as the entire
-        // Pool is fully dropped, and shutdown. This channel is never sent on,
-        // but Err(Canceled) will be received when the Pool is dropped.
-        #[pin]
-        pool_drop_notifier: oneshot::Receiver<Infallible>,
+    fn notify_idle<T>(&mut self, event: PoolEvent, key: &K, entry: &Idle<T>) {
+        self.notify(event, key, entry.created_at, Some(entry.idle_at));
     }
 }
 
-impl<T: Poolable + 'static, K: Key> Future for IdleTask<T, K> {
-    type Output = ();
+// A locked shard, which reports the events that happened while it was
+// locked once it is unlocked, so that observers can use the pool.
+struct ShardGuard<'a, T, K: Key> {
+    inner: Option<MutexGuard<'a, PoolInner<T, K>>>,
+}
 
-    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+fn lock_shard<T, K: Key>(shard: &Mutex<PoolInner<T, K>>) -> LockResult<ShardGuard<'_, T, K>> {
+    match shard.lock() {
+        Ok(inner) => Ok(ShardGuard { inner: Some(inner) }),
+        Err(err) => Err(PoisonError::new(ShardGuard {
+            inner: Some(err.into_inner()),
+        })),
+    }
+}
+
+impl<T, K: Key> Deref for ShardGuard<'_, T, K> {
+    type Target = PoolInner<T, K>;
+
+    fn deref(&self) -> &PoolInner<T, K> {
+        self.inner.as_ref().expect("locked")
+    }
+}
+
+impl<T, K: Key> DerefMut for ShardGuard<'_, T, K> {
+    fn deref_mut(&mut self) -> &mut PoolInner<T, K> {
+        self.inner.as_mut().expect("locked")
+    }
+}
+
+impl<T, K: Key> Drop for ShardGuard<'_, T, K> {
+    fn drop(&mut self) {
+        let mut inner = match self.inner.take() {
+            Some(inner) => inner,
+            None => return,
+        };
+        if inner.observer.pending.is_empty() {
+            return;
+        }
+        let pending = std::mem::take(&mut inner.observer.pending);
+        let observer = inner.observer.observer.clone();
+        drop(inner);
+        if let Some(observer) = observer.0 {
+            for (event, key, info) in pending {
+                observer.on_event(event, &key, &info);
+            }
+        }
+    }
+}
+
+impl<K> Clone for Observer<K> {
+    fn clone(&self) -> Self {
+        Observer(self.0.clone())
+    }
+}
+
+struct ActiveCount {
+    max: usize,
+    // How many checkouts may wait for a free slot.
+    max_queue: usize,
+    state: Mutex<ActiveState>,
+}
+
+struct ActiveState {
+    count: usize,
+    // Checkouts waiting for a free slot, served in the order they arrived.
+    queue: VecDeque<(u64, Waker)>,
+    next_ticket: u64,
+}
+
+impl ActiveState {
+    // Wake the next checkout in line, if there's a slot for it.
+    fn wake_next(&self, max: usize) {
+        if self.count < max {
+            if let Some((_, waker)) = self.queue.front() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+/// A taken slot for an open connection, released when dropped.
+// FIXME: allow() required due to `impl Trait` leaking types to this lint
+#[allow(missing_debug_implementations)]
+pub struct Active {
+    count: Arc<ActiveCount>,
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        // This lock is never held while dropping an `Active`, so this can't
+        // deadlock, even if the pool is locked.
+        if let Ok(mut state) = self.count.state.lock() {
+            state.count -= 1;
+            state.wake_next(self.count.max);
+        }
+    }
+}
+
+/// A future that resolves once a slot for a new connection is free.
+///
+/// Resolves to `None` if the pool is disabled, since there is nothing to
+/// count connections against.
+///
+/// Checkouts that have to wait are queued, and get a slot in the order
+/// they started waiting.
+// FIXME: allow() required due to `impl Trait` leaking types to this lint
+#[allow(missing_debug_implementations)]
+#[must_use = "futures do nothing unless polled"]
+pub struct ActiveSlot {
+    count: Option<Arc<ActiveCount>>,
+    ticket: Option<u64>,
+    timer: Option<Timer>,
+    timeout: Option<Duration>,
+    sleep: Option<Pin<Box<dyn Sleep>>>,
+}
+
+impl Future for ActiveSlot {
+    type Output = Result<Option<Active>, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let count = match self.count {
+            Some(ref count) => count.clone(),
+            None => return Poll::Ready(Ok(None)),
+        };
+        {
+            let mut state = count.state.lock().unwrap();
+            let first = state.queue.front().map(|&(ticket, _)| ticket);
+            let is_next = match self.ticket {
+                Some(ticket) => first == Some(ticket),
+                None => first.is_none(),
+            };
+            if state.count < count.max && is_next {
+                if self.ticket.take().is_some() {
+                    state.queue.pop_front();
+                }
+                state.count += 1;
+                state.wake_next(count.max);
+                return Poll::Ready(Ok(Some(Active {
+                    count: count.clone(),
+                })));
+            }
+
+            match self.ticket {
+                Some(ticket) => {
+                    if let Some(entry) = state.queue.iter_mut().find(|(t, _)| *t == ticket) {
+                        if !entry.1.will_wake(cx.waker()) {
+                            entry.1 = cx.waker().clone();
+                        }
+                    }
+                }
+                None => {
+                    if state.queue.len() >= count.max_queue {
+                        debug!("max active connections, and checkout queue is full");
+                        return Poll::Ready(Err(Error::CheckoutQueueFull));
+                    }
+                    trace!("max active connections, waiting for a free slot");
+                    let ticket = state.next_ticket;
+                    state.next_ticket += 1;
+                    state.queue.push_back((ticket, cx.waker().clone()));
+                    self.ticket = Some(ticket);
+                }
+            }
+        }
+
+        if self.sleep.is_none() {
+            if let (Some(timer), Some(timeout)) = (&self.timer, self.timeout) {
+                let sleep = timer.sleep(timeout);
+                self.sleep = Some(sleep);
+            }
+        }
+        if let Some(ref mut sleep) = self.sleep {
+            if sleep.as_mut().poll(cx).is_ready() {
+                debug!("timed out waiting for a free connection slot");
+                return Poll::Ready(Err(Error::CheckoutTimedOut));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for ActiveSlot {
+    fn drop(&mut self) {
+        // Give up our place in line, and pass our turn on if it was ours.
+        if let (Some(ticket), Some(count)) = (self.ticket.take(), &self.count) {
+            if let Ok(mut state) = count.state.lock() {
+                state.queue.retain(|&(t, _)| t != ticket);
+                state.wake_next(count.max);
+            }
+        }
+    }
+}
+
+struct Expiration {
+    idle_timeout: Option<Duration>,
+    max_lifetime: Option<Duration>,
+}
+
+impl Expiration {
+    fn new(idle_timeout: Option<Duration>, max_lifetime: Option<Duration>) -> Expiration {
+        Expiration {
+            idle_timeout,
+            max_lifetime,
+        }
+    }
+
+    fn expires<T>(&self, entry: &Idle<T>) -> bool {
+        // Avoid `Instant::elapsed` to avoid issues like rust-lang/rust#86470.
+        let now = Instant::now();
+        let exceeds = |instant: Instant, limit: Option<Duration>| match limit {
+            Some(limit) => now.saturating_duration_since(instant) > limit,
+            None => false,
+        };
+        exceeds(entry.idle_at, self.idle_timeout) || exceeds(entry.created_at, self.max_lifetime)
+    }
+}
+
+pin_project_lite::pin_project! {
+    struct IdleTask<T, K: Key> {
+        timer: Timer,
+        duration: Duration,
+        deadline: Instant,
+        fut: Pin<Box<dyn Sleep>>,
+        pool: Weak<Shards<T, K>>,
+        // This allows the IdleTask to be notified as soon as the entire
+        // Pool is fully dropped, and shutdown. This channel is never sent on,
+        // but Err(Canceled) will be received when the Pool is dropped.
+        #[pin]
+        pool_drop_notifier: oneshot::Receiver<Infallible>,
+    }
+}
+
+impl<T: Poolable + 'static, K: Key> Future for IdleTask<T, K> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
         let mut this = self.project();
         loop {
             match this.pool_drop_notifier.as_mut().poll(cx) {
@@ -804,12 +1721,14 @@ impl<T: Poolable + 'static, K: Key> Future for IdleTask<T, K> {
             }
             *this.fut = this.timer.sleep_until(*this.deadline);
 
-            if let Some(inner) = this.pool.upgrade() {
-                if let Ok(mut inner) = inner.lock() {
-                    trace!("idle interval checking for expired");
-                    inner.clear_expired();
-                    continue;
+            if let Some(shards) = this.pool.upgrade() {
+                trace!("idle interval checking for expired");
+                for shard in shards.list.iter() {
+                    if let Ok(mut inner) = lock_shard(shard) {
+                        inner.clear_expired();
+                    }
                 }
+                continue;
             }
             return Poll::Ready(());
         }
@@ -832,14 +1751,16 @@ impl<T> WeakOpt<T> {
 
 #[cfg(all(test, not(miri)))]
 mod tests {
+    use std::collections::VecDeque;
     use std::fmt::Debug;
     use std::future::Future;
     use std::hash::Hash;
     use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
     use std::task::{self, Poll};
     use std::time::Duration;
 
-    use super::{Connecting, Key, Pool, Poolable, Reservation, WeakOpt};
+    use super::{Connecting, IdlePool, Key, Pool, Poolable, Reservation, WeakOpt};
     use crate::rt::{TokioExecutor, TokioTimer};
 
     use crate::common::timer;
@@ -878,15 +1799,24 @@ mod tests {
         KeyImpl(http::uri::Scheme::HTTP, s.parse().expect("host key"))
     }
 
-    fn pool_no_timer<T, K: Key>() -> Pool<T, K> {
+    fn pool_no_timer<T: Send + 'static, K: Key>() -> Pool<T, K> {
         pool_max_idle_no_timer(::std::usize::MAX)
     }
 
-    fn pool_max_idle_no_timer<T, K: Key>(max_idle: usize) -> Pool<T, K> {
+    fn pool_max_idle_no_timer<T: Send + 'static, K: Key>(max_idle: usize) -> Pool<T, K> {
         let pool = Pool::new(
             super::Config {
                 idle_timeout: Some(Duration::from_millis(100)),
                 max_idle_per_host: max_idle,
+                reuse: super::Reuse::Lifo,
+                max_lifetime: None,
+                max_idle: std::usize::MAX,
+                max_active_per_host: std::usize::MAX,
+                sweep_interval: None,
+                max_checkout_queue: std::usize::MAX,
+                checkout_timeout: None,
+                max_connecting_per_host: std::usize::MAX,
+                shards: 1,
             },
             TokioExecutor::new(),
             Option::<timer::Timer>::None,
@@ -950,17 +1880,14 @@ mod tests {
         pool.pooled(c(key.clone()), Uniq(5));
         pool.pooled(c(key.clone()), Uniq(99));
 
-        assert_eq!(
-            pool.locked().idle.get(&key).map(|entries| entries.len()),
-            Some(3)
-        );
+        assert_eq!(pool.locked().idle.idle_count(&key), 3);
         tokio::time::sleep(pool.locked().timeout.unwrap()).await;
 
         let mut checkout = pool.checkout(key.clone());
         let poll_once = PollOnce(&mut checkout);
         // checkout.await should clean out the expired
         poll_once.await;
-        assert!(pool.locked().idle.get(&key).is_none());
+        assert_eq!(pool.locked().idle.idle_count(&key), 0);
     }
 
     #[test]
@@ -973,10 +1900,327 @@ mod tests {
         pool.pooled(c(key.clone()), Uniq(99));
 
         // pooled and dropped 3, max_idle should only allow 2
-        assert_eq!(
-            pool.locked().idle.get(&key).map(|entries| entries.len()),
-            Some(2)
+        assert_eq!(pool.locked().idle.idle_count(&key), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pool_checkout_fifo() {
+        let pool = Pool::new(
+            super::Config {
+                idle_timeout: None,
+                max_idle_per_host: std::usize::MAX,
+                reuse: super::Reuse::Fifo,
+                max_lifetime: None,
+                max_idle: std::usize::MAX,
+                max_active_per_host: std::usize::MAX,
+                sweep_interval: None,
+                max_checkout_queue: std::usize::MAX,
+                checkout_timeout: None,
+                max_connecting_per_host: std::usize::MAX,
+                shards: 1,
+            },
+            TokioExecutor::new(),
+            Option::<timer::Timer>::None,
+        );
+        let key = host_key("foo");
+
+        pool.pooled(c(key.clone()), Uniq(41));
+        pool.pooled(c(key.clone()), Uniq(5));
+        assert_eq!(pool.stats().host(&key).expect("foo").idle(), 2);
+
+        let first = pool.checkout(key.clone()).await.unwrap();
+        assert_eq!(*first, Uniq(41));
+        let second = pool.checkout(key.clone()).await.unwrap();
+        assert_eq!(*second, Uniq(5));
+    }
+
+    #[tokio::test]
+    async fn test_pool_max_lifetime() {
+        let pool = Pool::new(
+            super::Config {
+                idle_timeout: None,
+                max_idle_per_host: std::usize::MAX,
+                reuse: super::Reuse::Lifo,
+                max_lifetime: Some(Duration::from_millis(10)),
+                max_idle: std::usize::MAX,
+                max_active_per_host: std::usize::MAX,
+                sweep_interval: None,
+                max_checkout_queue: std::usize::MAX,
+                checkout_timeout: None,
+                max_connecting_per_host: std::usize::MAX,
+                shards: 1,
+            },
+            TokioExecutor::new(),
+            Option::<timer::Timer>::None,
         );
+        let key = host_key("foo");
+
+        pool.pooled(c(key.clone()), Uniq(41));
+        let in_flight = pool.pooled(c(key.clone()), Uniq(5));
+        assert_eq!(pool.locked().idle.idle_count(&key), 1);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // the in-flight connection is retired once it is done
+        drop(in_flight);
+        assert_eq!(pool.locked().idle.idle_count(&key), 1);
+
+        // and the idle one is not reused anymore
+        let mut checkout = pool.checkout(key.clone());
+        assert!(PollOnce(&mut checkout).await.is_none());
+        assert_eq!(pool.locked().idle.idle_count(&key), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_sweep() {
+        let pool = pool_no_timer();
+        let key = host_key("foo");
+
+        pool.pooled(c(key.clone()), Uniq(41));
+        pool.pooled(c(key.clone()), Uniq(5));
+        assert_eq!(pool.locked().idle.idle_count(&key), 2);
+
+        // nothing has expired yet
+        pool.sweep();
+        assert_eq!(pool.locked().idle.idle_count(&key), 2);
+
+        tokio::time::sleep(pool.locked().timeout.unwrap()).await;
+
+        pool.sweep();
+        assert_eq!(pool.locked().idle.idle_count(&key), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_sharded() {
+        let pool = Pool::new(
+            super::Config {
+                idle_timeout: None,
+                max_idle_per_host: std::usize::MAX,
+                reuse: super::Reuse::Lifo,
+                max_lifetime: None,
+                max_idle: std::usize::MAX,
+                max_active_per_host: std::usize::MAX,
+                sweep_interval: None,
+                max_checkout_queue: std::usize::MAX,
+                checkout_timeout: None,
+                max_connecting_per_host: std::usize::MAX,
+                shards: 4,
+            },
+            TokioExecutor::new(),
+            Option::<timer::Timer>::None,
+        );
+        let keys = (0..16)
+            .map(|i| host_key(&format!("host{}", i)))
+            .collect::<Vec<_>>();
+        for (i, key) in keys.iter().enumerate() {
+            pool.pooled(c(key.clone()), Uniq(i));
+        }
+
+        let stats = pool.stats();
+        assert_eq!(stats.hosts().count(), 16);
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(stats.host(key).unwrap().idle(), 1);
+            let pooled = pool.checkout(key.clone()).await.unwrap();
+            assert_eq!(*pooled, Uniq(i));
+        }
+
+        pool.close();
+        assert!(pool.is_closed());
+        assert_eq!(pool.stats().hosts().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_sharded_max_idle() {
+        let pool = Pool::new(
+            super::Config {
+                idle_timeout: None,
+                max_idle_per_host: std::usize::MAX,
+                reuse: super::Reuse::Lifo,
+                max_lifetime: None,
+                max_idle: 3,
+                max_active_per_host: std::usize::MAX,
+                sweep_interval: None,
+                max_checkout_queue: std::usize::MAX,
+                checkout_timeout: None,
+                max_connecting_per_host: std::usize::MAX,
+                shards: 4,
+            },
+            TokioExecutor::new(),
+            Option::<timer::Timer>::None,
+        );
+        let keys = (0..16)
+            .map(|i| host_key(&format!("host{}", i)))
+            .collect::<Vec<_>>();
+        for (i, key) in keys.iter().enumerate() {
+            pool.pooled(c(key.clone()), Uniq(i));
+        }
+
+        // the limit is of the whole pool, whichever shards the hosts hash to
+        let idle = |pool: &Pool<Uniq<usize>, KeyImpl>| {
+            pool.stats()
+                .hosts()
+                .map(|(_, host)| host.idle())
+                .sum::<usize>()
+        };
+        assert_eq!(idle(&pool), 3);
+
+        // checking out makes room for another host
+        let _pooled = pool.checkout(keys[0].clone()).await.unwrap();
+        assert_eq!(idle(&pool), 2);
+        pool.pooled(c(keys[15].clone()), Uniq(15));
+        assert_eq!(idle(&pool), 3);
+        assert_eq!(pool.stats().host(&keys[15]).unwrap().idle(), 1);
+    }
+
+    #[test]
+    fn test_pool_max_idle() {
+        let pool = pool_no_timer::<Uniq<i32>, KeyImpl>();
+        pool.locked().max_idle = 2;
+        let foo = host_key("foo");
+        let bar = host_key("bar");
+
+        pool.pooled(c(foo.clone()), Uniq(41));
+        pool.pooled(c(foo.clone()), Uniq(5));
+        pool.pooled(c(bar.clone()), Uniq(99));
+
+        // pooled and dropped 3, max_idle should only allow 2 in total
+        assert_eq!(pool.locked().idle.idle_count(&foo), 2);
+        assert_eq!(pool.locked().idle.idle_count(&bar), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_max_active_per_host() {
+        let pool = pool_no_timer::<Uniq<i32>, KeyImpl>();
+        pool.locked().max_active_per_host = 1;
+        let foo = host_key("foo");
+
+        let active = pool.active_slot(&foo).await.unwrap().expect("enabled");
+
+        // other hosts have their own limit
+        assert!(pool.active_slot(&host_key("bar")).await.unwrap().is_some());
+
+        let mut slot = pool.active_slot(&foo);
+        assert!(futures_util::FutureExt::now_or_never(&mut slot).is_none());
+
+        drop(active);
+        assert!(slot.await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_pool_checkout_queue_fifo() {
+        use futures_util::FutureExt;
+
+        let pool = pool_no_timer::<Uniq<i32>, KeyImpl>();
+        pool.locked().max_active_per_host = 1;
+        let foo = host_key("foo");
+
+        let active = pool.active_slot(&foo).await.unwrap().expect("enabled");
+
+        let mut first = pool.active_slot(&foo);
+        let mut second = pool.active_slot(&foo);
+        assert!((&mut first).now_or_never().is_none());
+        assert!((&mut second).now_or_never().is_none());
+
+        // a new checkout doesn't jump the queue once a slot is free
+        drop(active);
+        assert!(pool.active_slot(&foo).now_or_never().is_none());
+        assert!((&mut second).now_or_never().is_none());
+        let active = first.now_or_never().unwrap().unwrap().expect("slot");
+
+        // giving up a place in line passes it on
+        let mut third = pool.active_slot(&foo);
+        assert!((&mut third).now_or_never().is_none());
+        drop(second);
+        drop(active);
+        assert!(third.now_or_never().unwrap().unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_pool_checkout_queue_full() {
+        use futures_util::FutureExt;
+
+        let pool = pool_no_timer::<Uniq<i32>, KeyImpl>();
+        {
+            let mut inner = pool.locked();
+            inner.max_active_per_host = 1;
+            inner.max_checkout_queue = 1;
+        }
+        let foo = host_key("foo");
+
+        let _active = pool.active_slot(&foo).await.unwrap().expect("enabled");
+        let mut waiting = pool.active_slot(&foo);
+        assert!((&mut waiting).now_or_never().is_none());
+
+        match pool.active_slot(&foo).await {
+            Err(super::Error::CheckoutQueueFull) => {}
+            other => panic!(
+                "expected CheckoutQueueFull, got {:?}",
+                other.map(|a| a.is_some())
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pool_checkout_timeout() {
+        let pool = Pool::<Uniq<i32>, KeyImpl>::new(
+            super::Config {
+                idle_timeout: None,
+                max_idle_per_host: std::usize::MAX,
+                reuse: super::Reuse::Lifo,
+                max_lifetime: None,
+                max_idle: std::usize::MAX,
+                max_active_per_host: 1,
+                sweep_interval: None,
+                max_checkout_queue: std::usize::MAX,
+                checkout_timeout: Some(Duration::from_millis(10)),
+                max_connecting_per_host: std::usize::MAX,
+                shards: 1,
+            },
+            TokioExecutor::new(),
+            Some(TokioTimer::new()),
+        );
+        let foo = host_key("foo");
+
+        let active = pool.active_slot(&foo).await.unwrap().expect("enabled");
+        match pool.active_slot(&foo).await {
+            Err(super::Error::CheckoutTimedOut) => {}
+            other => panic!(
+                "expected CheckoutTimedOut, got {:?}",
+                other.map(|a| a.is_some())
+            ),
+        }
+
+        // the timed out checkout left the queue
+        drop(active);
+        assert!(pool.active_slot(&foo).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_pool_stats() {
+        use futures_util::FutureExt;
+
+        let pool = pool_no_timer::<Uniq<i32>, KeyImpl>();
+        let foo = host_key("foo");
+        let bar = host_key("bar");
+        let baz = host_key("baz");
+
+        drop(pool.pooled(c(foo.clone()), Uniq(41)));
+        drop(pool.pooled(c(foo.clone()), Uniq(5)));
+        let _active = pool.active_slot(&bar).await.unwrap().expect("enabled");
+        let mut checkout = pool.checkout(baz.clone());
+        assert!((&mut checkout).now_or_never().is_none());
+
+        let stats = pool.stats();
+        let foo_stats = stats.host(&foo).expect("foo");
+        assert_eq!(foo_stats.idle(), 2);
+        assert_eq!(foo_stats.idle_ages().len(), 2);
+        assert_eq!(stats.host(&bar).expect("bar").active(), 1);
+        assert_eq!(stats.host(&baz).expect("baz").pending(), 1);
+        assert_eq!(stats.hosts().count(), 3);
+        assert_eq!((stats.idle(), stats.active(), stats.pending()), (2, 1, 1));
+
+        drop(checkout);
+        assert!(pool.stats().host(&baz).is_none());
     }
 
     #[tokio::test]
@@ -985,6 +2229,15 @@ mod tests {
             super::Config {
                 idle_timeout: Some(Duration::from_millis(10)),
                 max_idle_per_host: std::usize::MAX,
+                reuse: super::Reuse::Lifo,
+                max_lifetime: None,
+                max_idle: std::usize::MAX,
+                max_active_per_host: std::usize::MAX,
+                sweep_interval: None,
+                max_checkout_queue: std::usize::MAX,
+                checkout_timeout: None,
+                max_connecting_per_host: std::usize::MAX,
+                shards: 1,
             },
             TokioExecutor::new(),
             Some(TokioTimer::new()),
@@ -996,17 +2249,14 @@ mod tests {
         pool.pooled(c(key.clone()), Uniq(5));
         pool.pooled(c(key.clone()), Uniq(99));
 
-        assert_eq!(
-            pool.locked().idle.get(&key).map(|entries| entries.len()),
-            Some(3)
-        );
+        assert_eq!(pool.locked().idle.idle_count(&key), 3);
 
         // Let the timer tick passed the expiration...
         tokio::time::sleep(Duration::from_millis(30)).await;
         // Yield so the Interval can reap...
         tokio::task::yield_now().await;
 
-        assert!(pool.locked().idle.get(&key).is_none());
+        assert_eq!(pool.locked().idle.idle_count(&key), 0);
     }
 
     #[tokio::test]
@@ -1089,6 +2339,145 @@ mod tests {
             },
         );
 
-        assert!(!pool.locked().idle.contains_key(&key));
+        assert_eq!(pool.locked().idle.idle_count(&key), 0);
+    }
+
+    /// Reuses the connection that has been idle the longest.
+    struct Fifo<T, K>(VecDeque<(K, T)>);
+
+    impl<T: Send + 'static, K: Eq + Send + 'static> IdlePool<T, K> for Fifo<T, K> {
+        fn checkout(&mut self, key: &K) -> Option<T> {
+            let pos = self.0.iter().position(|(k, _)| k == key)?;
+            self.0.remove(pos).map(|(_, value)| value)
+        }
+
+        fn checkin(&mut self, key: K, value: T) {
+            self.0.push_back((key, value));
+        }
+
+        fn close(&mut self, key: &K) {
+            self.0.retain(|(k, _)| k != key);
+        }
+
+        fn idle_count(&self, key: &K) -> usize {
+            self.0.iter().filter(|(k, _)| k == key).count()
+        }
+
+        fn total_idle_count(&self) -> usize {
+            self.0.len()
+        }
+
+        fn retain(&mut self, f: &mut dyn FnMut(&K, &mut T) -> bool) {
+            self.0.retain_mut(|(k, value)| f(k, value));
+        }
+
+        fn for_each(&self, f: &mut dyn FnMut(&K, &T)) {
+            for (k, value) in &self.0 {
+                f(k, value);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pool_custom_idle() {
+        let pool = Pool::with_idle(
+            super::Config {
+                idle_timeout: None,
+                max_idle_per_host: ::std::usize::MAX,
+                reuse: super::Reuse::Lifo,
+                max_lifetime: None,
+                max_idle: std::usize::MAX,
+                max_active_per_host: std::usize::MAX,
+                sweep_interval: None,
+                max_checkout_queue: std::usize::MAX,
+                checkout_timeout: None,
+                max_connecting_per_host: std::usize::MAX,
+                shards: 1,
+            },
+            TokioExecutor::new(),
+            Option::<timer::Timer>::None,
+            Fifo(VecDeque::new()),
+        );
+        let key = host_key("foo");
+
+        pool.pooled(c(key.clone()), Uniq(41));
+        pool.pooled(c(key.clone()), Uniq(5));
+        assert_eq!(pool.stats().host(&key).expect("foo").idle(), 2);
+
+        let first = pool.checkout(key.clone()).await.unwrap();
+        assert_eq!(*first, Uniq(41));
+        let second = pool.checkout(key.clone()).await.unwrap();
+        assert_eq!(*second, Uniq(5));
+    }
+
+    struct Recorder(Arc<Mutex<Vec<super::PoolEvent>>>);
+
+    impl<K> super::PoolObserver<K> for Recorder {
+        fn on_event(&self, event: super::PoolEvent, _: &K, _: &super::PoolEventInfo) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pool_observer() {
+        use super::PoolEvent::*;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let pool = pool_no_timer().with_observer(Arc::new(Recorder(events.clone())));
+        let key = host_key("foo");
+
+        pool.pooled(c(key.clone()), Uniq(41));
+        let pooled = pool.checkout(key.clone()).await.unwrap();
+        drop(pooled);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [Created, Returned, Reused, Returned]
+        );
+    }
+
+    // Records the idle connections of the pool, read from within the
+    // callbacks.
+    struct Reentrant<K: Key> {
+        pool: Mutex<Option<Pool<Uniq<i32>, K>>>,
+        events: Mutex<Vec<(super::PoolEvent, usize)>>,
+    }
+
+    impl<K: Key> super::PoolObserver<K> for Reentrant<K> {
+        fn on_event(&self, event: super::PoolEvent, key: &K, _: &super::PoolEventInfo) {
+            let pool = self.pool.lock().unwrap().clone().expect("pool");
+            let idle = pool.stats().hosts.get(key).map_or(0, |host| host.idle);
+            self.events.lock().unwrap().push((event, idle));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pool_observer_reenters_pool() {
+        use super::PoolEvent::*;
+
+        let observer = Arc::new(Reentrant {
+            pool: Mutex::new(None),
+            events: Mutex::new(Vec::new()),
+        });
+        let pool = pool_no_timer().with_observer(observer.clone());
+        *observer.pool.lock().unwrap() = Some(pool.clone());
+        let key = host_key("foo");
+
+        pool.pooled(c(key.clone()), Uniq(41));
+        let pooled = pool.checkout(key.clone()).await.unwrap();
+        drop(pooled);
+        pool.close();
+
+        assert_eq!(
+            *observer.events.lock().unwrap(),
+            [
+                (Created, 0),
+                (Returned, 1),
+                (Reused, 0),
+                (Returned, 1),
+                (Closed, 0)
+            ]
+        );
+        observer.pool.lock().unwrap().take();
     }
 }
@@ -1,5 +1,7 @@
 //! HTTP client utilities
 
+pub mod conn;
+
 /// Legacy implementations of `connect` module and `Client`
 #[cfg(feature = "client-legacy")]
 pub mod legacy;
@@ -3,10 +3,14 @@
 pub(crate) mod exec;
 #[cfg(feature = "client")]
 mod lazy;
-pub(crate) mod rewind;
 #[cfg(feature = "client")]
 mod sync;
 pub(crate) mod timer;
+#[cfg(all(
+    feature = "client-legacy",
+    any(feature = "http1", feature = "http2", feature = "tokio")
+))]
+pub(crate) mod trace;
 
 #[cfg(feature = "client")]
 pub(crate) use exec::Exec;
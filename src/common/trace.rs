@@ -0,0 +1,93 @@
+//! Spans that are only created with the `tracing` feature.
+//!
+//! Without it, the spans are disabled, so instrumenting a future with one
+//! and recording fields on it do nothing.
+
+use tracing::Span;
+
+pub(crate) fn span(make: impl FnOnce() -> Span) -> Span {
+    #[cfg(feature = "tracing")]
+    {
+        make()
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        drop(make);
+        Span::none()
+    }
+}
+
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub(crate) fn current() -> Span {
+    span(Span::current)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    // Records the fields of spans, as `name=value`.
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    struct Visitor<'a>(&'a Mutex<Vec<String>>);
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            span.record(&mut Visitor(&self.0));
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut Visitor(&self.0));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    impl Visit for Visitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            let field = format!("{}={:?}", field.name(), value);
+            self.0.lock().unwrap().push(field);
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn records_span_fields() {
+        let fields = Arc::new(Mutex::new(Vec::new()));
+        tracing::subscriber::with_default(Recorder(fields.clone()), || {
+            let span = super::span(|| {
+                tracing::debug_span!("request", method = "GET", reused = tracing::field::Empty)
+            });
+            span.record("reused", true);
+        });
+        assert_eq!(*fields.lock().unwrap(), ["method=\"GET\"", "reused=true"]);
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    #[test]
+    fn disables_spans() {
+        let fields = Arc::new(Mutex::new(Vec::new()));
+        tracing::subscriber::with_default(Recorder(fields.clone()), || {
+            let span = super::span(|| tracing::debug_span!("request", method = "GET"));
+            assert!(span.is_disabled());
+            span.record("method", "POST");
+        });
+        assert!(fields.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,440 @@
+//! Lifecycle events of connections and requests.
+//!
+//! An [`Events`](Events) implementation receives the events of both ends of
+//! hyper-util, instead of wiring up separate hooks for each of them.
+//!
+//! The legacy client `Builder::events` reports both the connections and the
+//! requests of a client. On servers, they are reported separately: the
+//! `auto::Builder::connection_events` reports connections, and wrapping the
+//! service in an `EventsService` reports its requests, without changing the
+//! executor bounds of the connections.
+//!
+//! # Example
+//!
+//! ```
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::Arc;
+//!
+//! use hyper_util::events::{ConnectionEvent, Events};
+//!
+//! #[derive(Default)]
+//! struct OpenConnections(AtomicUsize);
+//!
+//! impl Events for OpenConnections {
+//!     fn connection_opened(&self, _: &ConnectionEvent<'_>) {
+//!         self.0.fetch_add(1, Ordering::Relaxed);
+//!     }
+//!
+//!     fn connection_closed(&self, _: &ConnectionEvent<'_>) {
+//!         self.0.fetch_sub(1, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let events = Arc::new(OpenConnections::default());
+//! // client_builder.events(events.clone());
+//! // server_builder.connection_events(events.clone());
+//! // let service = EventsService::new(service, events.clone());
+//! # let _ = events;
+//! ```
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use http::{Method, StatusCode, Uri, Version};
+
+#[cfg(all(feature = "client-legacy", any(feature = "http1", feature = "http2")))]
+use crate::client::legacy::{
+    pool::{PoolEvent, PoolEventInfo},
+    CircuitState, PoolKey,
+};
+
+/// Receives the lifecycle events of connections and requests.
+///
+/// Every method does nothing by default, so only the events of interest
+/// need to be implemented. They are called inline, from the task driving
+/// the connection or request, so they should return quickly.
+pub trait Events: Send + Sync + 'static {
+    /// Called when a connection is ready to carry requests.
+    ///
+    /// For clients, that is after the HTTP handshake. For servers, after
+    /// the HTTP version of the connection was detected.
+    fn connection_opened(&self, conn: &ConnectionEvent<'_>) {
+        let _ = conn;
+    }
+
+    /// Called when an opened connection is closed, with the error that
+    /// closed it, if any.
+    fn connection_closed(&self, conn: &ConnectionEvent<'_>) {
+        let _ = conn;
+    }
+
+    /// Called when a request is started.
+    fn request_started(&self, req: &RequestEvent<'_>) {
+        let _ = req;
+    }
+
+    /// Called when the response to a request is received, for clients, or
+    /// returned by the service, for servers, or when that failed.
+    fn request_finished(&self, req: &RequestEvent<'_>) {
+        let _ = req;
+    }
+
+    /// Called when `event` happened to a pooled client connection.
+    ///
+    /// These are the same events a
+    /// [`PoolObserver`](crate::client::legacy::pool::PoolObserver) receives.
+    #[cfg(all(feature = "client-legacy", any(feature = "http1", feature = "http2")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "client-legacy")))]
+    fn pool_event(&self, event: PoolEvent, key: &PoolKey, info: &PoolEventInfo) {
+        let _ = (event, key, info);
+    }
+
+    /// Called when the circuit of a host changed from `from` to `to`.
+    ///
+    /// See [`CircuitBreaker`](crate::client::legacy::CircuitBreaker).
+    #[cfg(all(feature = "client-legacy", any(feature = "http1", feature = "http2")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "client-legacy")))]
+    fn circuit_changed(&self, key: &PoolKey, from: CircuitState, to: CircuitState) {
+        let _ = (key, from, to);
+    }
+}
+
+impl<T: Events + ?Sized> Events for Arc<T> {
+    fn connection_opened(&self, conn: &ConnectionEvent<'_>) {
+        (**self).connection_opened(conn)
+    }
+
+    fn connection_closed(&self, conn: &ConnectionEvent<'_>) {
+        (**self).connection_closed(conn)
+    }
+
+    fn request_started(&self, req: &RequestEvent<'_>) {
+        (**self).request_started(req)
+    }
+
+    fn request_finished(&self, req: &RequestEvent<'_>) {
+        (**self).request_finished(req)
+    }
+
+    #[cfg(all(feature = "client-legacy", any(feature = "http1", feature = "http2")))]
+    fn pool_event(&self, event: PoolEvent, key: &PoolKey, info: &PoolEventInfo) {
+        (**self).pool_event(event, key, info)
+    }
+
+    #[cfg(all(feature = "client-legacy", any(feature = "http1", feature = "http2")))]
+    fn circuit_changed(&self, key: &PoolKey, from: CircuitState, to: CircuitState) {
+        (**self).circuit_changed(key, from, to)
+    }
+}
+
+/// Which end of a connection an event happened at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// The legacy client.
+    Client,
+    /// A server connection.
+    Server,
+}
+
+/// A connection that was opened or closed.
+#[derive(Debug)]
+pub struct ConnectionEvent<'a> {
+    side: Side,
+    id: u64,
+    version: Version,
+    error: Option<&'a (dyn StdError + 'static)>,
+}
+
+/// A request that was started or finished.
+#[derive(Debug)]
+pub struct RequestEvent<'a> {
+    side: Side,
+    id: u64,
+    method: &'a Method,
+    uri: &'a Uri,
+    status: Option<StatusCode>,
+    error: Option<&'a (dyn StdError + 'static)>,
+    elapsed: Duration,
+}
+
+// A registered `Events`, or none, shared by builders and what they build.
+#[derive(Clone, Default)]
+pub(crate) struct EventsHandle(Option<Arc<dyn Events>>);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+pub(crate) fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// ===== impl ConnectionEvent =====
+
+impl<'a> ConnectionEvent<'a> {
+    pub(crate) fn new(side: Side, id: u64, version: Version) -> Self {
+        ConnectionEvent {
+            side,
+            id,
+            version,
+            error: None,
+        }
+    }
+
+    pub(crate) fn with_error(mut self, error: Option<&'a (dyn StdError + 'static)>) -> Self {
+        self.error = error;
+        self
+    }
+
+    /// Which end of the connection this is.
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// An id of the connection, unique within the process.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The HTTP version of the connection.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// The error that closed the connection, if any.
+    pub fn error(&self) -> Option<&(dyn StdError + 'static)> {
+        self.error
+    }
+}
+
+// ===== impl RequestEvent =====
+
+impl<'a> RequestEvent<'a> {
+    pub(crate) fn new(side: Side, id: u64, method: &'a Method, uri: &'a Uri) -> Self {
+        RequestEvent {
+            side,
+            id,
+            method,
+            uri,
+            status: None,
+            error: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn finished(
+        mut self,
+        elapsed: Duration,
+        status: Option<StatusCode>,
+        error: Option<&'a (dyn StdError + 'static)>,
+    ) -> Self {
+        self.elapsed = elapsed;
+        self.status = status;
+        self.error = error;
+        self
+    }
+
+    /// Which end of the connection this is.
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// An id of the request, unique within the process, and the same when
+    /// it is started and finished.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The method of the request.
+    pub fn method(&self) -> &Method {
+        self.method
+    }
+
+    /// The URI of the request.
+    pub fn uri(&self) -> &Uri {
+        self.uri
+    }
+
+    /// The status of the response, once finished, if there is one.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.status
+    }
+
+    /// The error the request failed with, once finished.
+    ///
+    /// Server errors are returned by the service as its own error type, so
+    /// they are not available here: a failed server request has neither a
+    /// status nor an error.
+    pub fn error(&self) -> Option<&(dyn StdError + 'static)> {
+        self.error
+    }
+
+    /// How long the request took, once finished.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+// ===== impl EventsHandle =====
+
+impl EventsHandle {
+    pub(crate) fn new(events: impl Events) -> Self {
+        EventsHandle(Some(Arc::new(events)))
+    }
+
+    pub(crate) fn get(&self) -> Option<&dyn Events> {
+        self.0.as_deref()
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl fmt::Debug for EventsHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EventsHandle")
+            .field(&self.is_enabled())
+            .finish()
+    }
+}
+
+#[cfg(feature = "server")]
+pub use self::server::{EventsFuture, EventsService};
+
+#[cfg(feature = "server")]
+mod server {
+    use std::fmt;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use std::time::Instant;
+
+    use futures_util::ready;
+    use http::{Method, Request, Response, Uri};
+    use hyper::service::Service;
+    use pin_project_lite::pin_project;
+
+    use super::{next_id, Events, EventsHandle, RequestEvent, Side};
+
+    /// A server service that reports its requests to an [`Events`].
+    ///
+    /// The connections of a server are reported by the
+    /// `auto::Builder::connection_events` they are served with, while their
+    /// requests are only reported once the service is wrapped in an
+    /// `EventsService`.
+    #[derive(Clone)]
+    pub struct EventsService<S> {
+        inner: S,
+        events: EventsHandle,
+    }
+
+    pin_project! {
+        /// The future of an [`EventsService`].
+        pub struct EventsFuture<F> {
+            #[pin]
+            inner: F,
+            started: Option<Started>,
+        }
+    }
+
+    struct Started {
+        events: EventsHandle,
+        id: u64,
+        method: Method,
+        uri: Uri,
+        at: Instant,
+    }
+
+    impl<S> EventsService<S> {
+        /// Wrap `service`, to report its requests to `events`.
+        pub fn new<V>(service: S, events: V) -> Self
+        where
+            V: Events,
+        {
+            EventsService {
+                inner: service,
+                events: EventsHandle::new(events),
+            }
+        }
+
+        /// Get a reference to the inner service.
+        pub fn get_ref(&self) -> &S {
+            &self.inner
+        }
+
+        /// Consume this wrapper, returning the inner service.
+        pub fn into_inner(self) -> S {
+            self.inner
+        }
+    }
+
+    impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for EventsService<S>
+    where
+        S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+        type Future = EventsFuture<S::Future>;
+
+        fn call(&self, req: Request<ReqBody>) -> Self::Future {
+            let started = self.events.get().map(|events| {
+                let id = next_id();
+                events.request_started(&RequestEvent::new(
+                    Side::Server,
+                    id,
+                    req.method(),
+                    req.uri(),
+                ));
+                Started {
+                    events: self.events.clone(),
+                    id,
+                    method: req.method().clone(),
+                    uri: req.uri().clone(),
+                    at: Instant::now(),
+                }
+            });
+            EventsFuture {
+                inner: self.inner.call(req),
+                started,
+            }
+        }
+    }
+
+    impl<S> fmt::Debug for EventsService<S> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("EventsService")
+                .field("events", &self.events)
+                .finish()
+        }
+    }
+
+    impl<F, B, E> Future for EventsFuture<F>
+    where
+        F: Future<Output = Result<Response<B>, E>>,
+    {
+        type Output = F::Output;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = self.project();
+            let res = ready!(this.inner.poll(cx));
+            if let Some(started) = this.started.take() {
+                if let Some(events) = started.events.get() {
+                    let status = res.as_ref().ok().map(|res| res.status());
+                    events.request_finished(
+                        &RequestEvent::new(Side::Server, started.id, &started.method, &started.uri)
+                            .finished(started.at.elapsed(), status, None),
+                    );
+                }
+            }
+            Poll::Ready(res)
+        }
+    }
+
+    impl<F> fmt::Debug for EventsFuture<F> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("EventsFuture").finish()
+        }
+    }
+}
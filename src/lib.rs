@@ -6,9 +6,15 @@
 //! This crate is less-stable than [`hyper`](https://docs.rs/hyper). However,
 //! does respect Rust's semantic version regarding breaking changes.
 
+pub mod body;
 #[cfg(feature = "client")]
 pub mod client;
 mod common;
+#[cfg(any(
+    all(feature = "client-legacy", any(feature = "http1", feature = "http2")),
+    feature = "server-auto"
+))]
+pub mod events;
 pub mod rt;
 #[cfg(feature = "server")]
 pub mod server;
@@ -0,0 +1,396 @@
+//! A coarse timer wheel
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use hyper::rt::{Sleep, Timer as _};
+
+use crate::common::timer::Timer;
+
+const SLOTS: usize = 512;
+
+/// A Timer that rounds deadlines up to a tick, and keeps its sleeps in a
+/// shared timer wheel.
+///
+/// Servers with very many connections have as many timers, for header
+/// read timeouts and keep-alive intervals, which rarely need to be precise.
+/// Each sleep of a `CoarseTimer` only costs a slot in the wheel, and a single
+/// [`CoarseTimerDriver`](CoarseTimerDriver) wakes the sleeps that are due
+/// once per tick. Sleeps complete up to one tick late, but never early.
+///
+/// The driver must be spawned for any sleep to complete. It finishes once
+/// the timer and all of its sleeps are dropped.
+///
+/// Clones of a `CoarseTimer` share its wheel and driver, so a single timer
+/// can be passed to the builders of all connections of a server, such as
+/// with [`auto::Builder::timer`](crate::server::conn::auto::Builder::timer).
+/// Periodic sleeps, like the HTTP/2 keep-alive interval of each connection,
+/// then land in the same few slots, and are woken together once per tick.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # async fn run() {
+/// use std::time::Duration;
+/// use hyper_util::rt::{CoarseTimer, TokioTimer};
+///
+/// let (timer, driver) = CoarseTimer::new(Duration::from_millis(100), TokioTimer::new());
+/// tokio::spawn(driver);
+///
+/// let mut builder = hyper::server::conn::http1::Builder::new();
+/// builder.timer(timer);
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct CoarseTimer {
+    shared: Arc<Shared>,
+}
+
+/// The future that drives a [`CoarseTimer`](CoarseTimer).
+#[must_use = "futures do nothing unless polled"]
+pub struct CoarseTimerDriver {
+    shared: Weak<Shared>,
+    timer: Timer,
+    sleep: Option<Pin<Box<dyn Sleep>>>,
+}
+
+struct Shared {
+    start: Instant,
+    resolution: Duration,
+    wheel: Mutex<Wheel>,
+}
+
+struct Wheel {
+    tick: u64,
+    slots: Vec<Vec<Weak<Mutex<Entry>>>>,
+}
+
+struct Entry {
+    deadline: u64,
+    // The deadline this entry is in the wheel for, if any.
+    scheduled: Option<u64>,
+    waker: Option<Waker>,
+}
+
+struct CoarseSleep {
+    shared: Arc<Shared>,
+    entry: Arc<Mutex<Entry>>,
+}
+
+// ===== impl CoarseTimer =====
+
+impl CoarseTimer {
+    /// Create a timer with ticks of `resolution`, and the driver that uses
+    /// `timer` to wait for each tick.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resolution` is zero.
+    pub fn new<M>(resolution: Duration, timer: M) -> (CoarseTimer, CoarseTimerDriver)
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        assert!(
+            resolution > Duration::from_secs(0),
+            "resolution must be positive"
+        );
+        let shared = Arc::new(Shared {
+            start: Instant::now(),
+            resolution,
+            wheel: Mutex::new(Wheel {
+                tick: 0,
+                slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+            }),
+        });
+        let driver = CoarseTimerDriver {
+            shared: Arc::downgrade(&shared),
+            timer: Timer::new(timer),
+            sleep: None,
+        };
+        (CoarseTimer { shared }, driver)
+    }
+
+    /// Create a timer with ticks of `resolution`, and spawn its driver on
+    /// the current tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resolution` is zero, or if called outside of a tokio
+    /// runtime.
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub fn spawn(resolution: Duration) -> CoarseTimer {
+        let (timer, driver) = CoarseTimer::new(resolution, crate::rt::TokioTimer::new());
+        tokio::spawn(driver);
+        timer
+    }
+
+    /// The resolution of the ticks of this timer.
+    pub fn resolution(&self) -> Duration {
+        self.shared.resolution
+    }
+}
+
+impl fmt::Debug for CoarseTimer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CoarseTimer")
+            .field("resolution", &self.shared.resolution)
+            .finish()
+    }
+}
+
+impl hyper::rt::Timer for CoarseTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+        self.sleep_until(Instant::now() + duration)
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Sleep>> {
+        Box::pin(CoarseSleep {
+            shared: self.shared.clone(),
+            entry: Arc::new(Mutex::new(Entry {
+                deadline: self.shared.tick_of(deadline),
+                scheduled: None,
+                waker: None,
+            })),
+        })
+    }
+
+    fn reset(&self, sleep: &mut Pin<Box<dyn Sleep>>, new_deadline: Instant) {
+        match sleep.as_mut().downcast_mut_pin::<CoarseSleep>() {
+            Some(sleep) if Arc::ptr_eq(&sleep.shared, &self.shared) => {
+                // Rescheduled on the next poll, if it moved.
+                sleep.entry.lock().unwrap().deadline = self.shared.tick_of(new_deadline);
+            }
+            _ => *sleep = self.sleep_until(new_deadline),
+        }
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    // The first tick at or after `deadline`.
+    fn tick_of(&self, deadline: Instant) -> u64 {
+        let since = deadline.saturating_duration_since(self.start);
+        let res = self.resolution.as_nanos();
+        let ticks = since.as_nanos() / res;
+        if ticks * res == since.as_nanos() {
+            ticks as u64
+        } else {
+            ticks as u64 + 1
+        }
+    }
+
+    // The last tick that is not in the future.
+    fn elapsed_ticks(&self) -> u64 {
+        let since = Instant::now().saturating_duration_since(self.start);
+        (since.as_nanos() / self.resolution.as_nanos()) as u64
+    }
+
+    fn advance_to(&self, target: u64) {
+        let mut due = Vec::new();
+        {
+            let mut wheel = self.wheel.lock().unwrap();
+            while wheel.tick < target {
+                wheel.tick += 1;
+                let tick = wheel.tick;
+                let slot = (tick % SLOTS as u64) as usize;
+                wheel.slots[slot].retain(|weak| {
+                    let entry = match weak.upgrade() {
+                        Some(entry) => entry,
+                        None => return false,
+                    };
+                    let mut entry = entry.lock().unwrap();
+                    if entry.scheduled.map(|s| s % SLOTS as u64) != Some(slot as u64) {
+                        // Rescheduled into another slot.
+                        return false;
+                    }
+                    if entry.deadline <= tick {
+                        entry.scheduled = None;
+                        due.extend(entry.waker.take());
+                        false
+                    } else if entry.deadline % SLOTS as u64 == slot as u64 {
+                        // Due in a later turn of the wheel.
+                        true
+                    } else {
+                        // Reset to a later deadline, it's rescheduled when
+                        // polled again.
+                        entry.scheduled = None;
+                        due.extend(entry.waker.take());
+                        false
+                    }
+                });
+            }
+        }
+        for waker in due {
+            waker.wake();
+        }
+    }
+}
+
+// ===== impl CoarseTimerDriver =====
+
+impl fmt::Debug for CoarseTimerDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CoarseTimerDriver").finish()
+    }
+}
+
+impl Future for CoarseTimerDriver {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            let shared = match self.shared.upgrade() {
+                Some(shared) => shared,
+                None => return Poll::Ready(()),
+            };
+
+            if let Some(ref mut sleep) = self.sleep {
+                if sleep.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                // At least one tick passed, even if the clock of the timer
+                // is behind.
+                let tick = shared.wheel.lock().unwrap().tick + 1;
+                shared.advance_to(tick.max(shared.elapsed_ticks()));
+            }
+
+            self.sleep = Some(self.timer.sleep(shared.resolution));
+        }
+    }
+}
+
+// ===== impl CoarseSleep =====
+
+impl Future for CoarseSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut wheel = self.shared.wheel.lock().unwrap();
+        let mut entry = self.entry.lock().unwrap();
+        if entry.deadline <= wheel.tick {
+            return Poll::Ready(());
+        }
+        match entry.waker {
+            Some(ref waker) if waker.will_wake(cx.waker()) => (),
+            _ => entry.waker = Some(cx.waker().clone()),
+        }
+        if entry.scheduled != Some(entry.deadline) {
+            entry.scheduled = Some(entry.deadline);
+            let slot = (entry.deadline % SLOTS as u64) as usize;
+            wheel.slots[slot].push(Arc::downgrade(&self.entry));
+        }
+        Poll::Pending
+    }
+}
+
+impl Sleep for CoarseSleep {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::FutureExt;
+    use hyper::rt::Timer;
+
+    use super::CoarseTimer;
+    use crate::rt::MockTimer;
+
+    #[test]
+    fn wakes_sleeps_on_ticks() {
+        let clock = MockTimer::new();
+        let (timer, mut driver) = CoarseTimer::new(Duration::from_secs(1), clock.clone());
+        assert!((&mut driver).now_or_never().is_none());
+
+        let mut short = timer.sleep(Duration::from_millis(1500));
+        let mut long = timer.sleep(Duration::from_millis(600_500));
+        assert!((&mut short).now_or_never().is_none());
+        assert!((&mut long).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(1));
+        assert!((&mut driver).now_or_never().is_none());
+        assert!((&mut short).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(1));
+        assert!((&mut driver).now_or_never().is_none());
+        assert!(short.now_or_never().is_some());
+
+        // a later turn of the wheel
+        for _ in 0..598 {
+            clock.advance(Duration::from_secs(1));
+            assert!((&mut driver).now_or_never().is_none());
+        }
+        assert!((&mut long).now_or_never().is_none());
+        clock.advance(Duration::from_secs(1));
+        assert!((&mut driver).now_or_never().is_none());
+        assert!(long.now_or_never().is_some());
+    }
+
+    #[test]
+    fn clones_share_the_wheel() {
+        let clock = MockTimer::new();
+        let (timer, mut driver) = CoarseTimer::new(Duration::from_secs(1), clock.clone());
+        assert!((&mut driver).now_or_never().is_none());
+
+        // as if two connections each started a keep-alive interval
+        let other = timer.clone();
+        let mut first = timer.sleep(Duration::from_millis(1200));
+        let mut second = other.sleep(Duration::from_millis(1700));
+        drop(timer);
+        assert!((&mut first).now_or_never().is_none());
+        assert!((&mut second).now_or_never().is_none());
+
+        for _ in 0..2 {
+            clock.advance(Duration::from_secs(1));
+            assert!((&mut driver).now_or_never().is_none());
+        }
+        assert!(first.now_or_never().is_some());
+        assert!(second.now_or_never().is_some());
+
+        drop(other);
+        assert!(driver.now_or_never().is_some());
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn spawns_driver() {
+        let timer = CoarseTimer::spawn(Duration::from_millis(10));
+        timer.sleep(Duration::from_millis(15)).await;
+    }
+
+    #[test]
+    fn reset_and_shutdown() {
+        let clock = MockTimer::new();
+        let (timer, mut driver) = CoarseTimer::new(Duration::from_secs(1), clock.clone());
+        assert!((&mut driver).now_or_never().is_none());
+
+        let mut sleep = timer.sleep(Duration::from_secs(1));
+        assert!((&mut sleep).now_or_never().is_none());
+        timer.reset(
+            &mut sleep,
+            std::time::Instant::now() + Duration::from_millis(2500),
+        );
+
+        clock.advance(Duration::from_secs(1));
+        assert!((&mut driver).now_or_never().is_none());
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(1));
+        assert!((&mut driver).now_or_never().is_none());
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(1));
+        assert!((&mut driver).now_or_never().is_none());
+        assert!((&mut sleep).now_or_never().is_some());
+
+        drop(sleep);
+        drop(timer);
+        assert!(driver.now_or_never().is_some());
+    }
+}
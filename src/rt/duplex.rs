@@ -0,0 +1,210 @@
+//! An in-memory pair of connected IOs
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use bytes::{Buf, BytesMut};
+use hyper::rt::{Read, ReadBufCursor, Write};
+
+use super::rewind::{put_slice, remaining};
+
+/// Create a pair of connected in-memory IOs.
+///
+/// What is written to one is read from the other. Each direction buffers up
+/// to `capacity` bytes, after which writes wait until the other side reads.
+/// The IOs implement hyper's `Read` and `Write` directly, so they can be
+/// served or connected by hyper's builders without sockets, such as in
+/// tests.
+///
+/// Shutting down or dropping one side makes the other side read EOF, and
+/// dropping one side makes writes of the other side fail.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(all(feature = "client", feature = "server", feature = "http1"))]
+/// # async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// use std::convert::Infallible;
+/// use http_body_util::Empty;
+/// use hyper::body::Bytes;
+/// use hyper::service::service_fn;
+///
+/// let (client_io, server_io) = hyper_util::rt::duplex(64 * 1024);
+/// let service = service_fn(|_| async {
+///     Ok::<_, Infallible>(hyper::Response::new(Empty::<Bytes>::new()))
+/// });
+/// tokio::spawn(hyper::server::conn::http1::Builder::new().serve_connection(server_io, service));
+///
+/// let (mut tx, conn) = hyper::client::conn::http1::handshake(client_io).await?;
+/// tokio::spawn(conn);
+/// let res = tx.send_request(hyper::Request::new(Empty::<Bytes>::new())).await?;
+/// assert!(res.status().is_success());
+/// # Ok(())
+/// # }
+/// ```
+pub fn duplex(capacity: usize) -> (DuplexStream, DuplexStream) {
+    assert!(capacity > 0, "duplex capacity must not be zero");
+    let one = Arc::new(Mutex::new(Pipe::new(capacity)));
+    let two = Arc::new(Mutex::new(Pipe::new(capacity)));
+    (
+        DuplexStream {
+            read: one.clone(),
+            write: two.clone(),
+        },
+        DuplexStream {
+            read: two,
+            write: one,
+        },
+    )
+}
+
+/// One side of a [`duplex`] pair.
+pub struct DuplexStream {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+// One direction of a duplex pair.
+struct Pipe {
+    buf: BytesMut,
+    capacity: usize,
+    closed: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+// ===== impl DuplexStream =====
+
+impl Read for DuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut pipe = self.read.lock().unwrap();
+        if pipe.buf.has_remaining() {
+            let n = pipe.buf.len().min(remaining(&mut buf));
+            put_slice(&mut buf, &pipe.buf[..n]);
+            pipe.buf.advance(n);
+            if let Some(waker) = pipe.write_waker.take() {
+                waker.wake();
+            }
+            Poll::Ready(Ok(()))
+        } else if pipe.closed {
+            Poll::Ready(Ok(()))
+        } else {
+            pipe.read_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Write for DuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.write.lock().unwrap();
+        if pipe.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let n = buf.len().min(pipe.capacity - pipe.buf.len());
+        if n == 0 && !buf.is_empty() {
+            pipe.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        pipe.buf.extend_from_slice(&buf[..n]);
+        if let Some(waker) = pipe.read_waker.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.write.lock().unwrap().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        // No need to panic on drop, that could abort!
+        if let Ok(mut pipe) = self.write.lock() {
+            pipe.close();
+        }
+        if let Ok(mut pipe) = self.read.lock() {
+            pipe.close();
+        }
+    }
+}
+
+impl fmt::Debug for DuplexStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DuplexStream").finish()
+    }
+}
+
+// ===== impl Pipe =====
+
+impl Pipe {
+    fn new(capacity: usize) -> Pipe {
+        Pipe {
+            buf: BytesMut::new(),
+            capacity,
+            closed: false,
+            read_waker: None,
+            write_waker: None,
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::duplex;
+    use crate::rt::TokioIo;
+
+    #[tokio::test]
+    async fn writes_are_read_by_the_other_side() {
+        let (one, two) = duplex(4);
+        let mut one = TokioIo::new(one);
+        let mut two = TokioIo::new(two);
+
+        let write = tokio::spawn(async move {
+            // more than the capacity, so this waits for reads
+            one.write_all(b"hello world").await.unwrap();
+            one.shutdown().await.unwrap();
+            one
+        });
+        let mut read = String::new();
+        two.read_to_string(&mut read).await.unwrap();
+        assert_eq!(read, "hello world");
+
+        let mut one = write.await.unwrap();
+        drop(two);
+        let err = one.write_all(b"gone").await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+}
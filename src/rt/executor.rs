@@ -0,0 +1,255 @@
+//! An executor instrumented with task counters
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use hyper::rt::Executor;
+use pin_project_lite::pin_project;
+
+/// An executor counting the tasks it spawns on the inner executor.
+///
+/// Servers spawn a task per connection, and per HTTP/2 stream, so the
+/// counters show how many of them a server is carrying. They can be read
+/// with [`stats`](InstrumentedExecutor::stats) at any time, from any clone.
+///
+/// Timing each poll of the tasks is opt-in, since it reads the clock twice
+/// per poll.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # fn run() {
+/// use hyper_util::rt::{InstrumentedExecutor, TokioExecutor};
+///
+/// let exec = InstrumentedExecutor::new(TokioExecutor::new());
+/// let builder = hyper::server::conn::http2::Builder::new(exec.clone());
+/// # let _ = builder;
+///
+/// let stats = exec.stats();
+/// println!("{} connection tasks alive", stats.alive());
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct InstrumentedExecutor<E> {
+    inner: E,
+    counters: Arc<Counters>,
+}
+
+/// A snapshot of the counters of an [`InstrumentedExecutor`](InstrumentedExecutor).
+#[derive(Clone, Copy, Debug)]
+pub struct ExecutorStats {
+    spawned: u64,
+    completed: u64,
+    dropped: u64,
+    polls: u64,
+    poll_time: Duration,
+    max_poll_time: Duration,
+}
+
+#[derive(Debug)]
+struct Counters {
+    time_polls: AtomicBool,
+    spawned: AtomicU64,
+    completed: AtomicU64,
+    dropped: AtomicU64,
+    polls: AtomicU64,
+    poll_nanos: AtomicU64,
+    max_poll_nanos: AtomicU64,
+}
+
+pin_project! {
+    /// A task spawned by an [`InstrumentedExecutor`](InstrumentedExecutor).
+    pub struct InstrumentedTask<F> {
+        #[pin]
+        inner: F,
+        counters: Arc<Counters>,
+        completed: bool,
+    }
+
+    impl<F> PinnedDrop for InstrumentedTask<F> {
+        fn drop(this: Pin<&mut Self>) {
+            this.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// ===== impl InstrumentedExecutor =====
+
+impl<E> InstrumentedExecutor<E> {
+    /// Wrap an executor, counting from zero.
+    pub fn new(inner: E) -> Self {
+        InstrumentedExecutor {
+            inner,
+            counters: Arc::new(Counters {
+                time_polls: AtomicBool::new(false),
+                spawned: AtomicU64::new(0),
+                completed: AtomicU64::new(0),
+                dropped: AtomicU64::new(0),
+                polls: AtomicU64::new(0),
+                poll_nanos: AtomicU64::new(0),
+                max_poll_nanos: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Set whether the time spent polling tasks is recorded.
+    ///
+    /// Default is `false`.
+    pub fn time_polls(self, enabled: bool) -> Self {
+        self.counters.time_polls.store(enabled, Ordering::Relaxed);
+        self
+    }
+
+    /// Take a snapshot of the counters.
+    pub fn stats(&self) -> ExecutorStats {
+        let c = &self.counters;
+        ExecutorStats {
+            spawned: c.spawned.load(Ordering::Relaxed),
+            completed: c.completed.load(Ordering::Relaxed),
+            dropped: c.dropped.load(Ordering::Relaxed),
+            polls: c.polls.load(Ordering::Relaxed),
+            poll_time: Duration::from_nanos(c.poll_nanos.load(Ordering::Relaxed)),
+            max_poll_time: Duration::from_nanos(c.max_poll_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Borrow the inner executor.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+}
+
+impl<E, F> Executor<F> for InstrumentedExecutor<E>
+where
+    E: Executor<InstrumentedTask<F>>,
+    F: Future,
+{
+    fn execute(&self, fut: F) {
+        self.counters.spawned.fetch_add(1, Ordering::Relaxed);
+        self.inner.execute(InstrumentedTask {
+            inner: fut,
+            counters: self.counters.clone(),
+            completed: false,
+        });
+    }
+}
+
+// ===== impl InstrumentedTask =====
+
+impl<F: Future> Future for InstrumentedTask<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let counters = &**this.counters;
+        let poll = if counters.time_polls.load(Ordering::Relaxed) {
+            let start = Instant::now();
+            let poll = this.inner.poll(cx);
+            let nanos = start.elapsed().as_nanos() as u64;
+            counters.polls.fetch_add(1, Ordering::Relaxed);
+            counters.poll_nanos.fetch_add(nanos, Ordering::Relaxed);
+            counters.max_poll_nanos.fetch_max(nanos, Ordering::Relaxed);
+            poll
+        } else {
+            this.inner.poll(cx)
+        };
+        if poll.is_ready() && !*this.completed {
+            *this.completed = true;
+            counters.completed.fetch_add(1, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+impl<F> std::fmt::Debug for InstrumentedTask<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedTask")
+            .field("completed", &self.completed)
+            .finish()
+    }
+}
+
+// ===== impl ExecutorStats =====
+
+impl ExecutorStats {
+    /// The number of tasks spawned.
+    pub fn spawned(&self) -> u64 {
+        self.spawned
+    }
+
+    /// The number of tasks that are spawned and not yet dropped.
+    pub fn alive(&self) -> u64 {
+        self.spawned.saturating_sub(self.dropped)
+    }
+
+    /// The number of tasks that ran to completion.
+    pub fn completed(&self) -> u64 {
+        self.completed
+    }
+
+    /// The number of tasks that were dropped before completing, such as
+    /// when the runtime shut down.
+    pub fn canceled(&self) -> u64 {
+        self.dropped.saturating_sub(self.completed)
+    }
+
+    /// The number of polls timed, if timing polls is enabled.
+    pub fn polls(&self) -> u64 {
+        self.polls
+    }
+
+    /// The total time spent in timed polls.
+    pub fn poll_time(&self) -> Duration {
+        self.poll_time
+    }
+
+    /// The longest timed poll.
+    pub fn max_poll_time(&self) -> Duration {
+        self.max_poll_time
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use futures_channel::oneshot;
+    use hyper::rt::Executor;
+
+    use super::InstrumentedExecutor;
+    use crate::rt::TokioExecutor;
+
+    #[tokio::test]
+    async fn counts_tasks() {
+        let exec = InstrumentedExecutor::new(TokioExecutor::new()).time_polls(true);
+
+        let (done_tx, done_rx) = oneshot::channel();
+        exec.execute(async move {
+            done_tx.send(()).unwrap();
+        });
+        let (hold_tx, hold_rx) = oneshot::channel::<()>();
+        exec.clone().execute(async move {
+            let _ = hold_rx.await;
+        });
+
+        done_rx.await.unwrap();
+        tokio::task::yield_now().await;
+        let stats = exec.stats();
+        assert_eq!(stats.spawned(), 2);
+        assert_eq!(stats.completed(), 1);
+        assert_eq!(stats.alive(), 1);
+        assert!(stats.polls() >= 2);
+        assert!(stats.max_poll_time() <= stats.poll_time());
+
+        drop(hold_tx);
+        while exec.stats().alive() > 0 {
+            tokio::task::yield_now().await;
+        }
+        let stats = exec.stats();
+        assert_eq!(stats.completed(), 2);
+        assert_eq!(stats.canceled(), 0);
+    }
+}
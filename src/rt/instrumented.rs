@@ -0,0 +1,332 @@
+//! IO instrumented with byte counters
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_util::ready;
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// A wrapper counting the bytes read from and written to an IO type.
+    ///
+    /// The counters are shared with [`IoStatsHandle`](IoStatsHandle)s, which
+    /// can take a snapshot of them at any time, even while the IO is owned by
+    /// a connection.
+    #[derive(Debug)]
+    pub struct InstrumentedIo<T> {
+        #[pin]
+        inner: T,
+        stats: Arc<Counters>,
+    }
+}
+
+/// A handle to the counters of an [`InstrumentedIo`](InstrumentedIo).
+#[derive(Clone, Debug)]
+pub struct IoStatsHandle {
+    stats: Arc<Counters>,
+}
+
+/// A snapshot of the counters of an [`InstrumentedIo`](InstrumentedIo).
+#[derive(Clone, Copy, Debug)]
+pub struct IoStats {
+    bytes_read: u64,
+    bytes_written: u64,
+    created_at: Instant,
+    last_read: Option<Instant>,
+    last_write: Option<Instant>,
+    taken_at: Instant,
+}
+
+#[derive(Debug)]
+struct Counters {
+    created_at: Instant,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    // Nanoseconds since `created_at`, plus one, or zero if never.
+    last_read: AtomicU64,
+    last_write: AtomicU64,
+}
+
+// ===== impl InstrumentedIo =====
+
+impl<T> InstrumentedIo<T> {
+    /// Wrap an IO type, counting from zero.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            stats: Arc::new(Counters {
+                created_at: Instant::now(),
+                bytes_read: AtomicU64::new(0),
+                bytes_written: AtomicU64::new(0),
+                last_read: AtomicU64::new(0),
+                last_write: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Get a handle to the counters of this IO.
+    pub fn handle(&self) -> IoStatsHandle {
+        IoStatsHandle {
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// Take a snapshot of the counters of this IO.
+    pub fn stats(&self) -> IoStats {
+        self.stats.snapshot()
+    }
+
+    /// Borrow the inner type.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Mut borrow the inner type.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner type.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> hyper::rt::Read for InstrumentedIo<T>
+where
+    T: hyper::rt::Read,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        let this = self.project();
+        let n = unsafe {
+            let mut tbuf = hyper::rt::ReadBuf::uninit(buf.as_mut());
+            match hyper::rt::Read::poll_read(this.inner, cx, tbuf.unfilled()) {
+                Poll::Ready(Ok(())) => tbuf.filled().len(),
+                other => return other,
+            }
+        };
+
+        unsafe {
+            buf.advance(n);
+        }
+        this.stats.read(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> hyper::rt::Write for InstrumentedIo<T>
+where
+    T: hyper::rt::Write,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.project();
+        let n = ready!(hyper::rt::Write::poll_write(this.inner, cx, buf))?;
+        this.stats.written(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        hyper::rt::Write::poll_flush(self.project().inner, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        hyper::rt::Write::poll_shutdown(self.project().inner, cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        hyper::rt::Write::is_write_vectored(&self.inner)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.project();
+        let n = ready!(hyper::rt::Write::poll_write_vectored(this.inner, cx, bufs))?;
+        this.stats.written(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+#[cfg(feature = "client-legacy")]
+impl<T> crate::client::legacy::connect::Connection for InstrumentedIo<T>
+where
+    T: crate::client::legacy::connect::Connection,
+{
+    fn connected(&self) -> crate::client::legacy::connect::Connected {
+        self.inner.connected()
+    }
+
+    fn liveness_probe(&self) -> Option<crate::client::legacy::connect::LivenessProbe> {
+        self.inner.liveness_probe()
+    }
+}
+
+// ===== impl IoStatsHandle =====
+
+impl IoStatsHandle {
+    /// Take a snapshot of the counters.
+    pub fn snapshot(&self) -> IoStats {
+        self.stats.snapshot()
+    }
+}
+
+// ===== impl IoStats =====
+
+impl IoStats {
+    /// The number of bytes read.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// The number of bytes written.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// The time the IO was wrapped.
+    pub fn created_at(&self) -> Instant {
+        self.created_at
+    }
+
+    /// The last time any bytes were read.
+    pub fn last_read(&self) -> Option<Instant> {
+        self.last_read
+    }
+
+    /// The last time any bytes were written.
+    pub fn last_write(&self) -> Option<Instant> {
+        self.last_write
+    }
+
+    /// The last time any bytes were read or written.
+    pub fn last_activity(&self) -> Option<Instant> {
+        match (self.last_read, self.last_write) {
+            (Some(read), Some(write)) => Some(read.max(write)),
+            (read, write) => read.or(write),
+        }
+    }
+
+    /// How long the IO had been wrapped when the snapshot was taken.
+    pub fn age(&self) -> Duration {
+        self.taken_at.saturating_duration_since(self.created_at)
+    }
+
+    /// The average bytes read per second, over the age of the IO.
+    pub fn read_throughput(&self) -> f64 {
+        per_sec(self.bytes_read, self.age())
+    }
+
+    /// The average bytes written per second, over the age of the IO.
+    pub fn write_throughput(&self) -> f64 {
+        per_sec(self.bytes_written, self.age())
+    }
+}
+
+fn per_sec(bytes: u64, age: Duration) -> f64 {
+    let secs = age.as_secs_f64();
+    if secs > 0.0 {
+        bytes as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+// ===== impl Counters =====
+
+impl Counters {
+    fn read(&self, n: usize) {
+        if n > 0 {
+            self.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+            self.last_read
+                .store(self.since_created(), Ordering::Relaxed);
+        }
+    }
+
+    fn written(&self, n: usize) {
+        if n > 0 {
+            self.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+            self.last_write
+                .store(self.since_created(), Ordering::Relaxed);
+        }
+    }
+
+    fn since_created(&self) -> u64 {
+        let nanos = Instant::now()
+            .saturating_duration_since(self.created_at)
+            .as_nanos();
+        (nanos as u64).saturating_add(1)
+    }
+
+    fn at(&self, since_created: u64) -> Option<Instant> {
+        match since_created {
+            0 => None,
+            nanos => Some(self.created_at + Duration::from_nanos(nanos - 1)),
+        }
+    }
+
+    fn snapshot(&self) -> IoStats {
+        IoStats {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            created_at: self.created_at,
+            last_read: self.at(self.last_read.load(Ordering::Relaxed)),
+            last_write: self.at(self.last_write.load(Ordering::Relaxed)),
+            taken_at: Instant::now(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::pin::Pin;
+
+    use futures_util::future::poll_fn;
+
+    use super::InstrumentedIo;
+    use crate::rt::TokioIo;
+
+    #[tokio::test]
+    async fn counts_bytes() {
+        let mock = tokio_test::io::Builder::new()
+            .read(b"hello")
+            .write(b"world!")
+            .build();
+        let mut io = InstrumentedIo::new(TokioIo::new(mock));
+        let handle = io.handle();
+        assert_eq!(handle.snapshot().bytes_read(), 0);
+        assert!(handle.snapshot().last_activity().is_none());
+
+        let mut buf = [0; 16];
+        let mut buf = hyper::rt::ReadBuf::new(&mut buf);
+        poll_fn(|cx| hyper::rt::Read::poll_read(Pin::new(&mut io), cx, buf.unfilled()))
+            .await
+            .unwrap();
+        assert_eq!(buf.filled(), b"hello");
+
+        let n = poll_fn(|cx| hyper::rt::Write::poll_write(Pin::new(&mut io), cx, b"world!"))
+            .await
+            .unwrap();
+        assert_eq!(n, 6);
+
+        let stats = handle.snapshot();
+        assert_eq!(stats.bytes_read(), 5);
+        assert_eq!(stats.bytes_written(), 6);
+        assert!(stats.last_read().is_some());
+        assert!(stats.last_write() >= stats.last_read());
+        assert_eq!(stats.last_activity(), stats.last_write());
+        assert_eq!(io.stats().bytes_written(), 6);
+    }
+}
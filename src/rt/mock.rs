@@ -0,0 +1,147 @@
+//! A Timer whose time is advanced manually
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use hyper::rt::{Sleep, Timer};
+
+/// A Timer whose time only moves when it is advanced.
+///
+/// This makes timeouts testable without waiting for them. Sleeps complete
+/// once the timer is [advanced](MockTimer::advance) past their deadline.
+/// Clones share the same time.
+///
+/// The time starts at the `Instant` the timer was created. Deadlines passed
+/// to `sleep_until` are usually computed from `Instant::now()`, so they
+/// include the real time that passed since.
+///
+/// To test with the `TokioTimer` instead, pause tokio's clock with
+/// `tokio::time::pause`, which the `TokioTimer` follows.
+#[derive(Clone, Debug)]
+pub struct MockTimer {
+    clock: Arc<Mutex<Clock>>,
+}
+
+#[derive(Debug)]
+struct Clock {
+    now: Instant,
+    waiting: Vec<Waker>,
+}
+
+struct MockSleep {
+    clock: Arc<Mutex<Clock>>,
+    deadline: Instant,
+}
+
+// ===== impl MockTimer =====
+
+impl MockTimer {
+    /// Create a new MockTimer, starting at the current time.
+    pub fn new() -> Self {
+        MockTimer {
+            clock: Arc::new(Mutex::new(Clock {
+                now: Instant::now(),
+                waiting: Vec::new(),
+            })),
+        }
+    }
+
+    /// The current time of this timer.
+    pub fn now(&self) -> Instant {
+        self.clock.lock().unwrap().now
+    }
+
+    /// Move the time forward by `duration`, waking the sleeps that are due.
+    pub fn advance(&self, duration: Duration) {
+        let waiting = {
+            let mut clock = self.clock.lock().unwrap();
+            clock.now += duration;
+            std::mem::take(&mut clock.waiting)
+        };
+        for waker in waiting {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for MockTimer {
+    fn default() -> Self {
+        MockTimer::new()
+    }
+}
+
+impl Timer for MockTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+        self.sleep_until(self.now() + duration)
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Sleep>> {
+        Box::pin(MockSleep {
+            clock: self.clock.clone(),
+            deadline,
+        })
+    }
+
+    fn reset(&self, sleep: &mut Pin<Box<dyn Sleep>>, new_deadline: Instant) {
+        if let Some(sleep) = sleep.as_mut().downcast_mut_pin::<MockSleep>() {
+            sleep.get_mut().deadline = new_deadline;
+        } else {
+            *sleep = self.sleep_until(new_deadline);
+        }
+    }
+}
+
+// ===== impl MockSleep =====
+
+impl Future for MockSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut clock = self.clock.lock().unwrap();
+        if clock.now >= self.deadline {
+            Poll::Ready(())
+        } else {
+            clock.waiting.push(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Sleep for MockSleep {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::FutureExt;
+    use hyper::rt::Timer;
+
+    use super::MockTimer;
+
+    #[test]
+    fn sleeps_until_advanced() {
+        let timer = MockTimer::new();
+        let mut sleep = timer.sleep(Duration::from_secs(10));
+        assert!((&mut sleep).now_or_never().is_none());
+
+        timer.clone().advance(Duration::from_secs(9));
+        assert!((&mut sleep).now_or_never().is_none());
+
+        timer.advance(Duration::from_secs(1));
+        assert!(sleep.now_or_never().is_some());
+    }
+
+    #[test]
+    fn reset_moves_deadline() {
+        let timer = MockTimer::new();
+        let mut sleep = timer.sleep(Duration::from_secs(1));
+        timer.reset(&mut sleep, timer.now() + Duration::from_secs(5));
+
+        timer.advance(Duration::from_secs(1));
+        assert!((&mut sleep).now_or_never().is_none());
+        timer.advance(Duration::from_secs(4));
+        assert!(sleep.now_or_never().is_some());
+    }
+}
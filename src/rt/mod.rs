@@ -1,7 +1,23 @@
 //! Runtime utilities
 
+mod coarse;
+mod duplex;
+mod executor;
+mod instrumented;
+mod mock;
+mod rewind;
+mod splice;
+mod throttled;
 #[cfg(feature = "tokio")]
 pub mod tokio;
 
+pub use self::coarse::{CoarseTimer, CoarseTimerDriver};
+pub use self::duplex::{duplex, DuplexStream};
+pub use self::executor::{ExecutorStats, InstrumentedExecutor, InstrumentedTask};
+pub use self::instrumented::{InstrumentedIo, IoStats, IoStatsHandle};
+pub use self::mock::MockTimer;
+pub use self::rewind::Rewind;
+pub use self::splice::Splice;
+pub use self::throttled::{RateLimit, ThrottledIo};
 #[cfg(feature = "tokio")]
-pub use self::tokio::{TokioExecutor, TokioIo, TokioTimer};
+pub use self::tokio::{LocalTokioExecutor, TokioExecutor, TokioIo, TokioTimer};
@@ -0,0 +1,307 @@
+//! IO with bytes pushed back in front of its reads
+use std::marker::Unpin;
+use std::{cmp, io};
+
+use bytes::{Buf, Bytes, BytesMut};
+use hyper::rt::{Read, ReadBufCursor, Write};
+
+use std::{
+    pin::Pin,
+    task::{self, Poll},
+};
+
+/// Combine a buffer with an IO, rewinding reads to use the buffer.
+///
+/// Reads return the buffered bytes first, and then read from the IO. This
+/// lets code that sniffed the start of a connection, such as to detect the
+/// protocol or parse a PROXY protocol header, push back what it read and
+/// still hand the connection to a hyper builder. Writes go straight to the
+/// IO.
+///
+/// # Example
+///
+/// ```
+/// use hyper::body::Bytes;
+/// use hyper_util::rt::Rewind;
+///
+/// # fn run<T>(io: T, sniffed: Vec<u8>) {
+/// // `sniffed` was read from `io` to look at the start of the connection
+/// let io = Rewind::new_buffered(io, Bytes::from(sniffed));
+/// # drop(io);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Rewind<T> {
+    pre: Option<Prefix>,
+    inner: T,
+}
+
+/// The size of the inline prefix, enough for the HTTP/2 preface.
+const INLINE_CAP: usize = 24;
+
+/// Buffered bytes, either shared or copied inline to skip an allocation.
+#[derive(Debug)]
+enum Prefix {
+    Shared(Bytes),
+    Inline {
+        buf: [u8; INLINE_CAP],
+        pos: usize,
+        len: usize,
+    },
+}
+
+impl<T> Rewind<T> {
+    /// Wrap an IO, without any bytes buffered yet.
+    pub fn new(io: T) -> Self {
+        Rewind {
+            pre: None,
+            inner: io,
+        }
+    }
+
+    /// Wrap an IO, with `buf` read before anything from the IO.
+    pub fn new_buffered(io: T, buf: Bytes) -> Self {
+        Rewind {
+            pre: Some(Prefix::Shared(buf)),
+            inner: io,
+        }
+    }
+
+    /// Wrap an IO, with a copy of the few bytes of `buf` read first.
+    ///
+    /// The bytes are kept inline, so that sniffing the start of each
+    /// connection doesn't allocate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` is longer than 24 bytes.
+    pub fn new_inline(io: T, buf: &[u8]) -> Self {
+        assert!(buf.len() <= INLINE_CAP, "inline prefix too long");
+        let mut inline = [0; INLINE_CAP];
+        inline[..buf.len()].copy_from_slice(buf);
+        Rewind {
+            pre: Some(Prefix::Inline {
+                buf: inline,
+                pos: 0,
+                len: buf.len(),
+            }),
+            inner: io,
+        }
+    }
+
+    /// Push `bs` back, so that it is read next.
+    ///
+    /// If some bytes are still buffered, `bs` is read before them.
+    pub fn rewind(&mut self, bs: Bytes) {
+        self.pre = match self.pre.take() {
+            Some(pre) if !pre.is_empty() => {
+                let pre = pre.as_slice();
+                let mut buf = BytesMut::with_capacity(bs.len() + pre.len());
+                buf.extend_from_slice(&bs);
+                buf.extend_from_slice(pre);
+                Some(Prefix::Shared(buf.freeze()))
+            }
+            _ => Some(Prefix::Shared(bs)),
+        };
+    }
+
+    /// Get a reference to the inner IO.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner IO.
+    ///
+    /// Reading from it directly skips the buffered bytes.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner IO, and the bytes that are
+    /// still buffered.
+    pub fn into_inner(self) -> (T, Bytes) {
+        let pre = match self.pre {
+            Some(Prefix::Shared(bytes)) => bytes,
+            Some(pre) => Bytes::copy_from_slice(pre.as_slice()),
+            None => Bytes::new(),
+        };
+        (self.inner, pre)
+    }
+}
+
+impl<T> Read for Rewind<T>
+where
+    T: Read + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        mut buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        if let Some(mut prefix) = self.pre.take() {
+            // If there are no remaining bytes, let the bytes get dropped.
+            if !prefix.is_empty() {
+                let copy_len = cmp::min(prefix.as_slice().len(), remaining(&mut buf));
+                // TODO: There should be a way to do following two lines cleaner...
+                put_slice(&mut buf, &prefix.as_slice()[..copy_len]);
+                prefix.advance(copy_len);
+                // Put back what's left
+                if !prefix.is_empty() {
+                    self.pre = Some(prefix);
+                }
+
+                return Poll::Ready(Ok(()));
+            }
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl Prefix {
+    fn as_slice(&self) -> &[u8] {
+        match *self {
+            Prefix::Shared(ref bytes) => bytes,
+            Prefix::Inline { ref buf, pos, len } => &buf[pos..len],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.as_slice().is_empty()
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match *self {
+            Prefix::Shared(ref mut bytes) => bytes.advance(cnt),
+            Prefix::Inline { ref mut pos, .. } => *pos += cnt,
+        }
+    }
+}
+
+pub(super) fn remaining(cursor: &mut ReadBufCursor<'_>) -> usize {
+    // SAFETY:
+    // We do not uninitialize any set bytes.
+    unsafe { cursor.as_mut().len() }
+}
+
+// Copied from `ReadBufCursor::put_slice`.
+// If that becomes public, we could ditch this.
+pub(super) fn put_slice(cursor: &mut ReadBufCursor<'_>, slice: &[u8]) {
+    assert!(
+        remaining(cursor) >= slice.len(),
+        "buf.len() must fit in remaining()"
+    );
+
+    let amt = slice.len();
+
+    // SAFETY:
+    // the length is asserted above
+    unsafe {
+        cursor.as_mut()[..amt]
+            .as_mut_ptr()
+            .cast::<u8>()
+            .copy_from_nonoverlapping(slice.as_ptr(), amt);
+        cursor.advance(amt);
+    }
+}
+
+impl<T> Write for Rewind<T>
+where
+    T: Write + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::Rewind;
+    use crate::rt::TokioIo;
+    use bytes::Bytes;
+    use tokio::io::AsyncReadExt;
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn partial_rewind() {
+        let underlying = [104, 101, 108, 108, 111];
+
+        let mock = tokio_test::io::Builder::new().read(&underlying).build();
+
+        let mut stream = TokioIo::new(Rewind::new(TokioIo::new(mock)));
+
+        // Read off some bytes, ensure we filled o1
+        let mut buf = [0; 2];
+        stream.read_exact(&mut buf).await.expect("read1");
+
+        // Rewind the stream so that it is as if we never read in the first place.
+        stream.inner_mut().rewind(Bytes::copy_from_slice(&buf[..]));
+
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.expect("read1");
+
+        // At this point we should have read everything that was in the MockStream
+        assert_eq!(&buf, &underlying);
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn full_rewind() {
+        let underlying = [104, 101, 108, 108, 111];
+
+        let mock = tokio_test::io::Builder::new().read(&underlying).build();
+
+        let mut stream = TokioIo::new(Rewind::new(TokioIo::new(mock)));
+
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.expect("read1");
+
+        // Rewind the stream so that it is as if we never read in the first place.
+        stream.inner_mut().rewind(Bytes::copy_from_slice(&buf[..]));
+
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.expect("read1");
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn inline_prefix() {
+        let mock = tokio_test::io::Builder::new().read(b" world").build();
+        let mut stream = TokioIo::new(Rewind::new_inline(TokioIo::new(mock), b"hello"));
+
+        let mut buf = [0; 11];
+        stream.read_exact(&mut buf).await.expect("read");
+        assert_eq!(&buf, b"hello world");
+    }
+
+    #[test]
+    fn rewind_before_buffered() {
+        let mut io = Rewind::new_buffered((), Bytes::from_static(b"lo"));
+        io.rewind(Bytes::from_static(b"hel"));
+        let ((), pre) = io.into_inner();
+        assert_eq!(pre, "hello");
+    }
+}
@@ -0,0 +1,375 @@
+//! Copying between two IO objects in both directions
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_util::ready;
+use hyper::rt::{Read, ReadBuf, Sleep, Timer as _, Write};
+
+use crate::common::timer::Timer;
+
+const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+const DEFAULT_BUDGET: usize = 128 * 1024;
+
+/// A future copying data between two IO objects in both directions, until
+/// both reach EOF.
+///
+/// This is like tokio's `copy_bidirectional`, for hyper's [`Read`] and
+/// [`Write`], such as an upgraded connection and the transport it's tunneled
+/// to. When one side reaches EOF, the write half of the other is shut down,
+/// and the other direction is still copied. The future resolves to the bytes
+/// copied from `a` to `b`, and from `b` to `a`.
+///
+/// The bytes copied so far can be read while it's running, with
+/// [`a_to_b`](Splice::a_to_b) and [`b_to_a`](Splice::b_to_a).
+///
+/// # Budget
+///
+/// A busy pair of IO objects could otherwise be copied between for as long
+/// as they are readable, without giving other tasks on the executor a turn.
+/// After copying its budget of bytes in a poll, the future yields, and is
+/// woken to continue right away.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tokio")]
+/// # async fn run(client: hyper::upgrade::Upgraded, upstream: tokio::net::TcpStream) {
+/// use std::time::Duration;
+/// use hyper_util::rt::{Splice, TokioIo, TokioTimer};
+///
+/// let (sent, received) = Splice::new(client, TokioIo::new(upstream))
+///     .idle_timeout(TokioTimer::new(), Duration::from_secs(60))
+///     .await
+///     .unwrap();
+/// # }
+/// # fn main() {}
+/// ```
+#[must_use = "futures do nothing unless polled"]
+pub struct Splice<A, B> {
+    a: A,
+    b: B,
+    a_to_b: Transfer,
+    b_to_a: Transfer,
+    budget: usize,
+    idle: Option<Idle>,
+    // Which direction is copied first, alternated so neither starves.
+    b_first: bool,
+}
+
+struct Transfer {
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+    amt: u64,
+    read_done: bool,
+    need_flush: bool,
+    done: bool,
+}
+
+struct Idle {
+    timer: Timer,
+    timeout: Duration,
+    sleep: Option<Pin<Box<dyn Sleep>>>,
+}
+
+// ===== impl Splice =====
+
+impl<A, B> Splice<A, B>
+where
+    A: Read + Write + Unpin,
+    B: Read + Write + Unpin,
+{
+    /// Copy between `a` and `b`, in both directions.
+    pub fn new(a: A, b: B) -> Self {
+        Splice {
+            a,
+            b,
+            a_to_b: Transfer::new(DEFAULT_BUF_SIZE),
+            b_to_a: Transfer::new(DEFAULT_BUF_SIZE),
+            budget: DEFAULT_BUDGET,
+            idle: None,
+            b_first: false,
+        }
+    }
+
+    /// Fail with a `TimedOut` error when no bytes are copied in either
+    /// direction for `timeout`.
+    ///
+    /// Default is no timeout.
+    pub fn idle_timeout<M>(mut self, timer: M, timeout: Duration) -> Self
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        self.idle = Some(Idle {
+            timer: Timer::new(timer),
+            timeout,
+            sleep: None,
+        });
+        self
+    }
+
+    /// Set how many bytes are copied in a poll, before yielding.
+    ///
+    /// Default is 128 KiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` is 0.
+    pub fn budget(mut self, bytes: usize) -> Self {
+        assert!(bytes > 0, "budget must be positive");
+        self.budget = bytes;
+        self
+    }
+
+    /// Set the size of the buffer of each direction.
+    ///
+    /// Default is 8 KiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn buffer_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "buffer size must be positive");
+        self.a_to_b = Transfer::new(size);
+        self.b_to_a = Transfer::new(size);
+        self
+    }
+}
+
+impl<A, B> Splice<A, B> {
+    /// The bytes copied from `a` to `b` so far.
+    pub fn a_to_b(&self) -> u64 {
+        self.a_to_b.amt
+    }
+
+    /// The bytes copied from `b` to `a` so far.
+    pub fn b_to_a(&self) -> u64 {
+        self.b_to_a.amt
+    }
+}
+
+impl<A, B> Future for Splice<A, B>
+where
+    A: Read + Write + Unpin,
+    B: Read + Write + Unpin,
+{
+    type Output = io::Result<(u64, u64)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Splice {
+            a,
+            b,
+            a_to_b,
+            b_to_a,
+            budget: limit,
+            idle,
+            b_first,
+        } = self.get_mut();
+        let before = a_to_b.amt + b_to_a.amt;
+        let mut budget = *limit;
+
+        *b_first = !*b_first;
+        if *b_first {
+            b_to_a.poll_copy(cx, b, a, &mut budget)?;
+            a_to_b.poll_copy(cx, a, b, &mut budget)?;
+        } else {
+            a_to_b.poll_copy(cx, a, b, &mut budget)?;
+            b_to_a.poll_copy(cx, b, a, &mut budget)?;
+        }
+
+        if a_to_b.done && b_to_a.done {
+            return Poll::Ready(Ok((a_to_b.amt, b_to_a.amt)));
+        }
+        if budget == 0 {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        if let Some(idle) = idle {
+            if a_to_b.amt + b_to_a.amt != before {
+                idle.sleep = None;
+            }
+            let (timer, timeout) = (&idle.timer, idle.timeout);
+            let sleep = idle.sleep.get_or_insert_with(|| timer.sleep(timeout));
+            if sleep.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "splice idle timeout",
+                )));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<A, B> fmt::Debug for Splice<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Splice")
+            .field("a_to_b", &self.a_to_b.amt)
+            .field("b_to_a", &self.b_to_a.amt)
+            .finish()
+    }
+}
+
+// ===== impl Transfer =====
+
+impl Transfer {
+    fn new(size: usize) -> Self {
+        Transfer {
+            buf: vec![0; size].into_boxed_slice(),
+            pos: 0,
+            cap: 0,
+            amt: 0,
+            read_done: false,
+            need_flush: false,
+            done: false,
+        }
+    }
+
+    // Copy from `reader` to `writer` until either is pending, the budget is
+    // spent, or the reader reached EOF and the writer was shut down.
+    //
+    // Returns `Err` only on IO errors: being pending is `Ok`, since the
+    // other direction should still be copied.
+    fn poll_copy<R, W>(
+        &mut self,
+        cx: &mut Context<'_>,
+        reader: &mut R,
+        writer: &mut W,
+        budget: &mut usize,
+    ) -> io::Result<()>
+    where
+        R: Read + Unpin,
+        W: Write + Unpin,
+    {
+        match self.poll_copy_inner(cx, reader, writer, budget) {
+            Poll::Ready(res) => res,
+            Poll::Pending => Ok(()),
+        }
+    }
+
+    fn poll_copy_inner<R, W>(
+        &mut self,
+        cx: &mut Context<'_>,
+        reader: &mut R,
+        writer: &mut W,
+        budget: &mut usize,
+    ) -> Poll<io::Result<()>>
+    where
+        R: Read + Unpin,
+        W: Write + Unpin,
+    {
+        if self.done {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            if self.pos == self.cap && !self.read_done {
+                if *budget == 0 {
+                    return Poll::Pending;
+                }
+                let mut buf = ReadBuf::new(&mut self.buf);
+                match Pin::new(&mut *reader).poll_read(cx, buf.unfilled()) {
+                    Poll::Ready(Ok(())) => {}
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => {
+                        // Flush what was written while waiting for more.
+                        if self.need_flush {
+                            ready!(Pin::new(&mut *writer).poll_flush(cx))?;
+                            self.need_flush = false;
+                        }
+                        return Poll::Pending;
+                    }
+                }
+                let n = buf.filled().len();
+                if n == 0 {
+                    self.read_done = true;
+                } else {
+                    self.pos = 0;
+                    self.cap = n;
+                }
+            }
+
+            while self.pos < self.cap {
+                let n =
+                    ready!(Pin::new(&mut *writer).poll_write(cx, &self.buf[self.pos..self.cap]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "write zero bytes into writer",
+                    )));
+                }
+                self.pos += n;
+                self.amt += n as u64;
+                self.need_flush = true;
+                *budget = budget.saturating_sub(n);
+            }
+
+            if self.read_done {
+                ready!(Pin::new(&mut *writer).poll_flush(cx))?;
+                ready!(Pin::new(&mut *writer).poll_shutdown(cx))?;
+                self.done = true;
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::Splice;
+    use crate::rt::{TokioIo, TokioTimer};
+
+    #[tokio::test]
+    async fn copies_both_ways() {
+        let (a, mut a_peer) = tokio::io::duplex(64);
+        let (b, mut b_peer) = tokio::io::duplex(64);
+        let splice = tokio::spawn(Splice::new(TokioIo::new(a), TokioIo::new(b)).budget(16));
+
+        let data = vec![7u8; 1000];
+        let write = {
+            let data = data.clone();
+            tokio::spawn(async move {
+                a_peer.write_all(&data).await.unwrap();
+                a_peer.shutdown().await.unwrap();
+                let mut back = Vec::new();
+                a_peer.read_to_end(&mut back).await.unwrap();
+                back
+            })
+        };
+
+        let mut received = Vec::new();
+        b_peer.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, data);
+        b_peer.write_all(b"pong").await.unwrap();
+        b_peer.shutdown().await.unwrap();
+
+        assert_eq!(write.await.unwrap(), b"pong");
+        assert_eq!(splice.await.unwrap().unwrap(), (1000, 4));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_timeout() {
+        let (a, mut a_peer) = tokio::io::duplex(64);
+        let (b, _b_peer) = tokio::io::duplex(64);
+        let splice = tokio::spawn(
+            Splice::new(TokioIo::new(a), TokioIo::new(b))
+                .idle_timeout(TokioTimer::new(), Duration::from_secs(10)),
+        );
+
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        a_peer.write_all(b"ping").await.unwrap();
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert!(!splice.is_finished());
+
+        let err = splice.await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    }
+}
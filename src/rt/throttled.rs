@@ -0,0 +1,329 @@
+//! IO with rate limited reads and writes
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use hyper::rt::{Sleep, Timer as _};
+use pin_project_lite::pin_project;
+
+use crate::common::timer::Timer;
+
+pin_project! {
+    /// A wrapper limiting how fast an IO type is read from and written to.
+    ///
+    /// Reads and writes each have their own token bucket, which refills at
+    /// the configured rate, up to its burst size. A read or write is
+    /// shortened to the bytes available in its bucket, and waits on the
+    /// `Timer` while the bucket is empty.
+    pub struct ThrottledIo<T> {
+        #[pin]
+        inner: T,
+        timer: Timer,
+        read: Option<Bucket>,
+        write: Option<Bucket>,
+    }
+}
+
+/// The rate limit of reads or writes of a [`ThrottledIo`](ThrottledIo).
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    bytes_per_sec: u64,
+    burst: u64,
+}
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+    sleep: Option<Pin<Box<dyn Sleep>>>,
+}
+
+// ===== impl ThrottledIo =====
+
+impl<T> ThrottledIo<T> {
+    /// Wrap an IO type, waiting on `timer` when a limit is reached.
+    ///
+    /// Neither reads nor writes are limited until a limit is set.
+    pub fn new<M>(inner: T, timer: M) -> Self
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        Self {
+            inner,
+            timer: Timer::new(timer),
+            read: None,
+            write: None,
+        }
+    }
+
+    /// RateLimit how fast the IO is read from.
+    pub fn with_read_limit(mut self, limit: RateLimit) -> Self {
+        self.read = Some(Bucket::new(limit));
+        self
+    }
+
+    /// RateLimit how fast the IO is written to.
+    pub fn with_write_limit(mut self, limit: RateLimit) -> Self {
+        self.write = Some(Bucket::new(limit));
+        self
+    }
+
+    /// Borrow the inner type.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Mut borrow the inner type.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner type.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ThrottledIo<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThrottledIo")
+            .field("inner", &self.inner)
+            .field("read_limit", &self.read.as_ref().map(|b| b.limit))
+            .field("write_limit", &self.write.as_ref().map(|b| b.limit))
+            .finish()
+    }
+}
+
+impl<T> hyper::rt::Read for ThrottledIo<T>
+where
+    T: hyper::rt::Read,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        let this = self.project();
+        let bucket = match this.read {
+            Some(bucket) => bucket,
+            None => return hyper::rt::Read::poll_read(this.inner, cx, buf),
+        };
+        let allowed = futures_util::ready!(bucket.poll_acquire(cx, this.timer, buf.remaining()));
+
+        let n = unsafe {
+            let mut tbuf = hyper::rt::ReadBuf::uninit(&mut buf.as_mut()[..allowed]);
+            match hyper::rt::Read::poll_read(this.inner, cx, tbuf.unfilled()) {
+                Poll::Ready(Ok(())) => tbuf.filled().len(),
+                other => return other,
+            }
+        };
+
+        unsafe {
+            buf.advance(n);
+        }
+        bucket.consume(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> hyper::rt::Write for ThrottledIo<T>
+where
+    T: hyper::rt::Write,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.project();
+        let bucket = match this.write {
+            Some(bucket) => bucket,
+            None => return hyper::rt::Write::poll_write(this.inner, cx, buf),
+        };
+        let allowed = futures_util::ready!(bucket.poll_acquire(cx, this.timer, buf.len()));
+
+        let n = futures_util::ready!(hyper::rt::Write::poll_write(
+            this.inner,
+            cx,
+            &buf[..allowed]
+        ))?;
+        bucket.consume(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        hyper::rt::Write::poll_flush(self.project().inner, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        hyper::rt::Write::poll_shutdown(self.project().inner, cx)
+    }
+}
+
+#[cfg(feature = "client-legacy")]
+impl<T> crate::client::legacy::connect::Connection for ThrottledIo<T>
+where
+    T: crate::client::legacy::connect::Connection,
+{
+    fn connected(&self) -> crate::client::legacy::connect::Connected {
+        self.inner.connected()
+    }
+
+    fn liveness_probe(&self) -> Option<crate::client::legacy::connect::LivenessProbe> {
+        self.inner.liveness_probe()
+    }
+}
+
+// ===== impl RateLimit =====
+
+impl RateLimit {
+    /// RateLimit to `bytes_per_sec`, with a burst of up to one second's worth
+    /// of bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_sec` is zero.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        assert!(bytes_per_sec > 0, "rate limit must be positive");
+        RateLimit {
+            bytes_per_sec,
+            burst: bytes_per_sec,
+        }
+    }
+
+    /// Set how many bytes may be read or written at once, after the IO has
+    /// been idle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `burst` is zero.
+    pub fn burst(mut self, burst: u64) -> Self {
+        assert!(burst > 0, "burst must be positive");
+        self.burst = burst;
+        self
+    }
+}
+
+// ===== impl Bucket =====
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        Bucket {
+            limit,
+            tokens: limit.burst as f64,
+            refilled_at: Instant::now(),
+            sleep: None,
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.refilled_at = now;
+        let tokens = self.tokens + elapsed.as_secs_f64() * self.limit.bytes_per_sec as f64;
+        self.tokens = tokens.min(self.limit.burst as f64);
+    }
+
+    // Wait until at least one byte may pass, and return how many may,
+    // up to `want`.
+    fn poll_acquire(&mut self, cx: &mut Context<'_>, timer: &Timer, want: usize) -> Poll<usize> {
+        if want == 0 {
+            return Poll::Ready(0);
+        }
+        loop {
+            self.refill();
+            if self.tokens >= 1.0 {
+                self.sleep = None;
+                return Poll::Ready((self.tokens as u64).min(want as u64) as usize);
+            }
+
+            let wait = (1.0 - self.tokens) / self.limit.bytes_per_sec as f64;
+            let deadline = self.refilled_at + Duration::from_secs_f64(wait);
+            match self.sleep {
+                Some(ref mut sleep) => timer.reset(sleep, deadline),
+                None => self.sleep = Some(timer.sleep_until(deadline)),
+            }
+            let sleep = self.sleep.as_mut().expect("sleep was just set");
+            futures_util::ready!(sleep.as_mut().poll(cx));
+        }
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.tokens -= n as f64;
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::pin::Pin;
+    use std::time::{Duration, Instant};
+
+    use futures_util::future::poll_fn;
+
+    use super::{RateLimit, ThrottledIo};
+    use crate::rt::{TokioIo, TokioTimer};
+
+    #[tokio::test]
+    async fn throttles_writes() {
+        let mock = tokio_test::io::Builder::new()
+            .write(b"abcd")
+            .write(b"ef")
+            .build();
+        let mut io = ThrottledIo::new(TokioIo::new(mock), TokioTimer::new())
+            .with_write_limit(RateLimit::new(100).burst(4));
+
+        let start = Instant::now();
+        let n = poll_fn(|cx| hyper::rt::Write::poll_write(Pin::new(&mut io), cx, b"abcdef"))
+            .await
+            .unwrap();
+        assert_eq!(n, 4, "write is shortened to the burst");
+
+        let mut rest = &b"ef"[..];
+        while !rest.is_empty() {
+            let n = poll_fn(|cx| hyper::rt::Write::poll_write(Pin::new(&mut io), cx, rest))
+                .await
+                .unwrap();
+            rest = &rest[n..];
+        }
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn unlimited_reads_pass_through() {
+        let mock = tokio_test::io::Builder::new().read(b"hello").build();
+        let mut io = ThrottledIo::new(TokioIo::new(mock), TokioTimer::new())
+            .with_write_limit(RateLimit::new(1));
+
+        let mut buf = [0; 16];
+        let mut buf = hyper::rt::ReadBuf::new(&mut buf);
+        poll_fn(|cx| hyper::rt::Read::poll_read(Pin::new(&mut io), cx, buf.unfilled()))
+            .await
+            .unwrap();
+        assert_eq!(buf.filled(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn throttles_reads() {
+        let mock = tokio_test::io::Builder::new().read(b"hello").build();
+        let mut io = ThrottledIo::new(TokioIo::new(mock), TokioTimer::new())
+            .with_read_limit(RateLimit::new(1000).burst(2));
+
+        let start = Instant::now();
+        let mut buf = [0; 16];
+        let mut buf = hyper::rt::ReadBuf::new(&mut buf);
+        poll_fn(|cx| hyper::rt::Read::poll_read(Pin::new(&mut io), cx, buf.unfilled()))
+            .await
+            .unwrap();
+        assert_eq!(buf.filled(), b"he", "read is shortened to the burst");
+
+        while buf.filled().len() < 5 {
+            poll_fn(|cx| hyper::rt::Read::poll_read(Pin::new(&mut io), cx, buf.unfilled()))
+                .await
+                .unwrap();
+        }
+        assert_eq!(buf.filled(), b"hello");
+        assert!(start.elapsed() >= Duration::from_millis(2));
+    }
+}
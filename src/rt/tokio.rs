@@ -15,6 +15,22 @@ use pin_project_lite::pin_project;
 #[derive(Default, Debug, Clone)]
 pub struct TokioExecutor {}
 
+/// Future executor that spawns on the current `tokio` [`LocalSet`].
+///
+/// Unlike [`TokioExecutor`], the futures don't need to be `Send`, so
+/// services and bodies holding `Rc`s or other thread-local state can be
+/// served, including over HTTP/2, from a single-threaded runtime.
+///
+/// # Panics
+///
+/// Executing a future panics if it isn't called from within a
+/// [`LocalSet`], as with [`tokio::task::spawn_local`].
+///
+/// [`LocalSet`]: tokio::task::LocalSet
+#[non_exhaustive]
+#[derive(Default, Debug, Clone)]
+pub struct LocalTokioExecutor {}
+
 pin_project! {
     /// A wrapping implementing hyper IO traits for a type that
     /// implements Tokio's IO traits.
@@ -26,6 +42,9 @@ pin_project! {
 }
 
 /// A Timer that uses the tokio runtime.
+///
+/// It follows tokio's clock, so it can be paused and advanced in tests with
+/// `tokio::time::pause` and `tokio::time::advance`.
 #[non_exhaustive]
 #[derive(Default, Clone, Debug)]
 pub struct TokioTimer;
@@ -59,6 +78,26 @@ impl TokioExecutor {
     }
 }
 
+// ===== impl LocalTokioExecutor =====
+
+impl<Fut> Executor<Fut> for LocalTokioExecutor
+where
+    Fut: Future + 'static,
+    Fut::Output: 'static,
+{
+    fn execute(&self, fut: Fut) {
+        tokio::task::spawn_local(fut);
+    }
+}
+
+impl LocalTokioExecutor {
+    /// Create new executor that relies on [`tokio::task::spawn_local`] to
+    /// execute futures.
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
 // ==== impl TokioIo =====
 
 impl<T> TokioIo<T> {
@@ -83,6 +122,49 @@ impl<T> TokioIo<T> {
     }
 }
 
+impl TokioIo<tokio::net::TcpStream> {
+    /// Attempt to receive data on the socket, without removing that data from
+    /// the queue, registering the current task for wakeup if data is not yet
+    /// available.
+    ///
+    /// See [`TcpStream::poll_peek`](tokio::net::TcpStream::poll_peek).
+    pub fn poll_peek(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<usize>> {
+        self.inner.poll_peek(cx, buf)
+    }
+}
+
+#[cfg(unix)]
+impl<T: std::os::unix::io::AsRawFd> std::os::unix::io::AsRawFd for TokioIo<T> {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(unix)]
+impl<T: std::os::unix::io::AsFd> std::os::unix::io::AsFd for TokioIo<T> {
+    fn as_fd(&self) -> std::os::unix::io::BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+#[cfg(windows)]
+impl<T: std::os::windows::io::AsRawSocket> std::os::windows::io::AsRawSocket for TokioIo<T> {
+    fn as_raw_socket(&self) -> std::os::windows::io::RawSocket {
+        self.inner.as_raw_socket()
+    }
+}
+
+#[cfg(windows)]
+impl<T: std::os::windows::io::AsSocket> std::os::windows::io::AsSocket for TokioIo<T> {
+    fn as_socket(&self) -> std::os::windows::io::BorrowedSocket<'_> {
+        self.inner.as_socket()
+    }
+}
+
 impl<T> hyper::rt::Read for TokioIo<T>
 where
     T: tokio::io::AsyncRead,
@@ -258,6 +340,12 @@ impl TokioSleep {
 
 #[cfg(test)]
 mod tests {
+    use std::io::{self, IoSlice};
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
     use crate::rt::TokioExecutor;
     use hyper::rt::Executor;
     use tokio::sync::oneshot;
@@ -272,4 +360,204 @@ mod tests {
         });
         rx.await.map_err(Into::into)
     }
+
+    #[cfg(all(not(miri), feature = "server-auto"))]
+    #[tokio::test]
+    async fn local_executor_serves_non_send_h2() {
+        use std::rc::Rc;
+
+        use http_body_util::{BodyExt, Full};
+        use hyper::body::Bytes;
+
+        use crate::rt::{LocalTokioExecutor, TokioIo};
+        use crate::server::conn::auto;
+
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let (client, server) = tokio::io::duplex(64 * 1024);
+                let greeting = Rc::new("hello");
+                let service = hyper::service::service_fn(move |_| {
+                    let greeting = greeting.clone();
+                    async move {
+                        Ok::<_, std::convert::Infallible>(hyper::Response::new(Full::new(
+                            Bytes::from(*greeting),
+                        )))
+                    }
+                });
+                tokio::task::spawn_local(async move {
+                    let builder = auto::Builder::new(LocalTokioExecutor::new());
+                    builder
+                        .serve_connection(TokioIo::new(server), service)
+                        .await
+                });
+
+                let (mut tx, conn) = hyper::client::conn::http2::handshake(
+                    LocalTokioExecutor::new(),
+                    TokioIo::new(client),
+                )
+                .await
+                .unwrap();
+                tokio::task::spawn_local(conn);
+                let req = hyper::Request::get("http://localhost/")
+                    .body(http_body_util::Empty::<Bytes>::new())
+                    .unwrap();
+                let res = tx.send_request(req).await.unwrap();
+                let body = res.into_body().collect().await.unwrap().to_bytes();
+                assert_eq!(body, "hello");
+            })
+            .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timer_follows_paused_clock() {
+        use futures_util::FutureExt;
+        use hyper::rt::Timer;
+        use std::time::Duration;
+
+        let mut sleep = crate::rt::TokioTimer::new().sleep(Duration::from_secs(60));
+        assert!((&mut sleep).now_or_never().is_none());
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(sleep.now_or_never().is_some());
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn tcp_peek_and_fd() {
+        use futures_util::future::poll_fn;
+        use tokio::io::AsyncWriteExt;
+        use tokio::net::{TcpListener, TcpStream};
+
+        use crate::rt::TokioIo;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = TcpStream::connect(addr).await.unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+        server.write_all(b"peek").await.unwrap();
+
+        #[cfg(unix)]
+        let fd = std::os::unix::io::AsRawFd::as_raw_fd(&client);
+        let io = TokioIo::new(client);
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::io::AsRawFd::as_raw_fd(&io), fd);
+
+        let mut buf = [0; 4];
+        let mut buf = tokio::io::ReadBuf::new(&mut buf);
+        let n = poll_fn(|cx| io.poll_peek(cx, &mut buf)).await.unwrap();
+        assert_eq!(n, 4);
+        assert_eq!(buf.filled(), b"peek");
+    }
+
+    pin_project_lite::pin_project! {
+        // A tokio IO that writes vectored, and records the most buffers it
+        // was handed at once.
+        struct Vectored {
+            #[pin]
+            inner: tokio::io::DuplexStream,
+            max_bufs: Arc<AtomicUsize>,
+        }
+    }
+
+    impl tokio::io::AsyncRead for Vectored {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.project().inner.poll_read(cx, buf)
+        }
+    }
+
+    impl tokio::io::AsyncWrite for Vectored {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.project().inner.poll_write(cx, buf)
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.project();
+            this.max_bufs.fetch_max(bufs.len(), Ordering::SeqCst);
+            this.inner.poll_write_vectored(cx, bufs)
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            true
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.project().inner.poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.project().inner.poll_shutdown(cx)
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn forwards_vectored_writes() {
+        use futures_util::future::poll_fn;
+        use http_body_util::{BodyExt, Full};
+        use hyper::body::Bytes;
+        use tokio::io::AsyncReadExt;
+
+        use crate::rt::TokioIo;
+
+        let max_bufs = Arc::new(AtomicUsize::new(0));
+        let (client, mut server) = tokio::io::duplex(64 * 1024);
+        let io = TokioIo::new(Vectored {
+            inner: client,
+            max_bufs: max_bufs.clone(),
+        });
+        assert!(hyper::rt::Write::is_write_vectored(&io));
+
+        // Both directions of the wrapper keep it.
+        let mut io = TokioIo::new(io);
+        assert!(tokio::io::AsyncWrite::is_write_vectored(&io));
+        let bufs = [IoSlice::new(b"he"), IoSlice::new(b"llo")];
+        let n =
+            poll_fn(|cx| tokio::io::AsyncWrite::poll_write_vectored(Pin::new(&mut io), cx, &bufs))
+                .await
+                .unwrap();
+        assert_eq!(max_bufs.load(Ordering::SeqCst), 2);
+        let mut buf = vec![0; n];
+        server.read_exact(&mut buf).await.unwrap();
+
+        // And hyper queues the head and body of a request, instead of
+        // flattening them into one buffer.
+        max_bufs.store(0, Ordering::SeqCst);
+        let (mut tx, conn) = hyper::client::conn::http1::handshake(io.into_inner())
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let service =
+            hyper::service::service_fn(|req: hyper::Request<hyper::body::Incoming>| async {
+                let body = req.into_body().collect().await?.to_bytes();
+                Ok::<_, hyper::Error>(hyper::Response::new(Full::new(body)))
+            });
+        tokio::spawn(
+            hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(server), service),
+        );
+
+        let body = Bytes::from(vec![b'a'; 16 * 1024]);
+        let res = tx
+            .send_request(
+                hyper::Request::post("/")
+                    .body(Full::new(body.clone()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), body);
+        assert!(max_bufs.load(Ordering::SeqCst) >= 2);
+    }
 }
@@ -7,21 +7,28 @@ use std::io::{Error as IoError, ErrorKind, Result as IoResult};
 use std::marker::PhantomPinned;
 use std::mem::MaybeUninit;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::{error::Error as StdError, marker::Unpin, time::Duration};
+use std::{error::Error as StdError, marker::Unpin, net::SocketAddr, time::Duration};
+#[cfg(feature = "tracing")]
+use std::{sync::atomic::AtomicBool, time::Instant};
 
 use bytes::Bytes;
 use http::{Request, Response};
 use http_body::Body;
 use hyper::{
     body::Incoming,
-    rt::{bounds::Http2ServerConnExec, Read, ReadBuf, Timer, Write},
+    rt::{bounds::Http2ServerConnExec, Read, ReadBuf, Sleep, Timer, Write},
     server::conn::{http1, http2},
     service::Service,
 };
 use pin_project_lite::pin_project;
 
-use crate::common::rewind::Rewind;
+use super::upgrade::{UpgradeIo, UpgradeService, Upgrades};
+use crate::common::timer;
+use crate::events::{self, ConnectionEvent, Events, EventsHandle};
+use crate::rt::Rewind;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
@@ -32,6 +39,13 @@ const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 pub struct Builder<E> {
     http1: http1::Builder,
     http2: http2::Builder<E>,
+    events: EventsHandle,
+    upgrades: Upgrades,
+    strict_preface: bool,
+    tls_client_hello: Option<TlsReply>,
+    timer: Option<timer::Timer>,
+    first_byte_timeout: Option<Duration>,
+    shutdown_requests: ShutdownRequests,
 }
 
 impl<E> Builder<E> {
@@ -54,6 +68,13 @@ impl<E> Builder<E> {
         Self {
             http1: http1::Builder::new(),
             http2: http2::Builder::new(executor),
+            events: EventsHandle::default(),
+            upgrades: Upgrades::default(),
+            strict_preface: false,
+            tls_client_hello: None,
+            timer: None,
+            first_byte_timeout: None,
+            shutdown_requests: ShutdownRequests::Reject,
         }
     }
 
@@ -67,6 +88,89 @@ impl<E> Builder<E> {
         Http2Builder { inner: self }
     }
 
+    /// Report the connections served by this builder to `events`.
+    ///
+    /// Only connection events are reported here. The requests of a
+    /// connection are reported by wrapping its service in an
+    /// [`EventsService`](crate::events::EventsService) registered with the
+    /// same `events`. See the [`events`](crate::events) module for details.
+    pub fn connection_events<V>(&mut self, events: V) -> &mut Self
+    where
+        V: Events,
+    {
+        self.events = EventsHandle::new(events);
+        self
+    }
+
+    /// Set the timer used in background tasks, by both HTTP/1 and HTTP/2
+    /// connections.
+    ///
+    /// All connections served by this builder share the timer. With a
+    /// [`CoarseTimer`](crate::rt::CoarseTimer), they also share its single
+    /// driver, and their header read timeouts and HTTP/2 keep-alive
+    /// intervals are woken in batches, once per tick.
+    pub fn timer<M>(&mut self, timer: M) -> &mut Self
+    where
+        M: Timer + Send + Sync + 'static,
+    {
+        let timer = timer::Timer::new(timer);
+        self.http1.timer(timer.clone());
+        self.http2.timer(timer.clone());
+        self.timer = Some(timer);
+        self
+    }
+
+    /// Set a timeout for receiving the first request of a connection.
+    ///
+    /// This covers everything before it, whatever the protocol: detecting
+    /// the protocol, and then reading the HTTP/1 request headers, or the
+    /// HTTP/2 preface, settings and request headers. A connection that
+    /// doesn't send a whole request in time fails with a
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut) error.
+    ///
+    /// Default is None.
+    ///
+    /// # Panics
+    ///
+    /// This requires a timer set with [`timer`](Builder::timer). Serving a
+    /// connection, or taking a [`Config`], panics without one.
+    pub fn first_byte_timeout(&mut self, timeout: impl Into<Option<Duration>>) -> &mut Self {
+        self.first_byte_timeout = timeout.into();
+        self
+    }
+
+    /// Set whether connections that clearly don't start with an HTTP request
+    /// fail right away.
+    ///
+    /// Without this, any bytes that aren't the HTTP/2 preface are parsed as
+    /// HTTP/1, and binary junk fails with a parse error of the HTTP/1 parser.
+    /// With it, first bytes that can't start an HTTP/1 request line fail the
+    /// connection with an [`InvalidFirstBytes`] error, which carries the bytes
+    /// read.
+    ///
+    /// A TLS `ClientHello` always fails with a [`TlsClientHello`] error.
+    ///
+    /// Default is false.
+    pub fn strict_preface(&mut self, enabled: bool) -> &mut Self {
+        self.strict_preface = enabled;
+        self
+    }
+
+    /// Call `reply` when a connection starts with a TLS `ClientHello`,
+    /// usually because a client was pointed at this plaintext server with an
+    /// `https` URL.
+    ///
+    /// Such connections fail with a [`TlsClientHello`] error. `reply` gets
+    /// the first bytes read from the connection, and may return bytes to
+    /// write to it before it is closed, such as a TLS alert record.
+    pub fn tls_client_hello<F>(&mut self, reply: F) -> &mut Self
+    where
+        F: Fn(&[u8]) -> Option<Bytes> + Send + Sync + 'static,
+    {
+        self.tls_client_hello = Some(TlsReply(Arc::new(reply)));
+        self
+    }
+
     /// Bind a connection together with a [`Service`].
     pub fn serve_connection<I, S, B>(&self, io: I, service: S) -> Connection<'_, I, S, E>
     where
@@ -78,18 +182,77 @@ impl<E> Builder<E> {
         I: Read + Write + Unpin + 'static,
         E: Http2ServerConnExec<S::Future, B>,
     {
-        Connection {
-            state: ConnState::ReadVersion {
-                read_version: read_version(io),
-                builder: self,
-                service: Some(service),
-            },
+        BuilderRef::Borrowed(self).serve_connection(io, service)
+    }
+
+    /// Bind a connection together with a [`Service`] made for it.
+    ///
+    /// `make` is given the [`ConnInfo`] of the IO, such as the address of the
+    /// peer, to construct the service of this connection. A factory shared by
+    /// connections can be passed by reference, as `&mut make`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "tokio")]
+    /// # async fn run(stream: tokio::net::TcpStream) {
+    /// use std::convert::Infallible;
+    /// use http_body_util::Full;
+    /// use hyper::body::Bytes;
+    /// use hyper::service::service_fn;
+    /// use hyper_util::rt::{TokioExecutor, TokioIo};
+    /// use hyper_util::server::conn::auto::{Builder, ConnInfo};
+    ///
+    /// let make = |info: &ConnInfo| {
+    ///     let peer = info.peer_addr();
+    ///     service_fn(move |_| async move {
+    ///         let body = format!("hello {:?}", peer);
+    ///         Ok::<_, Infallible>(http::Response::new(Full::new(Bytes::from(body))))
+    ///     })
+    /// };
+    /// let _ = Builder::new(TokioExecutor::new())
+    ///     .serve_connection_with_make(TokioIo::new(stream), make)
+    ///     .await;
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn serve_connection_with_make<I, M, S, B>(&self, io: I, make: M) -> Connection<'_, I, S, E>
+    where
+        M: FnOnce(&ConnInfo) -> S,
+        S: Service<Request<Incoming>, Response = Response<B>>,
+        S::Future: 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: ConnectionInfo + Read + Write + Unpin + 'static,
+        E: Http2ServerConnExec<S::Future, B>,
+    {
+        BuilderRef::Borrowed(self).serve_connection_with_make(io, make)
+    }
+
+    /// Take an immutable snapshot of this builder, to serve connections with.
+    ///
+    /// The connections served by a [`Config`] hold on to it instead of
+    /// borrowing the builder, and clones of it share the same configuration,
+    /// so they are cheap to hand to each accepted connection. Changing the
+    /// builder afterwards doesn't change the snapshot.
+    pub fn to_owned_config(&self) -> Config<E>
+    where
+        E: Clone,
+    {
+        self.check_timer();
+        Config {
+            builder: Arc::new(self.clone()),
         }
     }
 
     /// Bind a connection together with a [`Service`], with the ability to
     /// handle HTTP upgrades. This requires that the IO object implements
     /// `Send`.
+    ///
+    /// The upgrades of HTTP/1 connections are limited as configured with
+    /// [`Http1Builder::upgrades`], [`Http1Builder::max_upgraded_connections`]
+    /// and [`Http1Builder::upgrade_timeout`].
     pub fn serve_connection_with_upgrades<I, S, B>(
         &self,
         io: I,
@@ -104,22 +267,305 @@ impl<E> Builder<E> {
         I: Read + Write + Unpin + Send + 'static,
         E: Http2ServerConnExec<S::Future, B>,
     {
+        BuilderRef::Borrowed(self).serve_connection_with_upgrades(io, service)
+    }
+}
+
+/// An immutable snapshot of a [`Builder`], cheap to clone.
+///
+/// Returned by [`Builder::to_owned_config`].
+#[derive(Debug)]
+pub struct Config<E> {
+    builder: Arc<Builder<E>>,
+}
+
+impl<E> Config<E> {
+    /// Bind a connection together with a [`Service`].
+    ///
+    /// See [`Builder::serve_connection`].
+    pub fn serve_connection<I, S, B>(&self, io: I, service: S) -> Connection<'static, I, S, E>
+    where
+        S: Service<Request<Incoming>, Response = Response<B>>,
+        S::Future: 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: Read + Write + Unpin + 'static,
+        E: Http2ServerConnExec<S::Future, B>,
+    {
+        self.to_ref().serve_connection(io, service)
+    }
+
+    /// Bind a connection together with a [`Service`] made for it.
+    ///
+    /// See [`Builder::serve_connection_with_make`].
+    pub fn serve_connection_with_make<I, M, S, B>(
+        &self,
+        io: I,
+        make: M,
+    ) -> Connection<'static, I, S, E>
+    where
+        M: FnOnce(&ConnInfo) -> S,
+        S: Service<Request<Incoming>, Response = Response<B>>,
+        S::Future: 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: ConnectionInfo + Read + Write + Unpin + 'static,
+        E: Http2ServerConnExec<S::Future, B>,
+    {
+        self.to_ref().serve_connection_with_make(io, make)
+    }
+
+    /// Bind a connection together with a [`Service`], with the ability to
+    /// handle HTTP upgrades.
+    ///
+    /// See [`Builder::serve_connection_with_upgrades`].
+    pub fn serve_connection_with_upgrades<I, S, B>(
+        &self,
+        io: I,
+        service: S,
+    ) -> UpgradeableConnection<'static, I, S, E>
+    where
+        S: Service<Request<Incoming>, Response = Response<B>>,
+        S::Future: 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: Read + Write + Unpin + Send + 'static,
+        E: Http2ServerConnExec<S::Future, B>,
+    {
+        self.to_ref().serve_connection_with_upgrades(io, service)
+    }
+
+    fn to_ref(&self) -> BuilderRef<'static, E> {
+        BuilderRef::Owned(self.builder.clone())
+    }
+}
+
+impl<E> Builder<E> {
+    // Panic if a timeout is set without a timer, as hyper does for its own
+    // timeouts, rather than failing every connection.
+    fn check_timer(&self) {
+        if self.timer.is_some() {
+            return;
+        }
+        if self.first_byte_timeout.is_some() {
+            panic!("timeout `first_byte_timeout` set, but no timer set");
+        }
+        if let ShutdownRequests::ServeOne(_) = self.shutdown_requests {
+            panic!("timeout `ShutdownRequests::ServeOne` set, but no timer set");
+        }
+    }
+}
+
+impl<E> Clone for Config<E> {
+    fn clone(&self) -> Self {
+        Config {
+            builder: self.builder.clone(),
+        }
+    }
+}
+
+// The builder of a connection, borrowed or from a `Config`.
+enum BuilderRef<'a, E> {
+    Borrowed(&'a Builder<E>),
+    Owned(Arc<Builder<E>>),
+}
+
+impl<'a, E> BuilderRef<'a, E> {
+    fn serve_connection<I, S>(self, io: I, service: S) -> Connection<'a, I, S, E>
+    where
+        S: ConnService,
+        I: Read + Unpin,
+    {
+        let read_version = read_version(io, self.strict_preface, self.tls_client_hello.clone());
+        let events = ConnEvents::new(&self.events);
+        let requests = ConnRequests::new(&self);
+        Connection {
+            state: ConnState::ReadVersion {
+                read_version,
+                builder: self,
+                service: Some(service),
+            },
+            trace: ConnTrace::new(false),
+            events,
+            requests,
+        }
+    }
+
+    fn serve_connection_with_make<I, M, S>(self, io: I, make: M) -> Connection<'a, I, S, E>
+    where
+        M: FnOnce(&ConnInfo) -> S,
+        S: ConnService,
+        I: ConnectionInfo + Read + Unpin,
+    {
+        let info = io.conn_info();
+        let service = make(&info);
+        let conn = self.serve_connection(io, service);
+        #[cfg(feature = "tracing")]
+        if let Some(addr) = info.peer_addr {
+            conn.trace.peer_addr(addr);
+        }
+        conn
+    }
+
+    fn serve_connection_with_upgrades<I, S>(
+        self,
+        io: I,
+        service: S,
+    ) -> UpgradeableConnection<'a, I, S, E>
+    where
+        S: ConnService,
+        I: Read + Unpin,
+    {
+        let read_version = read_version(io, self.strict_preface, self.tls_client_hello.clone());
+        let events = ConnEvents::new(&self.events);
+        let requests = ConnRequests::new(&self);
         UpgradeableConnection {
             state: UpgradeableConnState::ReadVersion {
-                read_version: read_version(io),
+                read_version,
                 builder: self,
                 service: Some(service),
             },
+            trace: ConnTrace::new(true),
+            events,
+            requests,
+        }
+    }
+}
+
+impl<E> std::ops::Deref for BuilderRef<'_, E> {
+    type Target = Builder<E>;
+
+    fn deref(&self) -> &Builder<E> {
+        match *self {
+            BuilderRef::Borrowed(builder) => builder,
+            BuilderRef::Owned(ref builder) => builder,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TlsReply(Arc<TlsReplyFn>);
+
+type TlsReplyFn = dyn Fn(&[u8]) -> Option<Bytes> + Send + Sync;
+
+impl std::fmt::Debug for TlsReply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TlsReply")
+    }
+}
+
+/// Metadata of a connection, given to the service factory of
+/// [`Builder::serve_connection_with_make`].
+#[derive(Clone, Debug, Default)]
+pub struct ConnInfo {
+    local_addr: Option<SocketAddr>,
+    peer_addr: Option<SocketAddr>,
+}
+
+impl ConnInfo {
+    /// Create metadata of a connection, without any addresses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the local address of the connection.
+    pub fn with_local_addr(mut self, addr: SocketAddr) -> Self {
+        self.local_addr = Some(addr);
+        self
+    }
+
+    /// Set the address of the peer of the connection.
+    pub fn with_peer_addr(mut self, addr: SocketAddr) -> Self {
+        self.peer_addr = Some(addr);
+        self
+    }
+
+    /// The local address of the connection, if known.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// The address of the peer of the connection, if known.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+}
+
+/// An IO that knows the [`ConnInfo`] of its connection.
+///
+/// Implement it for wrappers of IO, such as TLS streams, to serve them with
+/// [`Builder::serve_connection_with_make`].
+pub trait ConnectionInfo {
+    /// The metadata of this connection.
+    fn conn_info(&self) -> ConnInfo;
+}
+
+#[cfg(feature = "tokio")]
+impl ConnectionInfo for crate::rt::TokioIo<tokio::net::TcpStream> {
+    fn conn_info(&self) -> ConnInfo {
+        ConnInfo {
+            local_addr: self.inner().local_addr().ok(),
+            peer_addr: self.inner().peer_addr().ok(),
         }
     }
 }
+
+/// Error of a connection whose first bytes can't start an HTTP request.
+///
+/// Only returned with [`Builder::strict_preface`] enabled.
+#[derive(Debug)]
+pub struct InvalidFirstBytes {
+    bytes: Bytes,
+}
+
+impl InvalidFirstBytes {
+    /// The first bytes read from the connection.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl std::fmt::Display for InvalidFirstBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("invalid first bytes of connection")
+    }
+}
+
+impl StdError for InvalidFirstBytes {}
+
+/// Error of a plaintext connection that starts with a TLS `ClientHello`.
+///
+/// See [`Builder::tls_client_hello`].
+#[derive(Debug)]
+pub struct TlsClientHello {
+    bytes: Bytes,
+}
+
+impl TlsClientHello {
+    /// The first bytes read from the connection.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl std::fmt::Display for TlsClientHello {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TLS handshake on a plaintext connection")
+    }
+}
+
+impl StdError for TlsClientHello {}
+
 #[derive(Copy, Clone)]
 enum Version {
     H1,
     H2,
 }
 
-fn read_version<I>(io: I) -> ReadVersion<I>
+fn read_version<I>(io: I, strict: bool, tls: Option<TlsReply>) -> ReadVersion<I>
 where
     I: Read + Unpin,
 {
@@ -128,10 +574,86 @@ where
         buf: [MaybeUninit::uninit(); 24],
         filled: 0,
         version: Version::H1,
+        strict,
+        tls,
+        hello: None,
         _pin: PhantomPinned,
     }
 }
 
+// Whether `bytes` are the start of a TLS record with a `ClientHello`, or
+// `None` if there are too few of them to tell.
+fn is_client_hello(bytes: &[u8]) -> Option<bool> {
+    // content type handshake, legacy record version 3.x, and after the
+    // record length, handshake type client_hello.
+    match bytes {
+        [] => None,
+        [0x16] | [0x16, 0x03] => None,
+        [0x16, 0x03, minor, rest @ ..] if *minor <= 0x04 => match rest {
+            [_, _, kind, ..] => Some(*kind == 0x01),
+            _ => None,
+        },
+        _ => Some(false),
+    }
+}
+
+// Whether `bytes` may be the start of an HTTP/1 request: optional empty
+// lines, then a method token, up to the space after it.
+fn may_be_http1(bytes: &[u8]) -> bool {
+    let start = bytes
+        .iter()
+        .position(|&b| b != b'\r' && b != b'\n')
+        .unwrap_or(bytes.len());
+    for (i, &b) in bytes[start..].iter().enumerate() {
+        if b == b' ' {
+            return i > 0;
+        }
+        if !is_tchar(b) {
+            return false;
+        }
+    }
+    true
+}
+
+fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+// Write the reply to a TLS handshake, then fail with the handshake.
+fn poll_tls_reply<I>(
+    io: &mut I,
+    hello: &mut Option<(Bytes, Bytes)>,
+    cx: &mut Context<'_>,
+) -> Poll<IoError>
+where
+    I: Write + Unpin,
+{
+    let (_, reply) = hello.as_mut().unwrap();
+    while !reply.is_empty() {
+        // The connection fails anyway, so errors writing are ignored.
+        match ready!(Pin::new(&mut *io).poll_write(cx, reply)) {
+            Ok(n) if n > 0 => *reply = reply.slice(n..),
+            _ => *reply = Bytes::new(),
+        }
+    }
+    let _ = ready!(Pin::new(io).poll_flush(cx));
+    let (bytes, _) = hello.take().unwrap();
+    Poll::Ready(IoError::new(
+        ErrorKind::InvalidData,
+        TlsClientHello { bytes },
+    ))
+}
+
+// The error of reading the version, as the error of the connection.
+fn version_error(err: IoError) -> Box<dyn StdError + Send + Sync> {
+    match err.get_ref() {
+        Some(inner) if inner.is::<InvalidFirstBytes>() || inner.is::<TlsClientHello>() => {
+            err.into_inner().unwrap()
+        }
+        _ => err.into(),
+    }
+}
+
 pin_project! {
     struct ReadVersion<I> {
         io: Option<I>,
@@ -139,6 +661,11 @@ pin_project! {
         // the amount of `buf` thats been filled
         filled: usize,
         version: Version,
+        strict: bool,
+        tls: Option<TlsReply>,
+        // The first bytes of a TLS handshake, and what is left of the reply
+        // to them.
+        hello: Option<(Bytes, Bytes)>,
         // Make this future `!Unpin` for compatibility with async trait methods.
         #[pin]
         _pin: PhantomPinned,
@@ -147,13 +674,17 @@ pin_project! {
 
 impl<I> Future for ReadVersion<I>
 where
-    I: Read + Unpin,
+    I: Read + Write + Unpin,
 {
     type Output = IoResult<(Version, Rewind<I>)>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
 
+        if this.hello.is_some() {
+            return poll_tls_reply(this.io.as_mut().unwrap(), this.hello, cx).map(Err);
+        }
+
         let mut buf = ReadBuf::uninit(&mut *this.buf);
         // SAFETY: `this.filled` tracks how many bytes have been read (and thus initialized) and
         // we're only advancing by that many.
@@ -163,31 +694,47 @@ where
 
         while buf.filled().len() < H2_PREFACE.len() {
             if buf.filled() != &H2_PREFACE[0..buf.filled().len()] {
-                let io = this.io.take().unwrap();
-                let buf = buf.filled().to_vec();
-                return Poll::Ready(Ok((
-                    *this.version,
-                    Rewind::new_buffered(io, Bytes::from(buf)),
-                )));
-            } else {
-                // if our buffer is empty, then we need to read some data to continue.
-                let len = buf.filled().len();
-                ready!(Pin::new(this.io.as_mut().unwrap()).poll_read(cx, buf.unfilled()))?;
-                *this.filled = buf.filled().len();
-                if buf.filled().len() == len {
-                    return Err(IoError::new(ErrorKind::UnexpectedEof, "early eof")).into();
+                match is_client_hello(buf.filled()) {
+                    Some(true) => {
+                        let bytes = Bytes::copy_from_slice(buf.filled());
+                        let reply = this.tls.as_ref().and_then(|tls| (tls.0)(&bytes));
+                        *this.hello = Some((bytes, reply.unwrap_or_default()));
+                        return poll_tls_reply(this.io.as_mut().unwrap(), this.hello, cx).map(Err);
+                    }
+                    Some(false) => {
+                        if *this.strict && !may_be_http1(buf.filled()) {
+                            let bytes = Bytes::copy_from_slice(buf.filled());
+                            return Err(IoError::new(
+                                ErrorKind::InvalidData,
+                                InvalidFirstBytes { bytes },
+                            ))
+                            .into();
+                        }
+                        let io = this.io.take().unwrap();
+                        return Poll::Ready(Ok((
+                            *this.version,
+                            Rewind::new_inline(io, buf.filled()),
+                        )));
+                    }
+                    // Too few bytes to tell a TLS handshake from HTTP/1.
+                    None => {}
                 }
             }
+            // if our buffer is empty, then we need to read some data to continue.
+            let len = buf.filled().len();
+            ready!(Pin::new(this.io.as_mut().unwrap()).poll_read(cx, buf.unfilled()))?;
+            *this.filled = buf.filled().len();
+            if buf.filled().len() == len {
+                return Err(IoError::new(ErrorKind::UnexpectedEof, "early eof")).into();
+            }
         }
+        let io = this.io.take().unwrap();
         if buf.filled() == H2_PREFACE {
-            *this.version = Version::H2;
+            // The preface is static, so it needs neither a copy nor an allocation.
+            let io = Rewind::new_buffered(io, Bytes::from_static(H2_PREFACE));
+            return Poll::Ready(Ok((Version::H2, io)));
         }
-        let io = this.io.take().unwrap();
-        let buf = buf.filled().to_vec();
-        Poll::Ready(Ok((
-            *this.version,
-            Rewind::new_buffered(io, Bytes::from(buf)),
-        )))
+        Poll::Ready(Ok((*this.version, Rewind::new_inline(io, buf.filled()))))
     }
 }
 
@@ -195,10 +742,13 @@ pin_project! {
     /// Connection future.
     pub struct Connection<'a, I, S, E>
     where
-        S: HttpService<Incoming>,
+        S: ConnService,
     {
         #[pin]
         state: ConnState<'a, I, S, E>,
+        trace: ConnTrace,
+        events: ConnEvents,
+        requests: ConnRequests,
     }
 }
 
@@ -206,34 +756,42 @@ pin_project! {
     #[project = ConnStateProj]
     enum ConnState<'a, I, S, E>
     where
-        S: HttpService<Incoming>,
+        S: ConnService,
     {
         ReadVersion {
             #[pin]
             read_version: ReadVersion<I>,
-            builder: &'a Builder<E>,
+            builder: BuilderRef<'a, E>,
             service: Option<S>,
         },
         H1 {
             #[pin]
-            conn: hyper::server::conn::http1::Connection<Rewind<I>, S>,
+            conn: H1Conn<I, S>,
         },
         H2 {
             #[pin]
-            conn: hyper::server::conn::http2::Connection<Rewind<I>, S, E>,
+            conn: H2Conn<I, S, E>,
         },
     }
 }
 
 impl<I, S, E, B> Connection<'_, I, S, E>
 where
-    S: HttpService<Incoming, ResBody = B>,
+    S: Service<Request<Incoming>, Response = Response<B>>,
     S::Error: Into<Box<dyn StdError + Send + Sync>>,
     I: Read + Write + Unpin,
     B: Body + 'static,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
     E: Http2ServerConnExec<S::Future, B>,
 {
+    /// Record the address of the peer on the tracing span of this connection.
+    #[cfg(feature = "tracing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+    pub fn with_peer_addr(self, addr: SocketAddr) -> Self {
+        self.trace.peer_addr(addr);
+        self
+    }
+
     /// Start a graceful shutdown process for this connection.
     ///
     /// This `Connection` should continue to be polled until shutdown can finish.
@@ -243,12 +801,129 @@ where
     /// This should only be called while the `Connection` future is still pending. If called after
     /// `Connection::poll` has resolved, this does nothing.
     pub fn graceful_shutdown(self: Pin<&mut Self>) {
-        match self.project().state.project() {
+        let this = self.project();
+        this.trace.graceful_shutdown();
+        match this.state.project() {
             ConnStateProj::ReadVersion { .. } => {}
-            ConnStateProj::H1 { conn } => conn.graceful_shutdown(),
+            ConnStateProj::H1 { conn } => {
+                if this.requests.start_drain() {
+                    conn.graceful_shutdown();
+                }
+            }
             ConnStateProj::H2 { conn } => conn.graceful_shutdown(),
         }
     }
+
+    /// The HTTP/1 connection, if the protocol was detected to be HTTP/1.
+    ///
+    /// This gives access to the HTTP/1 specific APIs of hyper.
+    pub fn http1_mut(self: Pin<&mut Self>) -> Option<Http1Connection<'_, I, S>> {
+        match self.project().state.project() {
+            ConnStateProj::H1 { conn } => Some(Http1Connection { conn }),
+            _ => None,
+        }
+    }
+
+    /// The HTTP/2 connection, if the protocol was detected to be HTTP/2.
+    ///
+    /// This gives access to the HTTP/2 specific APIs of hyper.
+    pub fn http2_mut(self: Pin<&mut Self>) -> Option<Http2Connection<'_, I, S, E>> {
+        match self.project().state.project() {
+            ConnStateProj::H2 { conn } => Some(Http2Connection { conn }),
+            _ => None,
+        }
+    }
+}
+
+/// The HTTP/1 connection of a [`Connection`].
+///
+/// Returned by [`Connection::http1_mut`].
+pub struct Http1Connection<'c, I, S>
+where
+    S: ConnService,
+{
+    conn: Pin<&'c mut H1Conn<I, S>>,
+}
+
+impl<I, S, B> Http1Connection<'_, I, S>
+where
+    S: Service<Request<Incoming>, Response = Response<B>>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    I: Read + Write + Unpin,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Start a graceful shutdown process for this connection, as HTTP/1
+    /// does it.
+    ///
+    /// Unlike [`Connection::graceful_shutdown`], this ignores
+    /// [`Http1Builder::shutdown_requests`].
+    pub fn graceful_shutdown(&mut self) {
+        self.conn.as_mut().graceful_shutdown();
+    }
+
+    /// Poll the connection for completion, but without calling `shutdown`
+    /// on the underlying IO.
+    ///
+    /// A connection polled this way doesn't report its end to
+    /// [`Builder::connection_events`], and its tracing span isn't finished, as when
+    /// polling the [`Connection`].
+    pub fn poll_without_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>>
+    where
+        S: Unpin,
+        S::Future: Unpin,
+        I: Unpin,
+    {
+        self.conn
+            .as_mut()
+            .get_mut()
+            .poll_without_shutdown(cx)
+            .map_err(Into::into)
+    }
+}
+
+impl<I, S> std::fmt::Debug for Http1Connection<'_, I, S>
+where
+    S: ConnService,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Http1Connection").finish()
+    }
+}
+
+/// The HTTP/2 connection of a [`Connection`].
+///
+/// Returned by [`Connection::http2_mut`].
+pub struct Http2Connection<'c, I, S, E>
+where
+    S: ConnService,
+{
+    conn: Pin<&'c mut H2Conn<I, S, E>>,
+}
+
+impl<I, S, E, B> Http2Connection<'_, I, S, E>
+where
+    S: Service<Request<Incoming>, Response = Response<B>>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    I: Read + Write + Unpin,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: Http2ServerConnExec<S::Future, B>,
+{
+    /// Start a graceful shutdown process for this connection, sending a
+    /// `GOAWAY` frame.
+    pub fn graceful_shutdown(&mut self) {
+        self.conn.as_mut().graceful_shutdown();
+    }
+}
+
+impl<I, S, E> std::fmt::Debug for Http2Connection<'_, I, S, E>
+where
+    S: ConnService,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Http2Connection").finish()
+    }
 }
 
 impl<I, S, E, B> Future for Connection<'_, I, S, E>
@@ -263,37 +938,63 @@ where
 {
     type Output = Result<()>;
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        loop {
-            let mut this = self.as_mut().project();
-
-            match this.state.as_mut().project() {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut state = this.state;
+        let trace = &*this.trace;
+        let events = this.events;
+        let requests = this.requests;
+        if let Poll::Ready(err) = requests.poll_first_expired(cx) {
+            let res = Err(err.into());
+            trace.finish(&res);
+            events.closed(&res);
+            return Poll::Ready(res);
+        }
+        let res = trace.in_scope(|| loop {
+            match state.as_mut().project() {
                 ConnStateProj::ReadVersion {
                     read_version,
                     builder,
                     service,
                 } => {
-                    let (version, io) = ready!(read_version.poll(cx))?;
-                    let service = service.take().unwrap();
+                    let (version, io) = match ready!(read_version.poll(cx)) {
+                        Ok(read) => read,
+                        Err(err) => {
+                            trace.version_error(&err);
+                            return Poll::Ready(Err(version_error(err)));
+                        }
+                    };
+                    trace.version(version);
+                    events.opened(version);
+                    let service = requests.wrap(service.take().unwrap());
                     match version {
                         Version::H1 => {
                             let conn = builder.http1.serve_connection(io, service);
-                            this.state.set(ConnState::H1 { conn });
+                            state.set(ConnState::H1 { conn });
                         }
                         Version::H2 => {
                             let conn = builder.http2.serve_connection(io, service);
-                            this.state.set(ConnState::H2 { conn });
+                            state.set(ConnState::H2 { conn });
                         }
                     }
                 }
-                ConnStateProj::H1 { conn } => {
-                    return conn.poll(cx).map_err(Into::into);
+                ConnStateProj::H1 { mut conn } => {
+                    let res = conn.as_mut().poll(cx);
+                    if res.is_pending() && requests.poll_drained(cx) {
+                        conn.graceful_shutdown();
+                        continue;
+                    }
+                    return res.map_err(Into::into);
                 }
                 ConnStateProj::H2 { conn } => {
                     return conn.poll(cx).map_err(Into::into);
                 }
             }
-        }
+        });
+        let res = ready!(res);
+        trace.finish(&res);
+        events.closed(&res);
+        Poll::Ready(res)
     }
 }
 
@@ -301,10 +1002,13 @@ pin_project! {
     /// Connection future.
     pub struct UpgradeableConnection<'a, I, S, E>
     where
-        S: HttpService<Incoming>,
+        S: ConnService,
     {
         #[pin]
         state: UpgradeableConnState<'a, I, S, E>,
+        trace: ConnTrace,
+        events: ConnEvents,
+        requests: ConnRequests,
     }
 }
 
@@ -312,34 +1016,45 @@ pin_project! {
     #[project = UpgradeableConnStateProj]
     enum UpgradeableConnState<'a, I, S, E>
     where
-        S: HttpService<Incoming>,
+        S: ConnService,
     {
         ReadVersion {
             #[pin]
             read_version: ReadVersion<I>,
-            builder: &'a Builder<E>,
+            builder: BuilderRef<'a, E>,
             service: Option<S>,
         },
         H1 {
             #[pin]
-            conn: hyper::server::conn::http1::UpgradeableConnection<Rewind<I>, S>,
+            conn: hyper::server::conn::http1::UpgradeableConnection<
+                UpgradeIo<Rewind<I>>,
+                UpgradeService<CountService<S>>,
+            >,
         },
         H2 {
             #[pin]
-            conn: hyper::server::conn::http2::Connection<Rewind<I>, S, E>,
+            conn: H2Conn<I, S, E>,
         },
     }
 }
 
 impl<I, S, E, B> UpgradeableConnection<'_, I, S, E>
 where
-    S: HttpService<Incoming, ResBody = B>,
+    S: Service<Request<Incoming>, Response = Response<B>>,
     S::Error: Into<Box<dyn StdError + Send + Sync>>,
     I: Read + Write + Unpin,
     B: Body + 'static,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
     E: Http2ServerConnExec<S::Future, B>,
 {
+    /// Record the address of the peer on the tracing span of this connection.
+    #[cfg(feature = "tracing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+    pub fn with_peer_addr(self, addr: SocketAddr) -> Self {
+        self.trace.peer_addr(addr);
+        self
+    }
+
     /// Start a graceful shutdown process for this connection.
     ///
     /// This `UpgradeableConnection` should continue to be polled until shutdown can finish.
@@ -349,9 +1064,15 @@ where
     /// This should only be called while the `Connection` future is still nothing. pending. If
     /// called after `UpgradeableConnection::poll` has resolved, this does nothing.
     pub fn graceful_shutdown(self: Pin<&mut Self>) {
-        match self.project().state.project() {
+        let this = self.project();
+        this.trace.graceful_shutdown();
+        match this.state.project() {
             UpgradeableConnStateProj::ReadVersion { .. } => {}
-            UpgradeableConnStateProj::H1 { conn } => conn.graceful_shutdown(),
+            UpgradeableConnStateProj::H1 { conn } => {
+                if this.requests.start_drain() {
+                    conn.graceful_shutdown();
+                }
+            }
             UpgradeableConnStateProj::H2 { conn } => conn.graceful_shutdown(),
         }
     }
@@ -369,37 +1090,428 @@ where
 {
     type Output = Result<()>;
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        loop {
-            let mut this = self.as_mut().project();
-
-            match this.state.as_mut().project() {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut state = this.state;
+        let trace = &*this.trace;
+        let events = this.events;
+        let requests = this.requests;
+        if let Poll::Ready(err) = requests.poll_first_expired(cx) {
+            let res = Err(err.into());
+            trace.finish(&res);
+            events.closed(&res);
+            return Poll::Ready(res);
+        }
+        let res = trace.in_scope(|| loop {
+            match state.as_mut().project() {
                 UpgradeableConnStateProj::ReadVersion {
                     read_version,
                     builder,
                     service,
                 } => {
-                    let (version, io) = ready!(read_version.poll(cx))?;
-                    let service = service.take().unwrap();
+                    let (version, io) = match ready!(read_version.poll(cx)) {
+                        Ok(read) => read,
+                        Err(err) => {
+                            trace.version_error(&err);
+                            return Poll::Ready(Err(version_error(err)));
+                        }
+                    };
+                    trace.version(version);
+                    events.opened(version);
+                    let service = requests.wrap(service.take().unwrap());
                     match version {
                         Version::H1 => {
+                            let (io, service) = builder.upgrades.wrap(io, service);
                             let conn = builder.http1.serve_connection(io, service).with_upgrades();
-                            this.state.set(UpgradeableConnState::H1 { conn });
+                            state.set(UpgradeableConnState::H1 { conn });
                         }
                         Version::H2 => {
                             let conn = builder.http2.serve_connection(io, service);
-                            this.state.set(UpgradeableConnState::H2 { conn });
+                            state.set(UpgradeableConnState::H2 { conn });
                         }
                     }
                 }
-                UpgradeableConnStateProj::H1 { conn } => {
-                    return conn.poll(cx).map_err(Into::into);
+                UpgradeableConnStateProj::H1 { mut conn } => {
+                    let res = conn.as_mut().poll(cx);
+                    if res.is_pending() && requests.poll_drained(cx) {
+                        conn.graceful_shutdown();
+                        continue;
+                    }
+                    return res.map_err(Into::into);
                 }
                 UpgradeableConnStateProj::H2 { conn } => {
                     return conn.poll(cx).map_err(Into::into);
                 }
             }
+        });
+        let res = ready!(res);
+        trace.finish(&res);
+        events.closed(&res);
+        Poll::Ready(res)
+    }
+}
+
+// Tracing of the lifecycle of a connection, which does nothing without the
+// `tracing` feature.
+struct ConnTrace {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    started: Instant,
+    #[cfg(feature = "tracing")]
+    shutdown: AtomicBool,
+    #[cfg(feature = "tracing")]
+    finished: AtomicBool,
+}
+
+#[cfg(feature = "tracing")]
+impl ConnTrace {
+    fn new(upgrades: bool) -> Self {
+        ConnTrace {
+            span: tracing::debug_span!(
+                "connection",
+                peer = tracing::field::Empty,
+                protocol = tracing::field::Empty,
+                upgrades,
+                duration = tracing::field::Empty,
+                shutdown_reason = tracing::field::Empty,
+            ),
+            started: Instant::now(),
+            shutdown: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        self.span.in_scope(f)
+    }
+
+    fn peer_addr(&self, addr: SocketAddr) {
+        self.span.record("peer", tracing::field::display(addr));
+    }
+
+    fn version(&self, version: Version) {
+        let protocol = match version {
+            Version::H1 => "HTTP/1.1",
+            Version::H2 => "HTTP/2",
+        };
+        self.span.record("protocol", protocol);
+        tracing::debug!(protocol, "detected protocol");
+    }
+
+    fn version_error(&self, err: &IoError) {
+        tracing::debug!(error = %err, "failed to detect protocol");
+    }
+
+    fn graceful_shutdown(&self) {
+        if !self.shutdown.swap(true, Ordering::Relaxed) {
+            tracing::debug!(parent: &self.span, "starting graceful shutdown");
+        }
+    }
+
+    fn finish(&self, res: &Result<()>) {
+        let reason = match res {
+            Err(err) => {
+                tracing::debug!(parent: &self.span, error = %err, "connection error");
+                "error"
+            }
+            Ok(()) if self.shutdown.load(Ordering::Relaxed) => "graceful_shutdown",
+            Ok(()) => "closed",
+        };
+        self.close(reason);
+    }
+
+    fn close(&self, reason: &'static str) {
+        if self.finished.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        self.span
+            .record("duration", tracing::field::debug(self.started.elapsed()));
+        self.span.record("shutdown_reason", reason);
+        tracing::debug!(parent: &self.span, reason, "connection finished");
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for ConnTrace {
+    fn drop(&mut self) {
+        self.close("dropped");
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl ConnTrace {
+    fn new(_upgrades: bool) -> Self {
+        ConnTrace {}
+    }
+
+    fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+
+    fn version(&self, _version: Version) {}
+
+    fn version_error(&self, _err: &IoError) {}
+
+    fn graceful_shutdown(&self) {}
+
+    fn finish(&self, _res: &Result<()>) {}
+}
+
+// The lifecycle of a connection, reported to the `Events` registered on the
+// builder, if any.
+struct ConnEvents {
+    events: EventsHandle,
+    id: u64,
+    opened: Option<http::Version>,
+}
+
+impl ConnEvents {
+    fn new(events: &EventsHandle) -> Self {
+        ConnEvents {
+            events: events.clone(),
+            id: if events.is_enabled() {
+                events::next_id()
+            } else {
+                0
+            },
+            opened: None,
+        }
+    }
+
+    fn opened(&mut self, version: Version) {
+        if let Some(events) = self.events.get() {
+            let version = match version {
+                Version::H1 => http::Version::HTTP_11,
+                Version::H2 => http::Version::HTTP_2,
+            };
+            self.opened = Some(version);
+            events.connection_opened(&ConnectionEvent::new(
+                events::Side::Server,
+                self.id,
+                version,
+            ));
+        }
+    }
+
+    fn closed(&mut self, res: &Result<()>) {
+        let error = match res {
+            Err(err) => Some(&**err as &(dyn StdError + 'static)),
+            Ok(()) => None,
+        };
+        self.close(error);
+    }
+
+    fn close(&mut self, error: Option<&(dyn StdError + 'static)>) {
+        if let (Some(events), Some(version)) = (self.events.get(), self.opened.take()) {
+            events.connection_closed(
+                &ConnectionEvent::new(events::Side::Server, self.id, version).with_error(error),
+            );
+        }
+    }
+}
+
+impl Drop for ConnEvents {
+    fn drop(&mut self) {
+        self.close(None);
+    }
+}
+
+/// What an HTTP/1 connection does with requests arriving after its graceful
+/// shutdown started.
+///
+/// See [`Http1Builder::shutdown_requests`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownRequests {
+    /// Close the connection right away, unless a request is being served,
+    /// in which case it is closed after that request.
+    Reject,
+    /// Keep the connection open for up to the duration, to serve a request
+    /// that may already be on its way, and close it after that request.
+    ///
+    /// This closes the race of a load balancer sending another request on a
+    /// connection that the server is closing.
+    ServeOne(Duration),
+}
+
+/// A set of HTTP/2 settings suited to a class of workload.
+///
+/// hyper's defaults keep the memory of each connection low, at the cost of
+/// throughput on fast links. A profile trades some of that memory for
+/// throughput, in the way that fits the traffic of the server.
+///
+/// See [`Http2Builder::profile`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Http2Profile {
+    /// Many concurrent requests with small bodies, such as JSON or gRPC
+    /// APIs.
+    ///
+    /// Flow control windows are fixed at 256 KiB per stream and 1 MiB per
+    /// connection, so the memory of a connection stays predictable with
+    /// many streams, and 512 concurrent streams are allowed.
+    Api,
+    /// Bodies of any size forwarded to and from upstreams.
+    ///
+    /// The flow control windows adapt to the bandwidth-delay product of
+    /// each connection, frames are up to 64 KiB, and each stream buffers up
+    /// to 1 MiB for sending, so that slow readers don't stall upstreams.
+    Proxy,
+    /// Few, long-lived streams with large bodies, such as downloads or
+    /// media.
+    ///
+    /// The flow control windows adapt to the bandwidth-delay product of
+    /// each connection, frames are up to 1 MiB, and each stream buffers up
+    /// to 4 MiB for sending.
+    Streaming,
+}
+
+// The requests of a connection, for the timeouts around them.
+struct ConnRequests {
+    // How many requests the service was called with, only counted when a
+    // timeout needs it.
+    count: Option<Arc<AtomicUsize>>,
+    first: Option<(timer::Timer, Duration)>,
+    first_sleep: Option<Pin<Box<dyn Sleep>>>,
+    grace: Option<(timer::Timer, Duration)>,
+    // The count when shutdown started, and the end of its grace period.
+    draining: Option<(usize, Pin<Box<dyn Sleep>>)>,
+}
+
+// The hyper connections an auto connection serves with, once the version
+// of a connection is known.
+type H1Conn<I, S> = http1::Connection<Rewind<I>, CountService<S>>;
+type H2Conn<I, S, E> = http2::Connection<Rewind<I>, CountService<S>, E>;
+
+// A service that counts the requests it is called with, if the connection
+// counts them, and otherwise only forwards them.
+struct CountService<S> {
+    inner: S,
+    count: Option<Arc<AtomicUsize>>,
+}
+
+impl ConnRequests {
+    fn new<E>(builder: &Builder<E>) -> Self {
+        builder.check_timer();
+        let first = match (&builder.timer, builder.first_byte_timeout) {
+            (Some(timer), Some(timeout)) => Some((timer.clone(), timeout)),
+            _ => None,
+        };
+        let grace = match (&builder.timer, builder.shutdown_requests) {
+            (Some(timer), ShutdownRequests::ServeOne(grace)) => Some((timer.clone(), grace)),
+            _ => None,
+        };
+        let count = if first.is_some() || grace.is_some() {
+            Some(Arc::new(AtomicUsize::new(0)))
+        } else {
+            None
+        };
+        ConnRequests {
+            count,
+            first,
+            first_sleep: None,
+            grace,
+            draining: None,
+        }
+    }
+
+    fn wrap<S>(&self, service: S) -> CountService<S> {
+        CountService {
+            inner: service,
+            count: self.count.clone(),
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.count
+            .as_ref()
+            .map_or(0, |count| count.load(Ordering::Acquire))
+    }
+
+    // Whether the first request didn't arrive in time.
+    fn poll_first_expired(&mut self, cx: &mut Context<'_>) -> Poll<IoError> {
+        if self.first.is_none() && self.first_sleep.is_none() {
+            return Poll::Pending;
+        }
+        if self.count() > 0 {
+            self.first = None;
+            self.first_sleep = None;
+            return Poll::Pending;
+        }
+        if let Some((timer, timeout)) = self.first.take() {
+            self.first_sleep = Some(timer.sleep(timeout));
+        }
+        let sleep = self.first_sleep.as_mut().unwrap();
+        ready!(sleep.as_mut().poll(cx));
+        self.first_sleep = None;
+        Poll::Ready(IoError::new(
+            ErrorKind::TimedOut,
+            "timed out waiting for the first request",
+        ))
+    }
+
+    // Start draining an HTTP/1 connection, returning whether it can be shut
+    // down right away.
+    fn start_drain(&mut self) -> bool {
+        match self.grace {
+            Some((ref timer, grace)) if self.draining.is_none() => {
+                self.draining = Some((self.count(), timer.sleep(grace)));
+                false
+            }
+            Some(_) => false,
+            None => true,
+        }
+    }
+
+    // Whether a draining connection got its final request, or its grace
+    // period is over.
+    fn poll_drained(&mut self, cx: &mut Context<'_>) -> bool {
+        let count = self.count();
+        let drained = match self.draining {
+            Some((seen, ref mut sleep)) => count > seen || sleep.as_mut().poll(cx).is_ready(),
+            None => false,
+        };
+        if drained {
+            self.draining = None;
+            self.grace = None;
         }
+        drained
+    }
+}
+
+impl<S> Service<Request<Incoming>> for CountService<S>
+where
+    S: ConnService,
+{
+    type Response = Response<S::ResBody>;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, req: Request<Incoming>) -> Self::Future {
+        if let Some(ref count) = self.count {
+            count.fetch_add(1, Ordering::AcqRel);
+        }
+        self.inner.call_ref(req)
+    }
+}
+
+/// The [`HttpService`] of a [`Connection`] or an [`UpgradeableConnection`].
+///
+/// This is implemented for all the [`Service`]s that hyper can serve
+/// connections with, and isn't meant to be implemented otherwise.
+pub trait ConnService: HttpService<Incoming> {
+    #[doc(hidden)]
+    fn call_ref(&self, req: Request<Incoming>) -> Self::Future;
+}
+
+impl<S, B> ConnService for S
+where
+    S: Service<Request<Incoming>, Response = Response<B>>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body,
+{
+    fn call_ref(&self, req: Request<Incoming>) -> Self::Future {
+        Service::call(self, req)
     }
 }
 
@@ -446,6 +1558,37 @@ impl<E> Http1Builder<'_, E> {
         self
     }
 
+    /// Set whether HTTP/1 connections will silently ignore malformed header
+    /// lines.
+    ///
+    /// If this is enabled and a header line does not start with a valid
+    /// header name, or does not include a colon at all, the line will be
+    /// silently ignored and no error will be reported. Proxies in front of the
+    /// server may parse such lines differently, which can be used to smuggle
+    /// requests past them, so keep this disabled when requests go through
+    /// other HTTP/1 implementations.
+    ///
+    /// Default is false.
+    pub fn ignore_invalid_headers(&mut self, enabled: bool) -> &mut Self {
+        self.inner.http1.ignore_invalid_headers(enabled);
+        self
+    }
+
+    /// Set whether multiple spaces are allowed as delimiters in request
+    /// lines.
+    ///
+    /// Like [`ignore_invalid_headers`](Self::ignore_invalid_headers), this
+    /// accepts requests that other HTTP/1 implementations may reject or
+    /// parse differently.
+    ///
+    /// Default is false.
+    pub fn allow_multiple_spaces_in_request_line_delimiters(&mut self, enabled: bool) -> &mut Self {
+        self.inner
+            .http1
+            .allow_multiple_spaces_in_request_line_delimiters(enabled);
+        self
+    }
+
     /// Set whether to support preserving original header cases.
     ///
     /// Currently, this will record the original cases received, and store them
@@ -517,7 +1660,100 @@ impl<E> Http1Builder<'_, E> {
     where
         M: Timer + Send + Sync + 'static,
     {
-        self.inner.http1.timer(timer);
+        self.inner.http1.timer(timer);
+        self
+    }
+
+    /// Set whether HTTP/1 connections may be upgraded.
+    ///
+    /// When disabled, the `Upgrade` header, and the `upgrade` option of the
+    /// `Connection` header, are removed from requests before they reach the
+    /// service, and [`hyper::upgrade::on`] fails for them. `CONNECT` requests
+    /// can't be upgraded either.
+    ///
+    /// Only applies to connections served with
+    /// [`serve_connection_with_upgrades`](Builder::serve_connection_with_upgrades).
+    ///
+    /// Default is true.
+    pub fn upgrades(&mut self, enabled: bool) -> &mut Self {
+        self.inner.upgrades.enabled(enabled);
+        self
+    }
+
+    /// Set the maximum number of upgraded connections at a time.
+    ///
+    /// A connection is counted from the response upgrading it, until the
+    /// upgraded IO is dropped. Once the maximum is reached, requests asking to
+    /// upgrade are handled as if [upgrades were disabled](Self::upgrades).
+    ///
+    /// The count is shared by all connections served by this builder, and its
+    /// clones.
+    ///
+    /// Default is no limit.
+    pub fn max_upgraded_connections(&mut self, max: usize) -> &mut Self {
+        self.inner.upgrades.max(max);
+        self
+    }
+
+    /// Set how long an upgraded connection may live.
+    ///
+    /// Once `timeout` has passed since the response upgrading it, reading
+    /// from or writing to the upgraded IO fails with a
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut) error.
+    ///
+    /// Default is None.
+    pub fn upgrade_timeout<M>(&mut self, timer: M, timeout: Duration) -> &mut Self
+    where
+        M: Timer + Send + Sync + 'static,
+    {
+        self.inner
+            .upgrades
+            .timeout(timer::Timer::new(timer), timeout);
+        self
+    }
+
+    /// Track upgraded connections with a [`GracefulShutdown`].
+    ///
+    /// Requests that may upgrade get a [`Draining`] extension, to notice the
+    /// shutdown starting and close the upgraded connection cleanly. Upgraded
+    /// connections count as active connections of `graceful`, so its
+    /// [`shutdown`](GracefulShutdown::shutdown) waits for them too. Once
+    /// `grace` has passed since the shutdown started, reading from or writing
+    /// to the upgraded IO fails with a
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut) error.
+    ///
+    /// Default is to not track upgraded connections.
+    ///
+    /// [`GracefulShutdown`]: crate::server::graceful::GracefulShutdown
+    /// [`Draining`]: crate::server::graceful::Draining
+    #[cfg(feature = "server-graceful")]
+    pub fn graceful_upgrades<M>(
+        &mut self,
+        graceful: &crate::server::graceful::GracefulShutdown,
+        timer: M,
+        grace: Duration,
+    ) -> &mut Self
+    where
+        M: Timer + Send + Sync + 'static,
+    {
+        self.inner
+            .upgrades
+            .graceful(graceful, timer::Timer::new(timer), grace);
+        self
+    }
+
+    /// Set what HTTP/1 connections do with requests arriving after their
+    /// graceful shutdown started.
+    ///
+    /// Default is [`ShutdownRequests::Reject`].
+    ///
+    /// # Panics
+    ///
+    /// [`ShutdownRequests::ServeOne`] requires a timer set with
+    /// [`Builder::timer`]. Serving a connection, or taking a [`Config`],
+    /// panics without one.
+    pub fn shutdown_requests(&mut self, requests: ShutdownRequests) -> &mut Self {
+        self.inner.shutdown_requests = requests;
         self
     }
 
@@ -657,6 +1893,32 @@ impl<E> Http2Builder<'_, E> {
         self
     }
 
+    /// Apply the settings of a workload [`Http2Profile`].
+    ///
+    /// The profile sets the flow control windows, frame size, send buffer
+    /// size and, for [`Http2Profile::Api`], the concurrent streams. Options
+    /// set after it override it.
+    pub fn profile(&mut self, profile: Http2Profile) -> &mut Self {
+        const KIB: u32 = 1024;
+        const MIB: u32 = 1024 * KIB;
+        match profile {
+            Http2Profile::Api => self
+                .adaptive_window(false)
+                .initial_stream_window_size(256 * KIB)
+                .initial_connection_window_size(MIB)
+                .max_frame_size(16 * KIB)
+                .max_concurrent_streams(512),
+            Http2Profile::Proxy => self
+                .adaptive_window(true)
+                .max_frame_size(64 * KIB)
+                .max_send_buf_size(MIB as usize),
+            Http2Profile::Streaming => self
+                .adaptive_window(true)
+                .max_frame_size(MIB)
+                .max_send_buf_size(4 * MIB as usize),
+        }
+    }
+
     /// Set the timer used in background tasks.
     pub fn timer<M>(&mut self, timer: M) -> &mut Self
     where
@@ -746,6 +2008,700 @@ mod tests {
         assert_eq!(body, BODY);
     }
 
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn http2_profiles() {
+        use super::Http2Profile;
+
+        for profile in [
+            Http2Profile::Api,
+            Http2Profile::Proxy,
+            Http2Profile::Streaming,
+        ] {
+            let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+                .await
+                .unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut builder = auto::Builder::new(TokioExecutor::new());
+                builder.http2().profile(profile);
+                let _ = builder
+                    .serve_connection(TokioIo::new(stream), service_fn(hello))
+                    .await;
+            });
+
+            let mut sender = connect_h2(addr).await;
+            let response = sender
+                .send_request(Request::new(Empty::<Bytes>::new()))
+                .await
+                .unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, BODY, "{:?}", profile);
+        }
+    }
+
+    #[cfg(all(not(miri), feature = "tracing"))]
+    #[tokio::test]
+    async fn traced_connection() {
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service_fn(hello))
+                .with_peer_addr(peer)
+                .await
+        });
+
+        let mut sender = connect_h1(addr).await;
+        let response = sender
+            .send_request(Request::new(Empty::<Bytes>::new()))
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, BODY);
+
+        drop(sender);
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn connection_events() {
+        use crate::events::{ConnectionEvent, Events, EventsService, RequestEvent};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl Events for Recorder {
+            fn connection_opened(&self, conn: &ConnectionEvent<'_>) {
+                let event = format!("opened {:?}", conn.version());
+                self.0.lock().unwrap().push(event);
+            }
+
+            fn connection_closed(&self, conn: &ConnectionEvent<'_>) {
+                let event = format!("closed {}", conn.error().is_some());
+                self.0.lock().unwrap().push(event);
+            }
+
+            fn request_started(&self, req: &RequestEvent<'_>) {
+                let event = format!("started {} {}", req.method(), req.uri());
+                self.0.lock().unwrap().push(event);
+            }
+
+            fn request_finished(&self, req: &RequestEvent<'_>) {
+                let event = format!("finished {:?}", req.status());
+                self.0.lock().unwrap().push(event);
+            }
+        }
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let recorder = Arc::new(Recorder::default());
+
+        let events = recorder.clone();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = EventsService::new(service_fn(hello), events.clone());
+            auto::Builder::new(TokioExecutor::new())
+                .connection_events(events)
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+        });
+
+        let mut sender = connect_h1(addr).await;
+        let request = Request::builder()
+            .uri("/hello")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        response.into_body().collect().await.unwrap();
+
+        drop(sender);
+        server.await.unwrap().unwrap();
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "opened HTTP/1.1",
+                "started GET /hello",
+                "finished Some(200)",
+                "closed false",
+            ]
+        );
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn request_events() {
+        use crate::events::{Events, EventsService, RequestEvent, Side};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(String, u64)>>);
+
+        impl Events for Recorder {
+            fn request_started(&self, req: &RequestEvent<'_>) {
+                assert_eq!(req.side(), Side::Server);
+                let event = format!("started {}", req.uri().path());
+                self.0.lock().unwrap().push((event, req.id()));
+            }
+
+            fn request_finished(&self, req: &RequestEvent<'_>) {
+                assert_eq!(req.side(), Side::Server);
+                let event = format!("finished {} {:?}", req.uri().path(), req.status());
+                self.0.lock().unwrap().push((event, req.id()));
+            }
+        }
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let recorder = Arc::new(Recorder::default());
+
+        let events = recorder.clone();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = EventsService::new(service_fn(hello), events);
+            auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+        });
+
+        let mut sender = connect_h2(addr).await;
+        for path in ["/a", "/b"] {
+            let request = Request::builder()
+                .uri(format!("http://localhost{}", path))
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+            let response = sender.send_request(request).await.unwrap();
+            response.into_body().collect().await.unwrap();
+        }
+
+        drop(sender);
+        server.await.unwrap().unwrap();
+        let recorded = recorder.0.lock().unwrap();
+        let names: Vec<_> = recorded.iter().map(|(event, _)| &**event).collect();
+        assert_eq!(
+            names,
+            [
+                "started /a",
+                "finished /a Some(200)",
+                "started /b",
+                "finished /b Some(200)",
+            ]
+        );
+        assert_eq!(recorded[0].1, recorded[1].1);
+        assert_eq!(recorded[2].1, recorded[3].1);
+        assert_ne!(recorded[0].1, recorded[2].1);
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn upgrade_limits() {
+        use crate::rt::TokioTimer;
+        use std::time::Duration;
+        use tokio::sync::oneshot;
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let mut builder = auto::Builder::new(TokioExecutor::new());
+            builder
+                .http1()
+                .max_upgraded_connections(1)
+                .upgrade_timeout(TokioTimer::new(), Duration::from_millis(50));
+            let tx = std::sync::Arc::new(std::sync::Mutex::new(Some(tx)));
+            let service = service_fn(move |req: Request<body::Incoming>| {
+                let upgrade = req.headers().contains_key(http::header::UPGRADE);
+                if upgrade {
+                    let tx = tx.lock().unwrap().take();
+                    tokio::spawn(async move {
+                        let upgraded = hyper::upgrade::on(req).await.unwrap();
+                        let mut upgraded = TokioIo::new(upgraded);
+                        let mut buf = [0; 1];
+                        let res = tokio::io::AsyncReadExt::read(&mut upgraded, &mut buf).await;
+                        let _ = tx.unwrap().send(res.unwrap_err().kind());
+                    });
+                }
+                async move {
+                    let status = if upgrade { 101 } else { 200 };
+                    let mut res = Response::new(Empty::<Bytes>::new());
+                    *res.status_mut() = http::StatusCode::from_u16(status).unwrap();
+                    if upgrade {
+                        res.headers_mut()
+                            .insert(http::header::UPGRADE, "foo".parse().unwrap());
+                    }
+                    Ok::<_, Infallible>(res)
+                }
+            });
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let builder = builder.clone();
+                let service = service.clone();
+                tokio::spawn(async move {
+                    let _ = builder
+                        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        let upgrade = || {
+            Request::builder()
+                .header(http::header::CONNECTION, "upgrade")
+                .header(http::header::UPGRADE, "foo")
+                .body(Empty::<Bytes>::new())
+                .unwrap()
+        };
+
+        let stream = TokioIo::new(TcpStream::connect(addr).await.unwrap());
+        let (mut sender, conn) = client::conn::http1::handshake(stream).await.unwrap();
+        tokio::spawn(conn.with_upgrades());
+        let response = sender.send_request(upgrade()).await.unwrap();
+        assert_eq!(response.status(), 101);
+        let _upgraded = hyper::upgrade::on(response).await.unwrap();
+
+        // the one upgraded connection is still open
+        let mut sender = connect_h1(addr).await;
+        let response = sender.send_request(upgrade()).await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        assert_eq!(rx.await.unwrap(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[cfg(all(not(miri), feature = "server-graceful"))]
+    #[tokio::test]
+    async fn graceful_upgrades() {
+        use crate::rt::TokioTimer;
+        use crate::server::graceful::{Draining, GracefulShutdown};
+        use std::time::Duration;
+        use tokio::sync::oneshot;
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (notified_tx, notified_rx) = oneshot::channel();
+        let (closed_tx, closed_rx) = oneshot::channel();
+
+        let graceful = GracefulShutdown::new();
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .graceful_upgrades(&graceful, TokioTimer::new(), Duration::from_millis(50));
+        let watcher = graceful.watcher();
+        let txs = std::sync::Arc::new(std::sync::Mutex::new(Some((notified_tx, closed_tx))));
+        let service = service_fn(move |mut req: Request<body::Incoming>| {
+            let draining = req.extensions_mut().remove::<Draining>().unwrap();
+            let (notified_tx, closed_tx) = txs.lock().unwrap().take().unwrap();
+            tokio::spawn(async move {
+                let upgraded = hyper::upgrade::on(req).await.unwrap();
+                let mut upgraded = TokioIo::new(upgraded);
+                draining.wait().await;
+                let _ = notified_tx.send(());
+                let mut buf = [0; 1];
+                let res = tokio::io::AsyncReadExt::read(&mut upgraded, &mut buf).await;
+                let _ = closed_tx.send(res.unwrap_err().kind());
+            });
+            async move {
+                let mut res = Response::new(Empty::<Bytes>::new());
+                *res.status_mut() = http::StatusCode::SWITCHING_PROTOCOLS;
+                res.headers_mut()
+                    .insert(http::header::UPGRADE, "foo".parse().unwrap());
+                Ok::<_, Infallible>(res)
+            }
+        });
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            let _ = watcher.watch(conn).await;
+        });
+
+        let stream = TokioIo::new(TcpStream::connect(addr).await.unwrap());
+        let (mut sender, conn) = client::conn::http1::handshake(stream).await.unwrap();
+        tokio::spawn(conn.with_upgrades());
+        let req = Request::builder()
+            .header(http::header::CONNECTION, "upgrade")
+            .header(http::header::UPGRADE, "foo")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(req).await.unwrap();
+        assert_eq!(response.status(), 101);
+        let _upgraded = hyper::upgrade::on(response).await.unwrap();
+
+        // the upgraded connection keeps the shutdown waiting until it closes
+        assert!(graceful.count() > 0);
+        graceful.shutdown().await;
+        notified_rx.await.unwrap();
+        assert_eq!(closed_rx.await.unwrap(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn make_service_per_connection() {
+        use super::ConnInfo;
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut made = 0;
+            let mut make = |info: &ConnInfo| {
+                made += 1;
+                let body = format!("{} {}", made, info.peer_addr().unwrap());
+                service_fn(move |_| {
+                    let body = body.clone();
+                    async move { Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(body)))) }
+                })
+            };
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let conn = auto::Builder::new(TokioExecutor::new())
+                    .serve_connection_with_make(TokioIo::new(stream), &mut make)
+                    .await;
+                conn.unwrap();
+            }
+        });
+
+        for n in 1..=2 {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let local = stream.local_addr().unwrap();
+            let (mut sender, conn) = client::conn::http1::handshake(TokioIo::new(stream))
+                .await
+                .unwrap();
+            tokio::spawn(conn);
+            let response = sender
+                .send_request(Request::new(Empty::<Bytes>::new()))
+                .await;
+            let body = response.unwrap().into_body().collect().await.unwrap();
+            assert_eq!(body.to_bytes(), format!("{} {}", n, local));
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn owned_config() {
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let config = auto::Builder::new(TokioExecutor::new()).to_owned_config();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(config.serve_connection(TokioIo::new(stream), service_fn(hello)));
+            }
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        tokio::io::AsyncWriteExt::write_all(&mut stream, b"GET / HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 12];
+        tokio::io::AsyncReadExt::read_exact(&mut stream, &mut buf)
+            .await
+            .unwrap();
+        assert_eq!(&buf, b"HTTP/1.1 200");
+    }
+
+    #[test]
+    fn may_be_http1() {
+        use super::may_be_http1;
+
+        assert!(may_be_http1(b"GET / HTTP/1.1\r\n"));
+        assert!(may_be_http1(b"\r\nPOST"));
+        assert!(may_be_http1(b"PR"));
+        assert!(!may_be_http1(b" / HTTP/1.1"));
+        assert!(!may_be_http1(b"\x16\x03\x01\x02\x00"));
+        assert!(!may_be_http1(b"GE\0T /"));
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn strict_preface() {
+        use tokio::io::AsyncWriteExt;
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            auto::Builder::new(TokioExecutor::new())
+                .strict_preface(true)
+                .serve_connection(TokioIo::new(stream), service_fn(hello))
+                .await
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"\x00\x01junk").await.unwrap();
+
+        let err = server.await.unwrap().unwrap_err();
+        let err = err.downcast::<auto::InvalidFirstBytes>().unwrap();
+        assert!(b"\x00\x01junk".starts_with(err.bytes()));
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn lenient_parsing() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        async fn status(lenient: bool, request: &'static [u8]) -> Vec<u8> {
+            let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+            let listener = TcpListener::bind(addr).await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut builder = auto::Builder::new(TokioExecutor::new());
+                builder
+                    .http1()
+                    .ignore_invalid_headers(lenient)
+                    .allow_multiple_spaces_in_request_line_delimiters(lenient);
+                let _ = builder
+                    .serve_connection(TokioIo::new(stream), service_fn(hello))
+                    .await;
+            });
+
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(request).await.unwrap();
+            let mut buf = vec![0; 12];
+            stream.read_exact(&mut buf).await.unwrap();
+            buf
+        }
+
+        let invalid_header = b"GET / HTTP/1.1\r\nHost: a\r\nbad header\r\n\r\n";
+        let spaces = b"GET  /  HTTP/1.1\r\nHost: a\r\n\r\n";
+        assert_eq!(status(false, invalid_header).await, b"HTTP/1.1 400");
+        assert_eq!(status(true, invalid_header).await, b"HTTP/1.1 200");
+        assert_eq!(status(false, spaces).await, b"HTTP/1.1 400");
+        assert_eq!(status(true, spaces).await, b"HTTP/1.1 200");
+    }
+
+    #[test]
+    fn is_client_hello() {
+        use super::is_client_hello;
+
+        assert_eq!(is_client_hello(b"\x16\x03"), None);
+        assert_eq!(is_client_hello(b"\x16\x03\x01\x02\x00\x01"), Some(true));
+        assert_eq!(is_client_hello(b"\x16\x03\x01\x02\x00\x02"), Some(false));
+        assert_eq!(is_client_hello(b"\x16\x05"), Some(false));
+        assert_eq!(is_client_hello(b"GET / HTTP/1.1"), Some(false));
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn tls_client_hello() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            auto::Builder::new(TokioExecutor::new())
+                .tls_client_hello(|_| Some(Bytes::from_static(b"\x15\x03\x03\x00\x02\x02\x46")))
+                .serve_connection(TokioIo::new(stream), service_fn(hello))
+                .await
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        // sent in two parts, to read on until it can be told apart from HTTP/1
+        stream.write_all(b"\x16\x03").await.unwrap();
+        tokio::task::yield_now().await;
+        stream.write_all(b"\x01\x02\x00\x01\x00").await.unwrap();
+
+        let mut alert = Vec::new();
+        stream.read_to_end(&mut alert).await.unwrap();
+        assert_eq!(alert, b"\x15\x03\x03\x00\x02\x02\x46");
+
+        let err = server.await.unwrap().unwrap_err();
+        let err = err.downcast::<auto::TlsClientHello>().unwrap();
+        assert!(err.bytes().starts_with(b"\x16\x03\x01\x02\x00\x01"));
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn first_byte_timeout() {
+        use crate::rt::TokioTimer;
+        use std::time::Duration;
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let mut builder = auto::Builder::new(TokioExecutor::new());
+            builder
+                .timer(TokioTimer::new())
+                .first_byte_timeout(Duration::from_millis(50));
+            let mut results = Vec::new();
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let res = builder
+                    .serve_connection(TokioIo::new(stream), service_fn(hello))
+                    .await;
+                results.push(res);
+            }
+            results
+        });
+
+        // silent
+        let silent = TcpStream::connect(addr).await.unwrap();
+
+        // idle after its first request
+        let mut sender = connect_h1(addr).await;
+        let response = sender
+            .send_request(Request::new(Empty::<Bytes>::new()))
+            .await
+            .unwrap();
+        response.into_body().collect().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = sender
+            .send_request(Request::new(Empty::<Bytes>::new()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        drop((silent, sender));
+
+        let results = server.await.unwrap();
+        let err = results[0].as_ref().unwrap_err();
+        let err = err.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(results[1].is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "timeout `first_byte_timeout` set, but no timer set")]
+    fn first_byte_timeout_without_timer() {
+        use std::time::Duration;
+
+        let (io, _) = tokio::io::duplex(64);
+        let _conn = auto::Builder::new(TokioExecutor::new())
+            .first_byte_timeout(Duration::from_millis(50))
+            .serve_connection(TokioIo::new(io), service_fn(hello));
+    }
+
+    #[test]
+    #[should_panic(expected = "timeout `ShutdownRequests::ServeOne` set, but no timer set")]
+    fn shutdown_serves_one_without_timer() {
+        use std::time::Duration;
+
+        let (io, _) = tokio::io::duplex(64);
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .shutdown_requests(auto::ShutdownRequests::ServeOne(Duration::from_secs(5)));
+        let _conn = builder.serve_connection(TokioIo::new(io), service_fn(hello));
+    }
+
+    #[test]
+    #[should_panic(expected = "timeout `ShutdownRequests::ServeOne` set, but no timer set")]
+    fn shutdown_serves_one_without_timer_config() {
+        use std::time::Duration;
+
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .shutdown_requests(auto::ShutdownRequests::ServeOne(Duration::from_secs(5)));
+        let _config = builder.to_owned_config();
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn shutdown_serves_one_request() {
+        use crate::rt::TokioTimer;
+        use std::time::Duration;
+        use tokio::sync::oneshot;
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let (started_tx, started_rx) = oneshot::channel();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut builder = auto::Builder::new(TokioExecutor::new());
+            builder
+                .timer(TokioTimer::new())
+                .http1()
+                .shutdown_requests(auto::ShutdownRequests::ServeOne(Duration::from_secs(5)));
+            let conn = builder.serve_connection(TokioIo::new(stream), service_fn(hello));
+            tokio::pin!(conn);
+            tokio::select! {
+                res = conn.as_mut() => return res,
+                _ = shutdown_rx => conn.as_mut().graceful_shutdown(),
+            }
+            started_tx.send(()).unwrap();
+            conn.await
+        });
+
+        let mut sender = connect_h1(addr).await;
+        let response = sender
+            .send_request(Request::new(Empty::<Bytes>::new()))
+            .await
+            .unwrap();
+        response.into_body().collect().await.unwrap();
+
+        shutdown_tx.send(()).unwrap();
+        started_rx.await.unwrap();
+        let response = sender
+            .send_request(Request::new(Empty::<Bytes>::new()))
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, BODY);
+
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn protocol_accessors() {
+        use tokio::sync::oneshot;
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let conn = builder.serve_connection(TokioIo::new(stream), service_fn(hello));
+            tokio::pin!(conn);
+            assert!(conn.as_mut().http1_mut().is_none());
+            tokio::select! {
+                res = conn.as_mut() => return res,
+                _ = shutdown_rx => {}
+            }
+            assert!(conn.as_mut().http2_mut().is_none());
+            conn.as_mut().http1_mut().unwrap().graceful_shutdown();
+            conn.await
+        });
+
+        let mut sender = connect_h1(addr).await;
+        let response = sender
+            .send_request(Request::new(Empty::<Bytes>::new()))
+            .await
+            .unwrap();
+        response.into_body().collect().await.unwrap();
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
     async fn connect_h1<B>(addr: SocketAddr) -> client::conn::http1::SendRequest<B>
     where
         B: Body + Send + 'static,
@@ -2,3 +2,5 @@
 
 #[cfg(feature = "server-auto")]
 pub mod auto;
+#[cfg(feature = "server-auto")]
+mod upgrade;
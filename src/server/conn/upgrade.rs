@@ -0,0 +1,502 @@
+//! Limits on the HTTP/1 upgrades of an auto connection.
+
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_util::ready;
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use hyper::body::Incoming;
+use hyper::rt::{Read, ReadBufCursor, Sleep, Timer as _, Write};
+use hyper::service::Service;
+use hyper::upgrade::OnUpgrade;
+use pin_project_lite::pin_project;
+
+use crate::common::timer::Timer;
+#[cfg(feature = "server-graceful")]
+use crate::server::graceful::{Draining, GracefulShutdown};
+
+// What the builder allows of upgrades.
+#[derive(Clone, Debug)]
+pub(crate) struct Upgrades {
+    enabled: bool,
+    max: Option<usize>,
+    // Shared by the connections of a builder, and its clones.
+    active: Arc<AtomicUsize>,
+    timeout: Option<(Timer, Duration)>,
+    #[cfg(feature = "server-graceful")]
+    graceful: Option<Graceful>,
+}
+
+// The graceful shutdown tracking upgraded connections, and how long they
+// have to close once it started.
+#[cfg(feature = "server-graceful")]
+#[derive(Clone)]
+struct Graceful {
+    tx: Arc<tokio::sync::watch::Sender<bool>>,
+    timer: Timer,
+    grace: Duration,
+}
+
+// A service that only lets requests upgrade within the limits.
+pub(crate) struct UpgradeService<S> {
+    inner: S,
+    upgrades: Upgrades,
+    conn: Arc<Upgraded>,
+}
+
+pin_project! {
+    pub(crate) struct UpgradeFuture<F> {
+        #[pin]
+        inner: F,
+        // Set if the request may upgrade.
+        pending: Option<Pending>,
+    }
+}
+
+struct Pending {
+    connect: bool,
+    permit: Option<Permit>,
+    timeout: Option<(Timer, Duration)>,
+    #[cfg(feature = "server-graceful")]
+    graceful: Option<(Draining, Timer, Duration)>,
+    conn: Arc<Upgraded>,
+}
+
+// The IO of a connection, which enforces the limits once it is upgraded.
+pub(crate) struct UpgradeIo<I> {
+    inner: I,
+    conn: Arc<Upgraded>,
+    // Whether the limits were taken from `conn`.
+    upgraded: bool,
+    deadline: Option<Pin<Box<dyn Sleep>>>,
+    #[cfg(feature = "server-graceful")]
+    drain: Option<Drain>,
+}
+
+// The graceful shutdown of an upgraded connection.
+#[cfg(feature = "server-graceful")]
+enum Drain {
+    Waiting {
+        signal: Pin<Box<dyn Future<Output = Draining> + Send>>,
+        timer: Timer,
+        grace: Duration,
+    },
+    // Holding on to `Draining` keeps the shutdown waiting for the connection.
+    Closing {
+        _draining: Draining,
+        deadline: Pin<Box<dyn Sleep>>,
+    },
+}
+
+// Shared by the service and the IO of a connection, and set when the
+// service responds to an upgrade.
+#[derive(Default)]
+struct Upgraded {
+    upgraded: AtomicBool,
+    state: Mutex<UpgradedState>,
+}
+
+#[derive(Default)]
+struct UpgradedState {
+    permit: Option<Permit>,
+    deadline: Option<Pin<Box<dyn Sleep>>>,
+    #[cfg(feature = "server-graceful")]
+    drain: Option<Drain>,
+}
+
+// A slot of the upgraded connections, given back when dropped.
+struct Permit(Arc<AtomicUsize>);
+
+// ===== impl Upgrades =====
+
+impl Upgrades {
+    pub(crate) fn enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub(crate) fn max(&mut self, max: usize) {
+        self.max = Some(max);
+    }
+
+    pub(crate) fn timeout(&mut self, timer: Timer, timeout: Duration) {
+        self.timeout = Some((timer, timeout));
+    }
+
+    #[cfg(feature = "server-graceful")]
+    pub(crate) fn graceful(&mut self, graceful: &GracefulShutdown, timer: Timer, grace: Duration) {
+        self.graceful = Some(Graceful {
+            tx: graceful.sender(),
+            timer,
+            grace,
+        });
+    }
+
+    pub(crate) fn wrap<I, S>(&self, io: I, service: S) -> (UpgradeIo<I>, UpgradeService<S>) {
+        let conn = Arc::new(Upgraded::default());
+        let io = UpgradeIo {
+            inner: io,
+            conn: conn.clone(),
+            upgraded: false,
+            deadline: None,
+            #[cfg(feature = "server-graceful")]
+            drain: None,
+        };
+        let service = UpgradeService {
+            inner: service,
+            upgrades: self.clone(),
+            conn,
+        };
+        (io, service)
+    }
+
+    fn acquire(&self) -> Option<Option<Permit>> {
+        if !self.enabled {
+            return None;
+        }
+        let max = match self.max {
+            Some(max) => max,
+            None => return Some(None),
+        };
+        let mut active = self.active.load(Ordering::Relaxed);
+        loop {
+            if active >= max {
+                return None;
+            }
+            match self.active.compare_exchange_weak(
+                active,
+                active + 1,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(Some(Permit(self.active.clone()))),
+                Err(actual) => active = actual,
+            }
+        }
+    }
+}
+
+impl Default for Upgrades {
+    fn default() -> Self {
+        Upgrades {
+            enabled: true,
+            max: None,
+            active: Arc::new(AtomicUsize::new(0)),
+            timeout: None,
+            #[cfg(feature = "server-graceful")]
+            graceful: None,
+        }
+    }
+}
+
+#[cfg(feature = "server-graceful")]
+impl fmt::Debug for Graceful {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Graceful")
+            .field("grace", &self.grace)
+            .finish()
+    }
+}
+
+// ===== impl UpgradeService =====
+
+impl<S, B> Service<Request<Incoming>> for UpgradeService<S>
+where
+    S: Service<Request<Incoming>, Response = Response<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = UpgradeFuture<S::Future>;
+
+    fn call(&self, mut req: Request<Incoming>) -> Self::Future {
+        // hyper only prepares an upgrade for requests that may upgrade.
+        if req.extensions().get::<OnUpgrade>().is_none() {
+            return UpgradeFuture {
+                inner: self.inner.call(req),
+                pending: None,
+            };
+        }
+
+        let pending = match self.upgrades.acquire() {
+            Some(permit) => Some(Pending {
+                connect: req.method() == Method::CONNECT,
+                permit,
+                timeout: self.upgrades.timeout.clone(),
+                #[cfg(feature = "server-graceful")]
+                graceful: self.upgrades.graceful.as_ref().map(|graceful| {
+                    let draining = Draining::new(&graceful.tx);
+                    req.extensions_mut().insert(draining.clone());
+                    (draining, graceful.timer.clone(), graceful.grace)
+                }),
+                conn: self.conn.clone(),
+            }),
+            None => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("refusing upgrade");
+                strip_upgrade(&mut req);
+                None
+            }
+        };
+        UpgradeFuture {
+            inner: self.inner.call(req),
+            pending,
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for UpgradeService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpgradeService")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+// Make a request look like it never asked to upgrade.
+fn strip_upgrade<B>(req: &mut Request<B>) {
+    req.extensions_mut().remove::<OnUpgrade>();
+    let headers = req.headers_mut();
+    headers.remove(header::UPGRADE);
+
+    let connection = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|token| !token.is_empty() && !token.eq_ignore_ascii_case("upgrade"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    headers.remove(header::CONNECTION);
+    if let Ok(value) = HeaderValue::from_str(&connection) {
+        if !connection.is_empty() {
+            headers.insert(header::CONNECTION, value);
+        }
+    }
+}
+
+// ===== impl UpgradeFuture =====
+
+impl<F, B, E> Future for UpgradeFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx));
+        if let (Some(pending), Ok(res)) = (this.pending.take(), &res) {
+            let status = res.status();
+            let upgraded = if pending.connect {
+                status.is_success()
+            } else {
+                status == StatusCode::SWITCHING_PROTOCOLS
+            };
+            if upgraded {
+                pending.upgrade();
+            }
+        }
+        Poll::Ready(res)
+    }
+}
+
+impl<F> fmt::Debug for UpgradeFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpgradeFuture").finish()
+    }
+}
+
+impl Pending {
+    fn upgrade(self) {
+        let mut state = self.conn.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.permit = self.permit;
+        state.deadline = self.timeout.map(|(timer, timeout)| timer.sleep(timeout));
+        #[cfg(feature = "server-graceful")]
+        {
+            state.drain = self
+                .graceful
+                .map(|(draining, timer, grace)| Drain::Waiting {
+                    signal: draining.into_signal(),
+                    timer,
+                    grace,
+                });
+        }
+        drop(state);
+        self.conn.upgraded.store(true, Ordering::Release);
+    }
+}
+
+// ===== impl UpgradeIo =====
+
+impl<I> UpgradeIo<I> {
+    fn poll_deadline(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        if !self.upgraded {
+            if !self.conn.upgraded.load(Ordering::Acquire) {
+                return Ok(());
+            }
+            self.upgraded = true;
+            let mut state = self.conn.state.lock().unwrap_or_else(|e| e.into_inner());
+            self.deadline = state.deadline.take();
+            #[cfg(feature = "server-graceful")]
+            {
+                self.drain = state.drain.take();
+            }
+        }
+        if let Some(ref mut deadline) = self.deadline {
+            if deadline.as_mut().poll(cx).is_ready() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "upgraded connection timed out",
+                ));
+            }
+        }
+        #[cfg(feature = "server-graceful")]
+        self.poll_drain(cx)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "server-graceful")]
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        if let Some(Drain::Waiting {
+            ref mut signal,
+            ref timer,
+            grace,
+        }) = self.drain
+        {
+            if let Poll::Ready(draining) = signal.as_mut().poll(cx) {
+                self.drain = Some(Drain::Closing {
+                    _draining: draining,
+                    deadline: timer.sleep(grace),
+                });
+            }
+        }
+        if let Some(Drain::Closing {
+            ref mut deadline, ..
+        }) = self.drain
+        {
+            if deadline.as_mut().poll(cx).is_ready() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "upgraded connection did not close after graceful shutdown",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I> Read for UpgradeIo<I>
+where
+    I: Read + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.poll_deadline(cx)?;
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<I> Write for UpgradeIo<I>
+where
+    I: Write + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.poll_deadline(cx)?;
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.poll_deadline(cx)?;
+        Pin::new(&mut this.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.poll_deadline(cx)?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<I: fmt::Debug> fmt::Debug for UpgradeIo<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpgradeIo")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+// ===== impl Permit =====
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{header, Request};
+
+    use super::{strip_upgrade, Upgrades};
+
+    #[test]
+    fn strips_upgrade_headers() {
+        let mut req = Request::builder()
+            .header(header::CONNECTION, "keep-alive, Upgrade")
+            .header(header::UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        strip_upgrade(&mut req);
+        assert!(req.headers().get(header::UPGRADE).is_none());
+        assert_eq!(req.headers()[header::CONNECTION], "keep-alive");
+
+        let mut req = Request::builder()
+            .header(header::CONNECTION, "upgrade")
+            .body(())
+            .unwrap();
+        strip_upgrade(&mut req);
+        assert!(req.headers().get(header::CONNECTION).is_none());
+    }
+
+    #[test]
+    fn caps_upgraded_connections() {
+        let mut upgrades = Upgrades::default();
+        upgrades.max(1);
+        let permit = upgrades.acquire().unwrap();
+        assert!(permit.is_some());
+        assert!(upgrades.acquire().is_none());
+        drop(permit);
+        assert!(upgrades.acquire().is_some());
+
+        upgrades.enabled(false);
+        assert!(upgrades.acquire().is_none());
+    }
+}
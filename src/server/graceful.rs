@@ -0,0 +1,498 @@
+//! Utility to gracefully shutdown a server.
+//!
+//! A [`GracefulShutdown`] watches connections, and on [`shutdown`] asks them
+//! all to shut down gracefully, then waits for them to finish.
+//!
+//! Connections upgraded by a server, such as WebSockets and `CONNECT`
+//! tunnels, are no longer connections of hyper, and escape its graceful
+//! shutdown. An [`auto`](crate::server::conn::auto) builder can be told to
+//! track them too, with
+//! [`Http1Builder::graceful_upgrades`](crate::server::conn::auto::Http1Builder::graceful_upgrades).
+//!
+//! [`shutdown`]: GracefulShutdown::shutdown
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures_util::future::{self, Either};
+use tokio::sync::watch;
+
+/// A graceful shutdown watcher.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(all(feature = "tokio", feature = "server-auto"))]
+/// # async fn run(listener: tokio::net::TcpListener, stop: impl std::future::Future<Output = ()>) {
+/// use std::convert::Infallible;
+/// use http_body_util::Empty;
+/// use hyper::body::Bytes;
+/// use hyper::service::service_fn;
+/// use hyper_util::rt::{TokioExecutor, TokioIo};
+/// use hyper_util::server::conn::auto;
+/// use hyper_util::server::graceful::GracefulShutdown;
+///
+/// let builder = auto::Builder::new(TokioExecutor::new());
+/// let graceful = GracefulShutdown::new();
+/// tokio::pin!(stop);
+///
+/// loop {
+///     tokio::select! {
+///         Ok((stream, _)) = listener.accept() => {
+///             let builder = builder.clone();
+///             let watcher = graceful.watcher();
+///             tokio::spawn(async move {
+///                 let service = service_fn(|_| async {
+///                     Ok::<_, Infallible>(http::Response::new(Empty::<Bytes>::new()))
+///                 });
+///                 let conn = builder.serve_connection(TokioIo::new(stream), service);
+///                 watcher.watch(conn).await
+///             });
+///         }
+///         _ = &mut stop => break,
+///     }
+/// }
+///
+/// graceful.shutdown().await;
+/// # }
+/// # fn main() {}
+/// ```
+pub struct GracefulShutdown {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+/// A handle to watch connections with a [`GracefulShutdown`], that can be
+/// moved into the task serving them.
+///
+/// Returned by [`GracefulShutdown::watcher`].
+pub struct Watcher {
+    rx: watch::Receiver<bool>,
+}
+
+/// A connection that can be shut down gracefully.
+///
+/// This is sealed, and implemented for the connections of hyper and of this
+/// crate.
+pub trait GracefulConnection: Future<Output = Result<(), Self::Error>> + private::Sealed {
+    /// The error of the connection.
+    type Error;
+
+    /// Start a graceful shutdown of the connection.
+    fn graceful_shutdown(self: Pin<&mut Self>);
+}
+
+/// A notification of a graceful shutdown, in the extensions of requests
+/// that may upgrade.
+///
+/// Inserted by an [`auto`](crate::server::conn::auto) builder with
+/// [graceful upgrades](crate::server::conn::auto::Http1Builder::graceful_upgrades),
+/// so that the upgraded connection can be closed cleanly, such as with a
+/// WebSocket close frame. Keeping it delays the end of the shutdown until
+/// it is dropped.
+#[derive(Clone, Debug)]
+pub struct Draining {
+    rx: watch::Receiver<bool>,
+}
+
+// ===== impl GracefulShutdown =====
+
+impl GracefulShutdown {
+    /// Create a new graceful shutdown watcher.
+    pub fn new() -> Self {
+        let (tx, _) = watch::channel(false);
+        GracefulShutdown { tx: Arc::new(tx) }
+    }
+
+    /// Watch a connection, shutting it down gracefully on
+    /// [`shutdown`](GracefulShutdown::shutdown).
+    ///
+    /// The connection counts as active until the returned future completes.
+    pub fn watch<C>(&self, conn: C) -> impl Future<Output = C::Output>
+    where
+        C: GracefulConnection,
+    {
+        self.watcher().watch(conn)
+    }
+
+    /// Get a handle to watch a connection with.
+    ///
+    /// It counts as an active connection until it is dropped, or the
+    /// connection it watches finishes.
+    pub fn watcher(&self) -> Watcher {
+        Watcher {
+            rx: self.tx.subscribe(),
+        }
+    }
+
+    /// The number of active connections, and upgraded connections.
+    pub fn count(&self) -> usize {
+        self.tx.receiver_count()
+    }
+
+    /// Shut down all watched connections gracefully, and wait for them to
+    /// finish.
+    pub async fn shutdown(self) {
+        let _ = self.tx.send(true);
+        self.tx.closed().await;
+    }
+
+    #[cfg(feature = "server-auto")]
+    pub(crate) fn sender(&self) -> Arc<watch::Sender<bool>> {
+        self.tx.clone()
+    }
+}
+
+impl Default for GracefulShutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for GracefulShutdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GracefulShutdown")
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+// ===== impl Watcher =====
+
+impl Watcher {
+    /// Watch a connection, shutting it down gracefully on
+    /// [`shutdown`](GracefulShutdown::shutdown).
+    pub fn watch<C>(self, conn: C) -> impl Future<Output = C::Output>
+    where
+        C: GracefulConnection,
+    {
+        let mut rx = self.rx;
+        async move {
+            futures_util::pin_mut!(conn);
+            {
+                let shutdown = signaled(&mut rx);
+                futures_util::pin_mut!(shutdown);
+                if let Either::Left((res, _)) = future::select(conn.as_mut(), shutdown).await {
+                    return res;
+                }
+            }
+            conn.as_mut().graceful_shutdown();
+            let res = conn.await;
+            // Counted until the connection finished.
+            drop(rx);
+            res
+        }
+    }
+}
+
+impl fmt::Debug for Watcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watcher").finish()
+    }
+}
+
+// Resolves once shutdown was signaled, which may be before it was called.
+async fn signaled(rx: &mut watch::Receiver<bool>) {
+    while !*rx.borrow_and_update() {
+        if rx.changed().await.is_err() {
+            // Dropped without shutting down.
+            future::pending::<()>().await;
+        }
+    }
+}
+
+// ===== impl Draining =====
+
+impl Draining {
+    /// Whether the graceful shutdown started.
+    pub fn is_draining(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Wait for the graceful shutdown to start.
+    pub async fn wait(mut self) {
+        signaled(&mut self.rx).await;
+    }
+
+    #[cfg(feature = "server-auto")]
+    pub(crate) fn new(tx: &watch::Sender<bool>) -> Self {
+        Draining { rx: tx.subscribe() }
+    }
+
+    #[cfg(feature = "server-auto")]
+    // A future of the graceful shutdown, which keeps counting as active
+    // once it resolves, for the upgraded connection holding it.
+    pub(crate) fn into_signal(mut self) -> Pin<Box<dyn Future<Output = Draining> + Send>> {
+        Box::pin(async move {
+            signaled(&mut self.rx).await;
+            self
+        })
+    }
+}
+
+// ===== impl GracefulConnection =====
+
+#[cfg(feature = "http1")]
+impl<I, B, S> GracefulConnection for hyper::server::conn::http1::Connection<I, S>
+where
+    S: hyper::service::HttpService<hyper::body::Incoming, ResBody = B>,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + 'static,
+    B: http_body::Body + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    type Error = hyper::Error;
+
+    fn graceful_shutdown(self: Pin<&mut Self>) {
+        hyper::server::conn::http1::Connection::graceful_shutdown(self);
+    }
+}
+
+#[cfg(feature = "http1")]
+impl<I, B, S> GracefulConnection for hyper::server::conn::http1::UpgradeableConnection<I, S>
+where
+    S: hyper::service::HttpService<hyper::body::Incoming, ResBody = B>,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+    B: http_body::Body + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    type Error = hyper::Error;
+
+    fn graceful_shutdown(self: Pin<&mut Self>) {
+        hyper::server::conn::http1::UpgradeableConnection::graceful_shutdown(self);
+    }
+}
+
+#[cfg(feature = "http2")]
+impl<I, B, S, E> GracefulConnection for hyper::server::conn::http2::Connection<I, S, E>
+where
+    S: hyper::service::HttpService<hyper::body::Incoming, ResBody = B>,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + 'static,
+    B: http_body::Body + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    E: hyper::rt::bounds::Http2ServerConnExec<S::Future, B>,
+{
+    type Error = hyper::Error;
+
+    fn graceful_shutdown(self: Pin<&mut Self>) {
+        hyper::server::conn::http2::Connection::graceful_shutdown(self);
+    }
+}
+
+#[cfg(feature = "server-auto")]
+impl<I, B, S, E> GracefulConnection for crate::server::conn::auto::Connection<'_, I, S, E>
+where
+    S: hyper::service::Service<http::Request<hyper::body::Incoming>, Response = http::Response<B>>,
+    S::Future: 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + 'static,
+    B: http_body::Body + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    E: hyper::rt::bounds::Http2ServerConnExec<S::Future, B>,
+{
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn graceful_shutdown(self: Pin<&mut Self>) {
+        crate::server::conn::auto::Connection::graceful_shutdown(self);
+    }
+}
+
+#[cfg(feature = "server-auto")]
+impl<I, B, S, E> GracefulConnection
+    for crate::server::conn::auto::UpgradeableConnection<'_, I, S, E>
+where
+    S: hyper::service::Service<http::Request<hyper::body::Incoming>, Response = http::Response<B>>,
+    S::Future: 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+    B: http_body::Body + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    E: hyper::rt::bounds::Http2ServerConnExec<S::Future, B>,
+{
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn graceful_shutdown(self: Pin<&mut Self>) {
+        crate::server::conn::auto::UpgradeableConnection::graceful_shutdown(self);
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+
+    #[cfg(feature = "http1")]
+    impl<I, S> Sealed for hyper::server::conn::http1::Connection<I, S> where
+        S: hyper::service::HttpService<hyper::body::Incoming>
+    {
+    }
+
+    #[cfg(feature = "http1")]
+    impl<I, S> Sealed for hyper::server::conn::http1::UpgradeableConnection<I, S> where
+        S: hyper::service::HttpService<hyper::body::Incoming>
+    {
+    }
+
+    #[cfg(feature = "http2")]
+    impl<I, S, E> Sealed for hyper::server::conn::http2::Connection<I, S, E> where
+        S: hyper::service::HttpService<hyper::body::Incoming>
+    {
+    }
+
+    #[cfg(feature = "server-auto")]
+    impl<I, S, E> Sealed for crate::server::conn::auto::Connection<'_, I, S, E> where
+        S: crate::server::conn::auto::ConnService
+    {
+    }
+
+    #[cfg(feature = "server-auto")]
+    impl<I, S, E> Sealed for crate::server::conn::auto::UpgradeableConnection<'_, I, S, E> where
+        S: crate::server::conn::auto::ConnService
+    {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::GracefulShutdown;
+
+    #[tokio::test]
+    async fn dropped_watcher() {
+        let graceful = GracefulShutdown::new();
+        let watcher = graceful.watcher();
+        assert_eq!(graceful.count(), 1);
+
+        drop(watcher);
+        assert_eq!(graceful.count(), 0);
+        tokio::time::timeout(Duration::from_secs(1), graceful.shutdown())
+            .await
+            .expect("nothing to wait for");
+    }
+
+    #[cfg(all(feature = "http1", not(miri)))]
+    #[tokio::test]
+    async fn drains_in_flight_connections() {
+        use std::convert::Infallible;
+        use std::sync::{Arc, Mutex};
+
+        use http_body_util::{BodyExt, Empty};
+        use hyper::body::Bytes;
+        use hyper::service::service_fn;
+        use tokio::net::{TcpListener, TcpStream};
+        use tokio::sync::oneshot;
+
+        use crate::rt::TokioIo;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (started_tx, started_rx) = oneshot::channel::<()>();
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+
+        let graceful = GracefulShutdown::new();
+        let watcher = graceful.watcher();
+        let txs = Arc::new(Mutex::new(Some((started_tx, release_rx))));
+        let service = service_fn(move |_| {
+            let (started_tx, release_rx) = txs.lock().unwrap().take().unwrap();
+            async move {
+                started_tx.send(()).unwrap();
+                release_rx.await.unwrap();
+                Ok::<_, Infallible>(http::Response::new(Empty::<Bytes>::new()))
+            }
+        });
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let conn = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service);
+            watcher.watch(conn).await
+        });
+
+        let stream = TokioIo::new(TcpStream::connect(addr).await.unwrap());
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(stream).await.unwrap();
+        tokio::spawn(conn);
+        let response = tokio::spawn(sender.send_request(http::Request::new(Empty::<Bytes>::new())));
+        started_rx.await.unwrap();
+
+        // the in-flight request keeps the shutdown waiting
+        let shutdown = tokio::spawn(graceful.shutdown());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!shutdown.is_finished());
+
+        release_tx.send(()).unwrap();
+        let response = response.await.unwrap().unwrap();
+        assert_eq!(response.status(), 200);
+        response.into_body().collect().await.unwrap();
+        shutdown.await.unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(all(feature = "server-auto", not(miri)))]
+    #[tokio::test]
+    async fn upgraded_connections_grace_timeout() {
+        use std::convert::Infallible;
+
+        use http_body_util::Empty;
+        use hyper::body::Bytes;
+        use hyper::service::service_fn;
+        use tokio::io::AsyncReadExt;
+        use tokio::net::{TcpListener, TcpStream};
+        use tokio::sync::oneshot;
+
+        use crate::rt::{TokioExecutor, TokioIo, TokioTimer};
+        use crate::server::conn::auto;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (closed_tx, closed_rx) = oneshot::channel();
+
+        let graceful = GracefulShutdown::new();
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .graceful_upgrades(&graceful, TokioTimer::new(), Duration::from_millis(50));
+        let watcher = graceful.watcher();
+        let closed_tx = std::sync::Mutex::new(Some(closed_tx));
+        let service = service_fn(move |req: http::Request<hyper::body::Incoming>| {
+            // a tunnel that ignores the shutdown, until its IO times out
+            let closed_tx = closed_tx.lock().unwrap().take().unwrap();
+            tokio::spawn(async move {
+                let upgraded = hyper::upgrade::on(req).await.unwrap();
+                let mut upgraded = TokioIo::new(upgraded);
+                let mut buf = [0; 1];
+                let res = upgraded.read(&mut buf).await;
+                let _ = closed_tx.send(res.unwrap_err().kind());
+            });
+            async move {
+                let mut res = http::Response::new(Empty::<Bytes>::new());
+                *res.status_mut() = http::StatusCode::SWITCHING_PROTOCOLS;
+                res.headers_mut()
+                    .insert(http::header::UPGRADE, "foo".parse().unwrap());
+                Ok::<_, Infallible>(res)
+            }
+        });
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            let _ = watcher.watch(conn).await;
+        });
+
+        let stream = TokioIo::new(TcpStream::connect(addr).await.unwrap());
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(stream).await.unwrap();
+        tokio::spawn(conn.with_upgrades());
+        let req = http::Request::builder()
+            .header(http::header::CONNECTION, "upgrade")
+            .header(http::header::UPGRADE, "foo")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(req).await.unwrap();
+        assert_eq!(response.status(), 101);
+        let _upgraded = hyper::upgrade::on(response).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(2), graceful.shutdown())
+            .await
+            .expect("the grace timeout ends the tunnel");
+        assert_eq!(closed_rx.await.unwrap(), std::io::ErrorKind::TimedOut);
+    }
+}
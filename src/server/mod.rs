@@ -1,3 +1,10 @@
 //! Server utilities.
 
 pub mod conn;
+
+#[cfg(feature = "server-graceful")]
+pub mod graceful;
+#[cfg(feature = "server-auto")]
+pub mod protect;
+#[cfg(any(feature = "server-rustls", feature = "server-native-tls"))]
+pub mod tls;
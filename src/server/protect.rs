@@ -0,0 +1,574 @@
+//! Protections against slow clients, such as slowloris attacks.
+//!
+//! A slowloris client opens many connections, and keeps them open by
+//! sending its requests a few bytes at a time. A [`Protection`] bounds what
+//! such clients can hold on to:
+//!
+//! - [`accept`](Protection::accept) caps the concurrent connections of each
+//!   peer IP, to call on every accepted connection.
+//! - [`configure`](Protection::configure) makes an `auto` builder time out
+//!   connections that don't send their first request headers in time.
+//! - [`service`](Protection::service) fails request bodies that are sent
+//!   slower than a minimum rate.
+//!
+//! The peers and bodies that were rejected are counted in
+//! [`stats`](Protection::stats).
+//!
+//! # Example
+//!
+//! ```
+//! # #[cfg(feature = "tokio")]
+//! # async fn run(listener: tokio::net::TcpListener) {
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//! use http_body_util::Empty;
+//! use hyper::body::Bytes;
+//! use hyper::service::service_fn;
+//! use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+//! use hyper_util::server::conn::auto;
+//! use hyper_util::server::protect::Protection;
+//!
+//! let protection = Protection::builder(TokioTimer::new())
+//!     .max_connections_per_peer(16)
+//!     .header_read_timeout(Duration::from_secs(10))
+//!     .min_body_rate(1024, Duration::from_secs(5))
+//!     .build();
+//! let mut builder = auto::Builder::new(TokioExecutor::new());
+//! protection.configure(&mut builder);
+//!
+//! loop {
+//!     let (stream, peer) = listener.accept().await.unwrap();
+//!     let permit = match protection.accept(peer.ip()) {
+//!         Ok(permit) => permit,
+//!         Err(_) => continue,
+//!     };
+//!     let builder = builder.clone();
+//!     let service = protection.service(service_fn(|_| async {
+//!         Ok::<_, Infallible>(http::Response::new(Empty::<Bytes>::new()))
+//!     }));
+//!     tokio::spawn(async move {
+//!         let _ = builder.serve_connection(TokioIo::new(stream), service).await;
+//!         drop(permit);
+//!     });
+//! }
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use http::{Request, Response};
+use http_body::{Body, Frame, SizeHint};
+use hyper::body::Incoming;
+use hyper::rt::{Sleep, Timer as _};
+use hyper::service::Service;
+use pin_project_lite::pin_project;
+use tracing::debug;
+
+use super::conn::auto;
+use crate::common::timer::Timer;
+
+/// Protections against slow clients, shared by the connections of a
+/// server.
+///
+/// Cloning a `Protection` is cheap, and clones share their limits and
+/// counters.
+#[derive(Clone)]
+pub struct Protection {
+    inner: Arc<Inner>,
+}
+
+/// A builder of a [`Protection`].
+///
+/// Returned by [`Protection::builder`].
+pub struct ProtectionBuilder {
+    timer: Timer,
+    max_per_peer: usize,
+    header_read_timeout: Option<Duration>,
+    min_body_rate: Option<MinRate>,
+}
+
+struct Inner {
+    timer: Timer,
+    max_per_peer: usize,
+    header_read_timeout: Option<Duration>,
+    min_body_rate: Option<MinRate>,
+    peers: Mutex<HashMap<IpAddr, usize>>,
+    rejected_connections: AtomicU64,
+    slow_bodies: AtomicU64,
+}
+
+#[derive(Clone, Copy)]
+struct MinRate {
+    bytes_per_sec: u64,
+    grace: Duration,
+}
+
+/// A connection of a peer, counted against its limit until dropped.
+///
+/// Returned by [`Protection::accept`]. Keep it for as long as the
+/// connection is served.
+#[must_use = "the connection is no longer counted once the permit is dropped"]
+pub struct PeerPermit {
+    inner: Arc<Inner>,
+    peer: IpAddr,
+}
+
+/// A peer reached its limit of concurrent connections.
+///
+/// Returned by [`Protection::accept`].
+#[derive(Debug)]
+pub struct PeerLimitExceeded {
+    peer: IpAddr,
+}
+
+/// A request body was sent slower than the minimum rate.
+#[derive(Debug)]
+pub struct BodyTooSlow(());
+
+/// The counters of a [`Protection`].
+///
+/// Returned by [`Protection::stats`].
+#[derive(Clone, Copy, Debug)]
+pub struct ProtectionStats {
+    connections: usize,
+    peers: usize,
+    rejected_connections: u64,
+    slow_bodies: u64,
+}
+
+/// A service whose request bodies must be sent at a minimum rate.
+///
+/// Returned by [`Protection::service`].
+#[derive(Clone)]
+pub struct ProtectedService<S> {
+    inner: S,
+    protection: Protection,
+}
+
+pin_project! {
+    /// A body that fails if it is received slower than a minimum rate.
+    ///
+    /// The rate is averaged over the whole body: after the grace period,
+    /// the body must have received at least the rate times the time since
+    /// it started.
+    pub struct MinRateBody<B> {
+        #[pin]
+        inner: B,
+        protection: Option<Arc<Inner>>,
+        started: Instant,
+        received: u64,
+        sleep: Option<(Pin<Box<dyn Sleep>>, Instant)>,
+    }
+}
+
+// ===== impl Protection =====
+
+impl Protection {
+    /// Create a builder of a `Protection`, that uses `timer` for the
+    /// timeouts.
+    pub fn builder<M>(timer: M) -> ProtectionBuilder
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        ProtectionBuilder {
+            timer: Timer::new(timer),
+            max_per_peer: usize::MAX,
+            header_read_timeout: None,
+            min_body_rate: None,
+        }
+    }
+
+    /// Count a new connection of `peer`.
+    ///
+    /// Fails if the peer already has as many connections as allowed, in
+    /// which case the connection should be dropped right away.
+    pub fn accept(&self, peer: IpAddr) -> Result<PeerPermit, PeerLimitExceeded> {
+        let mut peers = self.inner.peers.lock().unwrap();
+        let count = peers.get(&peer).copied().unwrap_or(0);
+        if count >= self.inner.max_per_peer {
+            drop(peers);
+            self.inner
+                .rejected_connections
+                .fetch_add(1, Ordering::Relaxed);
+            debug!("rejecting connection of {}, at its limit", peer);
+            return Err(PeerLimitExceeded { peer });
+        }
+        *peers.entry(peer).or_insert(0) += 1;
+        Ok(PeerPermit {
+            inner: self.inner.clone(),
+            peer,
+        })
+    }
+
+    /// Set the timer and header read timeouts of `builder`.
+    ///
+    /// Connections then fail if they don't send their first request, of
+    /// either protocol, within the header read timeout, and each later
+    /// HTTP/1 request header too.
+    pub fn configure<E>(&self, builder: &mut auto::Builder<E>) {
+        builder.timer(self.inner.timer.clone());
+        if let Some(timeout) = self.inner.header_read_timeout {
+            builder.first_byte_timeout(timeout);
+            builder.http1().header_read_timeout(timeout);
+        }
+    }
+
+    /// Wrap `service`, so that its request bodies fail with a
+    /// [`BodyTooSlow`] error if sent slower than the minimum rate.
+    pub fn service<S>(&self, service: S) -> ProtectedService<S> {
+        ProtectedService {
+            inner: service,
+            protection: self.clone(),
+        }
+    }
+
+    /// Wrap `body`, so that it fails with a [`BodyTooSlow`] error if
+    /// received slower than the minimum rate.
+    pub fn body<B>(&self, body: B) -> MinRateBody<B> {
+        MinRateBody {
+            inner: body,
+            protection: self.inner.min_body_rate.map(|_| self.inner.clone()),
+            started: Instant::now(),
+            received: 0,
+            sleep: None,
+        }
+    }
+
+    /// Take a snapshot of the counters.
+    pub fn stats(&self) -> ProtectionStats {
+        let peers = self.inner.peers.lock().unwrap();
+        ProtectionStats {
+            connections: peers.values().sum(),
+            peers: peers.len(),
+            rejected_connections: self.inner.rejected_connections.load(Ordering::Relaxed),
+            slow_bodies: self.inner.slow_bodies.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl fmt::Debug for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Protection")
+            .field("max_per_peer", &self.inner.max_per_peer)
+            .field("header_read_timeout", &self.inner.header_read_timeout)
+            .finish()
+    }
+}
+
+// ===== impl ProtectionBuilder =====
+
+impl ProtectionBuilder {
+    /// Set how many connections each peer IP may have at once.
+    ///
+    /// Default is no limit.
+    pub fn max_connections_per_peer(mut self, max: usize) -> Self {
+        self.max_per_peer = max;
+        self
+    }
+
+    /// Set how long connections may take to send request headers.
+    ///
+    /// Default is None.
+    pub fn header_read_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.header_read_timeout = timeout.into();
+        self
+    }
+
+    /// Set the rate, in bytes per second, that request bodies must be sent
+    /// at, once they were given `grace` to start.
+    ///
+    /// Default is no minimum. A rate of `0` removes the minimum.
+    pub fn min_body_rate(mut self, bytes_per_sec: u64, grace: Duration) -> Self {
+        self.min_body_rate = if bytes_per_sec > 0 {
+            Some(MinRate {
+                bytes_per_sec,
+                grace,
+            })
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Build the `Protection`.
+    pub fn build(self) -> Protection {
+        Protection {
+            inner: Arc::new(Inner {
+                timer: self.timer,
+                max_per_peer: self.max_per_peer,
+                header_read_timeout: self.header_read_timeout,
+                min_body_rate: self.min_body_rate,
+                peers: Mutex::new(HashMap::new()),
+                rejected_connections: AtomicU64::new(0),
+                slow_bodies: AtomicU64::new(0),
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for ProtectionBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProtectionBuilder")
+            .field("max_per_peer", &self.max_per_peer)
+            .field("header_read_timeout", &self.header_read_timeout)
+            .finish()
+    }
+}
+
+// ===== impl PeerPermit =====
+
+impl PeerPermit {
+    /// The IP of the peer.
+    pub fn peer(&self) -> IpAddr {
+        self.peer
+    }
+}
+
+impl Drop for PeerPermit {
+    fn drop(&mut self) {
+        if let Ok(mut peers) = self.inner.peers.lock() {
+            if let Some(count) = peers.get_mut(&self.peer) {
+                *count -= 1;
+                if *count == 0 {
+                    peers.remove(&self.peer);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for PeerPermit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerPermit")
+            .field("peer", &self.peer)
+            .finish()
+    }
+}
+
+// ===== impl PeerLimitExceeded =====
+
+impl PeerLimitExceeded {
+    /// The IP of the peer.
+    pub fn peer(&self) -> IpAddr {
+        self.peer
+    }
+}
+
+impl fmt::Display for PeerLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "too many connections of peer {}", self.peer)
+    }
+}
+
+impl StdError for PeerLimitExceeded {}
+
+// ===== impl BodyTooSlow =====
+
+impl fmt::Display for BodyTooSlow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request body sent too slowly")
+    }
+}
+
+impl StdError for BodyTooSlow {}
+
+// ===== impl ProtectionStats =====
+
+impl ProtectionStats {
+    /// The connections currently counted, across all peers.
+    pub fn connections(&self) -> usize {
+        self.connections
+    }
+
+    /// The peers with connections currently counted.
+    pub fn peers(&self) -> usize {
+        self.peers
+    }
+
+    /// The connections rejected because their peer was at its limit.
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected_connections
+    }
+
+    /// The request bodies that failed for being sent too slowly.
+    pub fn slow_bodies(&self) -> u64 {
+        self.slow_bodies
+    }
+}
+
+// ===== impl ProtectedService =====
+
+impl<S> ProtectedService<S> {
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume this wrapper, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ResBody> Service<Request<Incoming>> for ProtectedService<S>
+where
+    S: Service<Request<MinRateBody<Incoming>>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, req: Request<Incoming>) -> Self::Future {
+        self.inner.call(req.map(|body| self.protection.body(body)))
+    }
+}
+
+impl<S> fmt::Debug for ProtectedService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProtectedService").finish()
+    }
+}
+
+// ===== impl MinRateBody =====
+
+impl<B> MinRateBody<B> {
+    /// Get a reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Consume this wrapper, returning the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for MinRateBody<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Data = B::Data;
+    type Error = Box<dyn StdError + Send + Sync>;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if let Poll::Ready(frame) = this.inner.poll_frame(cx) {
+            if let Some(Ok(ref frame)) = frame {
+                if let Some(data) = frame.data_ref() {
+                    *this.received += bytes::Buf::remaining(data) as u64;
+                }
+            }
+            return Poll::Ready(frame.map(|res| res.map_err(Into::into)));
+        }
+
+        let inner = match this.protection {
+            Some(ref inner) => inner,
+            None => return Poll::Pending,
+        };
+        let rate = inner.min_body_rate.expect("protection has a min body rate");
+        // The body is too slow once the time its bytes are worth is over.
+        let worth =
+            Duration::from_nanos(this.received.saturating_mul(1_000_000_000) / rate.bytes_per_sec);
+        let deadline = *this.started + rate.grace.max(worth);
+        match this.sleep {
+            Some((ref mut sleep, ref mut at)) if *at != deadline => {
+                inner.timer.reset(sleep, deadline);
+                *at = deadline;
+            }
+            Some(_) => {}
+            None => *this.sleep = Some((inner.timer.sleep_until(deadline), deadline)),
+        }
+        let (sleep, _) = this.sleep.as_mut().expect("sleep was just set");
+        if Instant::now() < deadline && sleep.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        inner.slow_bodies.fetch_add(1, Ordering::Relaxed);
+        debug!("request body slower than {} B/s", rate.bytes_per_sec);
+        Poll::Ready(Some(Err(Box::new(BodyTooSlow(())))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> fmt::Debug for MinRateBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MinRateBody")
+            .field("received", &self.received)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures_util::StreamExt;
+    use http_body::Frame;
+    use http_body_util::{BodyExt, StreamBody};
+
+    use super::{BodyTooSlow, Protection};
+    use crate::rt::TokioTimer;
+
+    #[test]
+    fn caps_connections_per_peer() {
+        let protection = Protection::builder(TokioTimer::new())
+            .max_connections_per_peer(1)
+            .build();
+        let a = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let b = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        let permit = protection.accept(a).expect("first connection of a");
+        let err = protection.accept(a).expect_err("second connection of a");
+        assert_eq!(err.peer(), a);
+        let _other = protection.accept(b).expect("first connection of b");
+
+        let stats = protection.stats();
+        assert_eq!(stats.connections(), 2);
+        assert_eq!(stats.peers(), 2);
+        assert_eq!(stats.rejected_connections(), 1);
+
+        drop(permit);
+        assert_eq!(protection.stats().peers(), 1);
+        let _permit = protection.accept(a).expect("a after its permit dropped");
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn fails_slow_bodies() {
+        let protection = Protection::builder(TokioTimer::new())
+            .min_body_rate(1024, Duration::from_millis(20))
+            .build();
+        let chunks = futures_util::stream::iter(vec![Ok::<_, BodyTooSlow>(Frame::data(
+            Bytes::from_static(b"hello"),
+        ))])
+        .chain(futures_util::stream::pending());
+        let mut body = protection.body(StreamBody::new(chunks));
+
+        let frame = body.frame().await.expect("frame").expect("data");
+        assert_eq!(frame.into_data().unwrap(), "hello");
+        let err = body.frame().await.expect("frame").expect_err("too slow");
+        assert!(err.is::<BodyTooSlow>());
+        assert_eq!(protection.stats().slow_bodies(), 1);
+    }
+}
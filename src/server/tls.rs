@@ -0,0 +1,179 @@
+//! Details of the TLS sessions of accepted connections.
+//!
+//! Servers that accept TLS with `rustls` or `native-tls` can read a
+//! [`TlsInfo`] from each established stream, and wrap the connection's
+//! service in an `InjectExtension` from the `service` module to set it in
+//! the extensions of every request. Services that authenticate clients
+//! with certificates (mTLS) can then authorize each request by its peer
+//! certificates.
+//!
+//! This module needs the `server-rustls` or `server-native-tls` feature.
+//!
+//! # Example
+//!
+//! ```
+//! # #[cfg(all(feature = "server-rustls", feature = "server-auto", feature = "service"))]
+//! # async fn run(
+//! #     acceptor: tokio_rustls::TlsAcceptor,
+//! #     stream: tokio::net::TcpStream,
+//! # ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! use std::convert::Infallible;
+//! use http_body_util::Full;
+//! use hyper::body::Bytes;
+//! use hyper::service::service_fn;
+//! use hyper_util::rt::{TokioExecutor, TokioIo};
+//! use hyper_util::server::conn::auto;
+//! use hyper_util::server::tls::TlsInfo;
+//! use hyper_util::service::InjectExtension;
+//!
+//! let stream = acceptor.accept(stream).await?;
+//! let info = TlsInfo::from_rustls(&stream);
+//! let service = InjectExtension::new(
+//!     service_fn(|req: http::Request<_>| async move {
+//!         let info = req.extensions().get::<TlsInfo>();
+//!         let authorized = info.map_or(false, |info| info.peer_certificate().is_some());
+//!         let body = if authorized { "welcome" } else { "who are you?" };
+//!         Ok::<_, Infallible>(http::Response::new(Full::new(Bytes::from(body))))
+//!     }),
+//!     info,
+//! );
+//! auto::Builder::new(TokioExecutor::new())
+//!     .serve_connection(TokioIo::new(stream), service)
+//!     .await
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Details of the TLS session of an accepted connection.
+///
+/// Cloning a `TlsInfo` is cheap, so it can be set in the extensions of
+/// every request served on its connection.
+#[derive(Clone)]
+pub struct TlsInfo {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    server_name: Option<String>,
+    peer_certificates: Vec<Vec<u8>>,
+}
+
+// ===== impl TlsInfo =====
+
+impl TlsInfo {
+    /// Read the details of the TLS session of a `rustls` stream.
+    ///
+    /// The peer certificates are only set once the client authenticated
+    /// with them, as verified by the `ServerConfig`'s client verifier.
+    #[cfg(feature = "server-rustls")]
+    pub fn from_rustls<T>(stream: &tokio_rustls::server::TlsStream<T>) -> Self {
+        let (_, tls) = stream.get_ref();
+        TlsInfo::new(
+            tls.server_name().map(ToOwned::to_owned),
+            tls.peer_certificates()
+                .map(|certs| certs.iter().map(|cert| cert.to_vec()).collect())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Read the details of the TLS session of a `native-tls` stream.
+    ///
+    /// `native-tls` only exposes the end-entity certificate of the peer, and
+    /// not the name it requested, so the chain has at most one certificate
+    /// and the server name is never set.
+    #[cfg(feature = "server-native-tls")]
+    pub fn from_native_tls<T>(stream: &tokio_native_tls::TlsStream<T>) -> Self
+    where
+        T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        let cert = stream
+            .get_ref()
+            .peer_certificate()
+            .ok()
+            .flatten()
+            .and_then(|cert| cert.to_der().ok());
+        TlsInfo::new(None, cert.into_iter().collect())
+    }
+
+    fn new(server_name: Option<String>, peer_certificates: Vec<Vec<u8>>) -> Self {
+        TlsInfo {
+            inner: Arc::new(Inner {
+                server_name,
+                peer_certificates,
+            }),
+        }
+    }
+
+    /// Get the server name the client requested through SNI, if any.
+    pub fn server_name(&self) -> Option<&str> {
+        self.inner.server_name.as_deref()
+    }
+
+    /// Get the DER encoded certificate chain the client authenticated with,
+    /// starting with its end-entity certificate.
+    ///
+    /// It is empty if the client did not present certificates.
+    pub fn peer_certificates(&self) -> &[Vec<u8>] {
+        &self.inner.peer_certificates
+    }
+
+    /// Get the DER encoded end-entity certificate the client authenticated
+    /// with, if any.
+    pub fn peer_certificate(&self) -> Option<&[u8]> {
+        self.inner.peer_certificates.first().map(Vec::as_slice)
+    }
+}
+
+impl fmt::Debug for TlsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsInfo")
+            .field("server_name", &self.inner.server_name)
+            .field("peer_certificates", &self.inner.peer_certificates.len())
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "service", any(feature = "http1", feature = "http2")))]
+mod tests {
+    use std::convert::Infallible;
+
+    use bytes::Bytes;
+    use http_body_util::Empty;
+    use hyper::service::{service_fn, Service};
+
+    use super::TlsInfo;
+    use crate::service::InjectExtension;
+
+    #[tokio::test]
+    async fn sets_info_in_request_extensions() {
+        let info = TlsInfo::new(
+            Some("example.domain".to_owned()),
+            vec![b"leaf".to_vec(), b"intermediate".to_vec()],
+        );
+        let service = InjectExtension::new(
+            service_fn(|req: http::Request<Empty<Bytes>>| async move {
+                let mut res = http::Response::new(Empty::<Bytes>::new());
+                if let Some(info) = req.extensions().get::<TlsInfo>() {
+                    res.extensions_mut().insert(info.clone());
+                }
+                Ok::<_, Infallible>(res)
+            }),
+            info,
+        );
+
+        let res = service
+            .call(http::Request::new(Empty::new()))
+            .await
+            .unwrap();
+        let info = res
+            .extensions()
+            .get::<TlsInfo>()
+            .expect("info in extensions");
+        assert_eq!(info.server_name(), Some("example.domain"));
+        assert_eq!(info.peer_certificate(), Some(&b"leaf"[..]));
+        assert_eq!(info.peer_certificates().len(), 2);
+    }
+}
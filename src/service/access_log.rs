@@ -0,0 +1,428 @@
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use http::{Method, Request, Response, StatusCode, Uri, Version};
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+
+/// A service that emits one [`AccessLogRecord`] per request.
+///
+/// The record is emitted once the response body has been sent, or dropped
+/// because the connection closed, so that it includes the bytes of the body
+/// and the time taken to send it. If the inner service fails, it's emitted
+/// right away, without a status.
+///
+/// Records go to a sink, which is either a callback, or `tracing` events at
+/// the `INFO` level, with the `hyper_util::access_log` target.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "server")]
+/// # fn run(peer: std::net::SocketAddr) {
+/// use std::convert::Infallible;
+/// use http::{Request, Response};
+/// use hyper::service::service_fn;
+/// use hyper_util::service::AccessLog;
+///
+/// let svc = service_fn(|_req: Request<hyper::body::Incoming>| async move {
+///     Ok::<_, Infallible>(Response::new(String::from("hello")))
+/// });
+/// let svc = AccessLog::new(svc, |record| println!("{}", record)).with_peer_addr(peer);
+/// # let _ = svc;
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct AccessLog<S> {
+    inner: S,
+    sink: Sink,
+    peer: Option<SocketAddr>,
+}
+
+/// What an [`AccessLog`] records about a request.
+///
+/// Its `Display` is similar to the common log format:
+/// `peer "METHOD path VERSION" status bytes duration`.
+#[derive(Clone, Debug)]
+pub struct AccessLogRecord {
+    peer: Option<SocketAddr>,
+    method: Method,
+    uri: Uri,
+    version: Version,
+    status: Option<StatusCode>,
+    bytes: u64,
+    duration: Duration,
+}
+
+#[derive(Clone)]
+enum Sink {
+    Tracing,
+    Callback(Arc<dyn Fn(&AccessLogRecord) + Send + Sync>),
+}
+
+// The record being built, emitted once.
+struct Pending {
+    record: AccessLogRecord,
+    started: Instant,
+    sink: Sink,
+}
+
+pin_project! {
+    /// Response future for [`AccessLog`].
+    pub struct AccessLogFuture<F> {
+        #[pin]
+        inner: F,
+        pending: Option<Pending>,
+    }
+}
+
+pin_project! {
+    /// Response body of an [`AccessLog`] service.
+    pub struct AccessLogBody<B> {
+        #[pin]
+        inner: B,
+        pending: Option<Pending>,
+    }
+
+    impl<B> PinnedDrop for AccessLogBody<B> {
+        fn drop(this: Pin<&mut Self>) {
+            if let Some(pending) = this.project().pending.take() {
+                pending.emit();
+            }
+        }
+    }
+}
+
+// ===== impl AccessLog =====
+
+impl<S> AccessLog<S> {
+    /// Wrap `inner`, passing the record of each request to `sink`.
+    pub fn new<F>(inner: S, sink: F) -> Self
+    where
+        F: Fn(&AccessLogRecord) + Send + Sync + 'static,
+    {
+        AccessLog {
+            inner,
+            sink: Sink::Callback(Arc::new(sink)),
+            peer: None,
+        }
+    }
+
+    /// Wrap `inner`, emitting the record of each request as a `tracing`
+    /// event.
+    pub fn tracing(inner: S) -> Self {
+        AccessLog {
+            inner,
+            sink: Sink::Tracing,
+            peer: None,
+        }
+    }
+
+    /// Set the address of the peer the requests come from.
+    ///
+    /// Services are usually created per connection, so this is the address
+    /// the connection was accepted from.
+    pub fn with_peer_addr(mut self, peer: SocketAddr) -> Self {
+        self.peer = Some(peer);
+        self
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for AccessLog<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLog")
+            .field("inner", &self.inner)
+            .field("peer", &self.peer)
+            .finish()
+    }
+}
+
+impl<S, B, R> hyper::service::Service<Request<B>> for AccessLog<S>
+where
+    S: hyper::service::Service<Request<B>, Response = Response<R>>,
+{
+    type Response = Response<AccessLogBody<R>>;
+    type Error = S::Error;
+    type Future = AccessLogFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        let record = AccessLogRecord {
+            peer: self.peer,
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            status: None,
+            bytes: 0,
+            duration: Duration::from_secs(0),
+        };
+        AccessLogFuture {
+            inner: self.inner.call(req),
+            pending: Some(Pending {
+                record,
+                started: Instant::now(),
+                sink: self.sink.clone(),
+            }),
+        }
+    }
+}
+
+// ===== impl AccessLogFuture =====
+
+impl<F> fmt::Debug for AccessLogFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLogFuture").finish()
+    }
+}
+
+impl<F, R, E> Future for AccessLogFuture<F>
+where
+    F: Future<Output = Result<Response<R>, E>>,
+{
+    type Output = Result<Response<AccessLogBody<R>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = futures_util::ready!(this.inner.poll(cx));
+        let mut pending = this.pending.take().expect("polled after complete");
+        match res {
+            Ok(res) => {
+                pending.record.status = Some(res.status());
+                Poll::Ready(Ok(res.map(|inner| AccessLogBody {
+                    inner,
+                    pending: Some(pending),
+                })))
+            }
+            Err(err) => {
+                pending.emit();
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+}
+
+// ===== impl AccessLogBody =====
+
+impl<B: fmt::Debug> fmt::Debug for AccessLogBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLogBody")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<B: Body> Body for AccessLogBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let frame = futures_util::ready!(this.inner.as_mut().poll_frame(cx));
+        if let Some(Ok(ref frame)) = frame {
+            if let (Some(data), Some(pending)) = (frame.data_ref(), this.pending.as_mut()) {
+                pending.record.bytes += bytes::Buf::remaining(data) as u64;
+            }
+        }
+        let done = match frame {
+            Some(Ok(_)) => this.inner.is_end_stream(),
+            Some(Err(_)) | None => true,
+        };
+        if done {
+            if let Some(pending) = this.pending.take() {
+                pending.emit();
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+// ===== impl Pending =====
+
+impl Pending {
+    fn emit(mut self) {
+        self.record.duration = self.started.elapsed();
+        match self.sink {
+            Sink::Tracing => {
+                let record = &self.record;
+                tracing::info!(
+                    target: "hyper_util::access_log",
+                    peer = ?record.peer,
+                    method = %record.method,
+                    path = %record.path(),
+                    version = ?record.version,
+                    status = record.status.map(|s| s.as_u16()),
+                    bytes = record.bytes,
+                    duration = ?record.duration,
+                    "{}",
+                    record,
+                );
+            }
+            Sink::Callback(ref sink) => sink(&self.record),
+        }
+    }
+}
+
+// ===== impl AccessLogRecord =====
+
+impl AccessLogRecord {
+    /// The address of the peer, if it was set.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    /// The method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The URI of the request.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// The path and query of the request.
+    pub fn path(&self) -> &str {
+        self.uri
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or_else(|| self.uri.path())
+    }
+
+    /// The HTTP version of the request.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// The status of the response, or `None` if the service failed.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.status
+    }
+
+    /// The number of bytes of the response body that were sent.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// The time from receiving the request until the response body was
+    /// sent or dropped.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl fmt::Display for AccessLogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.peer {
+            Some(peer) => write!(f, "{} ", peer)?,
+            None => f.write_str("- ")?,
+        }
+        write!(f, "\"{} {} {:?}\" ", self.method, self.path(), self.version)?;
+        match self.status {
+            Some(status) => write!(f, "{} ", status.as_u16())?,
+            None => f.write_str("- ")?,
+        }
+        write!(f, "{} {:?}", self.bytes, self.duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+
+    use bytes::Bytes;
+    use futures_util::FutureExt;
+    use http::{Method, Request, Response, StatusCode};
+    use http_body_util::{BodyExt, Full};
+    use hyper::service::{service_fn, Service};
+
+    use super::{AccessLog, AccessLogRecord};
+
+    #[test]
+    fn records_once_body_is_sent() {
+        let records = Arc::new(Mutex::new(Vec::<AccessLogRecord>::new()));
+        let sink = records.clone();
+        let svc = service_fn(|_req: Request<String>| async move {
+            Ok::<_, Infallible>(Response::new(Full::new(Bytes::from_static(b"hello"))))
+        });
+        let svc = AccessLog::new(svc, move |record| sink.lock().unwrap().push(record.clone()))
+            .with_peer_addr(([127, 0, 0, 1], 8080).into());
+
+        let mut req = Request::new(String::new());
+        *req.method_mut() = Method::POST;
+        *req.uri_mut() = "/users?page=2".parse().unwrap();
+        let res = svc.call(req).now_or_never().unwrap().unwrap();
+        assert!(records.lock().unwrap().is_empty());
+
+        let body = res.into_body().collect().now_or_never().unwrap().unwrap();
+        assert_eq!(body.to_bytes(), "hello");
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.status(), Some(StatusCode::OK));
+        assert_eq!(record.bytes(), 5);
+        assert_eq!(record.path(), "/users?page=2");
+        let line = record.to_string();
+        assert!(
+            line.starts_with("127.0.0.1:8080 \"POST /users?page=2 HTTP/1.1\" 200 5 "),
+            "{}",
+            line
+        );
+    }
+
+    #[test]
+    fn records_when_body_dropped() {
+        let records = Arc::new(Mutex::new(Vec::<AccessLogRecord>::new()));
+        let sink = records.clone();
+        let svc = service_fn(|_req: Request<String>| async move {
+            Ok::<_, Infallible>(Response::new(String::from("unsent")))
+        });
+        let svc = AccessLog::new(svc, move |record| sink.lock().unwrap().push(record.clone()));
+
+        let res = svc
+            .call(Request::new(String::new()))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        drop(res);
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].bytes(), 0);
+        assert!(records[0]
+            .to_string()
+            .starts_with("- \"GET / HTTP/1.1\" 200 0 "));
+    }
+}
@@ -0,0 +1,151 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use pin_project_lite::pin_project;
+
+/// A service that is one of two services.
+///
+/// Both services must have the same response and error types, so that the
+/// choice between them can be made at runtime, such as when a connection is
+/// accepted, without boxing them.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "server")]
+/// # fn run() {
+/// use std::convert::Infallible;
+/// use http::{Request, Response, StatusCode};
+/// use hyper::body::Incoming;
+/// use hyper::service::service_fn;
+/// use hyper_util::service::Either;
+///
+/// let maintenance = true;
+/// let svc = if maintenance {
+///     Either::Left(service_fn(|_req: Request<Incoming>| async {
+///         let mut res = Response::new(String::from("down for maintenance"));
+///         *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+///         Ok::<_, Infallible>(res)
+///     }))
+/// } else {
+///     Either::Right(service_fn(|_req: Request<Incoming>| async {
+///         Ok::<_, Infallible>(Response::new(String::from("hello")))
+///     }))
+/// };
+/// # let _ = svc;
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub enum Either<A, B> {
+    /// The first service.
+    Left(A),
+    /// The second service.
+    Right(B),
+}
+
+pin_project! {
+    /// Response future for [`Either`].
+    #[derive(Debug)]
+    pub struct EitherFuture<A, B> {
+        #[pin]
+        inner: Inner<A, B>,
+    }
+}
+
+pin_project! {
+    #[project = InnerProj]
+    #[derive(Debug)]
+    enum Inner<A, B> {
+        Left {
+            #[pin]
+            future: A,
+        },
+        Right {
+            #[pin]
+            future: B,
+        },
+    }
+}
+
+impl<A, B, R> hyper::service::Service<R> for Either<A, B>
+where
+    A: hyper::service::Service<R>,
+    B: hyper::service::Service<R, Response = A::Response, Error = A::Error>,
+{
+    type Response = A::Response;
+    type Error = A::Error;
+    type Future = EitherFuture<A::Future, B::Future>;
+
+    fn call(&self, req: R) -> Self::Future {
+        match self {
+            Either::Left(a) => EitherFuture {
+                inner: Inner::Left {
+                    future: a.call(req),
+                },
+            },
+            Either::Right(b) => EitherFuture {
+                inner: Inner::Right {
+                    future: b.call(req),
+                },
+            },
+        }
+    }
+}
+
+impl<A, B> Future for EitherFuture<A, B>
+where
+    A: Future,
+    B: Future<Output = A::Output>,
+{
+    type Output = A::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().inner.project() {
+            InnerProj::Left { future } => future.poll(cx),
+            InnerProj::Right { future } => future.poll(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::{self, FutureExt};
+    use hyper::service::Service;
+
+    use super::Either;
+
+    struct Const(u8);
+
+    impl Service<()> for Const {
+        type Response = u8;
+        type Error = ();
+        type Future = future::Ready<Result<u8, ()>>;
+
+        fn call(&self, _: ()) -> Self::Future {
+            future::ok(self.0)
+        }
+    }
+
+    struct Fails;
+
+    impl Service<()> for Fails {
+        type Response = u8;
+        type Error = ();
+        type Future = future::Ready<Result<u8, ()>>;
+
+        fn call(&self, _: ()) -> Self::Future {
+            future::err(())
+        }
+    }
+
+    #[test]
+    fn calls_chosen_service() {
+        let left: Either<Const, Fails> = Either::Left(Const(1));
+        assert_eq!(left.call(()).now_or_never().unwrap(), Ok(1));
+
+        let right: Either<Const, Fails> = Either::Right(Fails);
+        assert_eq!(right.call(()).now_or_never().unwrap(), Err(()));
+    }
+}
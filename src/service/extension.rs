@@ -0,0 +1,108 @@
+use http::Request;
+
+/// A service that inserts a clone of a value into the extensions of every
+/// request, before calling the inner service.
+///
+/// This is useful to make per-connection values, such as the TLS info or
+/// the remote address, or per-server values, such as a tenant id, available
+/// to the service handling the requests.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "server")]
+/// # fn run() {
+/// use std::convert::Infallible;
+/// use http::{Request, Response};
+/// use hyper::service::service_fn;
+/// use hyper_util::service::InjectExtension;
+///
+/// #[derive(Clone)]
+/// struct ConnId(u64);
+///
+/// let svc = service_fn(|req: Request<hyper::body::Incoming>| async move {
+///     let id = req.extensions().get::<ConnId>().map(|id| id.0);
+///     Ok::<_, Infallible>(Response::new(format!("{:?}", id)))
+/// });
+/// let svc = InjectExtension::new(svc, ConnId(7));
+/// # let _ = svc;
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone)]
+pub struct InjectExtension<S, T> {
+    inner: S,
+    value: T,
+}
+
+impl<S, T> InjectExtension<S, T> {
+    /// Wrap `inner`, inserting a clone of `value` into every request.
+    pub fn new(inner: S, value: T) -> Self {
+        InjectExtension { inner, value }
+    }
+
+    /// Get a reference to the value inserted into requests.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, T, B> hyper::service::Service<Request<B>> for InjectExtension<S, T>
+where
+    S: hyper::service::Service<Request<B>>,
+    T: Clone + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        req.extensions_mut().insert(self.value.clone());
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures_util::FutureExt;
+    use http::{Request, Response};
+    use hyper::service::{service_fn, Service};
+
+    use super::InjectExtension;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Tenant(&'static str);
+
+    #[test]
+    fn inserts_value() {
+        let svc = service_fn(|req: Request<String>| async move {
+            let tenant = req.extensions().get::<Tenant>().cloned();
+            Ok::<_, Infallible>(Response::new(format!("{:?}", tenant)))
+        });
+        let svc = InjectExtension::new(svc, Tenant("acme"));
+
+        let res = svc
+            .call(Request::new(String::new()))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(res.into_body(), "Some(Tenant(\"acme\"))");
+    }
+}
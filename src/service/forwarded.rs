@@ -0,0 +1,577 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+use http::header::{HeaderName, HeaderValue, FORWARDED, HOST};
+use http::{HeaderMap, Request};
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// One hop of a request through proxies, as in a `Forwarded` header
+/// element (RFC 7239).
+///
+/// Parse the hops a request went through with
+/// [`from_headers`](Forwarded::from_headers), and add one with
+/// [`append_to`](Forwarded::append_to).
+///
+/// Its `Display` is the header element, such as
+/// `for=192.0.2.60;proto=http;by=203.0.113.43`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Forwarded {
+    forwarded_for: Option<String>,
+    by: Option<String>,
+    host: Option<String>,
+    proto: Option<String>,
+}
+
+/// A service that appends the hop from the peer to this proxy to the
+/// `Forwarded` header of every request, before calling the inner service.
+///
+/// The peer is the address set with
+/// [`with_peer_addr`](AddForwarded::with_peer_addr), or else a `SocketAddr`
+/// in the extensions of the request, such as one inserted per connection by
+/// [`InjectExtension`](super::InjectExtension). The protocol is that of the
+/// request URI, if it has one, or else the one set with
+/// [`proto`](AddForwarded::proto). The host is that of the `Host` header.
+///
+/// The legacy `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`
+/// headers can be added too, for upstreams that only understand those.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "server")]
+/// # fn run(peer: std::net::SocketAddr) {
+/// use std::convert::Infallible;
+/// use http::{Request, Response};
+/// use hyper::service::service_fn;
+/// use hyper_util::service::{AddForwarded, Forwarded};
+///
+/// let svc = service_fn(|req: Request<hyper::body::Incoming>| async move {
+///     // forward `req` upstream...
+///     let hops = Forwarded::from_headers(req.headers());
+///     Ok::<_, Infallible>(Response::new(format!("{} hops", hops.len())))
+/// });
+/// let svc = AddForwarded::new(svc)
+///     .with_peer_addr(peer)
+///     .by("proxy-1")
+///     .x_forwarded(true);
+/// # let _ = svc;
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct AddForwarded<S> {
+    inner: S,
+    peer: Option<SocketAddr>,
+    by: Option<String>,
+    proto: &'static str,
+    x_forwarded: bool,
+}
+
+// ===== impl Forwarded =====
+
+impl Forwarded {
+    /// Create a hop without any parameters.
+    pub fn new() -> Self {
+        Forwarded::default()
+    }
+
+    /// Create a hop for a request from `peer`.
+    ///
+    /// Only the IP address is used, since the port of a client is rarely
+    /// useful, and reveals more about it.
+    pub fn from_peer(peer: SocketAddr) -> Self {
+        Forwarded::new().with_for(node(peer.ip()))
+    }
+
+    /// Set the node the request came from, such as `192.0.2.60`,
+    /// `"[2001:db8::1]"`, or an obfuscated identifier like `_hidden`.
+    pub fn with_for(mut self, node: impl Into<String>) -> Self {
+        self.forwarded_for = Some(node.into());
+        self
+    }
+
+    /// Set the node of the proxy the request came in to.
+    pub fn with_by(mut self, node: impl Into<String>) -> Self {
+        self.by = Some(node.into());
+        self
+    }
+
+    /// Set the `Host` the request came in with.
+    pub fn with_host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Set the protocol the request came in with, such as `https`.
+    pub fn with_proto(mut self, proto: impl Into<String>) -> Self {
+        self.proto = Some(proto.into());
+        self
+    }
+
+    /// The node the request came from.
+    pub fn forwarded_for(&self) -> Option<&str> {
+        self.forwarded_for.as_deref()
+    }
+
+    /// The IP address the request came from, if it's not obfuscated.
+    pub fn for_ip(&self) -> Option<IpAddr> {
+        self.forwarded_for.as_deref().and_then(node_ip)
+    }
+
+    /// The node of the proxy the request came in to.
+    pub fn by(&self) -> Option<&str> {
+        self.by.as_deref()
+    }
+
+    /// The `Host` the request came in with.
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// The protocol the request came in with.
+    pub fn proto(&self) -> Option<&str> {
+        self.proto.as_deref()
+    }
+
+    /// Parse the hops a request went through, with the client first.
+    ///
+    /// These are the elements of the `Forwarded` headers, or, if there are
+    /// none, the addresses of the `X-Forwarded-For` headers. The
+    /// `X-Forwarded-Proto` and `X-Forwarded-Host` headers describe the
+    /// request of the client, so they are set on the first hop.
+    ///
+    /// Elements and parameters that can't be parsed are skipped, since
+    /// these headers are set by other parties, and are often malformed.
+    pub fn from_headers(headers: &HeaderMap) -> Vec<Forwarded> {
+        let hops = headers
+            .get_all(FORWARDED)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| split_quoted(value, ','))
+            .filter_map(parse_element)
+            .collect::<Vec<_>>();
+        if !hops.is_empty() {
+            return hops;
+        }
+
+        let mut hops = headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|node| !node.is_empty())
+            .map(|node| Forwarded::new().with_for(node))
+            .collect::<Vec<_>>();
+        let proto = first_value(headers, &X_FORWARDED_PROTO);
+        let host = first_value(headers, &X_FORWARDED_HOST);
+        if proto.is_some() || host.is_some() {
+            if hops.is_empty() {
+                hops.push(Forwarded::new());
+            }
+            hops[0].proto = proto.map(String::from);
+            hops[0].host = host.map(String::from);
+        }
+        hops
+    }
+
+    /// Append this hop to the `Forwarded` header of `headers`.
+    pub fn append_to(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&self.to_string()) {
+            headers.append(FORWARDED, value);
+        }
+    }
+
+    /// Append this hop to the `X-Forwarded-For` header of `headers`, and set
+    /// the `X-Forwarded-Proto` and `X-Forwarded-Host` headers, unless an
+    /// earlier proxy already did.
+    pub fn append_x_forwarded_to(&self, headers: &mut HeaderMap) {
+        if let Some(ref node) = self.forwarded_for {
+            // The legacy header has bare addresses, without the brackets
+            // and port of a node.
+            let addr = match node_ip(node) {
+                Some(ip) => ip.to_string(),
+                None => node.clone(),
+            };
+            let mut value = headers
+                .get_all(X_FORWARDED_FOR)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .collect::<Vec<_>>()
+                .join(", ");
+            if !value.is_empty() {
+                value.push_str(", ");
+            }
+            value.push_str(&addr);
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(X_FORWARDED_FOR, value);
+            }
+        }
+        for (name, value) in [
+            (X_FORWARDED_PROTO, &self.proto),
+            (X_FORWARDED_HOST, &self.host),
+        ] {
+            if let Some(value) = value.as_deref() {
+                if let Ok(value) = HeaderValue::from_str(value) {
+                    headers.entry(name).or_insert(value);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Forwarded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = [
+            ("for", &self.forwarded_for),
+            ("proto", &self.proto),
+            ("host", &self.host),
+            ("by", &self.by),
+        ];
+        let mut sep = "";
+        for (name, value) in params.iter() {
+            if let Some(value) = value {
+                f.write_str(sep)?;
+                write_param(f, name, value)?;
+                sep = ";";
+            }
+        }
+        Ok(())
+    }
+}
+
+// A node, in the form it takes in a `for` or `by` parameter.
+fn node(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("[{}]", ip),
+    }
+}
+
+// The IP address of a node, which may have a port, such as
+// `192.0.2.60:4711` or `[2001:db8::1]:4711`.
+fn node_ip(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    node.strip_prefix('[')
+        .and_then(|node| node.strip_suffix(']'))
+        .and_then(|ip| ip.parse().ok())
+}
+
+fn write_param(f: &mut fmt::Formatter<'_>, name: &str, value: &str) -> fmt::Result {
+    if !value.is_empty() && value.bytes().all(is_tchar) {
+        write!(f, "{}={}", name, value)
+    } else {
+        write!(f, "{}=\"", name)?;
+        for c in value.chars() {
+            if c == '"' || c == '\\' {
+                f.write_str("\\")?;
+            }
+            write!(f, "{}", c)?;
+        }
+        f.write_str("\"")
+    }
+}
+
+fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+// Split `s` at every `sep` that isn't inside a quoted string.
+fn split_quoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == sep && !quoted {
+            parts.push(&s[start..i]);
+            start = i + 1;
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn parse_element(element: &str) -> Option<Forwarded> {
+    let mut hop = Forwarded::new();
+    for pair in split_quoted(element, ';') {
+        let pair = pair.trim();
+        let eq = match pair.find('=') {
+            Some(eq) => eq,
+            None => continue,
+        };
+        let value = match unquote(pair[eq + 1..].trim()) {
+            Some(value) => value,
+            None => continue,
+        };
+        let name = pair[..eq].trim();
+        if name.eq_ignore_ascii_case("for") {
+            hop.forwarded_for = Some(value);
+        } else if name.eq_ignore_ascii_case("by") {
+            hop.by = Some(value);
+        } else if name.eq_ignore_ascii_case("host") {
+            hop.host = Some(value);
+        } else if name.eq_ignore_ascii_case("proto") {
+            hop.proto = Some(value);
+        }
+    }
+    if hop == Forwarded::default() {
+        None
+    } else {
+        Some(hop)
+    }
+}
+
+fn unquote(value: &str) -> Option<String> {
+    let inner = match value.strip_prefix('"') {
+        Some(inner) => inner.strip_suffix('"')?,
+        None => return Some(value.to_owned()).filter(|v| v.bytes().all(is_tchar)),
+    };
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            out.push(chars.next()?);
+        } else {
+            out.push(c);
+        }
+    }
+    Some(out)
+}
+
+fn first_value<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    let value = headers.get(name)?.to_str().ok()?;
+    let first = value.split(',').next()?.trim();
+    Some(first).filter(|v| !v.is_empty())
+}
+
+// ===== impl AddForwarded =====
+
+impl<S> AddForwarded<S> {
+    /// Wrap `inner`, adding a `Forwarded` header element to every request.
+    pub fn new(inner: S) -> Self {
+        AddForwarded {
+            inner,
+            peer: None,
+            by: None,
+            proto: "http",
+            x_forwarded: false,
+        }
+    }
+
+    /// Set the address of the peer the requests come from.
+    ///
+    /// Services are usually created per connection, so this is the address
+    /// the connection was accepted from.
+    pub fn with_peer_addr(mut self, peer: SocketAddr) -> Self {
+        self.peer = Some(peer);
+        self
+    }
+
+    /// Set the node of this proxy, added as the `by` parameter.
+    ///
+    /// Default is `None`.
+    pub fn by(mut self, node: impl Into<String>) -> Self {
+        self.by = Some(node.into());
+        self
+    }
+
+    /// Set the protocol of requests without a scheme in their URI, such as
+    /// `https` when the connections are TLS.
+    ///
+    /// Default is `http`.
+    pub fn proto(mut self, proto: &'static str) -> Self {
+        self.proto = proto;
+        self
+    }
+
+    /// Set whether the legacy `X-Forwarded-*` headers are added too.
+    ///
+    /// Default is `false`.
+    pub fn x_forwarded(mut self, enabled: bool) -> Self {
+        self.x_forwarded = enabled;
+        self
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn hop<B>(&self, req: &Request<B>) -> Forwarded {
+        let mut hop = Forwarded::new();
+        let peer = self
+            .peer
+            .or_else(|| req.extensions().get::<SocketAddr>().copied());
+        if let Some(peer) = peer {
+            hop = Forwarded::from_peer(peer);
+        }
+        hop.proto = Some(req.uri().scheme_str().unwrap_or(self.proto).to_owned());
+        hop.host = req
+            .headers()
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| req.uri().authority().map(|a| a.as_str()))
+            .map(String::from);
+        hop.by = self.by.clone();
+        hop
+    }
+}
+
+impl<S, B> hyper::service::Service<Request<B>> for AddForwarded<S>
+where
+    S: hyper::service::Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        let hop = self.hop(&req);
+        hop.append_to(req.headers_mut());
+        if self.x_forwarded {
+            hop.append_x_forwarded_to(req.headers_mut());
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+
+    use futures_util::FutureExt;
+    use http::{HeaderMap, HeaderValue, Request, Response};
+    use hyper::service::{service_fn, Service};
+
+    use super::{AddForwarded, Forwarded};
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn parses_forwarded() {
+        let hops = Forwarded::from_headers(&headers(&[
+            (
+                "forwarded",
+                "for=192.0.2.60;proto=http;by=203.0.113.43, For=\"[2001:db8:cafe::17]:4711\"",
+            ),
+            ("forwarded", "for=_hidden;host=\"a;b\", garbage"),
+            ("x-forwarded-for", "10.0.0.1"),
+        ]));
+        assert_eq!(hops.len(), 3);
+        assert_eq!(hops[0].for_ip(), Some([192, 0, 2, 60].into()));
+        assert_eq!(hops[0].proto(), Some("http"));
+        assert_eq!(hops[0].by(), Some("203.0.113.43"));
+        assert_eq!(hops[1].forwarded_for(), Some("[2001:db8:cafe::17]:4711"));
+        assert_eq!(hops[1].for_ip(), Some("2001:db8:cafe::17".parse().unwrap()));
+        assert_eq!(hops[2].forwarded_for(), Some("_hidden"));
+        assert_eq!(hops[2].for_ip(), None);
+        assert_eq!(hops[2].host(), Some("a;b"));
+    }
+
+    #[test]
+    fn parses_x_forwarded() {
+        let hops = Forwarded::from_headers(&headers(&[
+            ("x-forwarded-for", "192.0.2.60, 10.0.0.1"),
+            ("x-forwarded-for", "10.0.0.2"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "example.com"),
+        ]));
+        let nodes = hops
+            .iter()
+            .map(|hop| hop.forwarded_for().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(nodes, ["192.0.2.60", "10.0.0.1", "10.0.0.2"]);
+        assert_eq!(hops[0].proto(), Some("https"));
+        assert_eq!(hops[0].host(), Some("example.com"));
+        assert_eq!(hops[1].proto(), None);
+    }
+
+    #[test]
+    fn display_quotes_values() {
+        let hop = Forwarded::from_peer("[2001:db8::1]:4711".parse().unwrap())
+            .with_proto("https")
+            .with_by("proxy-1");
+        assert_eq!(
+            hop.to_string(),
+            "for=\"[2001:db8::1]\";proto=https;by=proxy-1"
+        );
+        let hop = Forwarded::new().with_host("a \"b\"");
+        assert_eq!(hop.to_string(), "host=\"a \\\"b\\\"\"");
+
+        let mut map = HeaderMap::new();
+        hop.append_to(&mut map);
+        assert_eq!(Forwarded::from_headers(&map), [hop]);
+    }
+
+    #[test]
+    fn adds_hop() {
+        let svc = service_fn(|req: Request<String>| async move {
+            let mut res = Response::new(String::new());
+            *res.headers_mut() = req.headers().clone();
+            Ok::<_, Infallible>(res)
+        });
+        let peer: SocketAddr = "192.0.2.60:4711".parse().unwrap();
+        let svc = AddForwarded::new(svc).by("proxy").x_forwarded(true);
+
+        let mut req = Request::builder()
+            .uri("/path")
+            .header("host", "example.com")
+            .header("forwarded", "for=198.51.100.1;proto=https")
+            .header("x-forwarded-for", "198.51.100.1")
+            .header("x-forwarded-for", "198.51.100.2")
+            .header("x-forwarded-proto", "https")
+            .body(String::new())
+            .unwrap();
+        req.extensions_mut().insert(peer);
+        let res = svc.call(req).now_or_never().unwrap().unwrap();
+        let headers = res.headers();
+        assert_eq!(headers.get_all("forwarded").iter().count(), 2);
+        assert_eq!(
+            headers["x-forwarded-for"],
+            "198.51.100.1, 198.51.100.2, 192.0.2.60"
+        );
+        assert_eq!(headers["x-forwarded-proto"], "https");
+        let hops = Forwarded::from_headers(headers);
+        assert_eq!(hops.len(), 2);
+        assert_eq!(
+            hops[1],
+            Forwarded::new()
+                .with_for("192.0.2.60")
+                .with_proto("http")
+                .with_host("example.com")
+                .with_by("proxy")
+        );
+    }
+}
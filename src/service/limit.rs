@@ -0,0 +1,354 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use http::{header, HeaderValue, Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+
+/// A service that limits how many requests the inner service handles at
+/// once.
+///
+/// Requests beyond the limit wait for one of the others to finish, in the
+/// order they arrived. Once the queue of waiting requests is full too, the
+/// requests are responded to with `503 Service Unavailable`, and a
+/// `Retry-After` header.
+///
+/// A request counts against the limit until the inner service has returned
+/// its response, but not while the response body is streamed.
+#[derive(Debug)]
+pub struct ConcurrencyLimit<S> {
+    inner: Arc<S>,
+    limiter: Arc<Limiter>,
+    retry_after: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Limiter {
+    max: usize,
+    max_queue: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    in_use: usize,
+    queue: VecDeque<(u64, Waker)>,
+    next_ticket: u64,
+}
+
+// A place in the queue, given up when dropped.
+struct Waiting {
+    limiter: Arc<Limiter>,
+    ticket: u64,
+}
+
+// A request being handled, released when dropped.
+struct Permit {
+    limiter: Arc<Limiter>,
+}
+
+pin_project! {
+    /// Response future for [`ConcurrencyLimit`].
+    pub struct ConcurrencyLimitFuture<S, B>
+    where
+        S: hyper::service::Service<Request<B>>,
+    {
+        #[pin]
+        state: LimitState<S, B>,
+    }
+}
+
+pin_project! {
+    #[project = LimitStateProj]
+    enum LimitState<S, B>
+    where
+        S: hyper::service::Service<Request<B>>,
+    {
+        Acquiring {
+            service: Arc<S>,
+            limiter: Arc<Limiter>,
+            retry_after: Option<Duration>,
+            req: Option<Request<B>>,
+            waiting: Option<Waiting>,
+        },
+        Called {
+            #[pin]
+            future: S::Future,
+            permit: Permit,
+        },
+    }
+}
+
+// ===== impl ConcurrencyLimit =====
+
+impl<S> ConcurrencyLimit<S> {
+    /// Limit `inner` to `max` requests at once, without a queue.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn new(inner: S, max: usize) -> Self {
+        assert!(max > 0, "concurrency limit must be positive");
+        ConcurrencyLimit {
+            inner: Arc::new(inner),
+            limiter: Arc::new(Limiter {
+                max,
+                max_queue: 0,
+                state: Mutex::new(State {
+                    in_use: 0,
+                    queue: VecDeque::new(),
+                    next_ticket: 0,
+                }),
+            }),
+            retry_after: Some(Duration::from_secs(1)),
+        }
+    }
+
+    /// Let up to `max_queue` requests wait for the others to finish.
+    ///
+    /// Default is `0`.
+    pub fn queue(mut self, max_queue: usize) -> Self {
+        Arc::get_mut(&mut self.limiter)
+            .expect("configured before the service is cloned")
+            .max_queue = max_queue;
+        self
+    }
+
+    /// Set the `Retry-After` of `503` responses, or `None` to leave it out.
+    ///
+    /// It's rounded up to whole seconds.
+    ///
+    /// Default is 1 second.
+    pub fn retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S> Clone for ConcurrencyLimit<S> {
+    fn clone(&self) -> Self {
+        ConcurrencyLimit {
+            inner: self.inner.clone(),
+            limiter: self.limiter.clone(),
+            retry_after: self.retry_after,
+        }
+    }
+}
+
+impl<S, B, R> hyper::service::Service<Request<B>> for ConcurrencyLimit<S>
+where
+    S: hyper::service::Service<Request<B>, Response = Response<R>>,
+    R: Default,
+{
+    type Response = Response<R>;
+    type Error = S::Error;
+    type Future = ConcurrencyLimitFuture<S, B>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        ConcurrencyLimitFuture {
+            state: LimitState::Acquiring {
+                service: self.inner.clone(),
+                limiter: self.limiter.clone(),
+                retry_after: self.retry_after,
+                req: Some(req),
+                waiting: None,
+            },
+        }
+    }
+}
+
+impl<S, B, R> Future for ConcurrencyLimitFuture<S, B>
+where
+    S: hyper::service::Service<Request<B>, Response = Response<R>>,
+    R: Default,
+{
+    type Output = Result<Response<R>, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.project().state;
+        loop {
+            match state.as_mut().project() {
+                LimitStateProj::Acquiring {
+                    service,
+                    limiter,
+                    retry_after,
+                    req,
+                    waiting,
+                } => {
+                    let permit = match limiter.poll_acquire(waiting, cx) {
+                        Poll::Ready(Some(permit)) => permit,
+                        Poll::Ready(None) => return Poll::Ready(Ok(unavailable(*retry_after))),
+                        Poll::Pending => return Poll::Pending,
+                    };
+                    let req = req.take().expect("polled after complete");
+                    let future = service.call(req);
+                    state.set(LimitState::Called { future, permit });
+                }
+                LimitStateProj::Called { future, .. } => return future.poll(cx),
+            }
+        }
+    }
+}
+
+fn unavailable<R: Default>(retry_after: Option<Duration>) -> Response<R> {
+    let mut res = Response::new(R::default());
+    *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    if let Some(retry_after) = retry_after {
+        let mut secs = retry_after.as_secs();
+        if retry_after.subsec_nanos() > 0 {
+            secs += 1;
+        }
+        res.headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+    }
+    res
+}
+
+// ===== impl Limiter =====
+
+impl Limiter {
+    // Resolves to `None` if the request is rejected.
+    fn poll_acquire(
+        self: &Arc<Self>,
+        waiting: &mut Option<Waiting>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Permit>> {
+        let mut state = self.state.lock().unwrap();
+        let first = state.queue.front().map(|&(ticket, _)| ticket);
+        let is_next = match waiting {
+            Some(ref waiting) => first == Some(waiting.ticket),
+            None => first.is_none(),
+        };
+        if state.in_use < self.max && is_next {
+            if let Some(waiting) = waiting.take() {
+                state.queue.pop_front();
+                // Already out of the queue.
+                std::mem::forget(waiting);
+            }
+            state.in_use += 1;
+            state.wake_next(self.max);
+            return Poll::Ready(Some(Permit {
+                limiter: self.clone(),
+            }));
+        }
+
+        match waiting {
+            Some(ref waiting) => {
+                if let Some(entry) = state.queue.iter_mut().find(|(t, _)| *t == waiting.ticket) {
+                    if !entry.1.will_wake(cx.waker()) {
+                        entry.1 = cx.waker().clone();
+                    }
+                }
+            }
+            None => {
+                if state.queue.len() >= self.max_queue {
+                    return Poll::Ready(None);
+                }
+                let ticket = state.next_ticket;
+                state.next_ticket += 1;
+                state.queue.push_back((ticket, cx.waker().clone()));
+                *waiting = Some(Waiting {
+                    limiter: self.clone(),
+                    ticket,
+                });
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl State {
+    fn wake_next(&self, max: usize) {
+        if self.in_use < max {
+            if let Some((_, waker)) = self.queue.front() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.limiter.state.lock() {
+            let ticket = self.ticket;
+            state.queue.retain(|&(t, _)| t != ticket);
+            state.wake_next(self.limiter.max);
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.limiter.state.lock() {
+            state.in_use -= 1;
+            state.wake_next(self.limiter.max);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures_channel::oneshot;
+    use futures_util::FutureExt;
+    use http::{header, Request, Response, StatusCode};
+    use hyper::service::Service;
+
+    use super::ConcurrencyLimit;
+
+    // Responds once the request's body, a channel, is sent to.
+    struct Pending;
+
+    impl Service<Request<oneshot::Receiver<()>>> for Pending {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = std::pin::Pin<
+            Box<dyn std::future::Future<Output = Result<Response<String>, Infallible>> + Send>,
+        >;
+
+        fn call(&self, req: Request<oneshot::Receiver<()>>) -> Self::Future {
+            Box::pin(async move {
+                let _ = req.into_body().await;
+                Ok(Response::new(String::from("done")))
+            })
+        }
+    }
+
+    fn req() -> (oneshot::Sender<()>, Request<oneshot::Receiver<()>>) {
+        let (tx, rx) = oneshot::channel();
+        (tx, Request::new(rx))
+    }
+
+    #[test]
+    fn queues_then_rejects() {
+        let svc = ConcurrencyLimit::new(Pending, 1).queue(1);
+
+        let (tx1, req1) = req();
+        let mut first = svc.call(req1);
+        assert!((&mut first).now_or_never().is_none());
+
+        let (tx2, req2) = req();
+        let mut second = svc.call(req2);
+        assert!((&mut second).now_or_never().is_none());
+
+        let (_tx3, req3) = req();
+        let res = svc.call(req3).now_or_never().unwrap().unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+
+        tx1.send(()).unwrap();
+        assert_eq!(first.now_or_never().unwrap().unwrap().into_body(), "done");
+
+        tx2.send(()).unwrap();
+        assert_eq!(second.now_or_never().unwrap().unwrap().into_body(), "done");
+    }
+}
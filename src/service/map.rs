@@ -0,0 +1,238 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::ready;
+use pin_project_lite::pin_project;
+
+/// A service that maps each request before calling the inner service.
+///
+/// For instance, to insert a header into every request.
+#[derive(Debug, Clone)]
+pub struct MapRequest<S, F> {
+    inner: S,
+    f: F,
+}
+
+/// A service that maps each successful response of the inner service.
+///
+/// For instance, to insert a header into every response.
+#[derive(Debug, Clone)]
+pub struct MapResponse<S, F> {
+    inner: S,
+    f: F,
+}
+
+/// A service that maps each error of the inner service.
+#[derive(Debug, Clone)]
+pub struct MapErr<S, F> {
+    inner: S,
+    f: F,
+}
+
+pin_project! {
+    /// Response future for [`MapResponse`].
+    #[derive(Debug)]
+    pub struct MapResponseFuture<Fut, F> {
+        #[pin]
+        future: Fut,
+        f: Option<F>,
+    }
+}
+
+pin_project! {
+    /// Response future for [`MapErr`].
+    #[derive(Debug)]
+    pub struct MapErrFuture<Fut, F> {
+        #[pin]
+        future: Fut,
+        f: Option<F>,
+    }
+}
+
+// ===== impl MapRequest =====
+
+impl<S, F> MapRequest<S, F> {
+    /// Wrap `inner`, mapping each request with `f`.
+    pub fn new(inner: S, f: F) -> Self {
+        MapRequest { inner, f }
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume this wrapper and get the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, R1, R2> hyper::service::Service<R1> for MapRequest<S, F>
+where
+    F: Fn(R1) -> R2,
+    S: hyper::service::Service<R2>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, req: R1) -> Self::Future {
+        self.inner.call((self.f)(req))
+    }
+}
+
+// ===== impl MapResponse =====
+
+impl<S, F> MapResponse<S, F> {
+    /// Wrap `inner`, mapping each response with `f`.
+    pub fn new(inner: S, f: F) -> Self {
+        MapResponse { inner, f }
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume this wrapper and get the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, R, T> hyper::service::Service<R> for MapResponse<S, F>
+where
+    S: hyper::service::Service<R>,
+    F: FnOnce(S::Response) -> T + Clone,
+{
+    type Response = T;
+    type Error = S::Error;
+    type Future = MapResponseFuture<S::Future, F>;
+
+    fn call(&self, req: R) -> Self::Future {
+        MapResponseFuture {
+            future: self.inner.call(req),
+            f: Some(self.f.clone()),
+        }
+    }
+}
+
+impl<Fut, F, T, U, E> Future for MapResponseFuture<Fut, F>
+where
+    Fut: Future<Output = Result<T, E>>,
+    F: FnOnce(T) -> U,
+{
+    type Output = Result<U, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.future.poll(cx));
+        let f = this.f.take().expect("polled after complete");
+        Poll::Ready(res.map(f))
+    }
+}
+
+// ===== impl MapErr =====
+
+impl<S, F> MapErr<S, F> {
+    /// Wrap `inner`, mapping each error with `f`.
+    pub fn new(inner: S, f: F) -> Self {
+        MapErr { inner, f }
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume this wrapper and get the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, R, E> hyper::service::Service<R> for MapErr<S, F>
+where
+    S: hyper::service::Service<R>,
+    F: FnOnce(S::Error) -> E + Clone,
+{
+    type Response = S::Response;
+    type Error = E;
+    type Future = MapErrFuture<S::Future, F>;
+
+    fn call(&self, req: R) -> Self::Future {
+        MapErrFuture {
+            future: self.inner.call(req),
+            f: Some(self.f.clone()),
+        }
+    }
+}
+
+impl<Fut, F, T, E, E2> Future for MapErrFuture<Fut, F>
+where
+    Fut: Future<Output = Result<T, E>>,
+    F: FnOnce(E) -> E2,
+{
+    type Output = Result<T, E2>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.future.poll(cx));
+        let f = this.f.take().expect("polled after complete");
+        Poll::Ready(res.map_err(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::{self, FutureExt};
+    use http::{HeaderValue, Request, Response};
+    use hyper::service::Service;
+
+    use super::{MapErr, MapRequest, MapResponse};
+
+    // Echoes the `x-id` header of the request, or fails without one.
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = Response<String>;
+        type Error = &'static str;
+        type Future = future::Ready<Result<Response<String>, &'static str>>;
+
+        fn call(&self, req: Request<()>) -> Self::Future {
+            future::ready(
+                req.headers()
+                    .get("x-id")
+                    .map(|id| Response::new(id.to_str().unwrap().to_string()))
+                    .ok_or("no id"),
+            )
+        }
+    }
+
+    #[test]
+    fn map_request_and_response() {
+        let svc = MapRequest::new(Echo, |mut req: Request<()>| {
+            req.headers_mut()
+                .insert("x-id", HeaderValue::from_static("7"));
+            req
+        });
+        let svc = MapResponse::new(svc, |res: Response<String>| res.map(|id| id + "!"));
+
+        let res = svc.call(Request::new(())).now_or_never().unwrap().unwrap();
+        assert_eq!(res.into_body(), "7!");
+    }
+
+    #[test]
+    fn map_err() {
+        let svc = MapErr::new(Echo, |err: &'static str| err.len());
+
+        let err = svc
+            .call(Request::new(()))
+            .now_or_never()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err, 5);
+    }
+}
@@ -0,0 +1,320 @@
+//! Service utilities.
+
+use pin_project_lite::pin_project;
+use std::{
+    error::Error as StdError,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tower::{util::Oneshot, ServiceExt};
+
+mod access_log;
+mod either;
+mod extension;
+mod forwarded;
+mod limit;
+mod map;
+mod router;
+mod slow;
+mod state;
+mod timeout;
+mod trace_context;
+
+pub use self::access_log::{AccessLog, AccessLogBody, AccessLogFuture, AccessLogRecord};
+pub use self::either::{Either, EitherFuture};
+pub use self::extension::InjectExtension;
+pub use self::forwarded::{AddForwarded, Forwarded};
+pub use self::limit::{ConcurrencyLimit, ConcurrencyLimitFuture};
+pub use self::map::{MapErr, MapErrFuture, MapRequest, MapResponse, MapResponseFuture};
+pub use self::router::Router;
+pub use self::slow::{SlowPhase, SlowRequest, SlowRequestBody, SlowRequestFuture, SlowRequests};
+pub use self::state::{service_fn_with_state, ServiceFnWithState};
+pub use self::timeout::{Elapsed, Timeout, TimeoutFuture, TimeoutResponseBody};
+pub use self::trace_context::{ExtractTraceContext, PropagateTraceContext, TraceContext};
+
+/// A tower service converted into a hyper service.
+#[derive(Debug, Copy, Clone)]
+pub struct TowerToHyperService<S> {
+    service: S,
+}
+
+impl<S> TowerToHyperService<S> {
+    /// Create a new `TowerToHyperService` from a tower service.
+    pub fn new(tower_service: S) -> Self {
+        Self {
+            service: tower_service,
+        }
+    }
+}
+
+impl<S, R> hyper::service::Service<R> for TowerToHyperService<S>
+where
+    S: tower_service::Service<R> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = TowerToHyperServiceFuture<S, R>;
+
+    fn call(&self, req: R) -> Self::Future {
+        TowerToHyperServiceFuture {
+            future: self.service.clone().oneshot(req),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`TowerToHyperService`].
+    pub struct TowerToHyperServiceFuture<S, R>
+    where
+        S: tower_service::Service<R>,
+    {
+        #[pin]
+        future: Oneshot<S, R>,
+    }
+}
+
+impl<S, R> Future for TowerToHyperServiceFuture<S, R>
+where
+    S: tower_service::Service<R>,
+{
+    type Output = Result<S::Response, S::Error>;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().future.poll(cx)
+    }
+}
+
+/// What a [`ReadyTowerToHyperService`] does with a request while the tower
+/// service isn't ready.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReadinessPolicy {
+    /// Wait for the service to become ready, with up to this many requests
+    /// waiting at once. Requests beyond that are shed.
+    Buffer(usize),
+    /// Fail the request right away.
+    Shed,
+}
+
+/// A tower service converted into a hyper service, that respects the
+/// readiness of the tower service.
+///
+/// Unlike [`TowerToHyperService`], which waits for each request until the
+/// service is ready, requests are only waited for according to a
+/// [`ReadinessPolicy`]. Requests that aren't waited for fail with an
+/// [`Overloaded`] error, so that middleware such as tower's `ConcurrencyLimit`
+/// sheds load instead of queueing it without bound.
+#[derive(Debug, Clone)]
+pub struct ReadyTowerToHyperService<S> {
+    service: S,
+    policy: ReadinessPolicy,
+    waiting: Arc<AtomicUsize>,
+}
+
+/// Error returned by a [`ReadyTowerToHyperService`] when a request was shed,
+/// because the service wasn't ready.
+#[derive(Debug)]
+pub struct Overloaded(());
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+impl<S> ReadyTowerToHyperService<S> {
+    /// Create a new `ReadyTowerToHyperService` from a tower service.
+    pub fn new(tower_service: S, policy: ReadinessPolicy) -> Self {
+        Self {
+            service: tower_service,
+            policy,
+            waiting: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl<S, R> hyper::service::Service<R> for ReadyTowerToHyperService<S>
+where
+    S: tower_service::Service<R> + Clone,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = ReadyTowerToHyperServiceFuture<S, R>;
+
+    fn call(&self, req: R) -> Self::Future {
+        ReadyTowerToHyperServiceFuture {
+            state: ReadyState::NotReady {
+                service: self.service.clone(),
+                req: Some(req),
+                policy: self.policy,
+                waiting: self.waiting.clone(),
+                guard: None,
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future for [`ReadyTowerToHyperService`].
+    pub struct ReadyTowerToHyperServiceFuture<S, R>
+    where
+        S: tower_service::Service<R>,
+    {
+        #[pin]
+        state: ReadyState<S, R>,
+    }
+}
+
+pin_project! {
+    #[project = ReadyStateProj]
+    enum ReadyState<S, R>
+    where
+        S: tower_service::Service<R>,
+    {
+        NotReady {
+            service: S,
+            req: Option<R>,
+            policy: ReadinessPolicy,
+            waiting: Arc<AtomicUsize>,
+            guard: Option<WaitingGuard>,
+        },
+        Called {
+            #[pin]
+            future: S::Future,
+        },
+    }
+}
+
+// Counts a request waiting for readiness, until dropped.
+struct WaitingGuard(Arc<AtomicUsize>);
+
+impl<S, R> Future for ReadyTowerToHyperServiceFuture<S, R>
+where
+    S: tower_service::Service<R>,
+    S::Error: Into<BoxError>,
+{
+    type Output = Result<S::Response, BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.project().state;
+        loop {
+            match state.as_mut().project() {
+                ReadyStateProj::NotReady {
+                    service,
+                    req,
+                    policy,
+                    waiting,
+                    guard,
+                } => {
+                    match service.poll_ready(cx) {
+                        Poll::Ready(Ok(())) => {}
+                        Poll::Ready(Err(err)) => return Poll::Ready(Err(err.into())),
+                        Poll::Pending => {
+                            if guard.is_none() {
+                                let max = match *policy {
+                                    ReadinessPolicy::Buffer(max) => max,
+                                    ReadinessPolicy::Shed => 0,
+                                };
+                                if waiting.fetch_add(1, Ordering::AcqRel) >= max {
+                                    waiting.fetch_sub(1, Ordering::AcqRel);
+                                    return Poll::Ready(Err(Overloaded(()).into()));
+                                }
+                                *guard = Some(WaitingGuard(waiting.clone()));
+                            }
+                            return Poll::Pending;
+                        }
+                    }
+                    let req = req.take().expect("polled after complete");
+                    let future = service.call(req);
+                    state.set(ReadyState::Called { future });
+                }
+                ReadyStateProj::Called { future } => {
+                    return future.poll(cx).map_err(Into::into);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for WaitingGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl fmt::Display for Overloaded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("service overloaded")
+    }
+}
+
+impl StdError for Overloaded {}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    use futures_util::future::{self, FutureExt};
+    use hyper::service::Service;
+
+    use super::{Overloaded, ReadinessPolicy, ReadyTowerToHyperService};
+
+    #[derive(Clone)]
+    struct Gate(Arc<AtomicBool>);
+
+    impl tower_service::Service<()> for Gate {
+        type Response = ();
+        type Error = Infallible;
+        type Future = future::Ready<Result<(), Infallible>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            if self.0.load(Ordering::SeqCst) {
+                Poll::Ready(Ok(()))
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+
+        fn call(&mut self, _: ()) -> Self::Future {
+            future::ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn sheds_when_not_ready() {
+        let open = Arc::new(AtomicBool::new(false));
+        let svc = ReadyTowerToHyperService::new(Gate(open.clone()), ReadinessPolicy::Shed);
+
+        let err = svc.call(()).now_or_never().unwrap().unwrap_err();
+        assert!(err.is::<Overloaded>());
+
+        open.store(true, Ordering::SeqCst);
+        assert!(svc.call(()).now_or_never().unwrap().is_ok());
+    }
+
+    #[test]
+    fn buffers_up_to_limit() {
+        let open = Arc::new(AtomicBool::new(false));
+        let svc = ReadyTowerToHyperService::new(Gate(open.clone()), ReadinessPolicy::Buffer(1));
+
+        let mut first = svc.call(());
+        assert!((&mut first).now_or_never().is_none());
+        let err = svc.call(()).now_or_never().unwrap().unwrap_err();
+        assert!(err.is::<Overloaded>());
+
+        open.store(true, Ordering::SeqCst);
+        assert!(first.now_or_never().unwrap().is_ok());
+
+        // the waiting request left the buffer
+        open.store(false, Ordering::SeqCst);
+        let mut second = svc.call(());
+        assert!((&mut second).now_or_never().is_none());
+    }
+}
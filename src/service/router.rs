@@ -0,0 +1,313 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures_util::future::{self, TryFutureExt};
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+
+use super::BoxError;
+
+type BoxFuture<R> = Pin<Box<dyn Future<Output = Result<Response<R>, BoxError>> + Send>>;
+type BoxService<B, R> = Box<dyn Fn(Request<B>) -> BoxFuture<R> + Send + Sync>;
+
+/// A minimal router, dispatching requests to services by path and method.
+///
+/// Routes are checked in the order they were added, and the first one that
+/// matches handles the request. If none does, the request goes to the
+/// fallback, which by default responds with `404 Not Found`. If a route
+/// matches the path, but not the method, the response is
+/// `405 Method Not Allowed` instead.
+///
+/// This is meant for serving a handful of endpoints, such as health checks
+/// and metrics, not to replace a web framework.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "server")]
+/// # fn run() {
+/// use std::convert::Infallible;
+/// use http::{Method, Request, Response};
+/// use http_body_util::Full;
+/// use hyper::body::{Bytes, Incoming};
+/// use hyper::service::service_fn;
+/// use hyper_util::service::Router;
+///
+/// let health = service_fn(|_req: Request<Incoming>| async {
+///     Ok::<_, Infallible>(Response::new(Full::new(Bytes::from("ok"))))
+/// });
+/// let api = service_fn(|_req: Request<Incoming>| async {
+///     Ok::<_, Infallible>(Response::new(Full::new(Bytes::from("api"))))
+/// });
+///
+/// let router = Router::new()
+///     .route(Method::GET, "/health", health)
+///     .prefix("/api/", api);
+/// # let _ = router;
+/// # }
+/// # fn main() {}
+/// ```
+pub struct Router<B, R> {
+    inner: Arc<Inner<B, R>>,
+}
+
+struct Inner<B, R> {
+    routes: Vec<Route<B, R>>,
+    fallback: Option<BoxService<B, R>>,
+}
+
+struct Route<B, R> {
+    method: Option<Method>,
+    path: &'static str,
+    prefix: bool,
+    service: BoxService<B, R>,
+}
+
+impl<B, R> Router<B, R>
+where
+    B: 'static,
+    R: 'static,
+{
+    /// Create a router without any routes.
+    pub fn new() -> Self {
+        Router {
+            inner: Arc::new(Inner {
+                routes: Vec::new(),
+                fallback: None,
+            }),
+        }
+    }
+
+    /// Route requests with `method` to exactly `path` to `service`.
+    pub fn route<S>(self, method: Method, path: &'static str, service: S) -> Self
+    where
+        S: hyper::service::Service<Request<B>, Response = Response<R>> + Send + Sync + 'static,
+        S::Future: Send + 'static,
+        S::Error: Into<BoxError> + 'static,
+    {
+        self.push(Some(method), path, false, service)
+    }
+
+    /// Route requests with any method to exactly `path` to `service`.
+    pub fn exact<S>(self, path: &'static str, service: S) -> Self
+    where
+        S: hyper::service::Service<Request<B>, Response = Response<R>> + Send + Sync + 'static,
+        S::Future: Send + 'static,
+        S::Error: Into<BoxError> + 'static,
+    {
+        self.push(None, path, false, service)
+    }
+
+    /// Route requests with any method to a path starting with `prefix` to
+    /// `service`.
+    pub fn prefix<S>(self, prefix: &'static str, service: S) -> Self
+    where
+        S: hyper::service::Service<Request<B>, Response = Response<R>> + Send + Sync + 'static,
+        S::Future: Send + 'static,
+        S::Error: Into<BoxError> + 'static,
+    {
+        self.push(None, prefix, true, service)
+    }
+
+    /// Send requests that no route matches to `service`.
+    pub fn fallback<S>(mut self, service: S) -> Self
+    where
+        S: hyper::service::Service<Request<B>, Response = Response<R>> + Send + Sync + 'static,
+        S::Future: Send + 'static,
+        S::Error: Into<BoxError> + 'static,
+    {
+        self.inner_mut().fallback = Some(boxed(service));
+        self
+    }
+
+    fn push<S>(
+        mut self,
+        method: Option<Method>,
+        path: &'static str,
+        prefix: bool,
+        service: S,
+    ) -> Self
+    where
+        S: hyper::service::Service<Request<B>, Response = Response<R>> + Send + Sync + 'static,
+        S::Future: Send + 'static,
+        S::Error: Into<BoxError> + 'static,
+    {
+        self.inner_mut().routes.push(Route {
+            method,
+            path,
+            prefix,
+            service: boxed(service),
+        });
+        self
+    }
+
+    fn inner_mut(&mut self) -> &mut Inner<B, R> {
+        Arc::get_mut(&mut self.inner).expect("routes are added before the router is cloned")
+    }
+}
+
+fn boxed<S, B, R>(service: S) -> BoxService<B, R>
+where
+    S: hyper::service::Service<Request<B>, Response = Response<R>> + Send + Sync + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError> + 'static,
+{
+    Box::new(move |req| Box::pin(service.call(req).map_err(Into::into)))
+}
+
+impl<B, R> Default for Router<B, R>
+where
+    B: 'static,
+    R: 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B, R> Clone for Router<B, R> {
+    fn clone(&self) -> Self {
+        Router {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<B, R> fmt::Debug for Router<B, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("routes", &self.inner.routes.len())
+            .finish()
+    }
+}
+
+impl<B, R> hyper::service::Service<Request<B>> for Router<B, R>
+where
+    R: Default + Send + 'static,
+{
+    type Response = Response<R>;
+    type Error = BoxError;
+    type Future = BoxFuture<R>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        let mut path_matched = false;
+        for route in &self.inner.routes {
+            let path = req.uri().path();
+            let matches = if route.prefix {
+                path.starts_with(route.path)
+            } else {
+                path == route.path
+            };
+            if !matches {
+                continue;
+            }
+            match route.method {
+                Some(ref method) if method != req.method() => path_matched = true,
+                _ => return (route.service)(req),
+            }
+        }
+
+        if path_matched {
+            let allow = self
+                .inner
+                .routes
+                .iter()
+                .filter(|route| route.path == req.uri().path())
+                .filter_map(|route| route.method.as_ref())
+                .map(Method::as_str)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let mut res = status(StatusCode::METHOD_NOT_ALLOWED);
+            if let Ok(allow) = HeaderValue::from_str(&allow) {
+                res.headers_mut().insert(header::ALLOW, allow);
+            }
+            return Box::pin(future::ok(res));
+        }
+
+        match self.inner.fallback {
+            Some(ref fallback) => fallback(req),
+            None => Box::pin(future::ok(status(StatusCode::NOT_FOUND))),
+        }
+    }
+}
+
+fn status<R: Default>(status: StatusCode) -> Response<R> {
+    let mut res = Response::new(R::default());
+    *res.status_mut() = status;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures_util::future::{self, FutureExt};
+    use http::{header, Method, Request, Response, StatusCode};
+    use hyper::service::Service;
+
+    use super::Router;
+
+    struct Text(&'static str);
+
+    impl Service<Request<String>> for Text {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = future::Ready<Result<Response<String>, Infallible>>;
+
+        fn call(&self, _req: Request<String>) -> Self::Future {
+            future::ok(Response::new(self.0.to_string()))
+        }
+    }
+
+    fn text(body: &'static str) -> Text {
+        Text(body)
+    }
+
+    fn get(router: &Router<String, String>, method: Method, path: &str) -> Response<String> {
+        let req = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(String::new())
+            .unwrap();
+        router.call(req).now_or_never().unwrap().unwrap()
+    }
+
+    #[test]
+    fn routes_by_path_and_method() {
+        let router = Router::new()
+            .route(Method::GET, "/health", text("health"))
+            .exact("/metrics", text("metrics"))
+            .prefix("/api/", text("api"));
+
+        assert_eq!(get(&router, Method::GET, "/health").into_body(), "health");
+        assert_eq!(
+            get(&router, Method::POST, "/metrics").into_body(),
+            "metrics"
+        );
+        assert_eq!(get(&router, Method::PUT, "/api/users/1").into_body(), "api");
+
+        let res = get(&router, Method::POST, "/health");
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[header::ALLOW], "GET");
+
+        assert_eq!(
+            get(&router, Method::GET, "/nope").status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get(&router, Method::GET, "/api").status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn fallback() {
+        let router = Router::new()
+            .exact("/", text("index"))
+            .fallback(text("fallback"));
+
+        assert_eq!(get(&router, Method::GET, "/").into_body(), "index");
+        assert_eq!(get(&router, Method::GET, "/other").into_body(), "fallback");
+    }
+}
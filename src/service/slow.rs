@@ -0,0 +1,479 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use http::{Method, Request, Response, Uri};
+use http_body::{Body, Frame, SizeHint};
+use hyper::rt::{Sleep, Timer as _};
+use pin_project_lite::pin_project;
+
+use crate::common::timer::Timer;
+
+/// A service that reports requests still in flight after a threshold.
+///
+/// Each slow request is reported once, as a [`SlowRequest`], with the
+/// [phase](SlowPhase) it was in: reading the request body, waiting for the
+/// handler, or writing the response body. By default, it's reported as a
+/// `tracing` event at the `WARN` level, with the `hyper_util::slow_request`
+/// target, or it can be passed to a callback instead.
+///
+/// To know when the request body is being read, the inner service is
+/// called with the body wrapped in a [`SlowRequestBody`]. While writing the
+/// response body, a slow request is reported the next time hyper polls the
+/// body, which it doesn't while the peer isn't reading.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(all(feature = "server", feature = "tokio"))]
+/// # fn run() {
+/// use std::convert::Infallible;
+/// use std::time::Duration;
+/// use http::{Request, Response};
+/// use hyper::service::service_fn;
+/// use hyper_util::rt::TokioTimer;
+/// use hyper_util::service::{SlowRequestBody, SlowRequests};
+///
+/// let svc = service_fn(|_req: Request<SlowRequestBody<hyper::body::Incoming>>| async move {
+///     Ok::<_, Infallible>(Response::new(String::from("hello")))
+/// });
+/// let svc = SlowRequests::new(svc, TokioTimer::new(), Duration::from_secs(10))
+///     .on_slow(|slow| eprintln!("slow request: {}", slow));
+/// # let _ = svc;
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct SlowRequests<S> {
+    inner: S,
+    timer: Timer,
+    threshold: Duration,
+    report: Report,
+}
+
+/// A request reported by [`SlowRequests`].
+#[derive(Clone, Debug)]
+pub struct SlowRequest {
+    method: Method,
+    uri: Uri,
+    elapsed: Duration,
+    phase: SlowPhase,
+}
+
+/// What a [`SlowRequest`] was waiting for when it was reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlowPhase {
+    /// The handler was waiting for the request body.
+    RequestBody,
+    /// The handler hadn't responded yet.
+    Handler,
+    /// The response body was being written.
+    ResponseBody,
+}
+
+#[derive(Clone)]
+enum Report {
+    Tracing,
+    Callback(Arc<dyn Fn(&SlowRequest) + Send + Sync>),
+}
+
+// Shared by the future and bodies of one request.
+struct Watch {
+    method: Method,
+    uri: Uri,
+    started: Instant,
+    report: Report,
+    reading: AtomicBool,
+    reported: AtomicBool,
+}
+
+pin_project! {
+    /// Response future for [`SlowRequests`].
+    pub struct SlowRequestFuture<F> {
+        #[pin]
+        inner: F,
+        watch: Arc<Watch>,
+        sleep: Option<Pin<Box<dyn Sleep>>>,
+    }
+}
+
+pin_project! {
+    /// A request or response body watched by [`SlowRequests`].
+    pub struct SlowRequestBody<B> {
+        #[pin]
+        inner: B,
+        watch: Arc<Watch>,
+        response: bool,
+        // Only the response body holds the sleep, if not yet fired.
+        sleep: Option<Pin<Box<dyn Sleep>>>,
+    }
+}
+
+// ===== impl SlowRequests =====
+
+impl<S> SlowRequests<S> {
+    /// Wrap `inner`, reporting requests in flight longer than `threshold`,
+    /// waiting on `timer`.
+    pub fn new<M>(inner: S, timer: M, threshold: Duration) -> Self
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        SlowRequests {
+            inner,
+            timer: Timer::new(timer),
+            threshold,
+            report: Report::Tracing,
+        }
+    }
+
+    /// Pass slow requests to `callback`, instead of emitting `tracing`
+    /// events.
+    pub fn on_slow<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&SlowRequest) + Send + Sync + 'static,
+    {
+        self.report = Report::Callback(Arc::new(callback));
+        self
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for SlowRequests<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowRequests")
+            .field("inner", &self.inner)
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+impl<S, B, R> hyper::service::Service<Request<B>> for SlowRequests<S>
+where
+    S: hyper::service::Service<Request<SlowRequestBody<B>>, Response = Response<R>>,
+{
+    type Response = Response<SlowRequestBody<R>>;
+    type Error = S::Error;
+    type Future = SlowRequestFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        let watch = Arc::new(Watch {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            started: Instant::now(),
+            report: self.report.clone(),
+            reading: AtomicBool::new(false),
+            reported: AtomicBool::new(false),
+        });
+        let req = req.map(|inner| SlowRequestBody {
+            inner,
+            watch: watch.clone(),
+            response: false,
+            sleep: None,
+        });
+        SlowRequestFuture {
+            inner: self.inner.call(req),
+            watch,
+            sleep: Some(self.timer.sleep(self.threshold)),
+        }
+    }
+}
+
+// ===== impl SlowRequestFuture =====
+
+impl<F> fmt::Debug for SlowRequestFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowRequestFuture").finish()
+    }
+}
+
+impl<F, R, E> Future for SlowRequestFuture<F>
+where
+    F: Future<Output = Result<Response<R>, E>>,
+{
+    type Output = Result<Response<SlowRequestBody<R>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(res) = this.inner.poll(cx) {
+            let watch = this.watch.clone();
+            let sleep = this.sleep.take();
+            return Poll::Ready(res.map(|res| {
+                res.map(|inner| SlowRequestBody {
+                    inner,
+                    watch,
+                    response: true,
+                    sleep,
+                })
+            }));
+        }
+
+        if let Some(ref mut sleep) = this.sleep {
+            if sleep.as_mut().poll(cx).is_ready() {
+                *this.sleep = None;
+                let phase = if this.watch.reading.load(Ordering::Acquire) {
+                    SlowPhase::RequestBody
+                } else {
+                    SlowPhase::Handler
+                };
+                this.watch.report(phase);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+// ===== impl SlowRequestBody =====
+
+impl<B> SlowRequestBody<B> {
+    /// Get a reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Consume this wrapper and get the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for SlowRequestBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowRequestBody")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<B: Body> Body for SlowRequestBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if let Some(ref mut sleep) = this.sleep {
+            if sleep.as_mut().poll(cx).is_ready() {
+                *this.sleep = None;
+                this.watch.report(SlowPhase::ResponseBody);
+            }
+        }
+
+        let frame = this.inner.poll_frame(cx);
+        if !*this.response {
+            this.watch
+                .reading
+                .store(frame.is_pending(), Ordering::Release);
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+// ===== impl Watch =====
+
+impl Watch {
+    fn report(&self, phase: SlowPhase) {
+        if self.reported.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let slow = SlowRequest {
+            method: self.method.clone(),
+            uri: self.uri.clone(),
+            elapsed: self.started.elapsed(),
+            phase,
+        };
+        match self.report {
+            Report::Tracing => {
+                tracing::warn!(
+                    target: "hyper_util::slow_request",
+                    method = %slow.method,
+                    uri = %slow.uri,
+                    elapsed = ?slow.elapsed,
+                    phase = ?slow.phase,
+                    "{}",
+                    slow,
+                );
+            }
+            Report::Callback(ref callback) => callback(&slow),
+        }
+    }
+}
+
+// ===== impl SlowRequest =====
+
+impl SlowRequest {
+    /// The method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The URI of the request.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// How long the request had been in flight when it was reported.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// What the request was waiting for when it was reported.
+    pub fn phase(&self) -> SlowPhase {
+        self.phase
+    }
+}
+
+impl fmt::Display for SlowRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} in flight for {:?}, {}",
+            self.method, self.uri, self.elapsed, self.phase
+        )
+    }
+}
+
+impl fmt::Display for SlowPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SlowPhase::RequestBody => "reading the request body",
+            SlowPhase::Handler => "waiting for the handler",
+            SlowPhase::ResponseBody => "writing the response body",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures_channel::oneshot;
+    use futures_util::FutureExt;
+    use http::{Request, Response};
+    use http_body::{Body, Frame};
+    use http_body_util::BodyExt;
+    use hyper::service::Service;
+
+    use super::{SlowPhase, SlowRequestBody, SlowRequests};
+    use crate::rt::MockTimer;
+
+    // A body that never ends.
+    #[derive(Default)]
+    struct Endless;
+
+    impl Body for Endless {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+            Poll::Pending
+        }
+    }
+
+    // Reads the request body if asked to, and responds once the channel
+    // is sent to.
+    struct Handler {
+        read_body: bool,
+        respond: Mutex<Option<oneshot::Receiver<()>>>,
+    }
+
+    impl Service<Request<SlowRequestBody<Endless>>> for Handler {
+        type Response = Response<Endless>;
+        type Error = Infallible;
+        type Future =
+            Pin<Box<dyn std::future::Future<Output = Result<Response<Endless>, Infallible>>>>;
+
+        fn call(&self, req: Request<SlowRequestBody<Endless>>) -> Self::Future {
+            let read_body = self.read_body;
+            let respond = self.respond.lock().unwrap().take().unwrap();
+            Box::pin(async move {
+                if read_body {
+                    let _ = req.into_body().frame().await;
+                }
+                let _ = respond.await;
+                Ok(Response::new(Endless))
+            })
+        }
+    }
+
+    fn phases(read_body: bool, respond: bool) -> Vec<SlowPhase> {
+        let timer = MockTimer::new();
+        let phases = Arc::new(Mutex::new(Vec::new()));
+        let reported = phases.clone();
+        let (tx, rx) = oneshot::channel();
+        let handler = Handler {
+            read_body,
+            respond: Mutex::new(Some(rx)),
+        };
+        let svc = SlowRequests::new(handler, timer.clone(), Duration::from_secs(1))
+            .on_slow(move |slow| reported.lock().unwrap().push(slow.phase()));
+
+        let mut fut = svc.call(Request::new(Endless));
+        assert!((&mut fut).now_or_never().is_none());
+        if respond {
+            tx.send(()).unwrap();
+            let mut body = fut.now_or_never().unwrap().unwrap().into_body();
+            assert!(body.frame().now_or_never().is_none());
+            timer.advance(Duration::from_secs(1));
+            assert!(body.frame().now_or_never().is_none());
+        } else {
+            timer.advance(Duration::from_secs(1));
+            assert!((&mut fut).now_or_never().is_none());
+            // reported once
+            timer.advance(Duration::from_secs(1));
+            assert!(fut.now_or_never().is_none());
+        }
+        let phases = phases.lock().unwrap().clone();
+        phases
+    }
+
+    #[test]
+    fn reports_handler_phase() {
+        assert_eq!(phases(false, false), [SlowPhase::Handler]);
+    }
+
+    #[test]
+    fn reports_request_body_phase() {
+        assert_eq!(phases(true, false), [SlowPhase::RequestBody]);
+    }
+
+    #[test]
+    fn reports_response_body_phase() {
+        assert_eq!(phases(false, true), [SlowPhase::ResponseBody]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+
+use http::{Request, Response};
+
+/// Create a `Service` from a function that is also given a shared state.
+///
+/// The state is put in an `Arc` once, and the `Arc` is cloned for every
+/// request, so it is shared by all connections and requests that use
+/// clones of the service. There is no need to clone it into the closure
+/// for each connection and again for each request.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "server")]
+/// # fn run() {
+/// use std::convert::Infallible;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use http::{Request, Response};
+/// use hyper::body::Incoming;
+/// use hyper_util::service::service_fn_with_state;
+///
+/// struct State {
+///     hits: AtomicUsize,
+/// }
+///
+/// async fn handle(state: Arc<State>, _req: Request<Incoming>) -> Result<Response<String>, Infallible> {
+///     let hits = state.hits.fetch_add(1, Ordering::Relaxed) + 1;
+///     Ok(Response::new(format!("{} hits", hits)))
+/// }
+///
+/// let svc = service_fn_with_state(State { hits: AtomicUsize::new(0) }, handle);
+/// // Clones share the same state.
+/// let _per_connection = svc.clone();
+/// # }
+/// # fn main() {}
+/// ```
+pub fn service_fn_with_state<S, F, B, Ret>(state: S, f: F) -> ServiceFnWithState<S, F>
+where
+    F: Fn(Arc<S>, Request<B>) -> Ret,
+    Ret: Future,
+{
+    ServiceFnWithState {
+        state: Arc::new(state),
+        f,
+    }
+}
+
+/// Service returned by [`service_fn_with_state`].
+pub struct ServiceFnWithState<S, F> {
+    state: Arc<S>,
+    f: F,
+}
+
+impl<S, F> ServiceFnWithState<S, F> {
+    /// Get a reference to the shared state.
+    pub fn state(&self) -> &Arc<S> {
+        &self.state
+    }
+}
+
+impl<S, F, B, Ret, ResBody, E> hyper::service::Service<Request<B>> for ServiceFnWithState<S, F>
+where
+    F: Fn(Arc<S>, Request<B>) -> Ret,
+    Ret: Future<Output = Result<Response<ResBody>, E>>,
+    E: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Response = Response<ResBody>;
+    type Error = E;
+    type Future = Ret;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        (self.f)(self.state.clone(), req)
+    }
+}
+
+impl<S, F: Clone> Clone for ServiceFnWithState<S, F> {
+    fn clone(&self) -> Self {
+        ServiceFnWithState {
+            state: self.state.clone(),
+            f: self.f.clone(),
+        }
+    }
+}
+
+impl<S, F> fmt::Debug for ServiceFnWithState<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceFnWithState").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use futures_util::FutureExt;
+    use http::{Request, Response};
+    use hyper::service::Service;
+
+    use super::service_fn_with_state;
+
+    #[test]
+    fn clones_share_state() {
+        let svc = service_fn_with_state(
+            AtomicUsize::new(0),
+            |hits: Arc<AtomicUsize>, _req: Request<()>| async move {
+                let n = hits.fetch_add(1, Ordering::SeqCst) + 1;
+                Ok::<_, Infallible>(Response::new(n))
+            },
+        );
+        let other = svc.clone();
+
+        let res = svc.call(Request::new(())).now_or_never().unwrap().unwrap();
+        assert_eq!(*res.body(), 1);
+        let res = other
+            .call(Request::new(()))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(*res.body(), 2);
+        assert_eq!(svc.state().load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,316 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use http::{Request, Response, StatusCode};
+use http_body::{Body, Frame, SizeHint};
+use hyper::rt::{Sleep, Timer as _};
+use pin_project_lite::pin_project;
+
+use super::BoxError;
+use crate::common::timer::Timer;
+
+/// A service that bounds how long the inner service takes to respond.
+///
+/// When the inner service hasn't responded by the deadline, its response
+/// future is dropped, and the request is responded to with an empty body and
+/// `504 Gateway Timeout`, or the configured status code.
+///
+/// The same deadline applies to the response body. A body still streaming
+/// when it passes is no longer polled, and ends with an [`Elapsed`] error,
+/// which makes hyper abort the response.
+#[derive(Clone, Debug)]
+pub struct Timeout<S> {
+    inner: S,
+    timer: Timer,
+    timeout: Duration,
+    status: StatusCode,
+}
+
+/// Error of a [`TimeoutResponseBody`] whose deadline passed.
+#[derive(Debug)]
+pub struct Elapsed(());
+
+pin_project! {
+    /// Response future for [`Timeout`].
+    pub struct TimeoutFuture<F> {
+        #[pin]
+        future: F,
+        sleep: Option<Pin<Box<dyn Sleep>>>,
+        status: StatusCode,
+    }
+}
+
+pin_project! {
+    /// Response body of a [`Timeout`] service.
+    ///
+    /// It is empty for the responses to requests that timed out.
+    pub struct TimeoutResponseBody<B> {
+        #[pin]
+        inner: Option<B>,
+        sleep: Option<Pin<Box<dyn Sleep>>>,
+        elapsed: bool,
+    }
+}
+
+// ===== impl Timeout =====
+
+impl<S> Timeout<S> {
+    /// Bound `inner` to respond within `timeout`, waiting on `timer`.
+    pub fn new<M>(inner: S, timeout: Duration, timer: M) -> Self
+    where
+        M: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        Timeout {
+            inner,
+            timer: Timer::new(timer),
+            timeout,
+            status: StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// Set the status code of responses to requests that timed out, such as
+    /// `503 Service Unavailable`.
+    ///
+    /// Default is `504 Gateway Timeout`.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B, R> hyper::service::Service<Request<B>> for Timeout<S>
+where
+    S: hyper::service::Service<Request<B>, Response = Response<R>>,
+{
+    type Response = Response<TimeoutResponseBody<R>>;
+    type Error = S::Error;
+    type Future = TimeoutFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        TimeoutFuture {
+            future: self.inner.call(req),
+            sleep: Some(self.timer.sleep(self.timeout)),
+            status: self.status,
+        }
+    }
+}
+
+// ===== impl TimeoutFuture =====
+
+impl<F> fmt::Debug for TimeoutFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeoutFuture").finish()
+    }
+}
+
+impl<F, R, E> Future for TimeoutFuture<F>
+where
+    F: Future<Output = Result<Response<R>, E>>,
+{
+    type Output = Result<Response<TimeoutResponseBody<R>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(res) = this.future.poll(cx) {
+            let sleep = this.sleep.take();
+            return Poll::Ready(
+                res.map(|res| res.map(|body| TimeoutResponseBody::new(Some(body), sleep))),
+            );
+        }
+
+        let sleep = this.sleep.as_mut().expect("polled after complete");
+        futures_util::ready!(sleep.as_mut().poll(cx));
+        *this.sleep = None;
+
+        let mut res = Response::new(TimeoutResponseBody::new(None, None));
+        *res.status_mut() = *this.status;
+        Poll::Ready(Ok(res))
+    }
+}
+
+// ===== impl TimeoutResponseBody =====
+
+impl<B> TimeoutResponseBody<B> {
+    fn new(inner: Option<B>, sleep: Option<Pin<Box<dyn Sleep>>>) -> Self {
+        TimeoutResponseBody {
+            inner,
+            sleep,
+            elapsed: false,
+        }
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for TimeoutResponseBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeoutResponseBody")
+            .field("inner", &self.inner)
+            .field("elapsed", &self.elapsed)
+            .finish()
+    }
+}
+
+impl<B> Body for TimeoutResponseBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = B::Data;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if *this.elapsed {
+            return Poll::Ready(None);
+        }
+        if let Some(ref mut sleep) = this.sleep {
+            if sleep.as_mut().poll(cx).is_ready() {
+                *this.elapsed = true;
+                *this.sleep = None;
+                return Poll::Ready(Some(Err(Elapsed(()).into())));
+            }
+        }
+        let inner = match this.inner.as_pin_mut() {
+            Some(inner) => inner,
+            None => return Poll::Ready(None),
+        };
+        match futures_util::ready!(inner.poll_frame(cx)) {
+            Some(Ok(frame)) => Poll::Ready(Some(Ok(frame))),
+            Some(Err(err)) => Poll::Ready(Some(Err(err.into()))),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self.inner {
+            Some(ref inner) => self.elapsed || inner.is_end_stream(),
+            None => true,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.inner {
+            Some(ref inner) => inner.size_hint(),
+            None => SizeHint::with_exact(0),
+        }
+    }
+}
+
+// ===== impl Elapsed =====
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request timed out")
+    }
+}
+
+impl StdError for Elapsed {}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures_channel::oneshot;
+    use futures_util::FutureExt;
+    use http::{Request, Response, StatusCode};
+    use http_body::{Body, Frame};
+    use http_body_util::BodyExt;
+    use hyper::service::Service;
+
+    use super::{Elapsed, Timeout};
+    use crate::rt::MockTimer;
+
+    // A body that never ends.
+    struct Endless;
+
+    impl Body for Endless {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+            Poll::Pending
+        }
+    }
+
+    // Responds once the request's body, a channel, is sent to.
+    struct Pending;
+
+    impl Service<Request<oneshot::Receiver<()>>> for Pending {
+        type Response = Response<Endless>;
+        type Error = Infallible;
+        type Future = Pin<
+            Box<dyn std::future::Future<Output = Result<Response<Endless>, Infallible>> + Send>,
+        >;
+
+        fn call(&self, req: Request<oneshot::Receiver<()>>) -> Self::Future {
+            Box::pin(async move {
+                let _ = req.into_body().await;
+                Ok(Response::new(Endless))
+            })
+        }
+    }
+
+    #[test]
+    fn responds_with_status_when_elapsed() {
+        let timer = MockTimer::new();
+        let svc = Timeout::new(Pending, Duration::from_secs(5), timer.clone())
+            .status(StatusCode::SERVICE_UNAVAILABLE);
+
+        let (_tx, rx) = oneshot::channel();
+        let mut fut = svc.call(Request::new(rx));
+        assert!((&mut fut).now_or_never().is_none());
+
+        timer.advance(Duration::from_secs(5));
+        let res = fut.now_or_never().unwrap().unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(res.body().is_end_stream());
+    }
+
+    #[test]
+    fn cancels_body_when_elapsed() {
+        let timer = MockTimer::new();
+        let svc = Timeout::new(Pending, Duration::from_secs(5), timer.clone());
+
+        let (tx, rx) = oneshot::channel();
+        let mut fut = svc.call(Request::new(rx));
+        tx.send(()).unwrap();
+        let res = (&mut fut).now_or_never().unwrap().unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut body = res.into_body();
+        assert!(body.frame().now_or_never().is_none());
+
+        timer.advance(Duration::from_secs(5));
+        let err = body.frame().now_or_never().unwrap().unwrap().unwrap_err();
+        assert!(err.is::<Elapsed>());
+        assert!(body.is_end_stream());
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+
+use http::{HeaderMap, HeaderValue, Request};
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+
+/// A W3C trace context, from the `traceparent` and `tracestate` headers.
+///
+/// The server side gets it from incoming requests with
+/// [`ExtractTraceContext`], and the client side sends it with outgoing
+/// requests with [`PropagateTraceContext`]. A request made while handling
+/// another is usually sent with a [child](TraceContext::child) of the
+/// incoming context.
+///
+/// See <https://www.w3.org/TR/trace-context/>.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: [u8; 16],
+    parent_id: [u8; 8],
+    flags: u8,
+    state: Option<HeaderValue>,
+}
+
+/// A service that parses the trace context of every request, and inserts it
+/// into the extensions of the request as a [`TraceContext`].
+///
+/// Requests without a valid `traceparent` header are passed on without one.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "server")]
+/// # fn run() {
+/// use std::convert::Infallible;
+/// use http::{Request, Response};
+/// use hyper::service::service_fn;
+/// use hyper_util::service::{ExtractTraceContext, TraceContext};
+///
+/// let svc = service_fn(|req: Request<hyper::body::Incoming>| async move {
+///     let trace_id = req.extensions().get::<TraceContext>().map(|cx| cx.trace_id_hex());
+///     Ok::<_, Infallible>(Response::new(format!("{:?}", trace_id)))
+/// });
+/// let svc = ExtractTraceContext::new(svc);
+/// # let _ = svc;
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExtractTraceContext<S> {
+    inner: S,
+}
+
+/// A client service that sends the [`TraceContext`] in the extensions of a
+/// request as its `traceparent` and `tracestate` headers.
+///
+/// Requests that already have a `traceparent` header are sent as they are.
+/// This wraps a tower service, such as the legacy `Client`.
+#[derive(Debug, Clone)]
+pub struct PropagateTraceContext<S> {
+    inner: S,
+}
+
+// ===== impl TraceContext =====
+
+impl TraceContext {
+    /// Start a new trace, with random ids.
+    pub fn new_root(sampled: bool) -> Self {
+        let mut trace_id = [0; 16];
+        trace_id[..8].copy_from_slice(&random_id());
+        trace_id[8..].copy_from_slice(&random_id());
+        TraceContext {
+            trace_id,
+            parent_id: random_id(),
+            flags: sampled as u8,
+            state: None,
+        }
+    }
+
+    /// Parse the trace context of a request from its headers.
+    ///
+    /// Returns `None` if the `traceparent` header is missing or invalid. An
+    /// invalid `tracestate` header is left out.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut parents = headers.get_all(TRACEPARENT).iter();
+        let parent = parents.next()?;
+        if parents.next().is_some() {
+            return None;
+        }
+        let mut cx = parse_traceparent(parent.as_bytes())?;
+        cx.state = join_tracestate(headers);
+        Some(cx)
+    }
+
+    /// A context for a request made as part of this one, with the same trace
+    /// id and state, and a new random parent id.
+    pub fn child(&self) -> Self {
+        TraceContext {
+            parent_id: random_id(),
+            ..self.clone()
+        }
+    }
+
+    /// Set the `tracestate` of this context.
+    pub fn with_tracestate(mut self, state: Option<HeaderValue>) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// The id of the whole trace.
+    pub fn trace_id(&self) -> [u8; 16] {
+        self.trace_id
+    }
+
+    /// The id of the trace as lowercase hex, as it's usually logged.
+    pub fn trace_id_hex(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    /// The id of the span that made the request.
+    pub fn parent_id(&self) -> [u8; 8] {
+        self.parent_id
+    }
+
+    /// The trace flags.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Whether the caller may have recorded the trace.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & 1 == 1
+    }
+
+    /// The vendor specific `tracestate`, if any.
+    pub fn tracestate(&self) -> Option<&HeaderValue> {
+        self.state.as_ref()
+    }
+
+    /// Insert the `traceparent` and `tracestate` headers of this context,
+    /// replacing any that are there.
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        let parent = HeaderValue::from_str(&self.to_string()).expect("traceparent is valid header");
+        headers.insert(TRACEPARENT, parent);
+        match self.state {
+            Some(ref state) => {
+                headers.insert(TRACESTATE, state.clone());
+            }
+            None => {
+                headers.remove(TRACESTATE);
+            }
+        }
+    }
+}
+
+impl fmt::Display for TraceContext {
+    /// Formats as a version `00` `traceparent`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{}-{}-{:02x}",
+            hex(&self.trace_id),
+            hex(&self.parent_id),
+            self.flags
+        )
+    }
+}
+
+fn parse_traceparent(value: &[u8]) -> Option<TraceContext> {
+    // version-trace_id-parent_id-flags, with later versions possibly
+    // appending more fields after another dash.
+    if value.len() < 55 {
+        return None;
+    }
+    let version = parse_hex::<1>(&value[0..2])?;
+    if version[0] == 0xff || (version[0] == 0 && value.len() != 55) {
+        return None;
+    }
+    if value.len() > 55 && value[55] != b'-' {
+        return None;
+    }
+    if value[2] != b'-' || value[35] != b'-' || value[52] != b'-' {
+        return None;
+    }
+    let trace_id = parse_hex::<16>(&value[3..35])?;
+    let parent_id = parse_hex::<8>(&value[36..52])?;
+    let flags = parse_hex::<1>(&value[53..55])?[0];
+    if trace_id == [0; 16] || parent_id == [0; 8] {
+        return None;
+    }
+    Some(TraceContext {
+        trace_id,
+        parent_id,
+        // Only the sampled flag is defined by version 00.
+        flags: flags & 1,
+        state: None,
+    })
+}
+
+fn join_tracestate(headers: &HeaderMap) -> Option<HeaderValue> {
+    let mut joined = Vec::new();
+    for value in headers.get_all(TRACESTATE) {
+        let value = value.to_str().ok()?.trim();
+        if value.is_empty() {
+            continue;
+        }
+        if !joined.is_empty() {
+            joined.push(b',');
+        }
+        joined.extend_from_slice(value.as_bytes());
+    }
+    if joined.is_empty() {
+        return None;
+    }
+    HeaderValue::from_bytes(&joined).ok()
+}
+
+fn parse_hex<const N: usize>(src: &[u8]) -> Option<[u8; N]> {
+    fn digit(b: u8) -> Option<u8> {
+        match b {
+            b'0'..=b'9' => Some(b - b'0'),
+            b'a'..=b'f' => Some(b - b'a' + 10),
+            _ => None,
+        }
+    }
+
+    let mut out = [0; N];
+    for (byte, pair) in out.iter_mut().zip(src.chunks(2)) {
+        *byte = digit(pair[0])? << 4 | digit(pair[1])?;
+    }
+    Some(out)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Ids only need to be unique, so they're derived from the random keys of
+// the std hasher, and a counter.
+fn random_id() -> [u8; 8] {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    loop {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        let id = hasher.finish();
+        if id != 0 {
+            return id.to_be_bytes();
+        }
+    }
+}
+
+// ===== impl ExtractTraceContext =====
+
+impl<S> ExtractTraceContext<S> {
+    /// Wrap `inner`, extracting the trace context of every request.
+    pub fn new(inner: S) -> Self {
+        ExtractTraceContext { inner }
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> hyper::service::Service<Request<B>> for ExtractTraceContext<S>
+where
+    S: hyper::service::Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        if let Some(cx) = TraceContext::from_headers(req.headers()) {
+            req.extensions_mut().insert(cx);
+        }
+        self.inner.call(req)
+    }
+}
+
+// ===== impl PropagateTraceContext =====
+
+impl<S> PropagateTraceContext<S> {
+    /// Wrap `inner`, sending the trace context of every request.
+    pub fn new(inner: S) -> Self {
+        PropagateTraceContext { inner }
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> tower_service::Service<Request<B>> for PropagateTraceContext<S>
+where
+    S: tower_service::Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if !req.headers().contains_key(TRACEPARENT) {
+            if let Some(cx) = req.extensions().get::<TraceContext>().cloned() {
+                cx.insert_headers(req.headers_mut());
+            }
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures_util::FutureExt;
+    use http::{HeaderMap, HeaderValue, Request, Response};
+    use hyper::service::{service_fn, Service as _};
+    use tower::ServiceExt;
+
+    use super::{ExtractTraceContext, PropagateTraceContext, TraceContext};
+
+    const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn with_parent(parent: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", HeaderValue::from_str(parent).unwrap());
+        headers
+    }
+
+    #[test]
+    fn parses_and_formats_traceparent() {
+        let mut headers = with_parent(PARENT);
+        headers.append("tracestate", HeaderValue::from_static("a=1"));
+        headers.append("tracestate", HeaderValue::from_static("b=2"));
+        let cx = TraceContext::from_headers(&headers).unwrap();
+        assert_eq!(cx.trace_id_hex(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(
+            cx.parent_id(),
+            [0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7]
+        );
+        assert!(cx.is_sampled());
+        assert_eq!(cx.tracestate().unwrap(), "a=1,b=2");
+        assert_eq!(cx.to_string(), PARENT);
+
+        let child = cx.child();
+        assert_eq!(child.trace_id(), cx.trace_id());
+        assert_ne!(child.parent_id(), cx.parent_id());
+
+        // a later version, with an extra field
+        let future = format!("01{}-extra", &PARENT[2..]);
+        assert!(TraceContext::from_headers(&with_parent(&future)).is_some());
+    }
+
+    #[test]
+    fn rejects_invalid_traceparent() {
+        for parent in &[
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00_4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert!(
+                TraceContext::from_headers(&with_parent(parent)).is_none(),
+                "{:?}",
+                parent
+            );
+        }
+    }
+
+    #[test]
+    fn extracts_into_extensions() {
+        let svc = ExtractTraceContext::new(service_fn(|req: Request<String>| async move {
+            let cx = req.extensions().get::<TraceContext>();
+            let body = cx.map(ToString::to_string).unwrap_or_default();
+            Ok::<_, Infallible>(Response::new(body))
+        }));
+
+        let mut req = Request::new(String::new());
+        *req.headers_mut() = with_parent(PARENT);
+        let res = svc.call(req).now_or_never().unwrap().unwrap();
+        assert_eq!(res.into_body(), PARENT);
+
+        let res = svc
+            .call(Request::new(String::new()))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(res.into_body().is_empty());
+    }
+
+    #[test]
+    fn propagates_from_extensions() {
+        let svc = PropagateTraceContext::new(tower::service_fn(|req: Request<()>| async move {
+            Ok::<_, Infallible>(req.headers().get("traceparent").cloned())
+        }));
+
+        let cx = TraceContext::new_root(true);
+        let mut req = Request::new(());
+        req.extensions_mut().insert(cx.clone());
+        let sent = svc
+            .clone()
+            .oneshot(req)
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(sent, cx.to_string().as_str());
+
+        let sent = svc
+            .oneshot(Request::new(()))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(sent.is_none());
+    }
+}
@@ -356,6 +356,456 @@ async fn socket_disconnect_closes_idle_conn() {
 }
 
 #[cfg(not(miri))]
+#[tokio::test]
+async fn pool_identity_separates_shared_pool() {
+    use hyper_util::client::legacy::{IdlePool, PoolIdentity, PoolKey};
+    use std::sync::{Arc, Mutex};
+
+    // An idle store that several clients share.
+    struct Shared<T>(Arc<Mutex<Vec<(PoolKey, T)>>>);
+
+    impl<T: Send + 'static> IdlePool<T, PoolKey> for Shared<T> {
+        fn checkout(&mut self, key: &PoolKey) -> Option<T> {
+            let mut idle = self.0.lock().unwrap();
+            let pos = idle.iter().position(|(k, _)| k == key)?;
+            Some(idle.remove(pos).1)
+        }
+
+        fn checkin(&mut self, key: PoolKey, value: T) {
+            self.0.lock().unwrap().push((key, value));
+        }
+
+        fn close(&mut self, key: &PoolKey) {
+            self.0.lock().unwrap().retain(|(k, _)| k != key);
+        }
+
+        fn idle_count(&self, key: &PoolKey) -> usize {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(k, _)| k == key)
+                .count()
+        }
+
+        fn total_idle_count(&self) -> usize {
+            self.0.lock().unwrap().len()
+        }
+
+        fn retain(&mut self, f: &mut dyn FnMut(&PoolKey, &mut T) -> bool) {
+            self.0.lock().unwrap().retain_mut(|(k, v)| f(k, v));
+        }
+    }
+
+    let _ = pretty_env_logger::try_init();
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+
+    thread::spawn(move || {
+        for sock in server.incoming() {
+            let mut sock = sock.unwrap();
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                while sock.read(&mut buf).map(|n| n > 0).unwrap_or(false) {
+                    let _ = sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+                }
+            });
+        }
+    });
+
+    let connector = DebugConnector::new();
+    let connects = connector.connects.clone();
+    let idle = Arc::new(Mutex::new(Vec::new()));
+
+    let client_a: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new())
+        .pool_identity(PoolIdentity::new("a"))
+        .build_with_pool(connector.clone(), Shared(idle.clone()));
+    let client_b: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new())
+        .pool_identity(PoolIdentity::new("b"))
+        .build_with_pool(connector, Shared(idle.clone()));
+
+    let get = |client: &Client<DebugConnector, Empty<Bytes>>| {
+        let req = Request::builder()
+            .uri(&*format!("http://{}/", addr))
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        client.request(req)
+    };
+
+    get(&client_a)
+        .await
+        .unwrap()
+        .into_body()
+        .collect()
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(idle.lock().unwrap().len(), 1);
+    assert_eq!(
+        idle.lock().unwrap()[0].0.identity(),
+        Some(&PoolIdentity::new("a"))
+    );
+
+    // the idle connection of client a is not reused by client b
+    get(&client_b)
+        .await
+        .unwrap()
+        .into_body()
+        .collect()
+        .await
+        .unwrap();
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+    // but it is by client a
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    get(&client_a)
+        .await
+        .unwrap()
+        .into_body()
+        .collect()
+        .await
+        .unwrap();
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn error_kinds() {
+    use hyper_util::client::legacy::connect::dns::Name;
+    use hyper_util::client::legacy::ErrorKind;
+
+    // nothing listens on the port once the listener is dropped
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let client: Client<_, Empty<Bytes>> =
+        Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+    let uri = format!("http://{}", addr).parse().unwrap();
+    let err = client.get(uri).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Connect);
+    assert!(err.is_retryable());
+
+    let resolver = tower::service_fn(|_: Name| async {
+        Err::<std::vec::IntoIter<SocketAddr>, _>(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no such host",
+        ))
+    });
+    let client: Client<_, Empty<Bytes>> =
+        Client::builder(TokioExecutor::new()).build(HttpConnector::new_with_resolver(resolver));
+    let err = client
+        .get("http://example.invalid".parse().unwrap())
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Dns);
+
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut sock = server.accept().unwrap().0;
+        let mut buf = [0; 4096];
+        let _ = sock.read(&mut buf);
+        sock.write_all(b"not http\r\n\r\n").unwrap();
+    });
+    let client: Client<_, Empty<Bytes>> =
+        Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+    let uri = format!("http://{}", addr).parse().unwrap();
+    let err = client.get(uri).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Protocol);
+    assert!(!err.is_retryable());
+
+    let err = client.get("/relative".parse().unwrap()).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::User);
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn client_events() {
+    use hyper_util::client::legacy::pool::PoolEventInfo;
+    use hyper_util::client::legacy::{pool::PoolEvent, PoolKey};
+    use hyper_util::events::{ConnectionEvent, Events, RequestEvent, Side};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Events for Recorder {
+        fn connection_opened(&self, conn: &ConnectionEvent<'_>) {
+            assert_eq!(conn.side(), Side::Client);
+            let event = format!("opened {:?}", conn.version());
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn request_started(&self, req: &RequestEvent<'_>) {
+            let event = format!("started {} {}", req.method(), req.uri());
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn request_finished(&self, req: &RequestEvent<'_>) {
+            let event = format!("finished {:?}", req.status());
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn pool_event(&self, event: PoolEvent, _: &PoolKey, _: &PoolEventInfo) {
+            self.0.lock().unwrap().push(format!("pool {:?}", event));
+        }
+    }
+
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut sock = server.accept().unwrap().0;
+        let mut buf = [0; 4096];
+        let _ = sock.read(&mut buf);
+        sock.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        let _ = sock.read(&mut buf);
+    });
+
+    let recorder = Arc::new(Recorder::default());
+    let client: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new())
+        .events(recorder.clone())
+        .build(HttpConnector::new());
+    let uri: hyper::Uri = format!("http://{}/a", addr).parse().unwrap();
+    let res = client.get(uri.clone()).await.unwrap();
+    assert_eq!(res.status(), 204);
+
+    // the connection may be returned to the pool before or after the
+    // response is, depending on when it is ready again
+    let events = recorder.0.lock().unwrap();
+    assert_eq!(
+        events[..3],
+        [
+            format!("started GET {}", uri),
+            "opened HTTP/1.1".to_string(),
+            "pool Created".to_string(),
+        ]
+    );
+    assert!(events.contains(&"finished Some(204)".to_string()));
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn balancer_skips_failed_upstream() {
+    use hyper_util::client::legacy::{Balancer, ErrorKind, Upstream};
+
+    // nothing listens on the port once the listener is dropped
+    let refused = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut sock = server.accept().unwrap().0;
+        let mut buf = [0; 4096];
+        for _ in 0..2 {
+            let n = sock.read(&mut buf).unwrap();
+            let req = s(&buf[..n]);
+            assert!(req.starts_with("GET /a?b HTTP/1.1\r\n"));
+            assert!(req.contains(&format!("host: {}\r\n", addr)));
+            sock.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        }
+    });
+
+    let client: Client<_, Empty<Bytes>> =
+        Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+    let upstream = |addr: SocketAddr| Upstream::new(format!("http://{}", addr).parse().unwrap());
+    let balancer = Balancer::new(client, vec![upstream(refused), upstream(addr)]);
+
+    let req = || {
+        Request::builder()
+            .uri("http://proxy.local/a?b")
+            .header("host", "proxy.local")
+            .body(Empty::new())
+            .unwrap()
+    };
+    let err = balancer.request(req()).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Connect);
+    let down = balancer.down();
+    assert_eq!(down.len(), 1);
+    assert_eq!(down[0].authority().as_str(), refused.to_string());
+
+    for _ in 0..2 {
+        let res = balancer.request(req()).await.unwrap();
+        assert_eq!(res.status(), 204);
+    }
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn proxy_protocol_header_per_client() {
+    use hyper_util::client::legacy::connect::proxy::{ProxyAddrs, ProxyProtocol};
+
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        for _ in 0..2 {
+            let mut sock = server.accept().unwrap().0;
+            let mut header = [0; 28];
+            sock.read_exact(&mut header).unwrap();
+            tx.send(header).unwrap();
+            let mut buf = [0; 4096];
+            let n = sock.read(&mut buf).unwrap();
+            assert!(s(&buf[..n]).starts_with("GET / HTTP/1.1\r\n"));
+            sock.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        }
+    });
+
+    let client: Client<_, Empty<Bytes>> =
+        Client::builder(TokioExecutor::new()).build(ProxyProtocol::new(HttpConnector::new()));
+    for port in [5000u16, 5001] {
+        let mut req = Request::new(Empty::new());
+        *req.uri_mut() = format!("http://{}", addr).parse().unwrap();
+        req.extensions_mut()
+            .insert(ProxyAddrs::new(SocketAddr::from(([10, 0, 0, 1], port))));
+        let res = client.request(req).await.unwrap();
+        assert_eq!(res.status(), 204);
+
+        // a connection per client, each with its address
+        let header = rx.recv().unwrap();
+        assert_eq!(&header[..16], b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c");
+        assert_eq!(&header[16..20], &[10, 0, 0, 1]);
+        assert_eq!(&header[24..26], &port.to_be_bytes());
+    }
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn circuit_breaker_fails_fast() {
+    use hyper_util::client::legacy::{CircuitBreaker, CircuitState, ErrorKind};
+
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut sock = server.accept().unwrap().0;
+        let mut buf = [0; 4096];
+        for _ in 0..2 {
+            let _ = sock.read(&mut buf).unwrap();
+            sock.write_all(b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+        }
+    });
+
+    let client: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new())
+        .circuit_breaker(CircuitBreaker::new(2, Duration::from_secs(60)))
+        .build(HttpConnector::new());
+    let uri: hyper::Uri = format!("http://{}/", addr).parse().unwrap();
+    for _ in 0..2 {
+        let res = client.get(uri.clone()).await.unwrap();
+        assert_eq!(res.status(), 503);
+    }
+
+    let err = client.get(uri).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::CircuitOpen);
+    let key = client
+        .pool_stats()
+        .hosts()
+        .next()
+        .map(|(key, _)| key.clone());
+    assert_eq!(client.circuit_state(&key.unwrap()), CircuitState::Open);
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn hedge_takes_faster_attempt() {
+    use hyper_util::client::legacy::Hedge;
+    use hyper_util::rt::TokioTimer;
+
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        for (i, delay) in [(1, 5000), (2, 0)] {
+            let mut sock = server.accept().unwrap().0;
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                let _ = sock.read(&mut buf).unwrap();
+                thread::sleep(Duration::from_millis(delay));
+                let res = format!(
+                    "HTTP/1.1 200 OK\r\nx-conn: {}\r\ncontent-length: 0\r\n\r\n",
+                    i
+                );
+                let _ = sock.write_all(res.as_bytes());
+            });
+        }
+    });
+
+    let client: Client<_, Empty<Bytes>> =
+        Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+    let hedge = Hedge::new(client, TokioTimer::new(), Duration::from_millis(50));
+    let started = std::time::Instant::now();
+    let res = hedge
+        .get(format!("http://{}/", addr).parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(res.headers()["x-conn"], "2");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(all(feature = "client-cache", not(miri)))]
+#[tokio::test]
+async fn cache_hits_and_revalidates() {
+    use hyper_util::client::legacy::cache::{Cache, CacheStatus};
+
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        let mut sock = server.accept().unwrap().0;
+        let mut buf = [0; 4096];
+        loop {
+            let n = sock.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            let req = s(&buf[..n]).to_owned();
+            let res: &[u8] = if req.starts_with("GET /fresh ") {
+                b"HTTP/1.1 200 OK\r\ncache-control: max-age=60\r\ncontent-length: 5\r\n\r\nfresh"
+            } else if req.contains("if-none-match: \"v1\"\r\n") {
+                b"HTTP/1.1 304 Not Modified\r\netag: \"v1\"\r\n\r\n"
+            } else {
+                b"HTTP/1.1 200 OK\r\ncache-control: no-cache\r\netag: \"v1\"\r\ncontent-length: 5\r\n\r\nstale"
+            };
+            tx.send(req).unwrap();
+            sock.write_all(res).unwrap();
+        }
+    });
+
+    let client: Client<_, Empty<Bytes>> =
+        Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+    let cache = Cache::new(client);
+    let get = |path: &str| {
+        let fut = cache.get(format!("http://{}{}", addr, path).parse().unwrap());
+        async move {
+            let res = fut.await.unwrap();
+            let status = *res.extensions().get::<CacheStatus>().unwrap();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, body)
+        }
+    };
+
+    assert_eq!(
+        get("/fresh").await,
+        (CacheStatus::Miss, Bytes::from("fresh"))
+    );
+    assert_eq!(
+        get("/fresh").await,
+        (CacheStatus::Hit, Bytes::from("fresh"))
+    );
+    assert_eq!(rx.try_iter().count(), 1);
+
+    assert_eq!(
+        get("/stale").await,
+        (CacheStatus::Miss, Bytes::from("stale"))
+    );
+    assert_eq!(
+        get("/stale").await,
+        (CacheStatus::Revalidated, Bytes::from("stale"))
+    );
+    assert_eq!(rx.try_iter().count(), 2);
+}
+
 #[test]
 fn connect_call_is_lazy() {
     // We especially don't want connects() triggered if there's
@@ -876,3 +1326,342 @@ fn alpn_h2() {
     );
     drop(client);
 }
+
+#[cfg(all(unix, not(miri)))]
+#[tokio::test]
+async fn unix_socket_connector() {
+    use http::Response;
+    use hyper::service::service_fn;
+    use hyper_util::client::legacy::connect::{UnixConnector, UnixInfo};
+    use tokio::net::UnixListener;
+
+    let _ = pretty_env_logger::try_init();
+
+    let path = std::env::temp_dir().join(format!(
+        "hyper-util-legacy-client-{}.sock",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.expect("accept");
+        let _ = hyper::server::conn::http1::Builder::new()
+            .serve_connection(
+                TokioIo::new(stream),
+                service_fn(|req| async move {
+                    assert_eq!(req.uri(), "/info");
+                    Ok::<_, hyper::Error>(Response::new(Full::<Bytes>::from("unix")))
+                }),
+            )
+            .await;
+    });
+
+    let client = Client::builder(TokioExecutor::new()).build(UnixConnector::new());
+    let uri = UnixConnector::uri(&path, "/info").unwrap();
+
+    let res = client.get(uri).await.unwrap();
+    assert_eq!(res.extensions().get::<UnixInfo>().unwrap().path(), path);
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "unix");
+
+    let _: &Client<_, Empty<Bytes>> = &client;
+    let _ = std::fs::remove_file(&path);
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn shutdown_closes_idle_and_waits_for_requests() {
+    use http::Response;
+    use hyper::service::service_fn;
+    use hyper_util::client::legacy::ErrorKind;
+    use hyper_util::rt::TokioTimer;
+    use std::sync::{Arc, Mutex};
+
+    let _ = pretty_env_logger::try_init();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (release_tx, release_rx) = oneshot::channel::<()>();
+    let release_rx = Arc::new(Mutex::new(Some(release_rx)));
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let release_rx = release_rx.clone();
+            let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                let release = match req.uri().path() {
+                    "/slow" => release_rx.lock().unwrap().take(),
+                    _ => None,
+                };
+                async move {
+                    if let Some(release) = release {
+                        let _ = release.await;
+                    }
+                    Ok::<_, hyper::Error>(Response::new(Full::<Bytes>::from("done")))
+                }
+            });
+            tokio::spawn(
+                hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service),
+            );
+        }
+    });
+
+    let (closes_tx, mut closes) = mpsc::channel(10);
+    let client = Client::builder(TokioExecutor::new())
+        .pool_timer(TokioTimer::new())
+        .build(DebugConnector::with_http_and_closes(
+            HttpConnector::new(),
+            closes_tx,
+        ));
+    let get = |path: &str| {
+        client.request(
+            Request::builder()
+                .uri(format!("http://{}{}", addr, path))
+                .body(Empty::<Bytes>::new())
+                .unwrap(),
+        )
+    };
+
+    // one connection busy, and another idle
+    let slow = tokio::spawn(get("/slow"));
+    let res = get("/fast").await.unwrap();
+    res.into_body().collect().await.unwrap();
+
+    assert!(!client.shutdown(Duration::from_millis(50)).await);
+    // the idle connection was closed
+    closes.next().await.unwrap();
+
+    let err = get("/fast").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Canceled);
+
+    release_tx.send(()).unwrap();
+    let res = slow.await.unwrap().unwrap();
+    res.into_body().collect().await.unwrap();
+    assert!(client.shutdown(Duration::from_secs(5)).await);
+    // and the busy one isn't kept idle
+    closes.next().await.unwrap();
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn http1_max_headers_allows_many_headers() {
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut sock = server.accept().unwrap().0;
+        sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        sock.set_write_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut buf = [0; 4096];
+        sock.read(&mut buf).expect("read 1");
+        let mut res = String::from("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n");
+        for i in 0..150 {
+            res.push_str(&format!("x-header-{}: {}\r\n", i, i));
+        }
+        res.push_str("\r\n");
+        sock.write_all(res.as_bytes()).unwrap();
+    });
+
+    let client = Client::builder(TokioExecutor::new())
+        .http1_max_headers(200)
+        .build(HttpConnector::new());
+
+    let req = Request::builder()
+        .uri(&*format!("http://{}/a", addr))
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let res = client.request(req).await.unwrap();
+    assert_eq!(res.status(), hyper::StatusCode::OK);
+    assert_eq!(res.headers().len(), 151);
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn max_pending_requests_fails_fast() {
+    use hyper_util::client::legacy::connect::dns::Name;
+    use hyper_util::client::legacy::ErrorKind;
+
+    // resolving never finishes, so requests keep waiting for a connection
+    let resolver = tower::service_fn(|_: Name| {
+        future::pending::<Result<std::vec::IntoIter<SocketAddr>, std::io::Error>>()
+    });
+    let client: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new())
+        .max_pending_requests(1)
+        .build(HttpConnector::new_with_resolver(resolver));
+
+    let mut first = client.get("http://a.local".parse().unwrap());
+    assert!((&mut first).now_or_never().is_none());
+
+    let err = client
+        .get("http://b.local".parse().unwrap())
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PoolFull);
+
+    drop(first);
+    let mut third = client.get("http://a.local".parse().unwrap());
+    assert!((&mut third).now_or_never().is_none());
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn alpn_h2_coalesces_dials() {
+    use http::Response;
+    use hyper::service::service_fn;
+    use tokio::net::TcpListener;
+
+    let _ = pretty_env_logger::try_init();
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut connector = DebugConnector::new();
+    connector.alpn_h2 = true;
+    let connects = connector.connects.clone();
+
+    let client: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new())
+        .pool_max_connecting_per_host(1)
+        .build(connector);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.expect("accept");
+            tokio::spawn(
+                hyper::server::conn::http2::Builder::new(TokioExecutor::new()).serve_connection(
+                    TokioIo::new(stream),
+                    service_fn(|_| async {
+                        Ok::<_, hyper::Error>(Response::new(Full::<Bytes>::from("hello")))
+                    }),
+                ),
+            );
+        }
+    });
+
+    let url = format!("http://{}/a", addr)
+        .parse::<::hyper::Uri>()
+        .unwrap();
+    let res1 = client.get(url.clone());
+    let res2 = client.get(url.clone());
+    let res3 = client.get(url.clone());
+    future::try_join3(res1, res2, res3).await.unwrap();
+
+    // Only the first request dialed, and the others shared its connection.
+    assert_eq!(connects.load(Ordering::SeqCst), 1);
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn no_pool_uses_dedicated_connection() {
+    use http::Response;
+    use hyper::service::service_fn;
+    use hyper_util::client::legacy::NoPool;
+    use tokio::net::TcpListener;
+
+    let _ = pretty_env_logger::try_init();
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.expect("accept");
+            tokio::spawn(hyper::server::conn::http1::Builder::new().serve_connection(
+                TokioIo::new(stream),
+                service_fn(|req| async move {
+                    let close = req.headers().get("connection").is_some();
+                    Ok::<_, hyper::Error>(Response::new(Full::<Bytes>::from(if close {
+                        "close"
+                    } else {
+                        "keep-alive"
+                    })))
+                }),
+            ));
+        }
+    });
+
+    let connector = DebugConnector::new();
+    let connects = connector.connects.clone();
+    let client: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new()).build(connector);
+    let url = format!("http://{}/a", addr);
+
+    let send = |no_pool: bool| {
+        let mut req = Request::builder()
+            .uri(&*url)
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        if no_pool {
+            req.extensions_mut().insert(NoPool);
+        }
+        let res = client.request(req);
+        async move {
+            res.await
+                .unwrap()
+                .into_body()
+                .collect()
+                .await
+                .unwrap()
+                .to_bytes()
+        }
+    };
+
+    assert_eq!(send(false).await, "keep-alive");
+    assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+    // the idle connection is left alone
+    assert_eq!(send(true).await, "close");
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+    // and the dedicated one isn't pooled
+    assert_eq!(send(false).await, "keep-alive");
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+    assert_eq!(client.pool_stats().idle(), 1);
+}
+
+#[cfg(not(miri))]
+#[tokio::test]
+async fn pool_preconnect_opens_connection_ahead() {
+    use http::Response;
+    use hyper::service::service_fn;
+    use tokio::net::TcpListener;
+
+    let _ = pretty_env_logger::try_init();
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.expect("accept");
+            tokio::spawn(hyper::server::conn::http1::Builder::new().serve_connection(
+                TokioIo::new(stream),
+                service_fn(|_| async {
+                    Ok::<_, hyper::Error>(Response::new(Full::<Bytes>::from("hi")))
+                }),
+            ));
+        }
+    });
+
+    let connector = DebugConnector::new();
+    let connects = connector.connects.clone();
+    let client: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new())
+        .pool_preconnect(Duration::from_secs(0))
+        .build(connector);
+
+    let res = client
+        .get(format!("http://{}/a", addr).parse().unwrap())
+        .await
+        .unwrap();
+    res.into_body().collect().await.unwrap();
+
+    // the request took the only connection, so another one is opened
+    for _ in 0..100 {
+        if client.pool_stats().idle() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+    assert_eq!(client.pool_stats().idle(), 2);
+}